@@ -1,113 +0,0 @@
-use std::fmt;
-
-#[derive(PartialEq, Clone, Debug)]
-pub struct Ident(pub String);
-
-#[derive(PartialEq, Clone, Debug)]
-pub enum Prefix {
-    Plus,
-    Minus,
-    Not,
-}
-
-impl fmt::Display for Prefix {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match *self {
-            Prefix::Plus => write!(f, "+"),
-            Prefix::Minus => write!(f, "-"),
-            Prefix::Not => write!(f, "!"),
-        }
-    }
-}
-
-#[derive(PartialEq, Clone, Debug)]
-pub enum Infix {
-    Plus,
-    Minus,
-    Divide,
-    Multiply,
-    Equal,
-    NotEqual,
-    GreaterThanEqual,
-    GreaterThan,
-    LessThanEqual,
-    LessThan,
-}
-
-impl fmt::Display for Infix {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match *self {
-            Infix::Plus => write!(f, "+"),
-            Infix::Minus => write!(f, "-"),
-            Infix::Divide => write!(f, "/"),
-            Infix::Multiply => write!(f, "*"),
-            Infix::Equal => write!(f, "=="),
-            Infix::NotEqual => write!(f, "!="),
-            Infix::GreaterThanEqual => write!(f, ">="),
-            Infix::GreaterThan => write!(f, ">"),
-            Infix::LessThanEqual => write!(f, "<="),
-            Infix::LessThan => write!(f, "<"),
-        }
-    }
-}
-
-#[derive(PartialEq, Clone, Debug)]
-pub enum Expr {
-    Ident(Ident),
-    Literal(Literal),
-    Prefix(Prefix, Box<Expr>),
-    Infix(Infix, Box<Expr>, Box<Expr>),
-    Index(Box<Expr>, Box<Expr>),
-    If {
-        cond: Box<Expr>,
-        consequence: BlockStmt,
-        alternative: Option<BlockStmt>,
-    },
-    While {
-        cond: Box<Expr>,
-        consequence: BlockStmt,
-    },
-    Func {
-        params: Vec<Ident>,
-        body: BlockStmt,
-    },
-    Call {
-        func: Box<Expr>,
-        args: Vec<Expr>,
-    },
-}
-
-#[derive(PartialEq, Clone, Debug)]
-pub enum Literal {
-    Int(i64),
-    String(String),
-    Bool(bool),
-    Array(Vec<Expr>),
-    Hash(Vec<(Expr, Expr)>),
-}
-
-#[derive(PartialEq, Clone, Debug)]
-pub enum Stmt {
-    Blank,
-    Break,
-    Continue,
-    Let(Ident, Expr),
-    Return(Expr),
-    Expr(Expr),
-}
-
-pub type BlockStmt = Vec<Stmt>;
-
-pub type Program = BlockStmt;
-
-#[derive(PartialEq, PartialOrd, Debug, Clone)]
-pub enum Precedence {
-    Lowest,
-    Equals,      // ==
-    LessGreater, // > or <
-    Sum,         // +
-    Product,     // *
-    Prefix,      // -X or !X
-    Call,        // myFunction(x)
-    Index,       // array[index]
-}
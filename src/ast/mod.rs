@@ -0,0 +1,310 @@
+pub mod visit;
+
+use crate::span::Span;
+use std::fmt;
+
+#[derive(PartialEq, Clone, Debug)]
+pub struct Ident(pub String);
+
+/// A type written after `:` or `->`, e.g. `整数`. Only checked by tooling
+/// and strict mode; plain evaluation ignores it.
+#[derive(PartialEq, Clone, Debug)]
+pub struct TypeAnnotation(pub String);
+
+#[derive(PartialEq, Clone, Debug)]
+pub struct Param {
+    pub ident: Ident,
+    pub ty: Option<TypeAnnotation>,
+    /// `= expr`, evaluated in the call's scope when the argument is left out.
+    /// Only the last parameters can have one.
+    pub default: Option<Expr>,
+}
+
+impl From<Ident> for Param {
+    fn from(ident: Ident) -> Self {
+        Param {
+            ident,
+            ty: None,
+            default: None,
+        }
+    }
+}
+
+/// How many of `params` a call has to give, the ones before the defaults.
+pub fn required_params(params: &[Param]) -> usize {
+    params
+        .iter()
+        .take_while(|param| param.default.is_none())
+        .count()
+}
+
+#[derive(PartialEq, Clone, Debug)]
+pub enum Prefix {
+    Plus,
+    Minus,
+    Not,
+    Await,
+}
+
+impl fmt::Display for Prefix {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Prefix::Plus => write!(f, "+"),
+            Prefix::Minus => write!(f, "-"),
+            Prefix::Not => write!(f, "!"),
+            Prefix::Await => write!(f, "await"),
+        }
+    }
+}
+
+#[derive(PartialEq, Clone, Debug)]
+pub enum Infix {
+    Plus,
+    Minus,
+    Divide,
+    Multiply,
+    Equal,
+    NotEqual,
+    GreaterThanEqual,
+    GreaterThan,
+    LessThanEqual,
+    LessThan,
+    And,
+    Or,
+    /// `a ?? b`: `b` only when `a` is null.
+    Coalesce,
+}
+
+impl fmt::Display for Infix {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Infix::Plus => write!(f, "+"),
+            Infix::Minus => write!(f, "-"),
+            Infix::Divide => write!(f, "/"),
+            Infix::Multiply => write!(f, "*"),
+            Infix::Equal => write!(f, "=="),
+            Infix::NotEqual => write!(f, "!="),
+            Infix::GreaterThanEqual => write!(f, ">="),
+            Infix::GreaterThan => write!(f, ">"),
+            Infix::LessThanEqual => write!(f, "<="),
+            Infix::LessThan => write!(f, "<"),
+            Infix::And => write!(f, "&&"),
+            Infix::Or => write!(f, "||"),
+            Infix::Coalesce => write!(f, "??"),
+        }
+    }
+}
+
+impl Infix {
+    /// `==`, `!=`, `<`, `<=`, `>` and `>=`.
+    pub fn is_comparison(&self) -> bool {
+        !matches!(
+            self,
+            Infix::Plus
+                | Infix::Minus
+                | Infix::Divide
+                | Infix::Multiply
+                | Infix::And
+                | Infix::Or
+                | Infix::Coalesce
+        )
+    }
+}
+
+#[derive(PartialEq, Clone, Debug)]
+pub enum Expr {
+    Ident(Ident),
+    Literal(Literal),
+    Prefix(Prefix, Box<Expr>),
+    Infix(Infix, Box<Expr>, Box<Expr>),
+    Index(Box<Expr>, Box<Expr>),
+    If {
+        cond: Box<Expr>,
+        consequence: BlockStmt,
+        alternative: Option<BlockStmt>,
+    },
+    While {
+        cond: Box<Expr>,
+        consequence: BlockStmt,
+    },
+    /// `do { ... } while (cond)`: `While` with the body run before the
+    /// first check.
+    DoWhile {
+        consequence: BlockStmt,
+        cond: Box<Expr>,
+    },
+    /// `match x { 1 => a, "b" => { ... }, _ => c }`: the body of the first
+    /// arm whose pattern matches, or `null` when none does.
+    Match {
+        subject: Box<Expr>,
+        arms: Vec<MatchArm>,
+    },
+    /// `for (x in items) { ... }`, or `for (i, x in items) { ... }` to get
+    /// the index (the key, for a hash) too.
+    For {
+        vars: Vec<Ident>,
+        iterable: Box<Expr>,
+        body: BlockStmt,
+    },
+    /// `for (init; cond; step) { body }`, any of the three may be left out.
+    /// Only a parser `without_macro_expansion` keeps this, so the formatter
+    /// can give it back; otherwise it becomes a `While`, see
+    /// `parser::desugar_c_for`.
+    CFor {
+        init: Option<Box<Stmt>>,
+        cond: Option<Box<Expr>>,
+        step: Option<Box<Stmt>>,
+        body: BlockStmt,
+    },
+    Func {
+        params: Vec<Param>,
+        return_ty: Option<TypeAnnotation>,
+        body: BlockStmt,
+        /// `async fn`: calling it starts a task instead of running the body.
+        is_async: bool,
+    },
+    Call {
+        func: Box<Expr>,
+        args: Vec<Expr>,
+    },
+}
+
+impl Expr {
+    /// `receiver` and `name` for `receiver.name`, which calls a method when
+    /// it's the callee of a call.
+    pub fn as_dot_access(&self) -> Option<(&Expr, &str)> {
+        match self {
+            Expr::Index(receiver, index) => match index.as_ref() {
+                Expr::Literal(Literal::String(name)) => Some((receiver, name)),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+}
+
+/// Formats a float so it reads back as one: `2.0` rather than `2`.
+pub fn format_float(value: f64) -> String {
+    let result = value.to_string();
+    if value.is_finite() && !result.contains('.') {
+        format!("{result}.0")
+    } else {
+        result
+    }
+}
+
+#[derive(PartialEq, Clone, Debug)]
+pub struct MatchArm {
+    pub pattern: Pattern,
+    /// A single expression arm is a block of just that expression.
+    pub body: BlockStmt,
+}
+
+/// What a `match` arm compares the value against.
+#[derive(PartialEq, Clone, Debug)]
+pub enum Pattern {
+    /// `_`, which matches anything.
+    Wildcard,
+    /// A number, string or bool, compared like `==`.
+    Literal(Literal),
+    /// A name, which matches anything and binds it.
+    Binding(Ident),
+    /// `[老大, 2, ...其余]`: an array with one item per pattern, or at
+    /// least that many when there is a rest name, which gets the others.
+    Array(Vec<Pattern>, Option<Ident>),
+    /// `{名字, 年龄: 18}`: a hash with these keys, whose values match the
+    /// patterns. `名字` alone is short for `名字: 名字`. Other keys are ignored.
+    Hash(Vec<(String, Pattern)>),
+}
+
+impl Pattern {
+    /// The names the pattern binds when it matches, in order.
+    pub fn bindings(&self) -> Vec<&Ident> {
+        match self {
+            Pattern::Wildcard | Pattern::Literal(_) => vec![],
+            Pattern::Binding(ident) => vec![ident],
+            Pattern::Array(items, rest) => items
+                .iter()
+                .flat_map(Pattern::bindings)
+                .chain(rest.as_ref())
+                .collect(),
+            Pattern::Hash(entries) => entries
+                .iter()
+                .flat_map(|(_, pattern)| pattern.bindings())
+                .collect(),
+        }
+    }
+}
+
+#[derive(PartialEq, Clone, Debug)]
+pub enum Literal {
+    Int(i64),
+    Float(f64),
+    /// `5元`: a number and its unit.
+    Quantity(i64, String),
+    String(String),
+    Bool(bool),
+    Array(Vec<Expr>),
+    Hash(Vec<(Expr, Expr)>),
+}
+
+#[derive(PartialEq, Clone, Debug)]
+pub enum Comment {
+    /// The text after `//` or `吐槽`.
+    Line(String),
+    /// The text between `/*` and `*/`, nested comments and all.
+    Block(String),
+    /// The `#她版本 n` header, which the lexer has already acted on.
+    Version(u32),
+}
+
+#[derive(PartialEq, Clone, Debug)]
+pub enum Stmt {
+    Blank,
+    /// A comment on its own line, or before a statement.
+    Comment(Comment),
+    Break,
+    Continue,
+    Let(Ident, Option<TypeAnnotation>, Expr),
+    /// `i++` or `i--`: rebinds `i` to the value in the nearest scope that
+    /// has it, where `let` would shadow it.
+    Assign(Ident, Expr),
+    Return(Expr),
+    Import(String),
+    /// `造个句式 name(params) => { template }`. The parser expands calls to
+    /// it, so the definition itself does nothing at runtime.
+    Macro {
+        name: Ident,
+        params: Vec<Param>,
+        body: BlockStmt,
+    },
+    /// `考一考 "name" { ... }`. Skipped when the program runs; `herlang test`
+    /// runs each one on its own.
+    Test {
+        name: String,
+        body: BlockStmt,
+    },
+    Expr(Expr),
+    /// Where the next statement is in the source. Only emitted by
+    /// `Parser::with_positions`, for stepping and error locations.
+    Mark(Span),
+}
+
+pub type BlockStmt = Vec<Stmt>;
+
+pub type Program = BlockStmt;
+
+#[derive(PartialEq, PartialOrd, Debug, Clone)]
+pub enum Precedence {
+    Lowest,
+    Coalesce,    // ??
+    Or,          // ||
+    And,         // &&
+    Equals,      // ==
+    LessGreater, // > or <
+    Sum,         // +
+    Product,     // *
+    Prefix,      // -X or !X
+    Call,        // myFunction(x)
+    Index,       // array[index]
+}
@@ -0,0 +1,420 @@
+//! Walking the AST without a match over every node.
+//!
+//! Implement `Visitor` (or `VisitorMut` to rewrite in place) and override the
+//! methods for the nodes you care about. Each default method calls the
+//! matching `walk_*` function, which visits the node's children; an override
+//! calls it too to keep going deeper, or leaves it out to skip them.
+
+use crate::ast::*;
+
+pub trait Visitor {
+    fn visit_block(&mut self, block: &BlockStmt) {
+        walk_block(self, block);
+    }
+
+    fn visit_stmt(&mut self, stmt: &Stmt) {
+        walk_stmt(self, stmt);
+    }
+
+    fn visit_expr(&mut self, expr: &Expr) {
+        walk_expr(self, expr);
+    }
+
+    fn visit_literal(&mut self, literal: &Literal) {
+        walk_literal(self, literal);
+    }
+
+    fn visit_pattern(&mut self, pattern: &Pattern) {
+        walk_pattern(self, pattern);
+    }
+
+    fn visit_param(&mut self, param: &Param) {
+        self.visit_ident(&param.ident);
+        if let Some(default) = &param.default {
+            self.visit_expr(default);
+        }
+    }
+
+    fn visit_ident(&mut self, _ident: &Ident) {}
+}
+
+pub fn walk_block<V: Visitor + ?Sized>(visitor: &mut V, block: &BlockStmt) {
+    for stmt in block {
+        visitor.visit_stmt(stmt);
+    }
+}
+
+pub fn walk_stmt<V: Visitor + ?Sized>(visitor: &mut V, stmt: &Stmt) {
+    match stmt {
+        Stmt::Let(ident, _, expr) | Stmt::Assign(ident, expr) => {
+            visitor.visit_ident(ident);
+            visitor.visit_expr(expr);
+        }
+        Stmt::Return(expr) | Stmt::Expr(expr) => visitor.visit_expr(expr),
+        Stmt::Macro { name, params, body } => {
+            visitor.visit_ident(name);
+            for param in params {
+                visitor.visit_param(param);
+            }
+            visitor.visit_block(body);
+        }
+        Stmt::Test { body, .. } => visitor.visit_block(body),
+        Stmt::Blank
+        | Stmt::Comment(_)
+        | Stmt::Break
+        | Stmt::Continue
+        | Stmt::Import(_)
+        | Stmt::Mark(_) => {}
+    }
+}
+
+pub fn walk_expr<V: Visitor + ?Sized>(visitor: &mut V, expr: &Expr) {
+    match expr {
+        Expr::Ident(ident) => visitor.visit_ident(ident),
+        Expr::Literal(literal) => visitor.visit_literal(literal),
+        Expr::Prefix(_, right) => visitor.visit_expr(right),
+        Expr::Infix(_, left, right) | Expr::Index(left, right) => {
+            visitor.visit_expr(left);
+            visitor.visit_expr(right);
+        }
+        Expr::If {
+            cond,
+            consequence,
+            alternative,
+        } => {
+            visitor.visit_expr(cond);
+            visitor.visit_block(consequence);
+            if let Some(alternative) = alternative {
+                visitor.visit_block(alternative);
+            }
+        }
+        Expr::While { cond, consequence } => {
+            visitor.visit_expr(cond);
+            visitor.visit_block(consequence);
+        }
+        Expr::DoWhile { consequence, cond } => {
+            visitor.visit_block(consequence);
+            visitor.visit_expr(cond);
+        }
+        Expr::Match { subject, arms } => {
+            visitor.visit_expr(subject);
+            for MatchArm { pattern, body } in arms {
+                visitor.visit_pattern(pattern);
+                visitor.visit_block(body);
+            }
+        }
+        Expr::For {
+            vars,
+            iterable,
+            body,
+        } => {
+            for var in vars {
+                visitor.visit_ident(var);
+            }
+            visitor.visit_expr(iterable);
+            visitor.visit_block(body);
+        }
+        Expr::CFor {
+            init,
+            cond,
+            step,
+            body,
+        } => {
+            if let Some(init) = init {
+                visitor.visit_stmt(init);
+            }
+            if let Some(cond) = cond {
+                visitor.visit_expr(cond);
+            }
+            if let Some(step) = step {
+                visitor.visit_stmt(step);
+            }
+            visitor.visit_block(body);
+        }
+        Expr::Func { params, body, .. } => {
+            for param in params {
+                visitor.visit_param(param);
+            }
+            visitor.visit_block(body);
+        }
+        Expr::Call { func, args } => {
+            visitor.visit_expr(func);
+            for arg in args {
+                visitor.visit_expr(arg);
+            }
+        }
+    }
+}
+
+pub fn walk_literal<V: Visitor + ?Sized>(visitor: &mut V, literal: &Literal) {
+    match literal {
+        Literal::Array(items) => {
+            for item in items {
+                visitor.visit_expr(item);
+            }
+        }
+        Literal::Hash(pairs) => {
+            for (key, value) in pairs {
+                visitor.visit_expr(key);
+                visitor.visit_expr(value);
+            }
+        }
+        Literal::Int(_)
+        | Literal::Float(_)
+        | Literal::Quantity(..)
+        | Literal::String(_)
+        | Literal::Bool(_) => {}
+    }
+}
+
+pub fn walk_pattern<V: Visitor + ?Sized>(visitor: &mut V, pattern: &Pattern) {
+    match pattern {
+        Pattern::Wildcard => {}
+        Pattern::Literal(literal) => visitor.visit_literal(literal),
+        Pattern::Binding(ident) => visitor.visit_ident(ident),
+        Pattern::Array(items, rest) => {
+            for item in items {
+                visitor.visit_pattern(item);
+            }
+            if let Some(rest) = rest {
+                visitor.visit_ident(rest);
+            }
+        }
+        Pattern::Hash(entries) => {
+            for (_, value) in entries {
+                visitor.visit_pattern(value);
+            }
+        }
+    }
+}
+
+/// Like `Visitor`, for passes that change the tree as they go.
+pub trait VisitorMut {
+    fn visit_block_mut(&mut self, block: &mut BlockStmt) {
+        walk_block_mut(self, block);
+    }
+
+    fn visit_stmt_mut(&mut self, stmt: &mut Stmt) {
+        walk_stmt_mut(self, stmt);
+    }
+
+    fn visit_expr_mut(&mut self, expr: &mut Expr) {
+        walk_expr_mut(self, expr);
+    }
+
+    fn visit_literal_mut(&mut self, literal: &mut Literal) {
+        walk_literal_mut(self, literal);
+    }
+
+    fn visit_pattern_mut(&mut self, pattern: &mut Pattern) {
+        walk_pattern_mut(self, pattern);
+    }
+
+    fn visit_param_mut(&mut self, param: &mut Param) {
+        self.visit_ident_mut(&mut param.ident);
+        if let Some(default) = &mut param.default {
+            self.visit_expr_mut(default);
+        }
+    }
+
+    fn visit_ident_mut(&mut self, _ident: &mut Ident) {}
+}
+
+pub fn walk_block_mut<V: VisitorMut + ?Sized>(visitor: &mut V, block: &mut BlockStmt) {
+    for stmt in block {
+        visitor.visit_stmt_mut(stmt);
+    }
+}
+
+pub fn walk_stmt_mut<V: VisitorMut + ?Sized>(visitor: &mut V, stmt: &mut Stmt) {
+    match stmt {
+        Stmt::Let(ident, _, expr) | Stmt::Assign(ident, expr) => {
+            visitor.visit_ident_mut(ident);
+            visitor.visit_expr_mut(expr);
+        }
+        Stmt::Return(expr) | Stmt::Expr(expr) => visitor.visit_expr_mut(expr),
+        Stmt::Macro { name, params, body } => {
+            visitor.visit_ident_mut(name);
+            for param in params {
+                visitor.visit_param_mut(param);
+            }
+            visitor.visit_block_mut(body);
+        }
+        Stmt::Test { body, .. } => visitor.visit_block_mut(body),
+        Stmt::Blank
+        | Stmt::Comment(_)
+        | Stmt::Break
+        | Stmt::Continue
+        | Stmt::Import(_)
+        | Stmt::Mark(_) => {}
+    }
+}
+
+pub fn walk_expr_mut<V: VisitorMut + ?Sized>(visitor: &mut V, expr: &mut Expr) {
+    match expr {
+        Expr::Ident(ident) => visitor.visit_ident_mut(ident),
+        Expr::Literal(literal) => visitor.visit_literal_mut(literal),
+        Expr::Prefix(_, right) => visitor.visit_expr_mut(right),
+        Expr::Infix(_, left, right) | Expr::Index(left, right) => {
+            visitor.visit_expr_mut(left);
+            visitor.visit_expr_mut(right);
+        }
+        Expr::If {
+            cond,
+            consequence,
+            alternative,
+        } => {
+            visitor.visit_expr_mut(cond);
+            visitor.visit_block_mut(consequence);
+            if let Some(alternative) = alternative {
+                visitor.visit_block_mut(alternative);
+            }
+        }
+        Expr::While { cond, consequence } => {
+            visitor.visit_expr_mut(cond);
+            visitor.visit_block_mut(consequence);
+        }
+        Expr::DoWhile { consequence, cond } => {
+            visitor.visit_block_mut(consequence);
+            visitor.visit_expr_mut(cond);
+        }
+        Expr::Match { subject, arms } => {
+            visitor.visit_expr_mut(subject);
+            for MatchArm { pattern, body } in arms {
+                visitor.visit_pattern_mut(pattern);
+                visitor.visit_block_mut(body);
+            }
+        }
+        Expr::For {
+            vars,
+            iterable,
+            body,
+        } => {
+            for var in vars {
+                visitor.visit_ident_mut(var);
+            }
+            visitor.visit_expr_mut(iterable);
+            visitor.visit_block_mut(body);
+        }
+        Expr::CFor {
+            init,
+            cond,
+            step,
+            body,
+        } => {
+            if let Some(init) = init {
+                visitor.visit_stmt_mut(init);
+            }
+            if let Some(cond) = cond {
+                visitor.visit_expr_mut(cond);
+            }
+            if let Some(step) = step {
+                visitor.visit_stmt_mut(step);
+            }
+            visitor.visit_block_mut(body);
+        }
+        Expr::Func { params, body, .. } => {
+            for param in params {
+                visitor.visit_param_mut(param);
+            }
+            visitor.visit_block_mut(body);
+        }
+        Expr::Call { func, args } => {
+            visitor.visit_expr_mut(func);
+            for arg in args {
+                visitor.visit_expr_mut(arg);
+            }
+        }
+    }
+}
+
+pub fn walk_literal_mut<V: VisitorMut + ?Sized>(visitor: &mut V, literal: &mut Literal) {
+    match literal {
+        Literal::Array(items) => {
+            for item in items {
+                visitor.visit_expr_mut(item);
+            }
+        }
+        Literal::Hash(pairs) => {
+            for (key, value) in pairs {
+                visitor.visit_expr_mut(key);
+                visitor.visit_expr_mut(value);
+            }
+        }
+        Literal::Int(_)
+        | Literal::Float(_)
+        | Literal::Quantity(..)
+        | Literal::String(_)
+        | Literal::Bool(_) => {}
+    }
+}
+
+pub fn walk_pattern_mut<V: VisitorMut + ?Sized>(visitor: &mut V, pattern: &mut Pattern) {
+    match pattern {
+        Pattern::Wildcard => {}
+        Pattern::Literal(literal) => visitor.visit_literal_mut(literal),
+        Pattern::Binding(ident) => visitor.visit_ident_mut(ident),
+        Pattern::Array(items, rest) => {
+            for item in items {
+                visitor.visit_pattern_mut(item);
+            }
+            if let Some(rest) = rest {
+                visitor.visit_ident_mut(rest);
+            }
+        }
+        Pattern::Hash(entries) => {
+            for (_, value) in entries {
+                visitor.visit_pattern_mut(value);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ast::visit::*;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+
+    fn parse(input: &str) -> Program {
+        Parser::new(Lexer::new(input)).parse()
+    }
+
+    #[test]
+    fn test_visitor() {
+        struct Idents(Vec<String>);
+
+        impl Visitor for Idents {
+            fn visit_ident(&mut self, Ident(name): &Ident) {
+                self.0.push(name.clone());
+            }
+        }
+
+        let mut idents = Idents(vec![]);
+        idents.visit_block(&parse(
+            "let f = fn(x) { if (x) { [y, {z: w}] } else { g(x) } }; while (a) { -b; }",
+        ));
+        assert_eq!(
+            vec!["f", "x", "x", "y", "z", "w", "g", "x", "a", "b"],
+            idents.0
+        );
+    }
+
+    #[test]
+    fn test_visitor_mut() {
+        struct DoubleInts;
+
+        impl VisitorMut for DoubleInts {
+            fn visit_literal_mut(&mut self, literal: &mut Literal) {
+                if let Literal::Int(n) = literal {
+                    *n *= 2;
+                }
+                walk_literal_mut(self, literal);
+            }
+        }
+
+        let mut program = parse("let a = [1, f(2)]; return 3 + {4: 5}[4];");
+        DoubleInts.visit_block_mut(&mut program);
+        assert_eq!(parse("let a = [2, f(4)]; return 6 + {8: 10}[8];"), program);
+    }
+}
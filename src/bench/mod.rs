@@ -0,0 +1,97 @@
+//! Benchmark scripts and a timer that splits a run into lexing, parsing and
+//! evaluation, so performance work can be checked against the same corpus.
+
+use crate::evaluator::Evaluator;
+use crate::evaluator::builtins::{capture_output, new_builtins};
+use crate::evaluator::env::Env;
+use crate::evaluator::object::Object;
+use crate::evaluator::shared::Shared;
+use crate::lexer::Lexer;
+use crate::parser::Parser;
+use crate::token::Token;
+use std::time::{Duration, Instant};
+
+/// The standard benchmarks as `(name, source)`.
+pub const CORPUS: &[(&str, &str)] = &[
+    ("fib", include_str!("fib.her")),
+    ("loop", include_str!("loop.her")),
+    ("concat", include_str!("concat.her")),
+    ("hash", include_str!("hash.her")),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Timings {
+    pub lex: Duration,
+    /// Parsing, without the lexing it drives.
+    pub parse: Duration,
+    pub eval: Duration,
+}
+
+impl Timings {
+    pub fn total(&self) -> Duration {
+        self.lex + self.parse + self.eval
+    }
+}
+
+/// Times `src` `runs` times and keeps the fastest run of each stage. Parse
+/// errors and runtime errors are returned as their message.
+pub fn measure(src: &str, runs: usize) -> Result<Timings, String> {
+    let mut best: Option<Timings> = None;
+
+    for _ in 0..runs.max(1) {
+        let start = Instant::now();
+        let mut lexer = Lexer::new(src);
+        while lexer.next_token() != Token::Eof {}
+        let lex = start.elapsed();
+
+        let start = Instant::now();
+        let mut parser = Parser::new(Lexer::new(src));
+        let program = parser.parse();
+        let parse = start.elapsed().saturating_sub(lex);
+        if let Some(err) = parser.get_errors().first() {
+            return Err(format!("{}: {}", err.span(), err));
+        }
+
+        let env = Env::from(new_builtins());
+        let mut evaluator = Evaluator::new(Shared::new(env));
+        let start = Instant::now();
+        let (result, _) = capture_output(|| evaluator.eval(&program));
+        let eval = start.elapsed();
+        if let Some(Object::Error(msg)) = result {
+            return Err(msg);
+        }
+
+        let timings = Timings { lex, parse, eval };
+        best = Some(match best {
+            None => timings,
+            Some(best) => Timings {
+                lex: best.lex.min(timings.lex),
+                parse: best.parse.min(timings.parse),
+                eval: best.eval.min(timings.eval),
+            },
+        });
+    }
+
+    Ok(best.unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bench::*;
+
+    #[test]
+    fn test_corpus() {
+        for (name, src) in CORPUS {
+            assert!(measure(src, 1).is_ok(), "{name}");
+        }
+    }
+
+    #[test]
+    fn test_measure_errors() {
+        assert!(measure("let = 1;", 1).is_err());
+        assert_eq!(
+            Err(String::from("identifier not found: nope")),
+            measure("nope", 1)
+        );
+    }
+}
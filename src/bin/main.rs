@@ -4,15 +4,34 @@ extern crate rustyline;
 #[cfg(feature = "binaries")]
 extern crate rustyline_derive;
 
+use herlang::bench;
+use herlang::callgraph::CallGraph;
+use herlang::codegen;
+use herlang::diagnostic::{self, Diagnostic, DiagnosticCode, Lang, Severity};
+use herlang::engine::Engine;
 use herlang::evaluator::Evaluator;
-use herlang::evaluator::builtins::new_builtins;
+use herlang::evaluator::builtins::{BuiltinRegistry, new_builtins};
+use herlang::evaluator::debug::{Resume, Step};
 use herlang::evaluator::env::Env;
-use herlang::lexer::{Lexer, is_whitespace};
+use herlang::evaluator::object::{Object, ReprLimit};
+use herlang::evaluator::shared::Shared;
+use herlang::fix;
+use herlang::golden::{Outcome, Runner, collect_her_files};
+use herlang::herc;
+use herlang::highlight::{self, Theme};
+use herlang::json;
+use herlang::lexer::{LANGUAGE_VERSION, LexError, Lexer, is_whitespace};
 use herlang::parser::{ParseError, Parser};
+use herlang::serve;
+use herlang::sexpr;
+use herlang::share::encode_share;
+use herlang::span::Span;
+use herlang::stats::Stats;
 use herlang::token::Token;
+use herlang::typecheck;
+use rayon::prelude::*;
 use std::borrow::Cow::{self, Borrowed, Owned};
-use std::cell::RefCell;
-use std::rc::Rc;
+use std::io::{IsTerminal, Read};
 
 use rustyline::Cmd::{
     HistorySearchBackward as HerstorySearchBackward, HistorySearchForward as HerstorySearchForward,
@@ -28,7 +47,7 @@ use rustyline_derive::Helper;
 
 #[derive(Helper)]
 struct HerHelper {
-    env: Rc<RefCell<Env>>,
+    env: Shared<Env>,
     highlighter: MatchingBracketHighlighter,
     hinter: HerstoryHinter,
     colored_prompt: String,
@@ -97,6 +116,11 @@ impl Validator for HerHelper {
         &self,
         ctx: &mut validate::ValidationContext,
     ) -> rustyline::Result<validate::ValidationResult> {
+        let input = ctx.input().trim_start();
+        if input.starts_with(":help") || input.starts_with(":limit") {
+            return Ok(validate::ValidationResult::Valid(None));
+        }
+
         let mut parser = Parser::new(Lexer::new(ctx.input()));
         let _ = parser.parse();
         let errors = parser.get_errors();
@@ -105,9 +129,11 @@ impl Validator for HerHelper {
             0 => validate::ValidationResult::Valid(None),
             _ => match &errors[0] {
                 ParseError::UnexpectedToken {
-                    want: _,
-                    got: Token::Eof,
-                } => validate::ValidationResult::Incomplete,
+                    got: Token::Eof, ..
+                }
+                | ParseError::Lex(
+                    LexError::UnterminatedString { .. } | LexError::UnterminatedComment { .. },
+                ) => validate::ValidationResult::Incomplete,
                 x => validate::ValidationResult::Invalid(Some(format!("{}", x))),
             },
         })
@@ -124,7 +150,7 @@ impl Validator for HerHelper {
 /// try to find backward the start of a word.
 /// Return (0, `line[..pos]`) if no break char has been found.
 /// Return the word and its start position (idx, `line[idx..pos]`) otherwise.
-pub fn extract_word<'l>(line: &'l str, pos: usize) -> (usize, &'l str) {
+pub fn extract_word(line: &str, pos: usize) -> (usize, &str) {
     let line = &line[..pos];
     if line.is_empty() {
         return (0, line);
@@ -142,10 +168,781 @@ pub fn extract_word<'l>(line: &'l str, pos: usize) -> (usize, &'l str) {
     }
 }
 
+// ---- Share ----
+
+/// `herlang share [file]`: prints the playground share string for a file,
+/// or for stdin when no file is given.
+fn share(path: Option<&String>) -> rustyline::Result<()> {
+    let src = match path {
+        Some(path) => std::fs::read_to_string(path)?,
+        None => {
+            let mut src = String::new();
+            std::io::stdin().read_to_string(&mut src)?;
+            src
+        }
+    };
+
+    println!("{}", encode_share(&src));
+    Ok(())
+}
+
+// ---- Run ----
+
+/// `herlang run <file> [-- <arg>...]`: runs a script, with the arguments after
+/// it in `参数` (or `args`) as strings.
+fn run(args: &[String]) -> rustyline::Result<()> {
+    let Some((path, rest)) = args.split_first() else {
+        eprintln!("usage: herlang run <file> [-- <arg>...]");
+        std::process::exit(2);
+    };
+    let script_args = match rest.split_first() {
+        Some((dashes, rest)) if dashes == "--" => rest,
+        _ => rest,
+    };
+
+    let src = std::fs::read_to_string(path)?;
+    let base_dir = std::path::Path::new(path)
+        .parent()
+        .unwrap_or(std::path::Path::new("."));
+    let mut engine = Engine::new()
+        .with_base_dir(base_dir)
+        .with_args(script_args.iter().cloned());
+    // Piped or scheduled runs have nobody to answer, so they don't stop.
+    if std::io::stdin().is_terminal() {
+        engine = engine.with_breakpoint(inspect);
+    }
+
+    match engine.eval(&src) {
+        Err(diagnostics) => {
+            for diagnostic in &diagnostics {
+                eprintln!(
+                    "{}:{}",
+                    path,
+                    diagnostic.render_friendly(&src, Lang::default())
+                );
+            }
+            std::process::exit(1);
+        }
+        Ok(Some(Object::Exit(code))) => std::process::exit(code),
+        Ok(Some(Object::Error(msg))) => {
+            eprintln!("{}: {}", path, msg);
+            std::process::exit(1);
+        }
+        Ok(_) => {}
+    }
+
+    Ok(())
+}
+
+/// What `breakpoint()` does under `herlang run`: shows where the program
+/// stopped and waits for commands on stdin.
+fn inspect(step: &Step) -> Resume {
+    eprintln!(
+        "先别卷了: 停在第 {} 行 (输入 h 看命令)",
+        step.span.start.line
+    );
+    loop {
+        eprint!("(断点) ");
+        let mut line = String::new();
+        match std::io::stdin().read_line(&mut line) {
+            Ok(0) | Err(_) => return Resume::Continue,
+            Ok(_) => {}
+        }
+
+        match line.trim() {
+            "" | "c" | "continue" => return Resume::Continue,
+            "s" | "step" => return Resume::Step,
+            "l" | "locals" => {
+                for (name, value) in &step.locals {
+                    eprintln!("  {name} = {}", value.repr(ReprLimit::default()));
+                }
+            }
+            "h" | "help" => eprintln!(
+                "  l, locals    列出当前作用域的变量\n  <名字>       看一个变量的值\n  s, step      执行下一条语句后再停\n  c, continue  继续运行 (直接回车也行)"
+            ),
+            name => match step.locals.iter().find(|(local, _)| local == name) {
+                Some((_, value)) => eprintln!("  {}", value.repr(ReprLimit::default())),
+                None => eprintln!("  这里没有 {name}"),
+            },
+        }
+    }
+}
+
+// ---- Check ----
+
+/// The language `--lang=en|zh` asks for, English without one.
+fn lang_arg(args: &[String]) -> Lang {
+    match args.iter().find_map(|arg| arg.strip_prefix("--lang=")) {
+        Some(lang) => lang.parse().unwrap_or_else(|err| {
+            eprintln!("{}", err);
+            std::process::exit(2);
+        }),
+        None => Lang::default(),
+    }
+}
+
+/// `herlang check [--types] [--verbose] [--lang=en|zh] [--format=text|json]
+/// <file|dir>...`: reports parse errors, and with `--types` also what the
+/// type pass finds, without running anything. `--verbose` adds the parser's
+/// own wording. Directories are searched for `.her` files. Files are checked
+/// in parallel and reported in the order given; one that can't be read is
+/// reported like any other error, and the rest are still checked.
+/// `--format=json` prints a `{"path", "diagnostics"}` line per file.
+fn check(args: &[String]) -> rustyline::Result<()> {
+    let types = args.iter().any(|arg| arg == "--types");
+    let verbose = args.iter().any(|arg| arg == "--verbose");
+    let lang = lang_arg(args);
+    let json = match args.iter().find_map(|arg| arg.strip_prefix("--format=")) {
+        Some("text") | None => false,
+        Some("json") => true,
+        Some(format) => {
+            eprintln!("unknown format {:?}, want text or json", format);
+            std::process::exit(2);
+        }
+    };
+    let unreadable = |err: std::io::Error| {
+        let code = DiagnosticCode::UnreadableFile {
+            error: err.to_string(),
+        };
+        (
+            String::new(),
+            vec![Diagnostic::error(code, Span::default())],
+        )
+    };
+    let mut paths = vec![];
+
+    for arg in args.iter().filter(|arg| !arg.starts_with("--")) {
+        let path = std::path::PathBuf::from(arg);
+        if !path.is_dir() {
+            paths.push((path, None));
+            continue;
+        }
+
+        let mut found = vec![];
+        let listed = collect_her_files(&path, &mut found);
+        found.sort();
+        paths.extend(found.into_iter().map(|path| (path, None)));
+        if let Err(err) = listed {
+            paths.push((path, Some(err)));
+        }
+    }
+
+    let checked = paths
+        .par_iter_mut()
+        .map(|(path, listed)| -> (String, Vec<Diagnostic>) {
+            if let Some(err) = listed.take() {
+                return unreadable(err);
+            }
+            let src = match std::fs::read_to_string(path) {
+                Ok(src) => src,
+                Err(err) => return unreadable(err),
+            };
+            let mut diagnostics = diagnostic::check(&src);
+            let parsed = !diagnostics.iter().any(|d| d.severity == Severity::Error);
+            if types && parsed {
+                diagnostics.extend(typecheck::check(&src));
+            }
+            (src, diagnostics)
+        })
+        .collect::<Vec<_>>();
+    let mut failed = false;
+
+    for ((path, _), (src, diagnostics)) in paths.iter().zip(checked) {
+        let path = path.display();
+        failed |= diagnostics.iter().any(|d| d.severity == Severity::Error);
+        if json {
+            println!(
+                "{{\"path\":{},\"diagnostics\":{}}}",
+                json::string(&path.to_string()),
+                diagnostic::to_json(&diagnostics, lang)
+            );
+            continue;
+        }
+
+        for diagnostic in diagnostics {
+            println!("{}:{}", path, diagnostic.render_friendly(&src, lang));
+            let message = diagnostic.message(lang);
+            if verbose && message != diagnostic.friendly_message(&src, lang) {
+                match lang {
+                    Lang::En => println!("  = note: {}", message),
+                    Lang::Zh => println!("  = 细节：{}", message),
+                }
+            }
+        }
+    }
+
+    if failed {
+        std::process::exit(1);
+    }
+
+    Ok(())
+}
+
+// ---- Explain ----
+
+/// `herlang explain [--lang=en|zh] <code>`: prints what a diagnostic code
+/// like `HER0006` means, with an example.
+fn explain(args: &[String]) -> rustyline::Result<()> {
+    let lang = lang_arg(args);
+    let Some(code) = args.iter().find(|arg| !arg.starts_with("--")) else {
+        eprintln!("usage: herlang explain [--lang=en|zh] <code>");
+        std::process::exit(2);
+    };
+
+    match diagnostic::explain(code, lang) {
+        Some(explanation) => println!("{}", explanation),
+        None => {
+            eprintln!("unknown diagnostic code: {}", code);
+            std::process::exit(2);
+        }
+    }
+
+    Ok(())
+}
+
+// ---- Analyze ----
+
+/// `herlang analyze <file> --callgraph <out>`: writes the call graph as
+/// Graphviz, or as JSON when `out` ends in `.json`. `-` prints it instead.
+fn analyze(args: &[String]) -> rustyline::Result<()> {
+    let mut input = None;
+    let mut callgraph = None;
+    let mut args = args.iter();
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--callgraph" => callgraph = args.next(),
+            _ => input = Some(arg),
+        }
+    }
+
+    let (Some(input), Some(out)) = (input, callgraph) else {
+        eprintln!("usage: herlang analyze <file> --callgraph <out.dot|out.json|->");
+        std::process::exit(2);
+    };
+
+    let src = std::fs::read_to_string(input)?;
+    let mut parser = Parser::new(Lexer::new(&src));
+    let program = parser.parse();
+    for err in parser.get_errors() {
+        eprintln!("{}:{}: {}", input, err.span(), err);
+    }
+
+    let graph = CallGraph::build(&program);
+    let output = if out.ends_with(".json") {
+        graph.to_json()
+    } else {
+        graph.to_dot()
+    };
+
+    if out == "-" {
+        print!("{}", output);
+    } else {
+        std::fs::write(out, output)?;
+    }
+
+    Ok(())
+}
+
+// ---- Parse ----
+
+/// `herlang parse <file> [--emit=ast|ast-pretty]`: prints what the parser
+/// makes of a file, as an indented S-expression tree by default or as the
+/// raw `Debug` form.
+fn parse(args: &[String]) -> rustyline::Result<()> {
+    let mut input = None;
+    let mut emit = "ast-pretty";
+
+    for arg in args {
+        match arg.strip_prefix("--emit=") {
+            Some(value) => emit = value,
+            None => input = Some(arg),
+        }
+    }
+
+    let Some(input) = input else {
+        eprintln!("usage: herlang parse <file> [--emit=ast|ast-pretty]");
+        std::process::exit(2);
+    };
+
+    let src = std::fs::read_to_string(input)?;
+    let mut parser = Parser::new(Lexer::new(&src));
+    let program = parser.parse();
+    for err in parser.get_errors() {
+        eprintln!("{}:{}: {}", input, err.span(), err);
+    }
+
+    match emit {
+        "ast-pretty" => print!("{}", sexpr::to_sexpr(&program)),
+        "ast" => println!("{:?}", program),
+        _ => {
+            eprintln!("unknown emit {:?}, want ast or ast-pretty", emit);
+            std::process::exit(2);
+        }
+    }
+
+    Ok(())
+}
+
+// ---- Highlight ----
+
+/// `herlang highlight <file> [--format=html|json] [--theme=light|dark]`:
+/// prints the highlighted source, as an HTML snippet with its stylesheet by
+/// default.
+fn highlight(args: &[String]) -> rustyline::Result<()> {
+    let mut input = None;
+    let mut format = "html";
+    let mut theme = Theme::default();
+
+    for arg in args {
+        if let Some(value) = arg.strip_prefix("--format=") {
+            format = value;
+        } else if let Some(value) = arg.strip_prefix("--theme=") {
+            theme = value.parse().unwrap_or_else(|err| {
+                eprintln!("{}", err);
+                std::process::exit(2);
+            });
+        } else {
+            input = Some(arg);
+        }
+    }
+
+    let Some(input) = input else {
+        eprintln!("usage: herlang highlight <file> [--format=html|json] [--theme=light|dark]");
+        std::process::exit(2);
+    };
+
+    let src = std::fs::read_to_string(input)?;
+    match format {
+        "html" => print!(
+            "<style>\n{}</style>\n{}",
+            theme.css(),
+            highlight::to_html(&src, theme)
+        ),
+        "json" => println!("{}", highlight::to_json(&highlight::tokens(&src))),
+        _ => {
+            eprintln!("unknown format {:?}, want html or json", format);
+            std::process::exit(2);
+        }
+    }
+
+    Ok(())
+}
+
+// ---- Test ----
+
+/// `herlang test [file|dir] [--update]`: runs the `.her` file, or every one
+/// under `dir`, and compares its output with the `.expected` file next to
+/// it, or runs its `考一考` blocks if it has any. Each block counts as one
+/// test.
+fn test(args: &[String]) -> rustyline::Result<()> {
+    let mut path = ".";
+    let mut update = false;
+
+    for arg in args {
+        match arg.as_str() {
+            "--update" => update = true,
+            _ => path = arg,
+        }
+    }
+
+    let cases = match Runner::new()
+        .with_update(update)
+        .run(std::path::Path::new(path))
+    {
+        Ok(cases) => cases,
+        Err(err) => {
+            eprintln!("{}: {}", path, err);
+            std::process::exit(1);
+        }
+    };
+    let mut passed = 0;
+    let mut failed = 0;
+
+    for case in &cases {
+        let path = case.path.display();
+        match &case.outcome {
+            Outcome::Passed => {
+                passed += 1;
+                println!("ok      {}", path);
+            }
+            Outcome::Updated => {
+                passed += 1;
+                println!("updated {}", path);
+            }
+            Outcome::Tests(results) => {
+                for result in results {
+                    match &result.failure {
+                        None => {
+                            passed += 1;
+                            println!("ok      {}: {}", path, result.name);
+                        }
+                        Some(failure) => {
+                            failed += 1;
+                            println!("FAILED  {}: {}", path, result.name);
+                            println!("{}", failure);
+                        }
+                    }
+                }
+            }
+            Outcome::Missing { .. } => {
+                failed += 1;
+                println!("missing {} (run with --update to create it)", path);
+            }
+            Outcome::Failed { expected, actual } => {
+                failed += 1;
+                println!("FAILED  {}", path);
+                println!("--- expected\n{}--- actual\n{}", expected, actual);
+            }
+        }
+    }
+
+    println!("\n{} passed, {} failed", passed, failed);
+    if failed > 0 {
+        std::process::exit(1);
+    }
+
+    Ok(())
+}
+
+// ---- Bench ----
+
+/// `herlang bench [--runs=<n>] [files...]`: times lexing, parsing and
+/// evaluation of the given scripts, or of the built-in corpus without any.
+fn bench(args: &[String]) -> rustyline::Result<()> {
+    let mut runs = 5;
+    let mut scripts = vec![];
+
+    for arg in args {
+        if let Some(value) = arg.strip_prefix("--runs=") {
+            runs = value.parse().unwrap_or_else(|_| {
+                eprintln!("--runs must be a number, got {:?}", value);
+                std::process::exit(2);
+            });
+        } else {
+            scripts.push((arg.clone(), std::fs::read_to_string(arg)?));
+        }
+    }
+    if scripts.is_empty() {
+        scripts = bench::CORPUS
+            .iter()
+            .map(|(name, src)| (name.to_string(), src.to_string()))
+            .collect();
+    }
+
+    let ms = |d: std::time::Duration| format!("{:.3}ms", d.as_secs_f64() * 1000.0);
+    println!(
+        "{:<16} {:>12} {:>12} {:>12} {:>12}",
+        "script", "lex", "parse", "eval", "total"
+    );
+    let mut failed = false;
+    for (name, src) in &scripts {
+        match bench::measure(src, runs) {
+            Ok(t) => println!(
+                "{:<16} {:>12} {:>12} {:>12} {:>12}",
+                name,
+                ms(t.lex),
+                ms(t.parse),
+                ms(t.eval),
+                ms(t.total())
+            ),
+            Err(msg) => {
+                failed = true;
+                eprintln!("{}: {}", name, msg);
+            }
+        }
+    }
+    if failed {
+        std::process::exit(1);
+    }
+
+    Ok(())
+}
+
+// ---- Stats ----
+
+/// `herlang stats <file|dir>... [--format=table|json]`: line counts, function
+/// count, nesting depth and the most used keywords of the given scripts, with
+/// directories searched for `.her` files.
+fn stats(args: &[String]) -> rustyline::Result<()> {
+    let mut format = "table";
+    let mut paths = vec![];
+
+    for arg in args {
+        if let Some(value) = arg.strip_prefix("--format=") {
+            format = value;
+        } else if std::path::Path::new(arg).is_dir() {
+            collect_her_files(std::path::Path::new(arg), &mut paths)?;
+        } else {
+            paths.push(std::path::PathBuf::from(arg));
+        }
+    }
+    if paths.is_empty() || !matches!(format, "table" | "json") {
+        eprintln!("usage: herlang stats <file|dir>... [--format=table|json]");
+        std::process::exit(2);
+    }
+    paths.sort();
+
+    let mut total = Stats::default();
+    for path in &paths {
+        let src = std::fs::read_to_string(path)?;
+        let mut parser = Parser::new(Lexer::new(&src));
+        let program = parser.parse();
+        for err in parser.get_errors() {
+            eprintln!("{}:{}: {}", path.display(), err.span(), err);
+        }
+        total.add(Stats::collect(&src, &program));
+    }
+
+    if format == "json" {
+        println!("{}", total.to_json());
+        return Ok(());
+    }
+
+    println!("{:<16} {:>8}", "files", total.files);
+    println!("{:<16} {:>8}", "lines", total.lines);
+    println!("{:<16} {:>8}", "  code", total.code_lines);
+    println!("{:<16} {:>8}", "  comment", total.comment_lines);
+    println!("{:<16} {:>8}", "  blank", total.blank_lines);
+    println!("{:<16} {:>8}", "functions", total.functions);
+    println!("{:<16} {:>8}", "max depth", total.max_depth);
+    if !total.keywords.is_empty() {
+        println!("\ntop keywords");
+        for (spelling, count) in total.keywords.iter().take(10) {
+            println!("{:>8}  {}", count, spelling);
+        }
+    }
+
+    Ok(())
+}
+
+// ---- Fix ----
+
+/// `herlang fix [--from=<version>] [--write] <file|dir>...`: prints what it
+/// takes for scripts written for an older language version to run the same
+/// now, as a diff, or makes the changes with `--write`. Scripts without a
+/// `#她版本` header are taken to be `--from`, the current version by default.
+fn fix(args: &[String]) -> rustyline::Result<()> {
+    let mut from = LANGUAGE_VERSION;
+    let mut write = false;
+    let mut paths = vec![];
+
+    for arg in args {
+        if let Some(value) = arg.strip_prefix("--from=") {
+            from = match value.parse() {
+                Ok(version) if (1..=LANGUAGE_VERSION).contains(&version) => version,
+                _ => {
+                    eprintln!("--from must be a version from 1 to {}", LANGUAGE_VERSION);
+                    std::process::exit(2);
+                }
+            };
+        } else if arg == "--write" {
+            write = true;
+        } else if std::path::Path::new(arg).is_dir() {
+            collect_her_files(std::path::Path::new(arg), &mut paths)?;
+        } else {
+            paths.push(std::path::PathBuf::from(arg));
+        }
+    }
+    if paths.is_empty() {
+        eprintln!("usage: herlang fix [--from=<version>] [--write] <file|dir>...");
+        std::process::exit(2);
+    }
+    paths.sort();
+
+    for path in &paths {
+        let src = std::fs::read_to_string(path)?;
+        let edits = fix::fixes(&src, from);
+        if edits.is_empty() {
+            continue;
+        }
+
+        let fixed = fix::apply(&src, &edits);
+        if write {
+            std::fs::write(path, &fixed)?;
+            println!("fixed {} ({} changes)", path.display(), edits.len());
+        } else {
+            print!("{}", fix::diff(&path.display().to_string(), &src, &fixed));
+        }
+    }
+
+    Ok(())
+}
+
+// ---- Serve ----
+
+/// `herlang serve [--port=<port>] [--host=<host>] [--lang=en|zh]`: the
+/// playground server, on `127.0.0.1:8080` unless told otherwise.
+fn serve(args: &[String]) -> rustyline::Result<()> {
+    let mut host = "127.0.0.1";
+    let mut port = "8080";
+    let lang = lang_arg(args);
+
+    // How the server runs each request, see `serve::run_worker`.
+    if args.iter().any(|arg| arg == "--worker") {
+        serve::run_worker(lang)?;
+        return Ok(());
+    }
+
+    for arg in args {
+        if let Some(value) = arg.strip_prefix("--port=") {
+            port = value;
+        } else if let Some(value) = arg.strip_prefix("--host=") {
+            host = value;
+        }
+    }
+
+    println!("herlang playground on http://{}:{}", host, port);
+    serve::serve(format!("{}:{}", host, port), lang)?;
+
+    Ok(())
+}
+
+// ---- Build ----
+
+/// `herlang build --target=js <file> [-o <out>]`: translates a program to a
+/// standalone script, printed when no output file is given.
+///
+/// `--target=herc` saves the parsed program next to the source instead, for
+/// `引入` to load without parsing it again.
+fn build(args: &[String]) -> rustyline::Result<()> {
+    let mut input = None;
+    let mut target = None;
+    let mut out = None;
+    let mut args = args.iter();
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--target" => target = args.next().map(String::as_str),
+            "-o" => out = args.next(),
+            _ => match arg.strip_prefix("--target=") {
+                Some(value) => target = Some(value),
+                None => input = Some(arg),
+            },
+        }
+    }
+
+    let (Some(input), Some(target @ ("js" | "herc"))) = (input, target) else {
+        eprintln!("usage: herlang build --target=js <file> [-o <out.js>]");
+        eprintln!("       herlang build --target=herc <file> [-o <out.herc>]");
+        std::process::exit(2);
+    };
+
+    let src = std::fs::read_to_string(input)?;
+    let mut parser = Parser::new(Lexer::new(&src));
+    let program = parser.parse();
+    let errors = parser.get_errors();
+    for err in &errors {
+        eprintln!("{}:{}: {}", input, err.span(), err);
+    }
+    if !errors.is_empty() {
+        std::process::exit(1);
+    }
+
+    if target == "herc" {
+        let out = out.map_or_else(
+            || herc::cache_path(input.as_ref()),
+            std::path::PathBuf::from,
+        );
+        std::fs::write(out, herc::encode(&program, LANGUAGE_VERSION))?;
+        return Ok(());
+    }
+
+    let output = match codegen::to_js(&program) {
+        Ok(output) => output,
+        Err(err) => {
+            eprintln!("{}: {}", input, err);
+            std::process::exit(1);
+        }
+    };
+
+    match out {
+        Some(out) => std::fs::write(out, output)?,
+        None => print!("{}", output),
+    }
+
+    Ok(())
+}
+
+// ---- Docs ----
+
+/// `herlang docs`: the builtin reference as a Markdown table.
+fn docs() -> rustyline::Result<()> {
+    print!("{}", BuiltinRegistry::new().to_markdown());
+    Ok(())
+}
+
+/// `:help` in the REPL lists every builtin, `:help name` describes one.
+fn help(topic: &str) {
+    let registry = BuiltinRegistry::new();
+
+    if topic.is_empty() {
+        for def in registry.iter() {
+            println!("{}\n    {}", def.signature(), def.doc);
+        }
+        println!("\nType :help <name> for one builtin.\n");
+        return;
+    }
+
+    match registry.get(topic) {
+        Some(def) => {
+            println!("{}", def.signature());
+            if !def.aliases.is_empty() {
+                println!("aliases: {}", def.aliases.join(", "));
+            }
+            println!("{}\n", def.doc);
+        }
+        None => println!("No builtin named {topic}.\n"),
+    }
+}
+
+/// What the REPL prints of a value until `:limit` says otherwise.
+const REPL_REPR_LIMIT: ReprLimit = ReprLimit {
+    max_items: Some(100),
+    max_len: Some(1000),
+};
+
+/// `:limit <items> [<chars>]` in the REPL, or `:limit off` to print
+/// values in full. `None` if `arg` is neither.
+fn parse_repr_limit(arg: &str) -> Option<ReprLimit> {
+    let mut parts = arg.split_whitespace();
+    match (parts.next(), parts.next(), parts.next()) {
+        (Some("off"), None, _) => Some(ReprLimit::default()),
+        (Some(items), len, None) => Some(ReprLimit {
+            max_items: Some(items.parse().ok()?),
+            max_len: match len {
+                Some(len) => Some(len.parse().ok()?),
+                None => REPL_REPR_LIMIT.max_len,
+            },
+        }),
+        _ => None,
+    }
+}
+
 // ---- Main ----
 fn main() -> rustyline::Result<()> {
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    match args.first().map(String::as_str) {
+        Some("share") => return share(args.get(1)),
+        Some("run") => return run(&args[1..]),
+        Some("check") => return check(&args[1..]),
+        Some("explain") => return explain(&args[1..]),
+        Some("analyze") => return analyze(&args[1..]),
+        Some("build") => return build(&args[1..]),
+        Some("highlight") => return highlight(&args[1..]),
+        Some("parse") => return parse(&args[1..]),
+        Some("test") => return test(&args[1..]),
+        Some("bench") => return bench(&args[1..]),
+        Some("stats") => return stats(&args[1..]),
+        Some("fix") => return fix(&args[1..]),
+        Some("serve") => return serve(&args[1..]),
+        Some("docs") => return docs(),
+        _ => {}
+    }
+
     let env = Env::from(new_builtins());
-    let mut evaluator = Evaluator::new(Rc::new(RefCell::new(env)));
+    let mut repr_limit = REPL_REPR_LIMIT;
+    let mut evaluator = Evaluator::new(Shared::new(env)).with_repr_limit(repr_limit);
 
     let config = Config::builder()
         .history_ignore_space(true)
@@ -174,14 +971,35 @@ fn main() -> rustyline::Result<()> {
             Ok(line) => {
                 rl.add_history_entry(&line)?;
 
+                if let Some(topic) = line.trim().strip_prefix(":help") {
+                    help(topic.trim());
+                    continue;
+                }
+
+                if let Some(arg) = line.trim().strip_prefix(":limit") {
+                    match parse_repr_limit(arg) {
+                        Some(limit) => {
+                            repr_limit = limit;
+                            evaluator = evaluator.with_repr_limit(limit);
+                        }
+                        None => println!("usage: :limit <items> [<chars>] | :limit off\n"),
+                    }
+                    continue;
+                }
+
                 let mut parser = Parser::new(Lexer::new(&line));
                 let program = parser.parse();
 
                 // No error check: rl should handle that.
                 // Yes this is reckless.
 
-                if let Some(evaluated) = evaluator.eval(&program) {
-                    println!("{}\n", evaluated);
+                match evaluator.eval(&program) {
+                    Some(Object::Exit(code)) => {
+                        rl.append_history("herlang_herstory.txt")?;
+                        std::process::exit(code);
+                    }
+                    Some(evaluated) => println!("{}\n", evaluated.repr(repr_limit)),
+                    None => {}
                 }
             }
             Err(ReadlineError::Interrupted) => {
@@ -189,7 +1007,7 @@ fn main() -> rustyline::Result<()> {
                 break;
             }
             Err(ReadlineError::Eof) => {
-                println!("");
+                println!();
                 break;
             }
             Err(err) => {
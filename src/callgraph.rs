@@ -0,0 +1,266 @@
+//! Who calls whom, for getting around big scripts and spotting recursion.
+//!
+//! Functions are values, so only `let name = fn ...` bindings become nodes,
+//! and only direct calls `name(...)` to one of them become edges. Calls made
+//! by anonymous functions count for the named function they sit in; code
+//! outside any function belongs to `MAIN`. Bindings are keyed by name alone,
+//! so two functions with the same name in different scopes are merged.
+
+use crate::ast::visit::{Visitor, walk_expr, walk_stmt};
+use crate::ast::*;
+use crate::json;
+use std::collections::HashMap;
+
+/// Node for top-level code.
+pub const MAIN: &str = "<main>";
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct CallGraph {
+    /// `MAIN` first, then functions in the order they are defined.
+    pub functions: Vec<String>,
+    /// `(caller, callee)` pairs in source order, without duplicates.
+    pub calls: Vec<(String, String)>,
+}
+
+struct Builder {
+    graph: CallGraph,
+    current: Vec<String>,
+}
+
+impl Builder {
+    fn define(&mut self, name: &str) {
+        if !self.graph.functions.iter().any(|f| f == name) {
+            self.graph.functions.push(name.to_string());
+        }
+    }
+
+    fn call(&mut self, callee: &str) {
+        let caller = self.current.last().map_or(MAIN, String::as_str);
+        let call = (caller.to_string(), callee.to_string());
+
+        if !self.graph.calls.contains(&call) {
+            self.graph.calls.push(call);
+        }
+    }
+}
+
+impl Visitor for Builder {
+    fn visit_stmt(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Let(Ident(name), _, Expr::Func { body, .. }) => {
+                self.define(name);
+                self.current.push(name.clone());
+                self.visit_block(body);
+                self.current.pop();
+            }
+            stmt => walk_stmt(self, stmt),
+        }
+    }
+
+    fn visit_expr(&mut self, expr: &Expr) {
+        if let Expr::Call { func, .. } = expr
+            && let Expr::Ident(Ident(name)) = func.as_ref()
+        {
+            self.call(name);
+        }
+        walk_expr(self, expr);
+    }
+}
+
+impl CallGraph {
+    pub fn build(program: &Program) -> Self {
+        let mut builder = Builder {
+            graph: CallGraph {
+                functions: vec![String::from(MAIN)],
+                calls: vec![],
+            },
+            current: vec![],
+        };
+        builder.visit_block(program);
+
+        let mut graph = builder.graph;
+        let functions = graph.functions.clone();
+        graph.calls.retain(|(_, callee)| functions.contains(callee));
+
+        graph
+    }
+
+    /// Groups of functions that can end up calling themselves, each in
+    /// definition order. Tarjan's strongly connected components.
+    pub fn cycles(&self) -> Vec<Vec<String>> {
+        struct Tarjan<'a> {
+            graph: &'a CallGraph,
+            index: HashMap<&'a str, usize>,
+            lowlink: HashMap<&'a str, usize>,
+            stack: Vec<&'a str>,
+            cycles: Vec<Vec<String>>,
+        }
+
+        impl<'a> Tarjan<'a> {
+            fn visit(&mut self, node: &'a str) {
+                let index = self.index.len();
+                self.index.insert(node, index);
+                self.lowlink.insert(node, index);
+                self.stack.push(node);
+
+                for (caller, callee) in &self.graph.calls {
+                    if caller != node {
+                        continue;
+                    }
+                    let callee = callee.as_str();
+
+                    if !self.index.contains_key(callee) {
+                        self.visit(callee);
+                        let low = self.lowlink[node].min(self.lowlink[callee]);
+                        self.lowlink.insert(node, low);
+                    } else if self.stack.contains(&callee) {
+                        let low = self.lowlink[node].min(self.index[callee]);
+                        self.lowlink.insert(node, low);
+                    }
+                }
+
+                if self.lowlink[node] != self.index[node] {
+                    return;
+                }
+
+                let at = self.stack.iter().rposition(|n| *n == node).unwrap_or(0);
+                let component = self.stack.split_off(at);
+                let recursive = component.len() > 1
+                    || self
+                        .graph
+                        .calls
+                        .contains(&(node.to_string(), node.to_string()));
+
+                if recursive {
+                    let mut component = component.into_iter().map(String::from).collect::<Vec<_>>();
+                    component.sort_by_key(|name| self.graph.position(name));
+                    self.cycles.push(component);
+                }
+            }
+        }
+
+        let mut tarjan = Tarjan {
+            graph: self,
+            index: HashMap::new(),
+            lowlink: HashMap::new(),
+            stack: vec![],
+            cycles: vec![],
+        };
+
+        for function in &self.functions {
+            if !tarjan.index.contains_key(function.as_str()) {
+                tarjan.visit(function);
+            }
+        }
+
+        let mut cycles = tarjan.cycles;
+        cycles.sort_by_key(|cycle| self.position(&cycle[0]));
+        cycles
+    }
+
+    fn position(&self, name: &str) -> usize {
+        self.functions.iter().position(|f| f == name).unwrap_or(0)
+    }
+
+    /// Graphviz source; calls within a cycle are drawn in red.
+    pub fn to_dot(&self) -> String {
+        let cycles = self.cycles();
+        let mut result = String::from("digraph callgraph {\n");
+
+        for function in &self.functions {
+            result.push_str(&format!("  {};\n", json::string(function)));
+        }
+
+        for (caller, callee) in &self.calls {
+            let recursive = cycles
+                .iter()
+                .any(|cycle| cycle.contains(caller) && cycle.contains(callee));
+
+            result.push_str(&format!(
+                "  {} -> {}{};\n",
+                json::string(caller),
+                json::string(callee),
+                if recursive { " [color=red]" } else { "" }
+            ));
+        }
+
+        result.push_str("}\n");
+        result
+    }
+
+    pub fn to_json(&self) -> String {
+        let strings = |names: &[String]| json::array(names.iter().map(|name| json::string(name)));
+
+        format!(
+            "{{\"functions\":{},\"calls\":{},\"cycles\":{}}}",
+            strings(&self.functions),
+            json::array(self.calls.iter().map(|(caller, callee)| {
+                format!(
+                    "{{\"caller\":{},\"callee\":{}}}",
+                    json::string(caller),
+                    json::string(callee)
+                )
+            })),
+            json::array(self.cycles().iter().map(|cycle| strings(cycle)))
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::callgraph::*;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+
+    fn build(input: &str) -> CallGraph {
+        CallGraph::build(&Parser::new(Lexer::new(input)).parse())
+    }
+
+    #[test]
+    fn test_build() {
+        let graph = build(
+            r#"
+let even = fn(n) { if (n == 0) { true } else { odd(n - 1) } };
+let odd = fn(n) { if (n == 0) { false } else { even(n - 1) } };
+let fact = fn(n) { if (n == 0) { 1 } else { n * fact(n - 1) } };
+let main = fn() { puts(even(10)); push([], fn(x) { fact(x) }); };
+main();
+"#,
+        );
+
+        assert_eq!(
+            vec!["<main>", "even", "odd", "fact", "main"],
+            graph.functions
+        );
+        assert_eq!(
+            vec![
+                ("even", "odd"),
+                ("odd", "even"),
+                ("fact", "fact"),
+                ("main", "even"),
+                ("main", "fact"),
+                ("<main>", "main"),
+            ],
+            graph
+                .calls
+                .iter()
+                .map(|(a, b)| (a.as_str(), b.as_str()))
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(vec![vec!["even", "odd"], vec!["fact"]], graph.cycles());
+    }
+
+    #[test]
+    fn test_output() {
+        let graph = build("let f = fn() { f() }; f();");
+
+        assert_eq!(
+            "digraph callgraph {\n  \"<main>\";\n  \"f\";\n  \"f\" -> \"f\" [color=red];\n  \"<main>\" -> \"f\";\n}\n",
+            graph.to_dot()
+        );
+        assert_eq!(
+            r#"{"functions":["<main>","f"],"calls":[{"caller":"f","callee":"f"},{"caller":"<main>","callee":"f"}],"cycles":[["f"]]}"#,
+            graph.to_json()
+        );
+    }
+}
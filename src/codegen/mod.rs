@@ -0,0 +1,1032 @@
+//! Translates a program to a standalone JavaScript file, for running herlang
+//! in the browser without the wasm interpreter.
+//!
+//! Values map to plain JS ones and operators go through the runtime in
+//! `runtime.js`, which repeats the evaluator's checks and error messages.
+//! Known differences from the evaluator:
+//!
+//! - ints are JS numbers, so they lose precision past 2^53 instead of
+//!   overflowing;
+//! - an error stops the program right away, it isn't passed on as a value;
+//! - `let` works like `var`: reading a name before its `let` in the same
+//!   function gives `undefined` rather than an outer binding;
+//! - `import` is not supported;
+//! - float literals are not supported: JS numbers can't keep `7 / 2` and
+//!   `7.0 / 2` apart, so building fails with `CodegenError::Float`, which
+//!   names the literal.
+
+use crate::ast::*;
+use crate::evaluator::builtins::{new_builtins, new_easter_eggs};
+use crate::evaluator::object::Object;
+use crate::json;
+use crate::parser::desugar_c_for;
+use std::collections::HashSet;
+use std::fmt;
+use unicode_xid::UnicodeXID;
+
+const RUNTIME: &str = include_str!("runtime.js");
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CodegenError {
+    Import(String),
+    /// Floats have no JS counterpart that keeps int division and display apart.
+    Float(String),
+}
+
+impl fmt::Display for CodegenError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CodegenError::Import(path) => {
+                write!(f, "import is not supported when building to JS: {path}")
+            }
+            CodegenError::Float(value) => {
+                write!(
+                    f,
+                    "float literals are not supported when building to JS: {value}"
+                )
+            }
+        }
+    }
+}
+
+/// Where the value of a block or expression goes.
+enum Target {
+    Discard,
+    Assign(String),
+    Return,
+}
+
+struct Codegen {
+    lines: Vec<String>,
+    indent: usize,
+    temps: usize,
+    /// Every name bound by `let` or a parameter anywhere, plus builtins.
+    declared: HashSet<String>,
+    in_loop: bool,
+    /// Temp holding the value of the innermost `while`, if it's used.
+    loop_result: Option<String>,
+}
+
+impl Codegen {
+    fn line(&mut self, line: String) {
+        self.lines
+            .push(format!("{}{}", "  ".repeat(self.indent), line));
+    }
+
+    fn temp(&mut self) -> String {
+        self.temps += 1;
+        format!("$t{}", self.temps)
+    }
+
+    fn finish(&mut self, target: &Target, value: String) {
+        match target {
+            Target::Discard if is_pure(&value) => {}
+            Target::Discard => self.line(format!("{value};")),
+            Target::Assign(temp) => self.line(format!("{temp} = {value};")),
+            Target::Return => self.line(format!("return {value};")),
+        }
+    }
+
+    fn block(&mut self, stmts: &BlockStmt, target: &Target) -> Result<(), CodegenError> {
+        let stmts = stmts
+            .iter()
+            .filter(|stmt| !matches!(stmt, Stmt::Blank | Stmt::Comment(_) | Stmt::Mark(_)))
+            .collect::<Vec<_>>();
+
+        if stmts.is_empty() {
+            self.finish(target, String::from("null"));
+        }
+
+        for (i, stmt) in stmts.iter().enumerate() {
+            let last = i + 1 == stmts.len();
+
+            match stmt {
+                Stmt::Let(Ident(name), _, expr) => {
+                    let value = self.expr(expr)?;
+                    self.line(format!("var {} = {value};", mangle(name)));
+                    if last {
+                        self.finish(target, String::from("null"));
+                    }
+                }
+                Stmt::Assign(Ident(name), expr) => {
+                    let value = self.expr(expr)?;
+                    self.line(format!("{} = {value};", mangle(name)));
+                    if last {
+                        self.finish(target, String::from("null"));
+                    }
+                }
+                Stmt::Return(expr) => {
+                    let value = self.expr(expr)?;
+                    self.line(format!("return {value};"));
+                }
+                Stmt::Break | Stmt::Continue if self.in_loop => {
+                    if let Some(result) = self.loop_result.clone() {
+                        self.line(format!("{result} = null;"));
+                    }
+                    let jump = if **stmt == Stmt::Break {
+                        "break"
+                    } else {
+                        "continue"
+                    };
+                    self.line(format!("{jump};"));
+                }
+                Stmt::Expr(expr) if last => self.expr_into(expr, target)?,
+                Stmt::Expr(expr) => self.expr_into(expr, &Target::Discard)?,
+                Stmt::Import(path) => return Err(CodegenError::Import(path.clone())),
+                _ if last => self.finish(target, String::from("null")),
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    fn expr_into(&mut self, expr: &Expr, target: &Target) -> Result<(), CodegenError> {
+        match expr {
+            Expr::If {
+                cond,
+                consequence,
+                alternative,
+            } => {
+                let cond = self.expr(cond)?;
+                self.line(format!("if ($her.truthy({cond})) {{"));
+                self.indent += 1;
+                self.block(consequence, target)?;
+                self.indent -= 1;
+
+                match alternative {
+                    Some(alternative) => {
+                        self.line(String::from("} else {"));
+                        self.indent += 1;
+                        self.block(alternative, target)?;
+                        self.indent -= 1;
+                    }
+                    None if !matches!(target, Target::Discard) => {
+                        self.line(String::from("} else {"));
+                        self.indent += 1;
+                        self.finish(target, String::from("null"));
+                        self.indent -= 1;
+                    }
+                    None => {}
+                }
+                self.line(String::from("}"));
+            }
+            Expr::Match { subject, arms } => {
+                let subject = self.expr(subject)?;
+                let temp = self.temp();
+                self.line(format!("const {temp} = {subject};"));
+
+                let mut exhaustive = false;
+                for (i, MatchArm { pattern, body }) in arms.iter().enumerate() {
+                    let mut conds = vec![];
+                    let mut bindings = vec![];
+                    self.pattern(pattern, &temp, &mut conds, &mut bindings)?;
+                    match (conds.is_empty(), i) {
+                        (false, 0) => self.line(format!("if ({}) {{", conds.join(" && "))),
+                        (false, _) => self.line(format!("}} else if ({}) {{", conds.join(" && "))),
+                        (true, 0) => self.line(String::from("{")),
+                        (true, _) => self.line(String::from("} else {")),
+                    }
+                    self.indent += 1;
+                    for (Ident(name), value) in bindings {
+                        self.line(format!("var {} = {value};", mangle(name)));
+                    }
+                    self.block(body, target)?;
+                    self.indent -= 1;
+
+                    if conds.is_empty() {
+                        exhaustive = true;
+                        break;
+                    }
+                }
+
+                if !exhaustive && !matches!(target, Target::Discard) {
+                    self.line(String::from(if arms.is_empty() { "{" } else { "} else {" }));
+                    self.indent += 1;
+                    self.finish(target, String::from("null"));
+                    self.indent -= 1;
+                    self.line(String::from("}"));
+                } else if !arms.is_empty() {
+                    self.line(String::from("}"));
+                }
+            }
+            Expr::While { cond, consequence } if matches!(target, Target::Discard) => {
+                self.while_loop(cond, consequence, None)?;
+            }
+            Expr::DoWhile { consequence, cond } if matches!(target, Target::Discard) => {
+                self.do_while_loop(consequence, cond, None)?;
+            }
+            Expr::For {
+                vars,
+                iterable,
+                body,
+            } if matches!(target, Target::Discard) => {
+                self.for_loop(vars, iterable, body, None)?;
+            }
+            expr => {
+                let value = self.expr(expr)?;
+                self.finish(target, value);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The checks `value` has to pass to match `pattern`, and the JS for what
+    /// each name binds to. The checks have no side effects, so they can all
+    /// go in an `else if`; no checks at all means it always matches.
+    fn pattern<'a>(
+        &mut self,
+        pattern: &'a Pattern,
+        value: &str,
+        conds: &mut Vec<String>,
+        bindings: &mut Vec<(&'a Ident, String)>,
+    ) -> Result<(), CodegenError> {
+        match pattern {
+            Pattern::Wildcard => {}
+            Pattern::Literal(literal) => {
+                let literal = self.expr(&Expr::Literal(literal.clone()))?;
+                conds.push(format!(
+                    "$her.truthy($her.infix(\"==\", {value}, {literal}))"
+                ));
+            }
+            Pattern::Binding(ident) => bindings.push((ident, value.to_string())),
+            Pattern::Array(items, rest) => {
+                let op = if rest.is_some() { ">=" } else { "===" };
+                conds.push(format!("Array.isArray({value})"));
+                conds.push(format!("{value}.length {op} {}", items.len()));
+                for (i, item) in items.iter().enumerate() {
+                    self.pattern(item, &format!("{value}[{i}]"), conds, bindings)?;
+                }
+                if let Some(rest) = rest {
+                    bindings.push((rest, format!("{value}.slice({})", items.len())));
+                }
+            }
+            Pattern::Hash(entries) => {
+                let keys = entries.iter().map(|(key, _)| json::string(key));
+                conds.push(format!(
+                    "$her.has({})",
+                    std::iter::once(value.to_string())
+                        .chain(keys)
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ));
+                for (key, item) in entries {
+                    let item_value = format!("{value}.get({})", json::string(key));
+                    self.pattern(item, &item_value, conds, bindings)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn while_loop(
+        &mut self,
+        cond: &Expr,
+        consequence: &BlockStmt,
+        result: Option<String>,
+    ) -> Result<(), CodegenError> {
+        let outer = (self.in_loop, self.loop_result.clone());
+        self.in_loop = true;
+        self.loop_result = result.clone();
+
+        self.line(String::from("while (true) {"));
+        self.indent += 1;
+        let cond = self.expr(cond)?;
+        self.line(format!("if (!$her.truthy({cond})) break;"));
+        let target = match result {
+            Some(result) => Target::Assign(result),
+            None => Target::Discard,
+        };
+        self.block(consequence, &target)?;
+        self.indent -= 1;
+        self.line(String::from("}"));
+
+        (self.in_loop, self.loop_result) = outer;
+        Ok(())
+    }
+
+    /// `while_loop` with the check skipped the first time round. It stays at
+    /// the top of the loop so `continue` goes through it too.
+    fn do_while_loop(
+        &mut self,
+        consequence: &BlockStmt,
+        cond: &Expr,
+        result: Option<String>,
+    ) -> Result<(), CodegenError> {
+        let outer = (self.in_loop, self.loop_result.clone());
+        self.in_loop = true;
+        self.loop_result = result.clone();
+
+        let first = self.temp();
+        self.line(format!("let {first} = true;"));
+        self.line(String::from("while (true) {"));
+        self.indent += 1;
+        self.line(format!("if (!{first}) {{"));
+        self.indent += 1;
+        let cond = self.expr(cond)?;
+        self.line(format!("if (!$her.truthy({cond})) break;"));
+        self.indent -= 1;
+        self.line(String::from("}"));
+        self.line(format!("{first} = false;"));
+        let target = match result {
+            Some(result) => Target::Assign(result),
+            None => Target::Discard,
+        };
+        self.block(consequence, &target)?;
+        self.indent -= 1;
+        self.line(String::from("}"));
+
+        (self.in_loop, self.loop_result) = outer;
+        Ok(())
+    }
+
+    fn for_loop(
+        &mut self,
+        vars: &[Ident],
+        iterable: &Expr,
+        body: &BlockStmt,
+        result: Option<String>,
+    ) -> Result<(), CodegenError> {
+        let iterable = self.expr(iterable)?;
+        let outer = (self.in_loop, self.loop_result.clone());
+        self.in_loop = true;
+        self.loop_result = result.clone();
+
+        let entry = self.temp();
+        self.line(format!(
+            "for (const {entry} of $her.each({iterable}, {})) {{",
+            vars.len()
+        ));
+        self.indent += 1;
+        for (i, Ident(name)) in vars.iter().enumerate() {
+            self.line(format!("var {} = {entry}[{i}];", mangle(name)));
+        }
+        let target = match result {
+            Some(result) => Target::Assign(result),
+            None => Target::Discard,
+        };
+        self.block(body, &target)?;
+        self.indent -= 1;
+        self.line(String::from("}"));
+
+        (self.in_loop, self.loop_result) = outer;
+        Ok(())
+    }
+
+    /// Compiles `exprs` in order. When a later one needs statements of its
+    /// own, earlier values are saved in temps first so they keep their order.
+    fn exprs<'a>(
+        &mut self,
+        exprs: impl IntoIterator<Item = &'a Expr>,
+    ) -> Result<Vec<String>, CodegenError> {
+        let mut values: Vec<String> = vec![];
+
+        for expr in exprs {
+            let mut at = self.lines.len();
+            let value = self.expr(expr)?;
+
+            if self.lines.len() > at {
+                for earlier in values.iter_mut().filter(|v| !is_stable(v)) {
+                    let temp = self.temp();
+                    let line = format!("{}const {temp} = {earlier};", "  ".repeat(self.indent));
+                    self.lines.insert(at, line);
+                    at += 1;
+                    *earlier = temp;
+                }
+            }
+
+            values.push(value);
+        }
+
+        Ok(values)
+    }
+
+    fn expr(&mut self, expr: &Expr) -> Result<String, CodegenError> {
+        let value = match expr {
+            Expr::Ident(Ident(name)) if self.declared.contains(name) => mangle(name),
+            Expr::Ident(Ident(name)) => format!("$her.notFound({})", json::string(name)),
+            Expr::Literal(Literal::Int(value)) => value.to_string(),
+            Expr::Literal(Literal::Float(value)) => {
+                return Err(CodegenError::Float(format_float(*value)));
+            }
+            Expr::Literal(Literal::Quantity(value, unit)) => {
+                format!("$her.quantity({value}, {})", json::string(unit))
+            }
+            Expr::Literal(Literal::String(value)) => json::string(value),
+            Expr::Literal(Literal::Bool(value)) => value.to_string(),
+            Expr::Literal(Literal::Array(items)) => {
+                format!("[{}]", self.exprs(items)?.join(", "))
+            }
+            Expr::Literal(Literal::Hash(pairs)) => {
+                let values = self.exprs(pairs.iter().flat_map(|(k, v)| [k, v]))?;
+                let pairs = values
+                    .chunks(2)
+                    .map(|pair| format!("[{}, {}]", pair[0], pair[1]))
+                    .collect::<Vec<_>>();
+                format!("$her.hash([{}])", pairs.join(", "))
+            }
+            Expr::Prefix(Prefix::Await, right) => format!("$her.await({})", self.expr(right)?),
+            Expr::Prefix(prefix, right) => {
+                let right = self.expr(right)?;
+                format!("$her.prefix(\"{prefix}\", {right})")
+            }
+            Expr::Infix(infix @ (Infix::And | Infix::Or), left, right) => {
+                self.logical(infix, left, right)?
+            }
+            Expr::Infix(Infix::Coalesce, left, right) => self.coalesce(left, right)?,
+            Expr::Infix(infix, left, right) => {
+                let values = self.exprs([left.as_ref(), right.as_ref()])?;
+                format!("$her.infix(\"{infix}\", {}, {})", values[0], values[1])
+            }
+            Expr::Index(left, index) => {
+                let values = self.exprs([left.as_ref(), index.as_ref()])?;
+                format!("$her.index({}, {})", values[0], values[1])
+            }
+            Expr::If { .. } | Expr::Match { .. } => {
+                let temp = self.temp();
+                self.line(format!("let {temp};"));
+                self.expr_into(expr, &Target::Assign(temp.clone()))?;
+                temp
+            }
+            Expr::While { cond, consequence } => {
+                let temp = self.temp();
+                self.line(format!("let {temp} = null;"));
+                self.while_loop(cond, consequence, Some(temp.clone()))?;
+                temp
+            }
+            Expr::DoWhile { consequence, cond } => {
+                let temp = self.temp();
+                self.line(format!("let {temp} = null;"));
+                self.do_while_loop(consequence, cond, Some(temp.clone()))?;
+                temp
+            }
+            Expr::For {
+                vars,
+                iterable,
+                body,
+            } => {
+                let temp = self.temp();
+                self.line(format!("let {temp} = null;"));
+                self.for_loop(vars, iterable, body, Some(temp.clone()))?;
+                temp
+            }
+            Expr::CFor {
+                init,
+                cond,
+                step,
+                body,
+            } => self.expr(&desugar_c_for(
+                init.as_deref(),
+                cond.as_deref(),
+                step.as_deref(),
+                body,
+                None,
+            ))?,
+            Expr::Func {
+                params,
+                body,
+                is_async,
+                ..
+            } => {
+                let func = self.func(params, body)?;
+                match is_async {
+                    true => format!("$her.async({func})"),
+                    false => func,
+                }
+            }
+            Expr::Call { func, args } => match func.as_dot_access() {
+                Some((receiver, name)) => {
+                    let values = self.exprs(std::iter::once(receiver).chain(args))?;
+                    format!(
+                        "$her.call($her.method({}, {}), [{}])",
+                        values[0],
+                        json::string(name),
+                        values[1..].join(", ")
+                    )
+                }
+                None => {
+                    let values = self.exprs(std::iter::once(func.as_ref()).chain(args))?;
+                    format!("$her.call({}, [{}])", values[0], values[1..].join(", "))
+                }
+            },
+        };
+
+        Ok(value)
+    }
+
+    /// `&&` and `||`, keeping the right side's statements behind the check.
+    fn logical(
+        &mut self,
+        infix: &Infix,
+        left: &Expr,
+        right: &Expr,
+    ) -> Result<String, CodegenError> {
+        let left = self.expr(left)?;
+
+        let lines = std::mem::take(&mut self.lines);
+        self.indent += 1;
+        let right = self.expr(right);
+        self.indent -= 1;
+        let setup = std::mem::replace(&mut self.lines, lines);
+        let right = right?;
+
+        if setup.is_empty() {
+            return Ok(format!(
+                "($her.truthy({left}) {infix} $her.truthy({right}))"
+            ));
+        }
+
+        let temp = self.temp();
+        let check = if *infix == Infix::And { "" } else { "!" };
+        self.line(format!("let {temp} = $her.truthy({left});"));
+        self.line(format!("if ({check}{temp}) {{"));
+        self.lines.extend(setup);
+        self.indent += 1;
+        self.line(format!("{temp} = $her.truthy({right});"));
+        self.indent -= 1;
+        self.line(String::from("}"));
+
+        Ok(temp)
+    }
+
+    /// `left ?? right`, with the setup for `right` run only when needed.
+    fn coalesce(&mut self, left: &Expr, right: &Expr) -> Result<String, CodegenError> {
+        let left = self.expr(left)?;
+
+        let lines = std::mem::take(&mut self.lines);
+        self.indent += 1;
+        let right = self.expr(right);
+        self.indent -= 1;
+        let setup = std::mem::replace(&mut self.lines, lines);
+        let right = right?;
+
+        if setup.is_empty() {
+            return Ok(format!("({left} ?? {right})"));
+        }
+
+        let temp = self.temp();
+        self.line(format!("let {temp} = {left};"));
+        self.line(format!("if ({temp} === null) {{"));
+        self.lines.extend(setup);
+        self.indent += 1;
+        self.line(format!("{temp} = {right};"));
+        self.indent -= 1;
+        self.line(String::from("}"));
+
+        Ok(temp)
+    }
+
+    fn func(&mut self, params: &[Param], body: &BlockStmt) -> Result<String, CodegenError> {
+        let names = params
+            .iter()
+            .map(|param| json::string(&param.ident.0))
+            .collect::<Vec<_>>();
+        let args = params
+            .iter()
+            .map(|param| mangle(&param.ident.0))
+            .collect::<Vec<_>>();
+
+        let outer = (
+            std::mem::take(&mut self.lines),
+            self.in_loop,
+            self.loop_result.take(),
+        );
+        self.in_loop = false;
+        self.indent += 1;
+        let result = self
+            .defaults(params)
+            .and_then(|()| self.block(body, &Target::Return));
+        self.indent -= 1;
+        let lines = std::mem::replace(&mut self.lines, outer.0);
+        (self.in_loop, self.loop_result) = (outer.1, outer.2);
+        result?;
+
+        let required = required_params(params);
+        let required = match required == params.len() {
+            true => String::new(),
+            false => format!(", {required}"),
+        };
+        Ok(format!(
+            "$her.fn([{}], function ({}) {{\n{}\n{}}}{required})",
+            names.join(", "),
+            args.join(", "),
+            lines.join("\n"),
+            "  ".repeat(self.indent)
+        ))
+    }
+
+    /// Sets the parameters a call left out to their defaults, in order, so
+    /// each default sees the parameters before it.
+    fn defaults(&mut self, params: &[Param]) -> Result<(), CodegenError> {
+        for (i, param) in params.iter().enumerate() {
+            let Some(default) = &param.default else {
+                continue;
+            };
+            self.line(format!("if (arguments.length < {}) {{", i + 1));
+            self.indent += 1;
+            let value = self.expr(default)?;
+            self.line(format!("{} = {value};", mangle(&param.ident.0)));
+            self.indent -= 1;
+            self.line(String::from("}"));
+        }
+
+        Ok(())
+    }
+}
+
+/// `_` followed by the name, with `$` and anything JS doesn't take in an
+/// identifier written as `$<hex>$`. Runtime names and temps start with `$`,
+/// so they never clash with user names.
+fn mangle(name: &str) -> String {
+    let mut result = String::from("_");
+
+    for c in name.chars() {
+        if c != '$' && (c.is_ascii_alphanumeric() || c == '_' || c.is_xid_continue()) {
+            result.push(c);
+        } else {
+            result.push_str(&format!("${:x}$", c as u32));
+        }
+    }
+
+    result
+}
+
+/// Literals and temps, which nothing can change after they're computed.
+fn is_stable(value: &str) -> bool {
+    value.parse::<i64>().is_ok()
+        || value == "true"
+        || value == "false"
+        || value.starts_with("$t")
+        || (value.starts_with('"') && !value.contains('\n'))
+}
+
+/// Values with no side effects, which need no statement of their own.
+fn is_pure(value: &str) -> bool {
+    is_stable(value) || value == "null" || (value.starts_with('_') && mangled(value))
+}
+
+fn mangled(value: &str) -> bool {
+    value
+        .chars()
+        .all(|c| c == '$' || c == '_' || c.is_xid_continue())
+}
+
+fn bound_names(stmts: &BlockStmt, names: &mut HashSet<String>) {
+    for stmt in stmts {
+        match stmt {
+            Stmt::Let(Ident(name), _, expr) => {
+                names.insert(name.clone());
+                expr_bound_names(expr, names);
+            }
+            Stmt::Return(expr) | Stmt::Expr(expr) => expr_bound_names(expr, names),
+            _ => {}
+        }
+    }
+}
+
+fn expr_bound_names(expr: &Expr, names: &mut HashSet<String>) {
+    match expr {
+        Expr::Ident(_)
+        | Expr::Literal(
+            Literal::Int(_)
+            | Literal::Float(_)
+            | Literal::Quantity(..)
+            | Literal::String(_)
+            | Literal::Bool(_),
+        ) => {}
+        Expr::Literal(Literal::Array(items)) => {
+            for item in items {
+                expr_bound_names(item, names);
+            }
+        }
+        Expr::Literal(Literal::Hash(pairs)) => {
+            for (key, value) in pairs {
+                expr_bound_names(key, names);
+                expr_bound_names(value, names);
+            }
+        }
+        Expr::Prefix(_, right) => expr_bound_names(right, names),
+        Expr::Infix(_, left, right) | Expr::Index(left, right) => {
+            expr_bound_names(left, names);
+            expr_bound_names(right, names);
+        }
+        Expr::If {
+            cond,
+            consequence,
+            alternative,
+        } => {
+            expr_bound_names(cond, names);
+            bound_names(consequence, names);
+            if let Some(alternative) = alternative {
+                bound_names(alternative, names);
+            }
+        }
+        Expr::While { cond, consequence } | Expr::DoWhile { consequence, cond } => {
+            expr_bound_names(cond, names);
+            bound_names(consequence, names);
+        }
+        Expr::Match { subject, arms } => {
+            expr_bound_names(subject, names);
+            for arm in arms {
+                names.extend(
+                    arm.pattern
+                        .bindings()
+                        .into_iter()
+                        .map(|Ident(name)| name.clone()),
+                );
+                bound_names(&arm.body, names);
+            }
+        }
+        Expr::For {
+            vars,
+            iterable,
+            body,
+        } => {
+            names.extend(vars.iter().map(|Ident(name)| name.clone()));
+            expr_bound_names(iterable, names);
+            bound_names(body, names);
+        }
+        Expr::CFor {
+            init,
+            cond,
+            step,
+            body,
+        } => expr_bound_names(
+            &desugar_c_for(
+                init.as_deref(),
+                cond.as_deref(),
+                step.as_deref(),
+                body,
+                None,
+            ),
+            names,
+        ),
+        Expr::Func { params, body, .. } => {
+            for param in params {
+                names.insert(param.ident.0.clone());
+                if let Some(default) = &param.default {
+                    expr_bound_names(default, names);
+                }
+            }
+            bound_names(body, names);
+        }
+        Expr::Call { func, args } => {
+            expr_bound_names(func, names);
+            for arg in args {
+                expr_bound_names(arg, names);
+            }
+        }
+    }
+}
+
+/// JS for a plain value, `None` for anything a literal can't express.
+fn literal(value: &Object) -> Option<String> {
+    match value {
+        Object::Int(value) => Some(value.to_string()),
+        Object::Quantity(value, unit) => {
+            Some(format!("$her.quantity({value}, {})", json::string(unit)))
+        }
+        Object::String(value) => Some(json::string(value)),
+        Object::Bool(value) => Some(value.to_string()),
+        Object::Null => Some(String::from("null")),
+        _ => None,
+    }
+}
+
+/// Translates `program` to a script that runs on its own in a browser or Node.
+pub fn to_js(program: &Program) -> Result<String, CodegenError> {
+    let mut builtins = new_builtins().into_keys().collect::<Vec<_>>();
+    builtins.sort();
+
+    let mut eggs = new_easter_eggs()
+        .into_iter()
+        .filter_map(|(name, value)| Some((name, literal(&value)?)))
+        .collect::<Vec<_>>();
+    eggs.sort();
+
+    let mut declared = HashSet::new();
+    bound_names(program, &mut declared);
+    declared.extend(builtins.iter().cloned());
+    declared.extend(eggs.iter().map(|(name, _)| name.clone()));
+
+    let mut codegen = Codegen {
+        lines: vec![],
+        indent: 1,
+        temps: 0,
+        declared,
+        in_loop: false,
+        loop_result: None,
+    };
+    for name in &builtins {
+        codegen.line(format!(
+            "var {} = $her.builtins[{}];",
+            mangle(name),
+            json::string(name)
+        ));
+    }
+    for (name, value) in eggs {
+        codegen.line(format!("var {} = {value};", mangle(&name)));
+    }
+    codegen.block(program, &Target::Discard)?;
+
+    Ok(format!(
+        "// Generated by herlang. Do not edit.\n\"use strict\";\n\n{RUNTIME}\n$her.run(() => {{\n{}\n}});\n",
+        codegen.lines.join("\n")
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::codegen::*;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+
+    /// The translated program, without the runtime and global bindings.
+    fn body(input: &str) -> String {
+        let js = to_js(&Parser::new(Lexer::new(input)).parse()).unwrap();
+        let start = js.find("$her.run(() => {\n").unwrap();
+        let globals = new_builtins().len() + new_easter_eggs().len();
+
+        js[start..]
+            .lines()
+            .skip(1 + globals)
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    #[test]
+    fn test_to_js() {
+        assert_eq!(
+            r#"  var _f = $her.fn(["x"], function (_x) {
+    if ($her.truthy($her.infix("<", _x, 2))) {
+      return _x;
+    } else {
+      return null;
+    }
+  });
+  $her.call(_小作文, [$her.call(_f, [1]), $her.index([1], 0), nope]);
+});"#
+                .replace("nope", "$her.notFound(\"nope\")"),
+            body("宝宝你是一个 f = fn(x) { if (x < 2) { x } }; 小作文(f(1), [1][0], nope);")
+        );
+    }
+
+    #[test]
+    fn test_to_js_order() {
+        assert_eq!(
+            r#"  var _a = 1;
+  const $t2 = $her.infix("*", _a, 2);
+  let $t1 = null;
+  while (true) {
+    if (!$her.truthy(_a)) break;
+    $t1 = null;
+    break;
+  }
+  $her.infix("+", $t2, $t1);
+});"#,
+            body("let a = 1; a * 2 + while (a) { break; };")
+        );
+    }
+
+    #[test]
+    fn test_coalesce() {
+        assert_eq!(
+            r#"  var _a = [];
+  $her.call(_小作文, [($her.index(_a, 0) ?? 1)]);
+  const $t3 = _小作文;
+  let $t2 = _a;
+  if ($t2 === null) {
+    let $t1 = null;
+    while (true) {
+      if (!$her.truthy(_a)) break;
+      $t1 = null;
+      break;
+    }
+    $t2 = $t1;
+  }
+  $her.call($t3, [$t2]);
+});"#,
+            body("let a = []; 小作文(a[0] ?? 1); 小作文(a ?? while (a) { break; });")
+        );
+    }
+
+    #[test]
+    fn test_do_while_loop() {
+        assert_eq!(
+            r#"  var _x = 1;
+  let $t1 = true;
+  while (true) {
+    if (!$t1) {
+      if (!$her.truthy(_x)) break;
+    }
+    $t1 = false;
+    continue;
+  }
+});"#,
+            body("let x = 1; do { continue; } while (x);")
+        );
+    }
+
+    #[test]
+    fn test_match() {
+        assert_eq!(
+            r#"  let $t1;
+  const $t2 = 2;
+  if ($her.truthy($her.infix("==", $t2, 1))) {
+    $t1 = "a";
+  } else if ($her.truthy($her.infix("==", $t2, "b"))) {
+    $t1 = 3;
+  } else {
+    $t1 = null;
+  }
+  var _x = $t1;
+  const $t3 = _x;
+  {
+    $her.call(_小作文, [1]);
+  }
+});"#,
+            body("let x = match 2 { 1 => \"a\", \"b\" => { 3 } }; match x { _ => 小作文(1) }")
+        );
+    }
+
+    #[test]
+    fn test_match_structural_patterns() {
+        assert_eq!(
+            r#"  const $t1 = [1];
+  if (Array.isArray($t1) && $t1.length >= 1) {
+    var _a = $t1[0];
+    var _b = $t1.slice(1);
+  } else if ($her.has($t1, "名字", "k") && $her.truthy($her.infix("==", $t1.get("k"), 1))) {
+    var _名字 = $t1.get("名字");
+  } else {
+    var _x = $t1;
+  }
+});"#,
+            body("match [1] { [a, ...b] => a, {名字, k: 1} => 名字, x => x }")
+        );
+    }
+
+    #[test]
+    fn test_default_params() {
+        assert_eq!(
+            r#"  var _f = $her.fn(["x", "y"], function (_x, _y) {
+    if (arguments.length < 2) {
+      _y = $her.infix("+", _x, 1);
+    }
+    return _y;
+  }, 1);
+  $her.call(_f, [1]);
+});"#,
+            body("let f = fn(x, y = x + 1) { y }; f(1)")
+        );
+    }
+
+    #[test]
+    fn test_for_loop() {
+        assert_eq!(
+            r#"  for (const $t1 of $her.each([1, 2], 1)) {
+    var _x = $t1[0];
+    $her.call(_小作文, [_x]);
+  }
+  let $t2 = null;
+  for (const $t3 of $her.each(_x, 2)) {
+    var _i = $t3[0];
+    var _y = $t3[1];
+    $t2 = null;
+    continue;
+  }
+  var _a = $t2;
+});"#,
+            body("for (x in [1, 2]) { 小作文(x) }; let a = for (i, y in x) { continue; };")
+        );
+    }
+
+    #[test]
+    fn test_mangle() {
+        assert_eq!("_姐妹", mangle("姐妹"));
+        assert_eq!("_$24$a", mangle("$a"));
+        assert_eq!("_$a5$", mangle("¥"));
+        assert_ne!(mangle("$24$"), mangle("$"));
+    }
+
+    #[test]
+    fn test_import() {
+        let program = Parser::new(Lexer::new("引入 \"a.her\";")).parse();
+
+        assert_eq!(
+            Err(CodegenError::Import(String::from("a.her"))),
+            to_js(&program)
+        );
+    }
+
+    #[test]
+    fn test_float() {
+        let program = Parser::new(Lexer::new("让 x = 1.5;")).parse();
+
+        assert_eq!(
+            Err(CodegenError::Float(String::from("1.5"))),
+            to_js(&program)
+        );
+    }
+}
@@ -0,0 +1,558 @@
+// herlang runtime for scripts produced by `herlang build --target=js`.
+// Values: ints are numbers, strings, booleans, null, arrays, `HerHash`,
+// `HerQuantity`, `HerIter`, `HerTask`, user functions carry their `params`
+// (and `async` for async ones), builtins their `arity`.
+const $her = (() => {
+  class HerError extends Error {}
+  class HerQuit extends Error {}
+
+  class HerHash {
+    constructor() {
+      this.entries = new Map();
+    }
+    static key(key) {
+      return typeof key + ":" + String(key);
+    }
+    get(key) {
+      const entry = this.entries.get(HerHash.key(key));
+      return entry === undefined ? null : entry[1];
+    }
+    set(key, value) {
+      this.entries.set(HerHash.key(key), [key, value]);
+    }
+  }
+
+  class HerQuantity {
+    constructor(value, unit) {
+      this.value = value;
+      this.unit = unit;
+    }
+  }
+
+  class HerIter {
+    // `items` is any JS iterable; it's pulled one value per `next()`.
+    constructor(items) {
+      this.items = items[Symbol.iterator]();
+    }
+  }
+
+  class HerTask {
+    constructor(f, args) {
+      this.state = "pending";
+      this.f = f;
+      this.args = args;
+    }
+  }
+
+  const fail = (msg) => {
+    throw new HerError(msg);
+  };
+
+  const escape = (s) =>
+    '"' +
+    s.replace(/[\\"\n\r\t\0]|[\x01-\x1f\x7f]/g, (c) => {
+      switch (c) {
+        case "\\": return "\\\\";
+        case '"': return '\\"';
+        case "\n": return "\\n";
+        case "\r": return "\\r";
+        case "\t": return "\\t";
+        case "\0": return "\\0";
+        default: return "\\u{" + c.charCodeAt(0).toString(16) + "}";
+      }
+    }) +
+    '"';
+
+  const repr = (v) => {
+    if (v === null) return "null";
+    if (v instanceof HerQuantity) return v.value + v.unit;
+    if (v instanceof HerIter) return "[iterator]";
+    if (v instanceof HerTask) return "[task]";
+    if (typeof v === "string") return escape(v);
+    if (Array.isArray(v)) return "[" + v.map(repr).join(", ") + "]";
+    if (v instanceof HerHash) {
+      const pairs = [...v.entries.values()].map(([k, x]) => repr(k) + ": " + repr(x));
+      return "{" + pairs.join(", ") + "}";
+    }
+    if (typeof v === "function") {
+      if (!v.params) return "[builtin function]";
+      return (v.async ? "async " : "") + "fn(" + v.params.join(", ") + ") { ... }";
+    }
+    return String(v);
+  };
+
+  const isInt = (v) => typeof v === "number";
+  const isHashKey = (v) => isInt(v) || typeof v === "string" || typeof v === "boolean";
+
+  const truthy = (v) => !(v === null || v === false || v === 325);
+
+  const quantity = (value, unit) => new HerQuantity(value, unit);
+
+  const prefix = (op, v) => {
+    if (op === "!") return v === false || v === null;
+    if (v instanceof HerQuantity) return op === "-" ? quantity(-v.value, v.unit) : v;
+    if (!isInt(v)) fail("unknown operator: " + (op === "-" ? "-" : "") + repr(v));
+    return op === "-" ? -v : v;
+  };
+
+  // `Object::deep_eq`: functions are equal only to themselves here.
+  const deepEq = (a, b) => {
+    if (Array.isArray(a)) return Array.isArray(b) && a.length === b.length && a.every((x, i) => deepEq(x, b[i]));
+    if (a instanceof HerHash) {
+      if (!(b instanceof HerHash) || a.entries.size !== b.entries.size) return false;
+      return [...a.entries].every(([k, [, x]]) => b.entries.has(k) && deepEq(x, b.entries.get(k)[1]));
+    }
+    if (a instanceof HerQuantity) return b instanceof HerQuantity && a.value === b.value && a.unit === b.unit;
+    return a === b;
+  };
+  const rank = (v) =>
+    v === null ? 0
+    : typeof v === "boolean" ? 1
+    : isInt(v) ? 2
+    : v instanceof HerQuantity ? 3
+    : typeof v === "string" ? 4
+    : Array.isArray(v) ? 5
+    : v instanceof HerHash ? 6
+    : 7;
+  const sign = (a, b) => (a < b ? -1 : a > b ? 1 : 0);
+  const cmpAll = (a, b) => {
+    for (let i = 0; i < a.length && i < b.length; i++) {
+      const order = cmpTotal(a[i], b[i]);
+      if (order) return order;
+    }
+    return sign(a.length, b.length);
+  };
+  const sortedFlat = (h) => [...h.entries.values()].sort(([a], [b]) => cmpTotal(a, b)).flat();
+  // `Object::cmp_total`: by type, then by value; strings by code point.
+  const cmpTotal = (a, b) => {
+    if (rank(a) !== rank(b) || rank(a) === 7) return sign(rank(a), rank(b));
+    if (a instanceof HerQuantity) return sign(a.unit, b.unit) || sign(a.value, b.value);
+    if (typeof a === "string") return cmpAll([...a].map((c) => c.codePointAt(0)), [...b].map((c) => c.codePointAt(0)));
+    if (Array.isArray(a)) return cmpAll(a, b);
+    if (a instanceof HerHash) return cmpAll(sortedFlat(a), sortedFlat(b));
+    return sign(a, b);
+  };
+  const isNumber = (v) => isInt(v) || v instanceof HerQuantity;
+  const compare = (op, a, b) => {
+    if (op === "==") return deepEq(a, b);
+    if (op === "!=") return !deepEq(a, b);
+    const ordered = typeof a === "string" || typeof a === "boolean" || Array.isArray(a);
+    if (!ordered || rank(a) !== rank(b)) fail("type mismatch: " + repr(a) + " " + op + " " + repr(b));
+    const order = cmpTotal(a, b);
+    return op === "<" ? order < 0 : op === "<=" ? order <= 0 : op === ">" ? order > 0 : order >= 0;
+  };
+
+  const infix = (op, a, b) => {
+    if (!(isNumber(a) && isNumber(b)) && ["==", "!=", "<", "<=", ">", ">="].includes(op)) return compare(op, a, b);
+    if (isInt(a) && b instanceof HerQuantity && op === "*") return quantity(a * b.value, b.unit);
+    if (a instanceof HerQuantity) {
+      if (b instanceof HerQuantity && b.unit === a.unit) {
+        if (op === "*") fail("unknown operator: " + repr(a) + " * " + repr(b));
+        const v = infix(op, a.value, b.value);
+        return op === "+" || op === "-" ? quantity(v, a.unit) : v;
+      }
+      if (isInt(b) && (op === "*" || op === "/")) return quantity(infix(op, a.value, b), a.unit);
+      fail("type mismatch: " + repr(a) + " " + op + " " + repr(b));
+    }
+    if (isInt(a)) {
+      if (!isInt(b)) fail("type mismatch: " + repr(a) + " " + op + " " + repr(b));
+      switch (op) {
+        case "+": return a + b;
+        case "-": return a - b;
+        case "*": return a * b;
+        case "/": return b === 0 ? fail("division by zero") : Math.trunc(a / b);
+        case "<": return a < b;
+        case "<=": return a <= b;
+        case ">": return a > b;
+        case ">=": return a >= b;
+        case "==": return a === b;
+        case "!=": return a !== b;
+      }
+    }
+    if (typeof a === "string") {
+      if (typeof b !== "string") fail("type mismatch: " + a + " " + op + " " + repr(b));
+      if (op === "+") return a + b;
+      fail("unknown operator: " + a + " " + op + " " + b);
+    }
+    fail("unknown operator: " + repr(a) + " " + op + " " + repr(b));
+  };
+
+  const index = (v, i) => {
+    if (Array.isArray(v)) {
+      if (!isInt(i)) fail("index operator not supported: " + repr(v));
+      return i < 0 || i >= v.length ? null : v[i];
+    }
+    if (v instanceof HerHash) {
+      if (!isHashKey(i)) fail("unusable as hash key: " + repr(i));
+      return v.get(i);
+    }
+    fail("index operator not supported: " + repr(v) + "[" + repr(i) + "]");
+  };
+
+  // Whether `v` is a hash with all of `keys`, for hash patterns.
+  const has = (v, ...keys) => v instanceof HerHash && keys.every((k) => v.entries.has(HerHash.key(k)));
+
+  const hash = (pairs) => {
+    const h = new HerHash();
+    for (const [k, v] of pairs) h.set(k, v);
+    return h;
+  };
+
+  // `required` is how many of `params` come before the ones with defaults.
+  const fn = (params, f, required = params.length) => Object.assign(f, { params, required });
+  const async = (f) => Object.assign(f, { async: true });
+
+  // Tasks in the order they were started; see `run`.
+  const tasks = [];
+  const await_ = (v) => {
+    if (!(v instanceof HerTask)) return v;
+    if (v.state === "running") fail("task is waiting for itself");
+    if (v.state === "pending") {
+      v.state = "running";
+      try {
+        v.result = call(v.f, v.args);
+      } catch (e) {
+        v.error = e;
+      }
+      v.state = "done";
+    }
+    if (v.error) throw v.error;
+    return v.result;
+  };
+
+  const call = (f, args) => {
+    if (typeof f !== "function") fail(repr(f) + " is not a function");
+    if (f.async) {
+      const task = new HerTask(Object.assign((...a) => f(...a), { params: f.params, required: f.required }), args);
+      tasks.push(task);
+      return task;
+    }
+    if (f.params && (args.length < f.required || args.length > f.params.length)) {
+      const expected = f.required === f.params.length ? f.params.length : f.required + " to " + f.params.length;
+      fail("wrong number of arguments: " + expected + " expected but " + args.length + " given");
+    }
+    if (f.arity !== undefined && f.arity >= 0 && f.arity !== args.length) {
+      fail("wrong number of arguments: " + f.arity + " expected but " + args.length + " given");
+    }
+    return f(...args);
+  };
+
+  const notFound = (name) => fail("identifier not found: " + name);
+
+  const runtime = { output: (line) => console.log(line) };
+
+  const builtin = (arity, f) => Object.assign(f, { arity });
+  const array = (name, f) =>
+    builtin(1, (v) => (Array.isArray(v) ? f(v) : fail("argument to `" + name + "` must be array. got " + repr(v))));
+  // `print` can leave a line open, so whole lines are passed on to `runtime.output`.
+  let pending = "";
+  const write = (text) => {
+    const lines = (pending + text).split("\n");
+    pending = lines.pop();
+    lines.forEach((line) => runtime.output(line));
+  };
+  const display = (v) => (typeof v === "string" ? v : repr(v));
+  const output = builtin(-1, (...args) => {
+    args.forEach((v) => write(repr(v) + "\n"));
+    return null;
+  });
+  const print = builtin(-1, (...args) => {
+    let [sep, end] = [" ", "\n"];
+    const last = args[args.length - 1];
+    const options = last instanceof HerHash ? [...last.entries.values()] : [];
+    if (options.length && options.every(([k]) => k === "sep" || k === "end")) {
+      for (const [k, v] of options) {
+        if (typeof v !== "string") fail("option " + repr(k) + " of `print` must be string. got " + repr(v));
+        if (k === "sep") sep = v;
+        else end = v;
+      }
+      args.pop();
+    }
+    write(args.map(display).join(sep) + end);
+    return null;
+  });
+  const quit = builtin(-1, (...args) => {
+    if (args.length > 1) fail("Too many arguments to `quit` (want 0 or 1, got " + args.length + ")");
+    if (args.length === 1 && !isInt(args[0])) fail("argument to `quit` must be int. got " + repr(args[0]));
+    throw new HerQuit();
+  });
+  const reprBuiltin = builtin(1, repr);
+  const str = builtin(1, display);
+  const sort = array("sort", (v) => [...v].sort(cmpTotal));
+  const sleep = builtin(1, (ms) => {
+    if (!isInt(ms) || ms < 0) fail("argument to `sleep` must be non-negative int. got " + repr(ms));
+    // Blocks in Node and workers; a browser's main thread can't, so it goes on.
+    try {
+      Atomics.wait(new Int32Array(new SharedArrayBuffer(4)), 0, 0, ms);
+    } catch {}
+    return null;
+  });
+  // Pauses only when a debugger is attached, like the CLI's inspector.
+  const breakpoint = builtin(0, () => {
+    debugger;
+    return null;
+  });
+  const toFixed = builtin(2, (x, digits) => {
+    if (!isInt(digits) || digits < 0 || digits > 100)
+      fail("digits of `to_fixed` must be int between 0 and 100. got " + repr(digits));
+    if (!isInt(x)) fail("argument to `to_fixed` must be number. got " + repr(x));
+    return x.toFixed(digits);
+  });
+  const radix = (name, base) =>
+    isInt(base) && base >= 2 && base <= 36 ? base : fail("base of `" + name + "` must be int between 2 and 36. got " + repr(base));
+  const toBase = builtin(2, (n, base) => {
+    radix("to_base", base);
+    return isInt(n) ? n.toString(base) : fail("argument to `to_base` must be int. got " + repr(n));
+  });
+  const fromBase = builtin(2, (s, base) => {
+    radix("from_base", base);
+    if (typeof s !== "string") fail("argument to `from_base` must be string. got " + repr(s));
+    const digits = "0123456789abcdefghijklmnopqrstuvwxyz".slice(0, base);
+    const valid = /^[+-]?[0-9a-z]+$/i.test(s) && [...s.replace(/^[+-]/, "").toLowerCase()].every((c) => digits.includes(c));
+    return valid ? parseInt(s, base) : fail("argument to `from_base` must be valid base " + base + " digits. got " + escape(s));
+  });
+  const chr = builtin(1, (n) => {
+    if (!isInt(n)) fail("argument to `chr` must be int. got " + repr(n));
+    if (n < 0 || n > 0x10ffff || (n >= 0xd800 && n <= 0xdfff)) fail(n + " is not a Unicode scalar value");
+    return String.fromCodePoint(n);
+  });
+  const ord = builtin(1, (s) => {
+    if (typeof s !== "string") fail("argument to `ord` must be string. got " + repr(s));
+    const chars = [...s];
+    return chars.length === 1 ? chars[0].codePointAt(0) : fail("argument to `ord` must be a single character. got " + escape(s));
+  });
+  const template = builtin(2, (text, data) => {
+    if (typeof text !== "string") fail("argument to `template` must be string. got " + repr(text));
+    if (!(data instanceof HerHash)) fail("second argument to `template` must be hash. got " + repr(data));
+    return text.replace(/\{\{|\}\}|\{([^}]*)(\}?)/g, (m, key, close) => {
+      if (m === "{{" || m === "}}") return m[0];
+      if (!close) fail("unclosed `{` in template");
+      if (!data.entries.has(HerHash.key(key.trim()))) fail("template key not found: " + key.trim());
+      return display(data.get(key.trim()));
+    });
+  });
+  const flat = array("flat", (v) => v.flat());
+  const zip = builtin(2, (a, b) => {
+    for (const v of [a, b]) if (!Array.isArray(v)) fail("argument to `zip` must be array. got " + repr(v));
+    return a.slice(0, Math.min(a.length, b.length)).map((x, i) => [x, b[i]]);
+  });
+  const enumerate = array("enumerate", (v) => v.map((x, i) => [i, x]));
+  const range = builtin(-1, (...args) => {
+    if (!args.length || args.length > 3 || !args.every(isInt)) {
+      fail("illegal argument to `range` (want end, or start, end and step ints, got " + args.map(repr).join(", ") + ")");
+    }
+    const [start, end, by = 1] = args.length === 1 ? [0, args[0]] : args;
+    if (by === 0) fail("step of `range` must not be 0");
+    return new HerIter(
+      (function* () {
+        for (let i = start; by > 0 ? i < end : i > end; i += by) yield i;
+      })(),
+    );
+  });
+  const unique = array("unique", (v) => v.filter((x, i) => v.findIndex((y) => deepEq(x, y)) === i));
+  const atoi = builtin(1, (v) => {
+    if (typeof v !== "string") fail("illegal argument to `atoi` (want 1 string, got [" + repr(v) + "]");
+    return /^[+-]?[0-9]+$/.test(v) ? parseInt(v, 10) : fail("argument to `atoi` must be valid digits. got " + escape(v));
+  });
+
+  const numerals = (n, digits, units, small) => {
+    if (n === 0) return digits[0];
+    const groups = [];
+    for (let rest = Math.abs(n); rest > 0; rest = Math.floor(rest / 10000)) groups.push(rest % 10000);
+    let out = "";
+    let zero = false;
+    for (let i = groups.length - 1; i >= 0; i--) {
+      const group = groups[i];
+      if (group === 0) {
+        zero = true;
+        continue;
+      }
+      if (out && (zero || group < 1000)) out += digits[0];
+      zero = false;
+      let gap = false;
+      const places = [Math.floor(group / 1000), Math.floor(group / 100) % 10, Math.floor(group / 10) % 10, group % 10];
+      for (let place = places.findIndex((d) => d !== 0); place < 4; place++) {
+        const digit = places[place];
+        if (digit === 0) {
+          gap = true;
+          continue;
+        }
+        if (gap) out += digits[0];
+        gap = false;
+        if (!(small && !out && digit === 1 && place === 2)) out += digits[digit];
+        if (place < 3) out += units[2 - place];
+      }
+      out += ["", "万", "亿", "万亿", "亿亿"][i];
+    }
+    return n < 0 ? "负" + out : out;
+  };
+  const intOnly = (name, f) =>
+    builtin(1, (v) => (isInt(v) ? f(v) : fail("argument to `" + name + "` must be int. got " + repr(v))));
+  const chinese = intOnly("中文数字", (n) => numerals(n, "零一二三四五六七八九", "十百千", true));
+  const amount = intOnly("大写金额", (n) => numerals(n, "零壹贰叁肆伍陆柒捌玖", "拾佰仟", false) + "元整");
+
+  const sortedEntries = (h) =>
+    [...h.entries.values()].sort(([a], [b]) => (repr(a) < repr(b) ? -1 : repr(a) > repr(b) ? 1 : 0));
+  const hashOnly = (name, f) =>
+    builtin(1, (v) => (v instanceof HerHash ? f(v) : fail("argument to `" + name + "` must be hash. got " + repr(v))));
+  const keys = hashOnly("keys", (h) => sortedEntries(h).map(([k]) => k));
+  const values = hashOnly("values", (h) => sortedEntries(h).map(([, v]) => v));
+  const step = (value, done) => hash([["done", done], ["value", value]]);
+  const iter = builtin(1, (v) => {
+    if (v instanceof HerIter) return v;
+    if (Array.isArray(v)) return new HerIter([...v]);
+    if (typeof v === "string") return new HerIter([...v]);
+    if (v instanceof HerHash) return new HerIter(sortedEntries(v).map(([k]) => k));
+    fail("argument to `iter` must be array, string or hash. got " + repr(v));
+  });
+  const next = builtin(1, (v) => {
+    if (!(v instanceof HerIter)) fail("argument to `next` must be iterator. got " + repr(v));
+    const item = v.items.next();
+    return item.done ? step(null, true) : step(item.value, false);
+  });
+  // What a `for` loop with `vars` names binds on each turn.
+  const each = function* (v, vars) {
+    // A hash with a `next` function is an iterator of its own.
+    const own = v instanceof HerHash && typeof v.get("next") === "function";
+    const pick = (key, item) => (vars === 1 ? [v instanceof HerHash && !own ? key : item] : [key, item]);
+    if (v instanceof HerIter || own) {
+      const [f, args] = own ? [v.get("next"), []] : [next, [v]];
+      for (let i = 0; ; i++) {
+        const s = call(f, args);
+        if (!(s instanceof HerHash)) fail('next() must return {"value", "done"}, got ' + repr(s));
+        if (truthy(s.get("done"))) return;
+        yield pick(i, s.get("value"));
+      }
+    } else if (v instanceof HerHash) {
+      for (const [key, item] of sortedEntries(v)) yield pick(key, item);
+    } else if (Array.isArray(v) || typeof v === "string") {
+      for (const [i, item] of [...v].entries()) yield pick(i, item);
+    } else {
+      fail("for-in not supported: " + repr(v));
+    }
+  };
+
+  const assert = builtin(-1, (...args) => {
+    if (!args.length || args.length > 2) fail("wrong number of arguments to `assert` (want 1 or 2, got " + args.length + ")");
+    if (truthy(args[0])) return null;
+    fail(args.length === 2 ? "assertion failed: " + display(args[1]) : "assertion failed");
+  });
+  const callable = builtin(1, (v) => typeof v === "function");
+  const arity = builtin(1, (v) => {
+    if (typeof v !== "function") fail("argument to `arity` must be function. got " + repr(v));
+    if (v.params) return v.params.length;
+    return v.arity >= 0 ? v.arity : null;
+  });
+  // Scopes are plain JS variables once compiled, so there is nothing to list.
+  const scope = (name, arity) =>
+    builtin(arity, () => fail("`" + name + "` is not available in compiled scripts"));
+
+  const builtins = {
+    len: builtin(1, (v) => {
+      if (typeof v === "string") return new TextEncoder().encode(v).length;
+      if (Array.isArray(v)) return v.length;
+      fail("argument to `len` not supported, got " + repr(v));
+    }),
+    first: array("first", (v) => (v.length ? v[0] : null)),
+    last: array("last", (v) => (v.length ? v[v.length - 1] : null)),
+    rest: array("rest", (v) => (v.length ? v.slice(1) : null)),
+    push: builtin(2, (v, x) =>
+      Array.isArray(v) ? [...v, x] : fail("argument to `push` must be array. got " + repr(v))
+    ),
+    puts: output,
+    keys,
+    values,
+    iter,
+    next,
+    globals: scope("globals", 0),
+    locals: scope("locals", 0),
+    del: scope("del", 1),
+    exec: scope("exec", -1),
+    parse_float: scope("parse_float", 1),
+    json_parse: scope("json_parse", -1),
+    json_stringify: scope("json_stringify", 1),
+    摇人: scope("exec", -1),
+    assert,
+    必须的: assert,
+    callable,
+    arity,
+    quit,
+    print,
+    repr: reprBuiltin,
+    str,
+    atoi,
+    sort,
+    sleep,
+    breakpoint,
+    to_fixed: toFixed,
+    to_base: toBase,
+    from_base: fromBase,
+    chr,
+    ord,
+    template,
+    哼: quit,
+    哈: quit,
+    小作文: output,
+    家人们: output,
+    聚焦: print,
+    复用: reprBuiltin,
+    疏通: str,
+    抹零: atoi,
+    躺平: sleep,
+    先别卷了: breakpoint,
+    模板: template,
+    中文数字: chinese,
+    大写金额: amount,
+    flat,
+    zip,
+    enumerate,
+    unique,
+    range,
+    拍平: flat,
+    组CP: zip,
+    报数: enumerate,
+    去重: unique,
+    数到: range,
+  };
+
+  const methods = {
+    string: ["len", "atoi", "parse_float", "from_base", "ord", "template", "json_parse", "iter"],
+    int: ["to_fixed", "to_base", "chr"],
+    array: ["len", "first", "last", "rest", "push", "sort", "flat", "zip", "enumerate", "unique", "iter"],
+    hash: ["keys", "values", "iter"],
+    iterator: ["next", "iter"],
+  };
+
+  // `v.name(...)`: a hash entry called `name`, else a builtin taking `v` first.
+  const method = (v, name) => {
+    if (v instanceof HerHash && v.entries.has(HerHash.key(name))) return v.get(name);
+    const type =
+      typeof v === "string" ? "string"
+      : isInt(v) ? "int"
+      : Array.isArray(v) ? "array"
+      : v instanceof HerHash ? "hash"
+      : v instanceof HerIter ? "iterator"
+      : "";
+    if ((methods[type] || []).includes(name) || ["str", "repr", "json_stringify"].includes(name)) {
+      return (...args) => call(builtins[name], [v, ...args]);
+    }
+    return index(v, name);
+  };
+
+  // Tasks nobody awaited run once the program is done, oldest first.
+  const run = (main) => {
+    try {
+      main();
+      while (tasks.length) await_(tasks.shift());
+    } catch (e) {
+      if (e instanceof HerQuit) return;
+      if (!(e instanceof HerError)) throw e;
+      write("啊啊啊啊啊啊啊啊(" + e.message + ")\n");
+    } finally {
+      if (pending) runtime.output(pending);
+      pending = "";
+    }
+  };
+
+  return Object.assign(runtime, { builtins, truthy, quantity, async, await: await_, prefix, infix, index, has, hash, each, fn, call, method, notFound, run });
+})();
@@ -0,0 +1,215 @@
+//! Completion candidates for editors: keywords, builtins and the variables
+//! visible at the cursor.
+//!
+//! Code being typed rarely parses, so visibility is worked out from the token
+//! stream instead of the AST: every `{` opens a scope, `let` adds to the
+//! innermost one and function parameters belong to the body that follows.
+
+use crate::evaluator::builtins::BuiltinRegistry;
+use crate::json;
+use crate::lexer::{KEYWORDS, Lexer, is_id_continue};
+use crate::token::Token;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum CompletionKind {
+    Variable,
+    Builtin,
+    Keyword,
+}
+
+impl CompletionKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CompletionKind::Variable => "variable",
+            CompletionKind::Builtin => "builtin",
+            CompletionKind::Keyword => "keyword",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Completion {
+    pub label: String,
+    pub kind: CompletionKind,
+    /// The signature of a builtin.
+    pub detail: Option<String>,
+    /// What a builtin does.
+    pub documentation: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Completions {
+    /// Char offset where the word being completed starts.
+    pub from: usize,
+    pub items: Vec<Completion>,
+}
+
+impl Completions {
+    pub fn to_json(&self) -> String {
+        let items = json::array(self.items.iter().map(|item| {
+            let mut fields = format!(
+                "\"label\":{},\"kind\":{}",
+                json::string(&item.label),
+                json::string(item.kind.as_str())
+            );
+            if let Some(detail) = &item.detail {
+                fields.push_str(&format!(",\"detail\":{}", json::string(detail)));
+            }
+            if let Some(documentation) = &item.documentation {
+                fields.push_str(&format!(
+                    ",\"documentation\":{}",
+                    json::string(documentation)
+                ));
+            }
+            format!("{{{fields}}}")
+        }));
+
+        format!("{{\"from\":{},\"items\":{}}}", self.from, items)
+    }
+}
+
+/// Collects the names bound before the end of `input` that are still in scope there.
+fn visible_variables(input: &str) -> Vec<String> {
+    let mut lexer = Lexer::new(input);
+    let mut scopes: Vec<Vec<String>> = vec![vec![]];
+    let mut params: Option<Vec<String>> = None;
+    // Brackets open in a parameter list, counting its own `(`, so names in
+    // defaults aren't taken for parameters.
+    let mut params_depth = 0;
+    let mut prev = Token::Eof;
+
+    loop {
+        let tok = lexer.next_token();
+
+        match &tok {
+            Token::Eof => break,
+            Token::Lparen if prev == Token::Func => {
+                params_depth = 1;
+                params = Some(vec![]);
+            }
+            Token::Lparen | Token::Lbracket if params_depth > 0 => params_depth += 1,
+            Token::Rparen | Token::Rbracket if params_depth > 0 => params_depth -= 1,
+            Token::Ident(name)
+                if params_depth == 1 && matches!(prev, Token::Lparen | Token::Comma) =>
+            {
+                if let Some(params) = params.as_mut() {
+                    params.push(name.clone());
+                }
+            }
+            Token::Ident(name) if prev == Token::Let => {
+                if let Some(scope) = scopes.last_mut() {
+                    scope.push(name.clone());
+                }
+            }
+            Token::Lbrace => scopes.push(params.take().unwrap_or_default()),
+            Token::Rbrace if scopes.len() > 1 => {
+                scopes.pop();
+            }
+            _ => {}
+        }
+
+        prev = tok;
+    }
+
+    scopes.into_iter().flatten().collect()
+}
+
+/// Completes the word that ends at char `offset` of `input`.
+pub fn complete(input: &str, offset: usize) -> Completions {
+    let before = input.chars().take(offset).collect::<Vec<_>>();
+    let from = before
+        .iter()
+        .rposition(|c| !is_id_continue(*c))
+        .map_or(0, |i| i + 1);
+    let prefix = before[from..].iter().collect::<String>();
+    let code = before[..from].iter().collect::<String>();
+
+    let mut items: Vec<Completion> = vec![];
+    let mut push = |label: &str, kind: CompletionKind, docs: Option<(String, &str)>| {
+        if label.starts_with(&prefix) && !items.iter().any(|item| item.label == label) {
+            let (detail, documentation) = docs.unzip();
+            items.push(Completion {
+                label: label.to_string(),
+                kind,
+                detail,
+                documentation: documentation.map(String::from),
+            });
+        }
+    };
+
+    for name in visible_variables(&code).iter().rev() {
+        push(name, CompletionKind::Variable, None);
+    }
+
+    let registry = BuiltinRegistry::new();
+    let mut builtins = registry
+        .iter()
+        .flat_map(|def| {
+            std::iter::once(&def.name)
+                .chain(def.aliases)
+                .map(move |name| (*name, def))
+        })
+        .collect::<Vec<_>>();
+    builtins.sort_by_key(|(name, _)| *name);
+    for (name, def) in builtins {
+        push(
+            name,
+            CompletionKind::Builtin,
+            Some((def.signature(), def.doc)),
+        );
+    }
+
+    for (keyword, _) in KEYWORDS {
+        push(keyword, CompletionKind::Keyword, None);
+    }
+
+    Completions { from, items }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::completion::*;
+
+    fn labels(input: &str) -> Vec<String> {
+        let offset = input.chars().count();
+        complete(input, offset)
+            .items
+            .into_iter()
+            .map(|item| item.label)
+            .collect()
+    }
+
+    #[test]
+    fn test_complete() {
+        assert_eq!(
+            vec!["姐妹", "姐妹们觉得呢"],
+            labels("宝宝你是一个 姐妹 = 1;\n姐")
+        );
+        assert_eq!(
+            vec!["abc", "abd"],
+            labels("let abd = 1; let f = fn(abc) { ab")
+        );
+        assert_eq!(
+            vec!["abd"],
+            labels("let abd = 1; let f = fn(abc) { 1 }; ab")
+        );
+        assert_eq!(vec!["小作文"], labels("小"));
+        assert_eq!(vec!["反复横跳", "反手举报"], labels("let f = fn() { 反"));
+        assert_eq!(vec!["abc"], labels("let f = fn(abc: abd) { ab"));
+        assert_eq!(
+            vec!["abe", "abc"],
+            labels("let f = fn(abc = g(abd, [abd]), abe = abd) { ab")
+        );
+    }
+
+    #[test]
+    fn test_to_json() {
+        let completions = complete("let lenx = 1; le", 16);
+
+        assert_eq!(14, completions.from);
+        assert_eq!(
+            r#"{"from":14,"items":[{"label":"lenx","kind":"variable"},{"label":"len","kind":"builtin","detail":"len(字符串或数组)","documentation":"字符串的字节数，或者数组的元素个数"},{"label":"let","kind":"keyword"}]}"#,
+            completions.to_json()
+        );
+    }
+}
@@ -0,0 +1,970 @@
+//! Editor-facing diagnostics: only runs the lexer and parser, never evaluates.
+
+use crate::json;
+#[cfg(feature = "diagnostics")]
+use crate::lexer::Lexer;
+use crate::lexer::{KEYWORDS, LANGUAGE_VERSION, LexError};
+#[cfg(feature = "diagnostics")]
+use crate::lint;
+#[cfg(feature = "diagnostics")]
+use crate::parser::Parser;
+use crate::parser::syntax::ONLY_FORMULAS;
+use crate::parser::{HerRule, ParseError, TOO_LONG};
+use crate::span::Span;
+use crate::token::Token;
+use std::fmt;
+use std::str::FromStr;
+
+/// Language diagnostics are rendered in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Lang {
+    #[default]
+    En,
+    Zh,
+}
+
+impl FromStr for Lang {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "en" => Ok(Lang::En),
+            "zh" => Ok(Lang::Zh),
+            _ => Err(format!("unknown language {s:?}, want en or zh")),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+        }
+    }
+}
+
+impl Severity {
+    pub fn name(&self, lang: Lang) -> &'static str {
+        match (self, lang) {
+            (Severity::Error, Lang::En) => "error",
+            (Severity::Warning, Lang::En) => "warning",
+            (Severity::Error, Lang::Zh) => "错误",
+            (Severity::Warning, Lang::Zh) => "警告",
+        }
+    }
+}
+
+/// What a diagnostic is about, with the details its message needs. Operands
+/// are kept as already rendered strings. The evaluator words its runtime
+/// errors with the same codes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DiagnosticCode {
+    UnexpectedToken {
+        want: String,
+        got: String,
+    },
+    NoPrefixRule {
+        got: String,
+    },
+    SyntaxError {
+        rule: HerRule,
+    },
+    /// A `#她版本 N` header naming a version this build doesn't know.
+    UnknownVersion {
+        version: String,
+    },
+    TooLong,
+    UnreachableCode,
+    DivisionByZero,
+    IntegerOverflow {
+        expr: String,
+    },
+    IndexOutOfBounds {
+        index: i64,
+        len: usize,
+    },
+    TypeMismatch {
+        left: String,
+        op: String,
+        right: String,
+    },
+    UnknownPrefixOperator {
+        op: String,
+        right: String,
+    },
+    UnknownInfixOperator {
+        left: String,
+        op: String,
+        right: String,
+    },
+    IndexNotSupported {
+        left: String,
+        index: Option<String>,
+    },
+    UnusableHashKey {
+        key: String,
+    },
+    NotAFunction {
+        func: String,
+    },
+    WrongArgumentCount {
+        want: String,
+        got: usize,
+    },
+    InvalidEscape {
+        escape: String,
+    },
+    UnterminatedString,
+    UnterminatedComment,
+    MacroArgumentCount {
+        name: String,
+        want: usize,
+        got: usize,
+    },
+    NotAllowed,
+    UnknownIdentifier {
+        name: String,
+    },
+    /// A file `herlang check` was given but couldn't read.
+    UnreadableFile {
+        error: String,
+    },
+    /// A `match` with no arm for some value its subject can have, e.g.
+    /// `false`.
+    NonExhaustiveMatch {
+        missing: String,
+    },
+}
+
+impl DiagnosticCode {
+    /// The stable number of this kind of diagnostic, like `HER0006`, for
+    /// `herlang explain` and editors to link to. Numbers are never reused:
+    /// a new kind takes the next one.
+    pub fn id(&self) -> &'static str {
+        use DiagnosticCode::*;
+
+        match self {
+            UnexpectedToken { .. } => "HER0001",
+            NoPrefixRule { .. } => "HER0002",
+            SyntaxError {
+                rule: HerRule::MissingDefault { .. },
+            } => "HER0025",
+            SyntaxError {
+                rule: HerRule::NotAPattern { .. },
+            } => "HER0026",
+            SyntaxError { .. } => "HER0003",
+            TooLong => "HER0004",
+            UnreachableCode => "HER0005",
+            DivisionByZero => "HER0006",
+            IntegerOverflow { .. } => "HER0007",
+            IndexOutOfBounds { .. } => "HER0008",
+            TypeMismatch { .. } => "HER0009",
+            UnknownPrefixOperator { .. } => "HER0010",
+            UnknownInfixOperator { .. } => "HER0011",
+            IndexNotSupported { .. } => "HER0012",
+            UnusableHashKey { .. } => "HER0013",
+            NotAFunction { .. } => "HER0014",
+            WrongArgumentCount { .. } => "HER0015",
+            InvalidEscape { .. } => "HER0016",
+            UnterminatedString => "HER0017",
+            UnterminatedComment => "HER0018",
+            MacroArgumentCount { .. } => "HER0019",
+            NotAllowed => "HER0020",
+            UnknownIdentifier { .. } => "HER0021",
+            UnreadableFile { .. } => "HER0022",
+            NonExhaustiveMatch { .. } => "HER0023",
+            UnknownVersion { .. } => "HER0024",
+        }
+    }
+
+    /// The message catalog.
+    pub fn message(&self, lang: Lang) -> String {
+        use DiagnosticCode::*;
+
+        match (self, lang) {
+            (UnexpectedToken { want, got }, Lang::En) => {
+                format!("啊啊啊啊啊啊啊啊啊啊啊啊 Unexpected Token: expected `{want}`, got `{got}`")
+            }
+            (UnexpectedToken { want, got }, Lang::Zh) => {
+                format!("啊啊啊啊啊啊啊啊啊啊啊啊 语法错误：这里应该是 `{want}`，却遇到了 `{got}`")
+            }
+            (NoPrefixRule { got }, Lang::En) => {
+                format!("啊啊啊啊啊啊啊啊啊啊啊啊 Unexpected Token: no prefix rule for `{got}`")
+            }
+            (NoPrefixRule { got }, Lang::Zh) => {
+                format!("啊啊啊啊啊啊啊啊啊啊啊啊 语法错误：表达式不能以 `{got}` 开头")
+            }
+            (SyntaxError { rule }, Lang::En) => {
+                format!(
+                    "啊啊啊啊啊啊啊啊啊啊啊啊 SyntaxError: {}",
+                    rule_message(rule, lang)
+                )
+            }
+            (SyntaxError { rule }, Lang::Zh) => {
+                format!(
+                    "啊啊啊啊啊啊啊啊啊啊啊啊 语法错误：{}",
+                    rule_message(rule, lang)
+                )
+            }
+            (UnknownVersion { version }, Lang::En) => {
+                format!("unknown language version {version}, expected 1 to {LANGUAGE_VERSION}")
+            }
+            (UnknownVersion { version }, Lang::Zh) => {
+                format!("不认识的语言版本 {version}，只能是 1 到 {LANGUAGE_VERSION}")
+            }
+            (TooLong, Lang::En) => String::from("code too long, take a break sis"),
+            (TooLong, Lang::Zh) => String::from(TOO_LONG),
+            (UnreachableCode, Lang::En) => String::from("unreachable code"),
+            (UnreachableCode, Lang::Zh) => String::from("这里的代码永远执行不到"),
+            (DivisionByZero, Lang::En) => String::from("division by zero"),
+            (DivisionByZero, Lang::Zh) => String::from("除数为零"),
+            (IntegerOverflow { expr }, Lang::En) => format!("integer overflow: {expr}"),
+            (IntegerOverflow { expr }, Lang::Zh) => format!("整数溢出：{expr}"),
+            (IndexOutOfBounds { index, len }, Lang::En) => {
+                format!("index {index} out of bounds for array of length {len}, always null")
+            }
+            (IndexOutOfBounds { index, len }, Lang::Zh) => {
+                format!("下标 {index} 超出了长度为 {len} 的数组，结果总是 null")
+            }
+            (TypeMismatch { left, op, right }, Lang::En) => {
+                format!("type mismatch: {left} {op} {right}")
+            }
+            (TypeMismatch { left, op, right }, Lang::Zh) => {
+                format!("类型不匹配：{left} {op} {right}")
+            }
+            (UnknownPrefixOperator { op, right }, Lang::En) => {
+                format!("unknown operator: {op}{right}")
+            }
+            (UnknownPrefixOperator { op, right }, Lang::Zh) => {
+                format!("不支持的运算：{op}{right}")
+            }
+            (UnknownInfixOperator { left, op, right }, Lang::En) => {
+                format!("unknown operator: {left} {op} {right}")
+            }
+            (UnknownInfixOperator { left, op, right }, Lang::Zh) => {
+                format!("不支持的运算：{left} {op} {right}")
+            }
+            (IndexNotSupported { left, index }, Lang::En) => match index {
+                Some(index) => format!("index operator not supported: {left}[{index}]"),
+                None => format!("index operator not supported: {left}"),
+            },
+            (IndexNotSupported { left, index }, Lang::Zh) => match index {
+                Some(index) => format!("不能用 {index} 给 {left} 取下标"),
+                None => format!("不能给 {left} 取下标"),
+            },
+            (UnusableHashKey { key }, Lang::En) => format!("unusable as hash key: {key}"),
+            (UnusableHashKey { key }, Lang::Zh) => format!("{key} 不能用作哈希的键"),
+            (NotAFunction { func }, Lang::En) => format!("{func} is not a function"),
+            (NotAFunction { func }, Lang::Zh) => format!("{func} 不是函数"),
+            (WrongArgumentCount { want, got }, Lang::En) => {
+                format!("wrong number of arguments: {want} expected but {got} given")
+            }
+            (WrongArgumentCount { want, got }, Lang::Zh) => {
+                format!("参数个数不对：需要 {want} 个，给了 {got} 个")
+            }
+            (InvalidEscape { escape }, Lang::En) => format!("invalid escape in string: {escape}"),
+            (InvalidEscape { escape }, Lang::Zh) => format!("字符串里的转义写错了：{escape}"),
+            (UnterminatedString, Lang::En) => String::from("unterminated string"),
+            (UnterminatedString, Lang::Zh) => String::from("字符串缺少结尾的引号"),
+            (UnterminatedComment, Lang::En) => String::from("unterminated block comment"),
+            (UnterminatedComment, Lang::Zh) => String::from("块注释缺少结尾的 */"),
+            (MacroArgumentCount { name, want, got }, Lang::En) => {
+                format!("macro {name} takes {want} arguments but {got} were given")
+            }
+            (MacroArgumentCount { name, want, got }, Lang::Zh) => {
+                format!("句式 {name} 需要 {want} 个参数，给了 {got} 个")
+            }
+            (NotAllowed, Lang::En) => String::from("only a formula is allowed here"),
+            (NotAllowed, Lang::Zh) => String::from(ONLY_FORMULAS),
+            (UnknownIdentifier { name }, Lang::En) => format!("identifier not found: {name}"),
+            (UnknownIdentifier { name }, Lang::Zh) => format!("找不到 {name}，它还没有定义"),
+            (UnreadableFile { error }, Lang::En) => format!("can't read the file: {error}"),
+            (UnreadableFile { error }, Lang::Zh) => format!("读不了这个文件：{error}"),
+            (NonExhaustiveMatch { missing }, Lang::En) => {
+                format!("match has no arm for {missing}, so it gives null then")
+            }
+            (NonExhaustiveMatch { missing }, Lang::Zh) => {
+                format!("下头菜单 没有 {missing} 的分支，遇到它时结果是 null")
+            }
+        }
+    }
+}
+
+/// The part of a `SyntaxError` that says which rule was broken.
+fn rule_message(rule: &HerRule, lang: Lang) -> String {
+    match (rule, lang) {
+        (HerRule::ProtectedName, Lang::En) => String::from("women can't be defined!!!"),
+        (HerRule::ProtectedName, Lang::Zh) => String::from("女性是不能被定义的！！！"),
+        (HerRule::NotAPattern { .. }, Lang::En) => rule.to_string(),
+        (HerRule::NotAPattern { got }, Lang::Zh) => {
+            format!("`{got}` 不能当模式用，这里要写数字、字符串、布尔值、名字、数组、哈希或 `_`")
+        }
+        (HerRule::MissingDefault { .. }, Lang::En) => rule.to_string(),
+        (HerRule::MissingDefault { param }, Lang::Zh) => {
+            format!("参数 `{param}` 也要有默认值，和它前面的参数一样")
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub code: DiagnosticCode,
+    pub span: Span,
+}
+
+impl Diagnostic {
+    pub fn error(code: DiagnosticCode, span: Span) -> Self {
+        Diagnostic {
+            severity: Severity::Error,
+            code,
+            span,
+        }
+    }
+
+    pub fn warning(code: DiagnosticCode, span: Span) -> Self {
+        Diagnostic {
+            severity: Severity::Warning,
+            code,
+            span,
+        }
+    }
+
+    pub fn message(&self, lang: Lang) -> String {
+        self.code.message(lang)
+    }
+
+    /// `line:column: severity[code]: message`, like `Display` but in `lang`.
+    pub fn render(&self, lang: Lang) -> String {
+        format!(
+            "{}: {}[{}]: {}",
+            self.span,
+            self.severity.name(lang),
+            self.code.id(),
+            self.message(lang)
+        )
+    }
+
+    /// The message in plain words for people new to programming: for syntax
+    /// errors, what was written (quoted from `src`), what belongs there and a
+    /// likely fix. Other codes already read that way and give `message`.
+    pub fn friendly_message(&self, src: &str, lang: Lang) -> String {
+        let text = src
+            .chars()
+            .skip(self.span.start.offset)
+            .take(self.span.end.offset.saturating_sub(self.span.start.offset))
+            .collect::<String>();
+        let got = match (text.trim(), lang) {
+            ("", Lang::En) => String::from("the end of the code"),
+            ("", Lang::Zh) => String::from("代码的结尾"),
+            (text, _) => format!("`{text}`"),
+        };
+
+        match (&self.code, lang) {
+            (DiagnosticCode::UnexpectedToken { want, .. }, Lang::En) => {
+                let (want, hint) = expected_token(want, lang);
+                format!("`{want}` should go here, but this is {got}. {hint}")
+            }
+            (DiagnosticCode::UnexpectedToken { want, .. }, Lang::Zh) => {
+                let (want, hint) = expected_token(want, lang);
+                format!("这里应该写 `{want}`，却写了 {got}。{hint}")
+            }
+            (DiagnosticCode::NoPrefixRule { .. }, _) if text.trim().is_empty() => match lang {
+                Lang::En => String::from("The code ends in the middle of an expression."),
+                Lang::Zh => String::from("代码没写完就结束了，后面还缺一个值。"),
+            },
+            (DiagnosticCode::NoPrefixRule { .. }, Lang::En) => format!(
+                "{got} can't go here; a value like a number, a string or a name \
+                 should. {}",
+                misplaced_token(text.trim(), lang)
+            ),
+            (DiagnosticCode::NoPrefixRule { .. }, Lang::Zh) => format!(
+                "这里不能直接放 {got}，应该是一个值，比如数字、字符串或名字。{}",
+                misplaced_token(text.trim(), lang)
+            ),
+            (DiagnosticCode::SyntaxError { rule }, _) => rule_message(rule, lang),
+            (DiagnosticCode::NotAllowed, Lang::En) => {
+                format!("Only a formula is allowed here, so {got} can't be used.")
+            }
+            (DiagnosticCode::NotAllowed, Lang::Zh) => format!("{ONLY_FORMULAS}，不能用 {got}。"),
+            _ => self.message(lang),
+        }
+        .trim_end()
+        .to_string()
+    }
+
+    /// Like `render`, with `friendly_message`.
+    pub fn render_friendly(&self, src: &str, lang: Lang) -> String {
+        format!(
+            "{}: {}[{}]: {}",
+            self.span,
+            self.severity.name(lang),
+            self.code.id(),
+            self.friendly_message(src, lang)
+        )
+    }
+
+    /// The diagnostic as JSON, with the message in `lang`.
+    pub fn to_json(&self, lang: Lang) -> String {
+        format!(
+            "{{\"severity\":{},\"code\":{},\"message\":{},\"range\":{}}}",
+            json::string(&self.severity.to_string()),
+            json::string(self.code.id()),
+            json::string(&self.message(lang)),
+            json::span(&self.span)
+        )
+    }
+}
+
+/// How a token the parser expected is written, and when it's usually missing.
+fn expected_token(want: &str, lang: Lang) -> (&str, &'static str) {
+    match (want, lang) {
+        ("(", Lang::En) => (
+            "(",
+            "Did you leave out the parentheses around a condition? Like `if (x > 1) { ... }`.",
+        ),
+        ("(", Lang::Zh) => (
+            "(",
+            "你是不是少写了条件括号？比如 `姐妹们觉得呢 (x > 1) { ... }`。",
+        ),
+        (")", Lang::En) => (")", "The parentheses don't pair up; is a `)` missing?"),
+        (")", Lang::Zh) => (")", "括号没有配对，是不是少了 `)`？"),
+        ("{", Lang::En) => ("{", "A block of code starts with `{`."),
+        ("{", Lang::Zh) => ("{", "一段代码要用 `{` 开头。"),
+        ("}", Lang::En) => ("}", "A block or hash isn't closed; is a `}` missing?"),
+        ("}", Lang::Zh) => ("}", "代码块或哈希没有闭合，是不是少了 `}`？"),
+        ("]", Lang::En) => ("]", "An array or index isn't closed; is a `]` missing?"),
+        ("]", Lang::Zh) => ("]", "数组或下标没有闭合，是不是少了 `]`？"),
+        ("=", Lang::En) => ("=", "A new name needs a value: `let x = 1;`."),
+        ("=", Lang::Zh) => ("=", "起了名字要用 `=` 给它一个值：`宝宝你是一个 x = 1;`。"),
+        (":", Lang::En) => (":", "Keys and values in a hash are separated by `:`."),
+        (":", Lang::Zh) => (":", "哈希里的键和值之间要写 `:`。"),
+        (",", Lang::En) => (",", "Items are separated by `,`."),
+        (",", Lang::Zh) => (",", "多个元素之间要用 `,` 隔开。"),
+        ("想要你一个态度", Lang::En) => ("fn", "`async` only goes before a function."),
+        ("想要你一个态度", Lang::Zh) => ("想要你一个态度", "`慢慢来` 后面只能跟函数。"),
+        (want, _) => (want, ""),
+    }
+}
+
+/// A guess at why `text` is where a value should be.
+fn misplaced_token(text: &str, lang: Lang) -> &'static str {
+    let token = KEYWORDS
+        .iter()
+        .find(|(keyword, _)| *keyword == text)
+        .map(|(_, token)| token);
+    match (text, token, lang) {
+        (")" | "]" | "}" | ";" | ",", _, Lang::En) => {
+            "Is something missing before it, or is there an extra operator?"
+        }
+        (")" | "]" | "}" | ";" | ",", _, Lang::Zh) => {
+            "前面是不是漏写了什么，或者多写了一个运算符？"
+        }
+        (_, Some(Token::Else), Lang::En) => "`else` has to follow the `}` of an `if`.",
+        (_, Some(Token::Else), Lang::Zh) => {
+            "`那能一样吗` 要紧跟在 `姐妹们觉得呢 (...) { ... }` 的 `}` 后面。"
+        }
+        (_, Some(Token::Let | Token::Return | Token::Break | Token::Continue), Lang::En) => {
+            "Statements like this one can't be used as a value; is a `;` missing before it?"
+        }
+        (_, Some(Token::Let | Token::Return | Token::Break | Token::Continue), Lang::Zh) => {
+            "这是一句完整的语句，不能当成值用，前面是不是少了 `;`？"
+        }
+        _ => "",
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.render(Lang::En))
+    }
+}
+
+impl From<&ParseError> for Diagnostic {
+    fn from(err: &ParseError) -> Self {
+        let code = match err {
+            ParseError::UnexpectedToken {
+                want: Some(want),
+                text,
+                ..
+            } => DiagnosticCode::UnexpectedToken {
+                want: want.to_string(),
+                got: text.clone(),
+            },
+            ParseError::UnexpectedToken {
+                want: None, text, ..
+            } => DiagnosticCode::NoPrefixRule { got: text.clone() },
+            ParseError::HerUnexpectedToken { rule, .. } => {
+                DiagnosticCode::SyntaxError { rule: rule.clone() }
+            }
+            ParseError::TooLong { .. } => DiagnosticCode::TooLong,
+            ParseError::Lex(LexError::InvalidEscape { escape, .. }) => {
+                DiagnosticCode::InvalidEscape {
+                    escape: escape.clone(),
+                }
+            }
+            ParseError::Lex(LexError::UnterminatedString { .. }) => {
+                DiagnosticCode::UnterminatedString
+            }
+            ParseError::Lex(LexError::UnterminatedComment { .. }) => {
+                DiagnosticCode::UnterminatedComment
+            }
+            ParseError::Lex(LexError::IntegerTooLarge { literal, .. }) => {
+                DiagnosticCode::IntegerOverflow {
+                    expr: literal.clone(),
+                }
+            }
+            ParseError::Lex(LexError::UnknownVersion { version, .. }) => {
+                DiagnosticCode::UnknownVersion {
+                    version: version.clone(),
+                }
+            }
+            ParseError::MacroArity {
+                name, want, got, ..
+            } => DiagnosticCode::MacroArgumentCount {
+                name: name.clone(),
+                want: *want,
+                got: *got,
+            },
+            ParseError::NotAllowed { .. } => DiagnosticCode::NotAllowed,
+        };
+
+        Diagnostic::error(code, err.span())
+    }
+}
+
+/// Lexes and parses `input`, collecting everything worth a squiggly line:
+/// parse errors, or lint warnings if it parses.
+#[cfg(feature = "diagnostics")]
+pub fn check(input: &str) -> Vec<Diagnostic> {
+    check_with(Parser::new(Lexer::new(input)))
+}
+
+/// Like `check`, with a parser the caller has set up.
+#[cfg(feature = "diagnostics")]
+pub fn check_with(parser: Parser) -> Vec<Diagnostic> {
+    let mut parser = parser.with_positions();
+    let program = parser.parse();
+    let errors = parser.get_errors();
+    let warnings = parser
+        .get_warnings()
+        .iter()
+        .map(|err| Diagnostic {
+            severity: Severity::Warning,
+            ..Diagnostic::from(err)
+        })
+        .collect::<Vec<_>>();
+
+    if errors.is_empty() {
+        warnings
+            .into_iter()
+            .chain(lint::check_program(&program))
+            .collect()
+    } else {
+        errors
+            .iter()
+            .map(Diagnostic::from)
+            .chain(warnings)
+            .collect()
+    }
+}
+
+pub fn to_json(diagnostics: &[Diagnostic], lang: Lang) -> String {
+    json::array(diagnostics.iter().map(|d| d.to_json(lang)))
+}
+
+/// What each code means and how to fix it, with an example: `(id, en, zh)`.
+const EXPLANATIONS: &[(&str, &str, &str)] = &[
+    (
+        "HER0001",
+        "A token other than the one the grammar needs here was found, usually a \
+         missing `)`, `}` or `;`.\n\n    let x = (1 + 2;\n\nClose the parenthesis: `let x = (1 + 2);`",
+        "这里语法上需要某个符号，却遇到了别的，常见的是少了 `)`、`}` 或 `;`。\n\n    \
+         let x = (1 + 2;\n\n补上右括号：`let x = (1 + 2);`",
+    ),
+    (
+        "HER0002",
+        "An expression can't start with this token.\n\n    let x = ;\n\nGive the \
+         binding a value: `let x = 1;`",
+        "表达式不能以这个符号开头。\n\n    let x = ;\n\n给变量一个值：`let x = 1;`",
+    ),
+    (
+        "HER0003",
+        "herlang's own syntax rules were broken, like defining a name that is \
+         reserved for women.\n\n    let 女性 = 2;\n\nPick another name.",
+        "违反了 herlang 自己的语法规定，比如定义了保留给女性的名字。\n\n    let 女性 = 2;\n\n\
+         换个名字。",
+    ),
+    (
+        "HER0004",
+        "The code is too long for the parser's budget, so it stopped early. The \
+         playground limits input length and parser steps.\n\nSplit the program up, \
+         or raise the limits when embedding.",
+        "代码太长，超出了解析器的预算，只好提前停下。playground 限制了输入长度和解析步数。\n\n\
+         把程序拆小，或者嵌入时调高限制。",
+    ),
+    (
+        "HER0005",
+        "Code after `return`, `break` or `continue` in the same block never runs.\n\n    \
+         fn() { return 1; 2; }\n\nRemove it, or move it before the jump.",
+        "同一个代码块里 `return`、`break` 或 `continue` 后面的代码永远不会执行。\n\n    \
+         fn() { return 1; 2; }\n\n删掉它，或者挪到跳转前面。",
+    ),
+    (
+        "HER0006",
+        "Dividing by zero is an error at runtime.\n\n    1 / 0;\n\nCheck the divisor \
+         first: `if (n != 0) { 1 / n }`",
+        "运行时除以零会出错。\n\n    1 / 0;\n\n先检查除数：`if (n != 0) { 1 / n }`",
+    ),
+    (
+        "HER0007",
+        "Integer arithmetic goes beyond 64 bits. Between constants it's reported \
+         before running, and so is an integer literal that is too large, like \
+         `9223372036854775808`.\n\n    9223372036854775807 + 1;\n\nUse smaller numbers.",
+        "整数运算超出了 64 位。常量之间的运算在运行前就会报出来，太大的整数字面量也一样，\
+         比如 `9223372036854775808`。\n\n    9223372036854775807 + 1;\n\n换小一点的数。",
+    ),
+    (
+        "HER0008",
+        "Indexing an array literal past its end always gives null.\n\n    [1, 2][5];\n\n\
+         Use an index below `len(array)`.",
+        "数组字面量的下标超出了末尾，结果总是 null。\n\n    [1, 2][5];\n\n下标要小于 `len(数组)`。",
+    ),
+    (
+        "HER0009",
+        "The operator doesn't work between these two types.\n\n    1 + \"a\";\n\nConvert \
+         one side first: `str(1) + \"a\"`",
+        "这个运算符不能用在这两种类型之间。\n\n    1 + \"a\";\n\n先转换一边：`str(1) + \"a\"`",
+    ),
+    (
+        "HER0010",
+        "The prefix operator doesn't work on this type.\n\n    -true;\n\nOnly numbers \
+         can be negated; use `!` for bools.",
+        "这个前缀运算符不能用在这种类型上。\n\n    -true;\n\n只有数字能取负，布尔值用 `!`。",
+    ),
+    (
+        "HER0011",
+        "The operator isn't defined for these operands, even though their types \
+         match.\n\n    \"a\" - \"b\";\n\nStrings only support `+` and comparisons.",
+        "这两个操作数类型一样，但不支持这个运算。\n\n    \"a\" - \"b\";\n\n字符串只支持 `+` 和比较。",
+    ),
+    (
+        "HER0012",
+        "Only arrays (by int) and hashes (by key) can be indexed.\n\n    1[0];\n\n\
+         Index an array or a hash instead.",
+        "只有数组（用整数）和哈希（用键）能取下标。\n\n    1[0];\n\n换成数组或哈希。",
+    ),
+    (
+        "HER0013",
+        "Hash keys must be ints, strings or bools.\n\n    {[1]: 2};\n\nUse a string \
+         key, like `str([1])`.",
+        "哈希的键只能是整数、字符串或布尔值。\n\n    {[1]: 2};\n\n换成字符串键，比如 `str([1])`。",
+    ),
+    (
+        "HER0014",
+        "Only functions can be called.\n\n    let x = 1; x();\n\nCheck that the name \
+         refers to a function.",
+        "只有函数能被调用。\n\n    let x = 1; x();\n\n检查这个名字是不是函数。",
+    ),
+    (
+        "HER0015",
+        "The function was called with a different number of arguments than it \
+         takes.\n\n    let f = fn(a, b) { a }; f(1);\n\nPass every parameter: `f(1, 2)`",
+        "调用函数时给的参数个数和它要的不一样。\n\n    let f = fn(a, b) { a }; f(1);\n\n\
+         每个参数都要给：`f(1, 2)`",
+    ),
+    (
+        "HER0016",
+        "A `\\` in a string starts an escape, and this one isn't known.\n\n    \
+         \"a\\qb\";\n\nThe escapes are `\\n`, `\\r`, `\\t`, `\\0`, `\\\\`, `\\\"`, `\\'`, \
+         `\\x41` (up to `\\x7F`) and `\\u{4F60}`. Write `\\\\` for a backslash.",
+        "字符串里的 `\\` 表示转义，这个转义不认识。\n\n    \"a\\qb\";\n\n支持的转义有 `\\n`、`\\r`、\
+         `\\t`、`\\0`、`\\\\`、`\\\"`、`\\'`、`\\x41`（最大 `\\x7F`）和 `\\u{4F60}`。\
+         反斜杠本身要写成 `\\\\`。",
+    ),
+    (
+        "HER0017",
+        "The string has no closing quote, so it runs to the end of the file.\n\n    \
+         let s = \"abc;\n\nAdd the missing `\"`: `let s = \"abc\";`",
+        "字符串没有结尾的引号，一直延续到了文件末尾。\n\n    let s = \"abc;\n\n补上引号：`let s = \"abc\";`",
+    ),
+    (
+        "HER0018",
+        "A `/*` comment has no matching `*/`, so the rest of the file is commented \
+         out. Block comments nest, so every `/*` inside needs its own `*/`.\n\n    \
+         /* a /* b */ 1;\n\nClose both: `/* a /* b */ */ 1;`",
+        "`/*` 注释没有对应的 `*/`，后面的代码全被注释掉了。块注释可以嵌套，里面的每个 `/*` \
+         都要有自己的 `*/`。\n\n    /* a /* b */ 1;\n\n两层都要关上：`/* a /* b */ */ 1;`",
+    ),
+    (
+        "HER0019",
+        "A macro made with `造个句式` is used with a different number of arguments \
+         than its template has parameters.\n\n    造个句式 twice(x) => { x + x } twice(1, 2);\n\n\
+         Pass one argument per parameter: `twice(1)`",
+        "用 `造个句式` 造的句式，调用时给的参数个数和模板的参数个数对不上。\n\n    \
+         造个句式 twice(x) => { x + x } twice(1, 2);\n\n每个参数给一个值：`twice(1)`",
+    ),
+    (
+        "HER0020",
+        "The host only takes a formula here, like `price * 0.8`, and this is \
+         syntax it turned off with `SyntaxConfig`: `let`, loops, functions, \
+         `引入`, `考一考`, or a second statement. Write it as one expression, \
+         using `if` or `match` for choices.",
+        "这里只许写算式，比如 `price * 0.8`，这段代码用到了嵌入方用 `SyntaxConfig` 关掉的语法：\
+         `let`、循环、函数、`引入`、`考一考` 或者第二条语句。写成一个表达式就好，\
+         要分情况就用 `if` 或 `match`。",
+    ),
+    (
+        "HER0021",
+        "A name is used at runtime that no `let`, parameter or import defined, \
+         often a typo like `lenght` for `len`.\n\nDefine it first, or fix the \
+         spelling.",
+        "运行时用到了一个没有被 `let`、参数或引入定义过的名字，常见的是拼写错误，\
+         比如把 `len` 写成 `lenght`。\n\n先定义它，或者改正拼写。",
+    ),
+    (
+        "HER0022",
+        "`herlang check` couldn't read a file it was given, or one in a \
+         directory it was given: it doesn't exist, isn't readable, or isn't \
+         UTF-8. The other files are still checked.",
+        "`herlang check` 读不了传给它的某个文件（或目录里的某个文件）：文件不存在、\
+         没有读权限，或者不是 UTF-8 编码。其他文件照样检查。",
+    ),
+    (
+        "HER0023",
+        "A `match` on a bool leaves out `true` or `false`, and has no `_` arm. \
+         When the value isn't covered, the match gives null.\n\n    \
+         match 1 > 2 { true => \"yes\" };\n\nAdd an arm for the missing value, or a `_` arm.",
+        "对布尔值的 `下头菜单` 少了 `true` 或 `false` 的分支，也没有 `_` 分支。\
+         遇到没列出的值时结果是 null。\n\n    \
+         match 1 > 2 { true => \"yes\" };\n\n给缺的值加一个分支，或者加一个 `_` 分支。",
+    ),
+    (
+        "HER0024",
+        "The `#她版本` header on the first line names a language version this \
+         build doesn't know. Versions go from 1 to the newest one.\n\n    \
+         #她版本 9\n\nWrite a known version, like `#她版本 1`, or leave the \
+         header out to get the newest.",
+        "第一行的 `#她版本` 写了一个这个版本的 herlang 不认识的语言版本，只能是 1 \
+         到最新版。\n\n    #她版本 9\n\n写一个认识的版本，比如 `#她版本 1`，\
+         或者去掉这一行，用最新版。",
+    ),
+    (
+        "HER0025",
+        "Once a parameter has a default, every parameter after it needs one \
+         too, since arguments are passed by position.\n\n    \
+         fn(a = 1, b) { a + b };\n\nGive the later ones a default, or move the \
+         ones with defaults to the end: `fn(b, a = 1) { a + b }`",
+        "参数一旦有了默认值，后面的参数也都要有，因为参数是按位置传的。\n\n    \
+         fn(a = 1, b) { a + b };\n\n给后面的参数也加上默认值，或者把有默认值的参数挪到最后：\
+         `fn(b, a = 1) { a + b }`",
+    ),
+    (
+        "HER0026",
+        "A `match` arm starts with something that isn't a pattern. Patterns \
+         are numbers, strings, bools, names, `_`, and arrays or hashes of \
+         patterns; an expression like `(1 + 1)` isn't one.\n\n    \
+         match 2 { (1 + 1) => \"two\" };\n\nMatch on the value itself, `2 => \"two\"`, \
+         or bind a name and test it with `if` in the arm.",
+        "`下头菜单` 的分支开头写的不是模式。模式可以是数字、字符串、布尔值、名字、`_`，\
+         以及由模式组成的数组或哈希；`(1 + 1)` 这样的表达式不行。\n\n    \
+         match 2 { (1 + 1) => \"two\" };\n\n直接写值 `2 => \"two\"`，或者绑定一个名字，\
+         在分支里用 `if` 判断。",
+    ),
+];
+
+/// The long explanation of a code like `HER0006` for `herlang explain`, in
+/// `lang`. `None` if there is no such code.
+pub fn explain(id: &str, lang: Lang) -> Option<&'static str> {
+    EXPLANATIONS
+        .iter()
+        .find(|(code, ..)| code.eq_ignore_ascii_case(id))
+        .map(|(_, en, zh)| match lang {
+            Lang::En => *en,
+            Lang::Zh => *zh,
+        })
+}
+
+#[cfg(all(test, feature = "diagnostics"))]
+mod tests {
+    use crate::diagnostic::*;
+
+    #[test]
+    fn test_check() {
+        assert_eq!(Vec::<Diagnostic>::new(), check("宝宝你是一个 x = 1;"));
+
+        let diagnostics = check("宝宝你是一个 x = 1;\nlet 女性 = 2;");
+        assert_eq!(1, diagnostics.len());
+        assert_eq!(Severity::Error, diagnostics[0].severity);
+        assert_eq!(
+            (2, 5, 2, 7),
+            (
+                diagnostics[0].span.start.line,
+                diagnostics[0].span.start.column,
+                diagnostics[0].span.end.line,
+                diagnostics[0].span.end.column,
+            )
+        );
+
+        let diagnostics = check("let x = 99999999999999999999;");
+        assert_eq!(
+            vec!["1:9: error[HER0007]: integer overflow: 99999999999999999999"],
+            diagnostics
+                .iter()
+                .map(|d| d.to_string())
+                .collect::<Vec<_>>()
+        );
+
+        let diagnostics = check("if (x) { )");
+        assert_eq!(10, diagnostics[0].span.start.column);
+
+        let diagnostics = check("fn() { return 1; 2; }");
+        assert_eq!(
+            vec![Severity::Warning],
+            diagnostics.iter().map(|d| d.severity).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_lang() {
+        let diagnostics = check("1 / 0;\nlet 女性 = 2;");
+        assert_eq!(
+            "2:5: 错误[HER0003]: 啊啊啊啊啊啊啊啊啊啊啊啊 语法错误：女性是不能被定义的！！！",
+            diagnostics[0].render(Lang::Zh)
+        );
+
+        assert_eq!(
+            "2:5: error[HER0003]: 啊啊啊啊啊啊啊啊啊啊啊啊 SyntaxError: women can't be defined!!!",
+            diagnostics[0].render(Lang::En)
+        );
+
+        let diagnostics = check("1 / 0;");
+        assert_eq!(
+            "1:1: error[HER0006]: division by zero",
+            diagnostics[0].to_string()
+        );
+        assert_eq!(
+            "1:1: 错误[HER0006]: 除数为零",
+            diagnostics[0].render(Lang::Zh)
+        );
+
+        // Keywords are quoted the way the code spells them.
+        let diagnostics = check("let x = else;");
+        assert_eq!(
+            "1:9: error[HER0002]: 啊啊啊啊啊啊啊啊啊啊啊啊 Unexpected Token: no prefix rule for `else`",
+            diagnostics[0].to_string()
+        );
+        let diagnostics = check("match 1 { if => 1 }");
+        assert!(
+            diagnostics[0].to_string().contains("`if` is not a pattern"),
+            "{diagnostics:?}"
+        );
+        let diagnostics = check("if x");
+        assert_eq!(
+            "1:4: error[HER0001]: 啊啊啊啊啊啊啊啊啊啊啊啊 Unexpected Token: expected `(`, got `x`",
+            diagnostics[0].to_string()
+        );
+
+        assert_eq!(Ok(Lang::Zh), "zh".parse());
+        assert!("fr".parse::<Lang>().is_err());
+    }
+
+    #[test]
+    fn test_friendly_message() {
+        let tests = vec![
+            (
+                "姐妹们觉得呢 那咋了 { 1 }",
+                Lang::Zh,
+                "这里应该写 `(`，却写了 `那咋了`。你是不是少写了条件括号？比如 `姐妹们觉得呢 (x > 1) { ... }`。",
+            ),
+            (
+                "let x = [1, 2;",
+                Lang::Zh,
+                "这里应该写 `]`，却写了 `;`。数组或下标没有闭合，是不是少了 `]`？",
+            ),
+            (
+                "let x = ;",
+                Lang::Zh,
+                "这里不能直接放 `;`，应该是一个值，比如数字、字符串或名字。前面是不是漏写了什么，或者多写了一个运算符？",
+            ),
+            (
+                "let x = 1 +",
+                Lang::Zh,
+                "代码没写完就结束了，后面还缺一个值。",
+            ),
+            (
+                "1 + 那能一样吗",
+                Lang::En,
+                "`那能一样吗` can't go here; a value like a number, a string or a name should. `else` has to follow the `}` of an `if`.",
+            ),
+            ("let 女性 = 2;", Lang::Zh, "女性是不能被定义的！！！"),
+            ("let 女性 = 2;", Lang::En, "women can't be defined!!!"),
+            (
+                "fn(a = 1, b) { a }",
+                Lang::Zh,
+                "参数 `b` 也要有默认值，和它前面的参数一样",
+            ),
+            ("\"abc", Lang::En, "unterminated string"),
+        ];
+
+        for (src, lang, expect) in tests {
+            assert_eq!(expect, check(src)[0].friendly_message(src, lang), "{src}");
+        }
+
+        let src = "let x = ;";
+        assert_eq!(
+            "1:9: error[HER0002]: `;` can't go here; a value like a number, a string or a name should. Is something missing before it, or is there an extra operator?",
+            check(src)[0].render_friendly(src, Lang::En)
+        );
+    }
+
+    #[test]
+    fn test_to_json() {
+        let diagnostics = check("let x = ;");
+
+        assert_eq!(
+            r#"[{"severity":"error","code":"HER0002","message":"啊啊啊啊啊啊啊啊啊啊啊啊 Unexpected Token: no prefix rule for `;`","range":{"start":{"offset":8,"line":1,"column":9},"end":{"offset":9,"line":1,"column":10}}}]"#,
+            to_json(&diagnostics, Lang::En)
+        );
+
+        let diagnostics = check("1 / 0;");
+        assert!(to_json(&diagnostics, Lang::Zh).contains(r#""message":"除数为零""#));
+    }
+
+    #[test]
+    fn test_explain() {
+        let explanation = explain("HER0006", Lang::En).unwrap();
+        assert!(explanation.starts_with("Dividing by zero"));
+        assert!(
+            explain("her0006", Lang::Zh)
+                .unwrap()
+                .starts_with("运行时除以零")
+        );
+        assert_eq!(None, explain("HER9999", Lang::En));
+
+        for (i, (id, ..)) in EXPLANATIONS.iter().enumerate() {
+            assert_eq!(format!("HER{:04}", i + 1), *id);
+        }
+        assert_eq!(Some(&"HER0026"), EXPLANATIONS.last().map(|(id, ..)| id));
+
+        // Each example reports the code it explains.
+        for (id, en, _) in EXPLANATIONS {
+            let Some(example) = en.lines().find_map(|line| line.strip_prefix("    ")) else {
+                continue;
+            };
+            let mut diagnostics = check(example);
+            diagnostics.extend(crate::typecheck::check(example));
+            assert!(
+                diagnostics.iter().any(|d| d.code.id() == *id),
+                "{id}: {diagnostics:?}"
+            );
+        }
+    }
+}
@@ -0,0 +1,493 @@
+//! One place for embedders to set things up once and run code: parsing,
+//! evaluation and diagnostics with the same options.
+
+use crate::diagnostic::{self, Diagnostic, Lang};
+use crate::evaluator::Evaluator;
+use crate::evaluator::builtins::new_builtins;
+use crate::evaluator::debug::BreakpointHook;
+use crate::evaluator::env::Env;
+use crate::evaluator::object::{Object, ReprLimit};
+use crate::evaluator::sandbox::SandboxConfig;
+use crate::evaluator::shared::Shared;
+use crate::lexer::{LANGUAGE_VERSION, Lexer};
+use crate::parser::syntax::SyntaxConfig;
+use crate::parser::{HerKeyWordPolicy, Parser};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+/// The top-level bindings left after running a program, without the
+/// builtins. A host can run a script as configuration and read it back.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Snapshot {
+    bindings: BTreeMap<String, Object>,
+}
+
+impl Snapshot {
+    pub fn get(&self, name: &str) -> Option<&Object> {
+        self.bindings.get(name)
+    }
+
+    /// Bindings in name order.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Object)> {
+        self.bindings
+            .iter()
+            .map(|(name, value)| (name.as_str(), value))
+    }
+
+    pub fn len(&self) -> usize {
+        self.bindings.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bindings.is_empty()
+    }
+}
+
+pub struct Engine {
+    evaluator: Evaluator,
+    lang: Lang,
+    her_key_words: HerKeyWordPolicy,
+    version: u32,
+    repr_limit: ReprLimit,
+    syntax: SyntaxConfig,
+}
+
+impl Default for Engine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Engine {
+    /// An engine with the builtins and English diagnostics.
+    pub fn new() -> Self {
+        let env = Env::from(new_builtins());
+
+        Engine {
+            evaluator: Evaluator::new(Shared::new(env)),
+            lang: Lang::default(),
+            her_key_words: HerKeyWordPolicy::default(),
+            version: LANGUAGE_VERSION,
+            repr_limit: ReprLimit::default(),
+            syntax: SyntaxConfig::default(),
+        }
+    }
+
+    /// Words diagnostics and runtime errors in `lang`.
+    pub fn with_lang(mut self, lang: Lang) -> Self {
+        self.lang = lang;
+        self.evaluator = self.evaluator.with_lang(lang);
+        self
+    }
+
+    pub fn lang(&self) -> Lang {
+        self.lang
+    }
+
+    /// Whether binding one of `HER_KEY_WORDS` is an error, a warning or fine.
+    pub fn with_her_key_word_policy(mut self, policy: HerKeyWordPolicy) -> Self {
+        self.her_key_words = policy;
+        self
+    }
+
+    /// Runs programs as language `version` unless they start with a
+    /// `#她版本` header, e.g. to keep old scripts working. See
+    /// `LANGUAGE_VERSION`.
+    pub fn with_language_version(mut self, version: u32) -> Self {
+        self.version = version;
+        self.evaluator = self.evaluator.with_language_version(version);
+        self
+    }
+
+    /// Makes `name` read as `value` wherever the program doesn't bind it,
+    /// like the built-in `微胖`.
+    pub fn with_easter_egg(mut self, name: impl Into<String>, value: Object) -> Self {
+        self.evaluator = self.evaluator.with_easter_egg(name, value);
+        self
+    }
+
+    /// Binds each name to its value for every program this engine runs,
+    /// e.g. a version or the current user. Scripts can read them but
+    /// rebinding one with `let`, as a parameter or deleting it with `del` is
+    /// an error. They are left out of `snapshot`.
+    pub fn with_globals<K: Into<String>>(
+        mut self,
+        globals: impl IntoIterator<Item = (K, Object)>,
+    ) -> Self {
+        for (name, value) in globals {
+            self.evaluator = self.evaluator.with_constant(name, value);
+        }
+        self
+    }
+
+    /// Cuts long strings and big arrays or hashes short in error messages
+    /// and in `repr`, see `ReprLimit`.
+    pub fn with_repr_limit(mut self, limit: ReprLimit) -> Self {
+        self.repr_limit = limit;
+        self.evaluator = self.evaluator.with_repr_limit(limit);
+        self
+    }
+
+    /// See `Evaluator::with_max_depth`.
+    pub fn with_max_depth(mut self, depth: usize) -> Self {
+        self.evaluator = self.evaluator.with_max_depth(depth);
+        self
+    }
+
+    /// How `value` should be shown to the user, within the engine's
+    /// `ReprLimit`.
+    pub fn repr(&self, value: &Object) -> String {
+        value.repr(self.repr_limit)
+    }
+
+    /// Limits what programs run by this engine may do, see `SandboxConfig`.
+    pub fn with_sandbox(mut self, sandbox: SandboxConfig) -> Self {
+        self.evaluator = self.evaluator.with_sandbox(sandbox);
+        self
+    }
+
+    /// Limits the syntax programs may use, e.g. to a single expression with
+    /// `SyntaxConfig::expression()`. Anything else doesn't parse.
+    pub fn with_syntax(mut self, syntax: SyntaxConfig) -> Self {
+        self.syntax = syntax;
+        self
+    }
+
+    /// Lets `breakpoint()` stop the program and hand its state to `hook`,
+    /// see `Evaluator::with_breakpoint`.
+    pub fn with_breakpoint(mut self, hook: BreakpointHook) -> Self {
+        self.evaluator = self.evaluator.with_breakpoint(hook);
+        self
+    }
+
+    /// Where `引入` looks for relative paths, usually the script's folder.
+    pub fn with_base_dir(mut self, base_dir: impl Into<PathBuf>) -> Self {
+        self.evaluator = self.evaluator.with_base_dir(base_dir);
+        self
+    }
+
+    /// Binds `参数` and `args` to `args` as an array of strings, for scripts
+    /// run from the command line.
+    pub fn with_args<S: Into<String>>(self, args: impl IntoIterator<Item = S>) -> Self {
+        let args = Object::Array(
+            args.into_iter()
+                .map(|arg| Object::String(arg.into()))
+                .collect::<Vec<_>>()
+                .into(),
+        );
+        {
+            let mut env = self.evaluator.env.borrow_mut();
+            env.define(String::from("参数"), &args);
+            env.define(String::from("args"), &args);
+        }
+        self
+    }
+
+    fn parser(&self, input: &str) -> Parser {
+        Parser::new(Lexer::new(input).with_version(self.version))
+            .with_her_key_word_policy(self.her_key_words)
+            .with_syntax(self.syntax)
+    }
+
+    /// Parses and runs `input`. Bindings stay around for the next call, like
+    /// in the REPL. A program that doesn't parse is not run at all.
+    pub fn eval(&mut self, input: &str) -> Result<Option<Object>, Vec<Diagnostic>> {
+        let mut parser = self.parser(input).with_positions();
+        let program = parser.parse();
+        let errors = parser.get_errors();
+        if !errors.is_empty() {
+            return Err(errors.iter().map(Diagnostic::from).collect());
+        }
+
+        Ok(self.evaluator.eval(&program))
+    }
+
+    /// The bindings the programs run so far have left at the top level.
+    pub fn snapshot(&self) -> Snapshot {
+        let bindings = self
+            .evaluator
+            .env
+            .borrow()
+            .store
+            .iter()
+            .filter(|(name, value)| {
+                !matches!(value, Object::Builtin(..) | Object::EnvBuiltin(_))
+                    && !self.evaluator.is_constant(name)
+            })
+            .map(|(name, value)| (name.clone(), value.clone()))
+            .collect();
+
+        Snapshot { bindings }
+    }
+
+    /// Same as `diagnostic::check`, with the engine's parser options.
+    #[cfg(feature = "diagnostics")]
+    pub fn check(&self, input: &str) -> Vec<Diagnostic> {
+        diagnostic::check_with(self.parser(input))
+    }
+
+    /// Renders `diagnostic` in the engine's language.
+    pub fn render(&self, diagnostic: &Diagnostic) -> String {
+        diagnostic.render(self.lang)
+    }
+
+    /// `diagnostic::to_json` in the engine's language.
+    pub fn to_json(&self, diagnostics: &[Diagnostic]) -> String {
+        diagnostic::to_json(diagnostics, self.lang)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(feature = "diagnostics")]
+    use crate::diagnostic::Severity;
+    use crate::engine::*;
+
+    #[test]
+    fn test_engine() {
+        let mut engine = Engine::new();
+
+        assert_eq!(Ok(None), engine.eval("宝宝你是一个 x = 1;"));
+        assert_eq!(Ok(Some(Object::Int(2))), engine.eval("x + 1"));
+
+        let mut engine = Engine::new().with_her_key_word_policy(HerKeyWordPolicy::Warning);
+        assert_eq!(Ok(None), engine.eval("let her = 1;"));
+        assert!(Engine::new().eval("let her = 1;").is_err());
+
+        let mut engine = Engine::new().with_sandbox(SandboxConfig::locked());
+        assert_eq!(
+            Ok(Some(Object::Error(String::from("没权限，想peach")))),
+            engine.eval("quit();")
+        );
+    }
+
+    #[cfg(feature = "diagnostics")]
+    #[test]
+    fn test_check() {
+        let engine = Engine::new().with_lang(Lang::Zh);
+        let diagnostics = engine.check("1 / 0;");
+        assert_eq!(
+            "1:1: 错误[HER0006]: 除数为零",
+            engine.render(&diagnostics[0])
+        );
+
+        let engine = Engine::new().with_her_key_word_policy(HerKeyWordPolicy::Warning);
+        assert_eq!(
+            vec![Severity::Warning],
+            engine
+                .check("let her = 1;")
+                .iter()
+                .map(|d| d.severity)
+                .collect::<Vec<_>>()
+        );
+
+        let engine = Engine::new().with_syntax(SyntaxConfig::expression());
+        assert_eq!(1, engine.check("fn() { 1 }").len());
+    }
+
+    #[test]
+    fn test_snapshot() {
+        let mut engine = Engine::new();
+        assert!(engine.snapshot().is_empty());
+
+        engine
+            .eval("宝宝你是一个 port = 8080; let name = \"her\"; let len = fn(x) { x };")
+            .unwrap();
+        let snapshot = engine.snapshot();
+
+        assert_eq!(3, snapshot.len());
+        assert_eq!(Some(&Object::Int(8080)), snapshot.get("port"));
+        assert_eq!(
+            Some(&Object::String(String::from("her"))),
+            snapshot.get("name")
+        );
+        assert_eq!(
+            vec!["len", "name", "port"],
+            snapshot.iter().map(|(name, _)| name).collect::<Vec<_>>()
+        );
+        assert_eq!(None, snapshot.get("puts"));
+    }
+
+    #[test]
+    fn test_args() {
+        let mut engine = Engine::new().with_args(["a", "b c"]);
+
+        assert_eq!(
+            Ok(Some(Object::String(String::from("b c")))),
+            engine.eval("参数[1]")
+        );
+        assert_eq!(Ok(Some(Object::Int(2))), engine.eval("len(args)"));
+        assert_eq!(
+            Ok(Some(Object::Array(vec![].into()))),
+            Engine::new().with_args(Vec::<String>::new()).eval("参数")
+        );
+    }
+
+    #[test]
+    fn test_globals() {
+        let mut engine = Engine::new().with_globals([
+            ("version", Object::String(String::from("1.0"))),
+            ("用户", Object::String(String::from("her"))),
+        ]);
+
+        assert_eq!(
+            Ok(Some(Object::String(String::from("1.0her")))),
+            engine.eval("version + 用户")
+        );
+        assert_eq!(
+            Ok(Some(Object::Error(String::from(
+                "cannot assign to constant version"
+            )))),
+            engine.eval("let version = 2;")
+        );
+        assert_eq!(
+            Ok(Some(Object::Error(String::from(
+                "cannot assign to constant 用户"
+            )))),
+            engine.eval("let f = fn() { let 用户 = 1; }; f()")
+        );
+        assert_eq!(
+            Ok(Some(Object::Error(String::from(
+                "cannot assign to constant version"
+            )))),
+            engine.eval("for (version in [1]) { version }")
+        );
+        assert_eq!(
+            Ok(Some(Object::Error(String::from(
+                "cannot assign to constant version"
+            )))),
+            engine.eval("del(\"version\")")
+        );
+        assert_eq!(
+            Ok(Some(Object::Error(String::from(
+                "cannot assign to constant 用户"
+            )))),
+            engine.eval("fn(用户) { 用户 }(1)")
+        );
+        assert_eq!(
+            Ok(Some(Object::String(String::from("1.0")))),
+            engine.eval("version")
+        );
+        assert_eq!(
+            vec!["f"],
+            engine
+                .snapshot()
+                .iter()
+                .map(|(name, _)| name)
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_lang() {
+        let mut engine = Engine::new().with_lang(Lang::Zh);
+        assert_eq!(
+            Ok(Some(Object::Error(String::from("除数为零")))),
+            engine.eval("1 / 0")
+        );
+        assert_eq!(
+            Ok(Some(Object::Error(String::from(
+                "参数个数不对：需要 1 个，给了 2 个"
+            )))),
+            engine.eval("len(1, 2)")
+        );
+        assert_eq!(
+            Ok(Some(Object::Error(String::from("division by zero")))),
+            Engine::new().eval("1 / 0")
+        );
+    }
+
+    #[test]
+    fn test_repr_limit() {
+        let mut engine = Engine::new().with_repr_limit(ReprLimit {
+            max_items: Some(3),
+            max_len: Some(4),
+        });
+
+        let value = engine
+            .eval("[[0, 1, 2, 3, 4, 5], {\"k\": \"abcdefg\"}]")
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            "[[0, 1, 2, ……共 6 项], {\"k\": \"abcd……共 7 字\"}]",
+            engine.repr(&value)
+        );
+        assert_eq!(
+            Ok(Some(Object::Error(String::from(
+                "unknown operator: [0, 1, 2, ……共 5 项] + 1"
+            )))),
+            engine.eval("[0, 1, 2, 3, 4] + 1")
+        );
+
+        let engine = Engine::new().with_repr_limit(ReprLimit {
+            max_items: Some(0),
+            max_len: None,
+        });
+        assert_eq!("[……共 2 项]", engine.repr(&value));
+        assert_eq!(
+            "[1, 2]",
+            Engine::new().repr(&Object::Array(vec![Object::Int(1), Object::Int(2)].into()))
+        );
+    }
+
+    #[test]
+    fn test_language_version() {
+        let mut engine = Engine::new().with_language_version(1);
+        assert_eq!(
+            Ok(Some(Object::Int(2))),
+            engine.eval("let for = 1; let 在 = 1; for + 在")
+        );
+        assert_eq!(
+            Ok(Some(Object::Int(3))),
+            Engine::new().eval("#她版本 1\nlet loop = 3; loop")
+        );
+        assert!(Engine::new().eval("let loop = 3;").is_err());
+        assert!(
+            Engine::new()
+                .with_language_version(1)
+                .eval("#她版本 2\nlet loop = 3;")
+                .is_err()
+        );
+        assert!(Engine::new().eval("#她版本 3\n1").is_err());
+        assert!(Engine::new().with_language_version(0).eval("1").is_err());
+    }
+
+    #[test]
+    fn test_syntax() {
+        let mut engine = Engine::new()
+            .with_lang(Lang::Zh)
+            .with_syntax(SyntaxConfig::expression())
+            .with_globals([("price", Object::Int(200))]);
+
+        assert_eq!(
+            Ok(Some(Object::Int(180))),
+            engine.eval("if (price > 100) { price - 20 } else { price }")
+        );
+
+        let diagnostics = engine.eval("let x = price; x * 2").unwrap_err();
+        assert_eq!(2, diagnostics.len());
+        assert_eq!(
+            "1:1: 错误[HER0020]: 这里只许写算式",
+            engine.render(&diagnostics[0])
+        );
+        assert_eq!(
+            "这里只许写算式，不能用 `let`。",
+            diagnostics[0].friendly_message("let x = price; x * 2", Lang::Zh)
+        );
+    }
+
+    #[cfg(feature = "sync")]
+    #[test]
+    fn test_send() {
+        let mut engine = Engine::new();
+        engine.eval("let add = fn(a, b) { a + b };").unwrap();
+
+        let mut engine = std::thread::spawn(move || {
+            assert_eq!(Ok(Some(Object::Int(3))), engine.eval("add(1, 2)"));
+            engine
+        })
+        .join()
+        .unwrap();
+        assert_eq!(Ok(Some(Object::Int(5))), engine.eval("add(2, 3)"));
+    }
+}
@@ -1,33 +1,543 @@
+use crate::evaluator::json;
 use crate::evaluator::object::*;
+use crate::evaluator::shared::{Rc, Shared};
+use crate::evaluator::{INTERRUPTED, Interrupt};
+use std::cell::RefCell;
 use std::collections::HashMap;
+use std::io::{self, Write};
+use std::time::Duration;
+
+/// One builtin function and what the REPL's `:help`, completion and the
+/// generated docs say about it.
+pub struct BuiltinDef {
+    pub name: &'static str,
+    /// Aba-aba spellings bound to the same function.
+    pub aliases: &'static [&'static str],
+    /// `Object::type_name`s it can be called on as `value.name(args)`, with
+    /// the value as the first argument; `_` for any.
+    pub receivers: &'static [&'static str],
+    /// Number of arguments, -1 when it varies.
+    pub arity: i32,
+    /// What the arguments are, e.g. `数组, 值`.
+    pub params: &'static str,
+    pub doc: &'static str,
+    value: Object,
+}
+
+impl BuiltinDef {
+    /// `name(params)`.
+    pub fn signature(&self) -> String {
+        format!("{}({})", self.name, self.params)
+    }
+}
+
+/// Every builtin, in the order they are documented. `new_builtins` binds
+/// from it too, so a builtin can't exist without its docs.
+pub struct BuiltinRegistry {
+    defs: Vec<BuiltinDef>,
+}
+
+impl Default for BuiltinRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BuiltinRegistry {
+    pub fn new() -> Self {
+        let mut registry = BuiltinRegistry { defs: vec![] };
+
+        // Monkey builtins
+        registry.add(
+            "len",
+            &[],
+            &["string", "array"],
+            1,
+            "字符串或数组",
+            "字符串的字节数，或者数组的元素个数",
+            monkey_len,
+        );
+        registry.add(
+            "first",
+            &[],
+            &["array"],
+            1,
+            "数组",
+            "第一个元素，空数组是空值",
+            monkey_first,
+        );
+        registry.add(
+            "last",
+            &[],
+            &["array"],
+            1,
+            "数组",
+            "最后一个元素，空数组是空值",
+            monkey_last,
+        );
+        registry.add(
+            "rest",
+            &[],
+            &["array"],
+            1,
+            "数组",
+            "去掉第一个元素后的新数组，空数组是空值",
+            monkey_rest,
+        );
+        registry.add(
+            "push",
+            &[],
+            &["array"],
+            2,
+            "数组, 值",
+            "末尾加上值的新数组，原数组不变",
+            monkey_push,
+        );
+        registry.add(
+            "puts",
+            &["小作文", "家人们"],
+            &[],
+            -1,
+            "值...",
+            "把每个值按字面量的样子各输出一行",
+            her_output,
+        );
+        registry.add(
+            "keys",
+            &[],
+            &["hash"],
+            1,
+            "哈希",
+            "按键排序的所有键",
+            her_keys,
+        );
+        registry.add(
+            "values",
+            &[],
+            &["hash"],
+            1,
+            "哈希",
+            "按键排序的所有值",
+            her_values,
+        );
+        registry.add(
+            "iter",
+            &[],
+            &["string", "array", "hash", "iterator"],
+            1,
+            "数组、字符串或哈希",
+            "从头开始的迭代器，哈希按键排序",
+            her_iter,
+        );
+        registry.add(
+            "next",
+            &[],
+            &["iterator"],
+            1,
+            "迭代器",
+            "下一步，`{\"value\": 值, \"done\": 布尔值}`",
+            her_next,
+        );
+        registry.add_env(
+            "globals",
+            &[],
+            0,
+            "",
+            "顶层变量的快照，一个从名字到值的哈希",
+            EnvBuiltin::Globals,
+        );
+        registry.add_env(
+            "locals",
+            &[],
+            0,
+            "",
+            "当前作用域的变量快照",
+            EnvBuiltin::Locals,
+        );
+        registry.add_env(
+            "del",
+            &[],
+            1,
+            "名字",
+            "从当前作用域删掉变量并返回它的值",
+            EnvBuiltin::Del,
+        );
+        registry.add_env(
+            "breakpoint",
+            &["先别卷了"],
+            0,
+            "",
+            "停下来看看变量，命令行里交互运行时才生效",
+            EnvBuiltin::Breakpoint,
+        );
+        registry.add("callable", &[], &[], 1, "值", "能不能被调用", her_callable);
+        registry.add(
+            "arity",
+            &[],
+            &[],
+            1,
+            "函数",
+            "参数个数，个数不固定的内置函数是空值",
+            her_arity,
+        );
+
+        // herlang builtins
+        registry.add(
+            "assert",
+            &["必须的"],
+            &[],
+            -1,
+            "条件, [说明]",
+            "条件不成立时报错，`考一考` 里用它检查结果",
+            her_assert,
+        );
+        registry.add(
+            "quit",
+            &["哼", "哈"],
+            &[],
+            -1,
+            "[退出码]",
+            "结束程序，退出码默认是 0",
+            her_quit,
+        );
+        registry.add(
+            "print",
+            &["聚焦"],
+            &[],
+            -1,
+            "值..., [{\"sep\", \"end\"}]",
+            "用空格隔开输出，字符串不带引号，最后换行",
+            her_print,
+        );
+        registry.add(
+            "repr",
+            &["复用"],
+            &["_"],
+            1,
+            "值",
+            "值按字面量写出的字符串",
+            her_repr,
+        );
+        registry.add(
+            "str",
+            &["疏通"],
+            &["_"],
+            1,
+            "值",
+            "值的字符串，字符串原样返回",
+            her_str,
+        );
+        registry.add(
+            "atoi",
+            &["抹零"],
+            &["string"],
+            1,
+            "字符串",
+            "把数字字符串解析成整数",
+            her_atoi,
+        );
+        registry.add(
+            "sort",
+            &[],
+            &["array"],
+            1,
+            "数组",
+            "排好序的新数组",
+            her_sort,
+        );
+        registry.add(
+            "sleep",
+            &["躺平"],
+            &[],
+            1,
+            "毫秒",
+            "停一会儿再继续",
+            her_sleep,
+        );
+        registry.add(
+            "parse_float",
+            &[],
+            &["string"],
+            1,
+            "字符串",
+            "把字符串解析成小数，忽略首尾空白",
+            her_parse_float,
+        );
+        registry.add(
+            "to_fixed",
+            &[],
+            &["int", "float"],
+            2,
+            "数字, 位数",
+            "保留固定位数小数的字符串",
+            her_to_fixed,
+        );
+        registry.add(
+            "to_base",
+            &[],
+            &["int"],
+            2,
+            "整数, 进制",
+            "写成 2 到 36 进制的字符串",
+            her_to_base,
+        );
+        registry.add(
+            "from_base",
+            &[],
+            &["string"],
+            2,
+            "字符串, 进制",
+            "按 2 到 36 进制解析成整数",
+            her_from_base,
+        );
+        registry.add(
+            "chr",
+            &[],
+            &["int"],
+            1,
+            "整数",
+            "码点对应的单个字符",
+            her_chr,
+        );
+        registry.add(
+            "ord",
+            &[],
+            &["string"],
+            1,
+            "字符串",
+            "单个字符的 Unicode 码点",
+            her_ord,
+        );
+        registry.add(
+            "template",
+            &["模板"],
+            &["string"],
+            2,
+            "文本, 哈希",
+            "把文本里的 `{键}` 换成哈希里的值",
+            her_template,
+        );
+        registry.add(
+            "json_parse",
+            &[],
+            &["string"],
+            -1,
+            "字符串, [{\"loose\"}]",
+            "把 JSON 文本读成值",
+            her_json_parse,
+        );
+        registry.add(
+            "json_stringify",
+            &[],
+            &["_"],
+            1,
+            "值",
+            "把值写成紧凑的 JSON",
+            her_json_stringify,
+        );
+
+        // Chinese numerals
+        registry.add(
+            "中文数字",
+            &[],
+            &[],
+            1,
+            "整数",
+            "把整数写成中文",
+            her_chinese,
+        );
+        registry.add(
+            "大写金额",
+            &[],
+            &[],
+            1,
+            "整数",
+            "按元写成财务大写数字，以「元整」结尾",
+            her_amount,
+        );
+
+        // Array helpers
+        registry.add(
+            "flat",
+            &["拍平"],
+            &["array"],
+            1,
+            "数组",
+            "把里面的数组摊开一层",
+            her_flat,
+        );
+        registry.add(
+            "zip",
+            &["组CP"],
+            &["array"],
+            2,
+            "数组, 数组",
+            "两两配对成 `[a, b]`，按短的那个算",
+            her_zip,
+        );
+        registry.add(
+            "enumerate",
+            &["报数"],
+            &["array"],
+            1,
+            "数组",
+            "变成 `[下标, 元素]`，下标从 0 开始",
+            her_enumerate,
+        );
+        registry.add(
+            "unique",
+            &["去重"],
+            &["array"],
+            1,
+            "数组",
+            "去掉 `==` 相等的重复元素，保留第一个",
+            her_unique,
+        );
+        registry.add(
+            "range",
+            &["数到"],
+            &[],
+            -1,
+            "[起], 止, [步长]",
+            "从起（默认 0）按步长（默认 1）数到止，不含止，一次给一个数的迭代器",
+            her_range,
+        );
+
+        // Subprocesses
+        #[cfg(feature = "os")]
+        registry.add(
+            "exec",
+            &["摇人"],
+            &[],
+            -1,
+            "命令, [参数数组]",
+            "运行命令，返回 `{\"code\", \"stdout\", \"stderr\"}`",
+            her_exec,
+        );
+
+        registry
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn add(
+        &mut self,
+        name: &'static str,
+        aliases: &'static [&'static str],
+        receivers: &'static [&'static str],
+        arity: i32,
+        params: &'static str,
+        doc: &'static str,
+        func: BuiltinFunc,
+    ) {
+        self.defs.push(BuiltinDef {
+            name,
+            aliases,
+            receivers,
+            arity,
+            params,
+            doc,
+            value: Object::Builtin(arity, func),
+        });
+    }
+
+    fn add_env(
+        &mut self,
+        name: &'static str,
+        aliases: &'static [&'static str],
+        arity: i32,
+        params: &'static str,
+        doc: &'static str,
+        builtin: EnvBuiltin,
+    ) {
+        self.defs.push(BuiltinDef {
+            name,
+            aliases,
+            receivers: &[],
+            arity,
+            params,
+            doc,
+            value: Object::EnvBuiltin(builtin),
+        });
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &BuiltinDef> {
+        self.defs.iter()
+    }
+
+    /// The builtin called `name`, by its name or one of its aliases.
+    pub fn get(&self, name: &str) -> Option<&BuiltinDef> {
+        self.defs
+            .iter()
+            .find(|def| def.name == name || def.aliases.contains(&name))
+    }
+
+    /// The builtin behind `value.name(args)` for a value with
+    /// `Object::type_name` `type_name`. Only the name, not an alias, is a
+    /// method.
+    pub fn method(&self, type_name: &str, name: &str) -> Option<&BuiltinDef> {
+        self.defs.iter().find(|def| {
+            def.name == name
+                && def
+                    .receivers
+                    .iter()
+                    .any(|receiver| *receiver == "_" || *receiver == type_name)
+        })
+    }
+
+    /// Every name and alias, bound to its function.
+    pub fn bindings(&self) -> HashMap<String, Object> {
+        let mut builtins = HashMap::new();
+        for def in &self.defs {
+            for name in std::iter::once(&def.name).chain(def.aliases) {
+                builtins.insert(name.to_string(), def.value.clone());
+            }
+        }
+        builtins
+    }
+
+    /// A Markdown table of every builtin.
+    pub fn to_markdown(&self) -> String {
+        let mut doc = String::from("| 函数 | 别名 | 说明 |\n| --- | --- | --- |\n");
+        for def in &self.defs {
+            let aliases = def
+                .aliases
+                .iter()
+                .map(|alias| format!("`{alias}`"))
+                .collect::<Vec<_>>()
+                .join("、");
+            doc.push_str(&format!(
+                "| `{}` | {aliases} | {} |\n",
+                def.signature(),
+                def.doc.replace('|', "\\|")
+            ));
+        }
+        doc
+    }
+}
 
 pub fn new_builtins() -> HashMap<String, Object> {
-    let mut builtins = HashMap::new();
-    // Monkey builtins
-    builtins.insert(String::from("len"), Object::Builtin(1, monkey_len));
-    builtins.insert(String::from("first"), Object::Builtin(1, monkey_first));
-    builtins.insert(String::from("last"), Object::Builtin(1, monkey_last));
-    builtins.insert(String::from("rest"), Object::Builtin(1, monkey_rest));
-    builtins.insert(String::from("push"), Object::Builtin(2, monkey_push));
-    builtins.insert(String::from("puts"), Object::Builtin(-1, her_output));
-
-    // herlang builtin, but not aba-aba
-    builtins.insert(String::from("quit"), Object::Builtin(-1, her_quit));
-    builtins.insert(String::from("print"), Object::Builtin(1, her_print));
-    builtins.insert(String::from("repr"), Object::Builtin(1, her_repr));
-    builtins.insert(String::from("str"), Object::Builtin(1, her_str));
-    builtins.insert(String::from("atoi"), Object::Builtin(1, her_atoi));
-
-    // Aba-aba builtins
-    builtins.insert(String::from("哼"), Object::Builtin(-1, her_quit));
-    builtins.insert(String::from("哈"), Object::Builtin(-1, her_quit));
-    builtins.insert(String::from("小作文"), Object::Builtin(-1, her_output));
-    builtins.insert(String::from("家人们"), Object::Builtin(-1, her_output));
-    builtins.insert(String::from("聚焦"), Object::Builtin(1, her_print));
-    builtins.insert(String::from("复用"), Object::Builtin(1, her_repr));
-    builtins.insert(String::from("疏通"), Object::Builtin(1, her_str));
-    builtins.insert(String::from("抹零"), Object::Builtin(1, her_atoi));
-    builtins
+    BuiltinRegistry::new().bindings()
+}
+
+/// Names that read as a fixed value wherever they aren't bound by the program.
+pub fn new_easter_eggs() -> HashMap<String, Object> {
+    let mut eggs = HashMap::new();
+    eggs.insert(String::from("微胖"), Object::String(String::from("180kg")));
+    eggs
+}
+
+thread_local! {
+    static REGISTRY: BuiltinRegistry = BuiltinRegistry::new();
+}
+
+/// The builtin behind `value.name(args)`, which is `name(value, args)`, for
+/// a value with `Object::type_name` `type_name`; see `BuiltinRegistry::method`.
+pub fn method(type_name: &str, name: &str) -> Option<Object> {
+    REGISTRY.with(|registry| {
+        registry
+            .method(type_name, name)
+            .map(|def| def.value.clone())
+    })
 }
 
 fn monkey_len(args: Vec<Object>) -> Object {
@@ -68,7 +578,7 @@ fn monkey_rest(args: Vec<Object>) -> Object {
     match &args[0] {
         Object::Array(o) => {
             if !o.is_empty() {
-                Object::Array(o[1..].to_vec())
+                Object::Array(o[1..].to_vec().into())
             } else {
                 Object::Null
             }
@@ -78,20 +588,247 @@ fn monkey_rest(args: Vec<Object>) -> Object {
 }
 
 fn monkey_push(args: Vec<Object>) -> Object {
+    let mut args = args.into_iter();
+    match (args.next(), args.next()) {
+        (Some(Object::Array(mut items)), Some(item)) => {
+            Rc::make_mut(&mut items).push(item);
+            Object::Array(items)
+        }
+        (o, _) => Object::Error(format!(
+            "argument to `push` must be array. got {}",
+            o.unwrap_or(Object::Null)
+        )),
+    }
+}
+
+/// A sorted copy, in the order of `Object::cmp_total`.
+fn her_sort(args: Vec<Object>) -> Object {
     match &args[0] {
-        Object::Array(o) => {
-            let mut arr = o.clone();
-            arr.push(args[1].clone());
-            Object::Array(arr)
+        Object::Array(items) => {
+            let mut items = items.clone();
+            Rc::make_mut(&mut items).sort_by(Object::cmp_total);
+            Object::Array(items)
         }
-        o => Object::Error(format!("argument to `push` must be array. got {o}")),
+        o => Object::Error(format!("argument to `sort` must be array. got {o}")),
     }
 }
 
-fn her_str(args: Vec<Object>) -> Object {
+/// Arrays inside the array spread into it, one level deep.
+fn her_flat(args: Vec<Object>) -> Object {
+    match &args[0] {
+        Object::Array(items) => Object::Array(
+            items
+                .iter()
+                .flat_map(|item| match item {
+                    Object::Array(inner) => inner.to_vec(),
+                    item => vec![item.clone()],
+                })
+                .collect::<Vec<_>>()
+                .into(),
+        ),
+        o => Object::Error(format!("argument to `flat` must be array. got {o}")),
+    }
+}
+
+/// `[a, b]` pairs from two arrays, as many as the shorter one has.
+fn her_zip(args: Vec<Object>) -> Object {
+    match (&args[0], &args[1]) {
+        (Object::Array(a), Object::Array(b)) => Object::Array(
+            a.iter()
+                .zip(b.iter())
+                .map(|(x, y)| Object::Array(vec![x.clone(), y.clone()].into()))
+                .collect::<Vec<_>>()
+                .into(),
+        ),
+        (Object::Array(_), o) | (o, _) => {
+            Object::Error(format!("argument to `zip` must be array. got {o}"))
+        }
+    }
+}
+
+/// `[index, item]` pairs, counting from 0.
+fn her_enumerate(args: Vec<Object>) -> Object {
+    match &args[0] {
+        Object::Array(items) => Object::Array(
+            items
+                .iter()
+                .enumerate()
+                .map(|(i, item)| Object::Array(vec![Object::Int(i as i64), item.clone()].into()))
+                .collect::<Vec<_>>()
+                .into(),
+        ),
+        o => Object::Error(format!("argument to `enumerate` must be array. got {o}")),
+    }
+}
+
+/// `range(end)` counts from 0, `range(start, end)` from `start`, up to but
+/// not including `end`, and `range(start, end, step)` by `step`. The ints
+/// come from an iterator one at a time, so a long range costs nothing
+/// until it's used.
+fn her_range(args: Vec<Object>) -> Object {
+    let (start, end, step) = match &args[..] {
+        [Object::Int(end)] => (0, *end, 1),
+        [Object::Int(start), Object::Int(end)] => (*start, *end, 1),
+        [Object::Int(start), Object::Int(end), Object::Int(step)] => (*start, *end, *step),
+        _ => {
+            return Object::Error(format!(
+                "illegal argument to `range` (want end, or start, end and step ints, got {})",
+                args.iter()
+                    .map(|o| o.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ));
+        }
+    };
+    if step == 0 {
+        return Object::Error(String::from("step of `range` must not be 0"));
+    }
+
+    Object::Iter(Shared::new(Iter::Range {
+        next: start,
+        end,
+        step,
+    }))
+}
+
+/// The first of each group of `==` items, in their original order.
+fn her_unique(args: Vec<Object>) -> Object {
+    match &args[0] {
+        Object::Array(items) => {
+            let mut unique: Vec<Object> = vec![];
+            for item in items.iter() {
+                if interrupted() {
+                    return Object::Error(String::from(INTERRUPTED));
+                }
+                if !unique.iter().any(|seen| seen.deep_eq(item)) {
+                    unique.push(item.clone());
+                }
+            }
+            Object::Array(unique.into())
+        }
+        o => Object::Error(format!("argument to `unique` must be array. got {o}")),
+    }
+}
+
+/// Hash entries sorted by key, so the output doesn't depend on hashing.
+#[allow(clippy::mutable_key_type)]
+pub(crate) fn sorted_entries(hash: &HashMap<Object, Object>) -> Vec<(&Object, &Object)> {
+    let mut entries = hash.iter().collect::<Vec<_>>();
+    entries.sort_by_key(|(key, _)| key.to_string());
+    entries
+}
+
+fn her_keys(args: Vec<Object>) -> Object {
     match &args[0] {
-        Object::String(s) => Object::String(s.to_string()),
-        x => Object::String(format!("{x}")),
+        Object::Hash(hash) => Object::Array(
+            sorted_entries(hash)
+                .into_iter()
+                .map(|(key, _)| key.clone())
+                .collect::<Vec<_>>()
+                .into(),
+        ),
+        o => Object::Error(format!("argument to `keys` must be hash. got {o}")),
+    }
+}
+
+fn her_values(args: Vec<Object>) -> Object {
+    match &args[0] {
+        Object::Hash(hash) => Object::Array(
+            sorted_entries(hash)
+                .into_iter()
+                .map(|(_, value)| value.clone())
+                .collect::<Vec<_>>()
+                .into(),
+        ),
+        o => Object::Error(format!("argument to `values` must be hash. got {o}")),
+    }
+}
+
+fn her_iter(args: Vec<Object>) -> Object {
+    let items = match &args[0] {
+        Object::Array(items) => Rc::clone(items),
+        Object::String(s) => s
+            .chars()
+            .map(|c| Object::String(c.to_string()))
+            .collect::<Vec<_>>()
+            .into(),
+        Object::Hash(hash) => sorted_entries(hash)
+            .into_iter()
+            .map(|(key, _)| key.clone())
+            .collect::<Vec<_>>()
+            .into(),
+        Object::Iter(_) => return args[0].clone(),
+        o => {
+            return Object::Error(format!(
+                "argument to `iter` must be array, string or hash. got {o}"
+            ));
+        }
+    };
+
+    Object::Iter(Shared::new(Iter::Items { items, pos: 0 }))
+}
+
+fn her_next(args: Vec<Object>) -> Object {
+    match &args[0] {
+        Object::Iter(iter) => iter_step(iter.borrow_mut().next()),
+        o => Object::Error(format!("argument to `next` must be iterator. got {o}")),
+    }
+}
+
+/// What `next()` returns: `{"value": value, "done": false}`, or
+/// `{"value": null, "done": true}` once there is nothing left.
+#[allow(clippy::mutable_key_type)]
+pub fn iter_step(value: Option<Object>) -> Object {
+    let mut step = HashMap::new();
+    step.insert(
+        Object::String(String::from("done")),
+        Object::Bool(value.is_none()),
+    );
+    step.insert(
+        Object::String(String::from("value")),
+        value.unwrap_or(Object::Null),
+    );
+    Object::Hash(step.into())
+}
+
+fn her_assert(args: Vec<Object>) -> Object {
+    match &args[..] {
+        [err @ Object::Error(_), ..] => err.clone(),
+        [cond] | [cond, _] if cond.is_truthy() => Object::Null,
+        [_] => Object::Error(String::from("assertion failed")),
+        [_, message] => Object::Error(format!("assertion failed: {}", display(message))),
+        _ => Object::Error(format!(
+            "wrong number of arguments to `assert` (want 1 or 2, got {})",
+            args.len()
+        )),
+    }
+}
+
+fn her_callable(args: Vec<Object>) -> Object {
+    Object::Bool(args[0].type_name() == "function")
+}
+
+/// Parameter count, `null` for builtins taking any number.
+fn her_arity(args: Vec<Object>) -> Object {
+    match &args[0] {
+        Object::Func(params, ..) => Object::Int(params.len() as i64),
+        Object::Async(func) => her_arity(vec![*func.clone()]),
+        Object::Builtin(n, _) if *n >= 0 => Object::Int(*n as i64),
+        Object::Builtin(..) => Object::Null,
+        Object::EnvBuiltin(builtin) => Object::Int(builtin.arity() as i64),
+        o => Object::Error(format!("argument to `arity` must be function. got {o}")),
+    }
+}
+
+fn her_str(args: Vec<Object>) -> Object {
+    Object::String(display(&args[0]))
+}
+
+/// Strings as they are, everything else the way it is printed.
+fn display(obj: &Object) -> String {
+    match obj {
+        Object::String(s) => s.to_string(),
+        x => format!("{x}"),
     }
 }
 
@@ -99,28 +836,123 @@ fn her_repr(args: Vec<Object>) -> Object {
     Object::String(format!("{}", args[0]))
 }
 
-fn her_print(args: Vec<Object>) -> Object {
-    match &args[0] {
-        Object::String(o) => {
-            println!("{o}");
-            Object::Null
+/// Where the output builtins (`puts`, `print`, `小作文`, ...) write. Any
+/// `FnMut(&str)` is one.
+pub trait OutputSink {
+    fn write(&mut self, text: &str);
+}
+
+impl<F: FnMut(&str)> OutputSink for F {
+    fn write(&mut self, text: &str) {
+        self(text)
+    }
+}
+
+/// The default sink.
+pub struct Stdout;
+
+impl OutputSink for Stdout {
+    fn write(&mut self, text: &str) {
+        let mut stdout = io::stdout();
+        let _ = stdout
+            .write_all(text.as_bytes())
+            .and_then(|_| stdout.flush());
+    }
+}
+
+thread_local! {
+    static SINK: RefCell<Box<dyn OutputSink>> = RefCell::new(Box::new(Stdout));
+}
+
+/// Sends this thread's output to `sink` and returns the sink it replaces,
+/// to put back when done.
+pub fn set_output_sink(sink: Box<dyn OutputSink>) -> Box<dyn OutputSink> {
+    SINK.replace(sink)
+}
+
+fn write(text: &str) {
+    SINK.with_borrow_mut(|sink| sink.write(text))
+}
+
+fn write_line(line: &str) {
+    write(&format!("{line}\n"));
+}
+
+/// Runs `f` with what the builtins print collected into a string instead of
+/// going to stdout.
+pub fn capture_output<T>(f: impl FnOnce() -> T) -> (T, String) {
+    let output = Shared::new(String::new());
+    let sink = Shared::clone(&output);
+    let outer = set_output_sink(Box::new(move |text: &str| sink.borrow_mut().push_str(text)));
+    let result = f();
+    set_output_sink(outer);
+
+    let output = std::mem::take(&mut *output.borrow_mut());
+    (result, output)
+}
+
+thread_local! {
+    static INTERRUPT: RefCell<Interrupt> = RefCell::new(Interrupt::default());
+}
+
+/// Lets this thread's builtins that take long, like `unique`, stop when
+/// `interrupt` is set, and returns the one it replaces, to put back when done.
+pub(crate) fn set_interrupt(interrupt: Interrupt) -> Interrupt {
+    INTERRUPT.replace(interrupt)
+}
+
+fn interrupted() -> bool {
+    INTERRUPT.with_borrow(Interrupt::is_set)
+}
+
+/// `print(a, b, ...)` writes its arguments as `str` would, separated by
+/// `sep` and followed by `end`. A last argument that is a hash with only
+/// `"sep"` and `"end"` keys sets those instead of being printed.
+#[allow(clippy::mutable_key_type)]
+fn her_print(mut args: Vec<Object>) -> Object {
+    let (mut sep, mut end) = (String::from(" "), String::from("\n"));
+
+    let is_options = |hash: &HashMap<Object, Object>| {
+        !hash.is_empty()
+            && hash
+                .keys()
+                .all(|key| matches!(key, Object::String(k) if k == "sep" || k == "end"))
+    };
+    if let Some(Object::Hash(options)) = args.last()
+        && is_options(options)
+    {
+        for (key, value) in options.iter() {
+            let (Object::String(key), Object::String(value)) = (key, value) else {
+                return Object::Error(format!(
+                    "option {key} of `print` must be string. got {value}"
+                ));
+            };
+            if key == "sep" {
+                sep = value.clone();
+            } else {
+                end = value.clone();
+            }
         }
-        o => Object::Error(format!("argument to `push` must be array. got {o}")),
+        args.pop();
     }
+
+    let text = args.iter().map(display).collect::<Vec<_>>().join(&sep);
+    write(&format!("{text}{end}"));
+    Object::Null
 }
 
 fn her_output(args: Vec<Object>) -> Object {
     for arg in args {
-        println!("{arg}");
+        write_line(&arg.to_string());
     }
     Object::Null
 }
 
 fn her_quit(args: Vec<Object>) -> Object {
     match args.len() {
-        0 => std::process::exit(0),
+        0 => Object::Exit(0),
         1 => match &args[0] {
-            Object::Int(i) => std::process::exit(*i as i32),
+            Object::Int(i) => Object::Exit(*i as i32),
             o => Object::Error(format!("argument to `quit` must be int. got {o}")),
         },
         _ => Object::Error(format!(
@@ -130,6 +962,73 @@ fn her_quit(args: Vec<Object>) -> Object {
     }
 }
 
+/// `sleep(ms)` blocks the whole program for `ms` milliseconds.
+fn her_sleep(args: Vec<Object>) -> Object {
+    match sleep_millis(&args[0]) {
+        Ok(ms) => {
+            std::thread::sleep(Duration::from_millis(ms));
+            Object::Null
+        }
+        Err(err) => err,
+    }
+}
+
+/// The milliseconds `sleep` was asked for, for hosts that wait their own way.
+pub fn sleep_millis(arg: &Object) -> Result<u64, Object> {
+    match arg {
+        Object::Int(ms) if *ms >= 0 => Ok(*ms as u64),
+        o => Err(Object::Error(format!(
+            "argument to `sleep` must be non-negative int. got {o}"
+        ))),
+    }
+}
+
+/// `exec(cmd, [args])` runs `cmd` to the end and returns
+/// `{"code": int, "stdout": string, "stderr": string}`. `code` is null when
+/// the process was killed by a signal.
+#[cfg(feature = "os")]
+#[allow(clippy::mutable_key_type)]
+fn her_exec(args: Vec<Object>) -> Object {
+    let (cmd, argv) = match &args[..] {
+        [Object::String(cmd)] => (cmd, &[][..]),
+        [Object::String(cmd), Object::Array(argv)] => (cmd, &argv[..]),
+        _ => {
+            return Object::Error(format!(
+                "illegal argument to `exec` (want string and array of strings, got {args:?}"
+            ));
+        }
+    };
+    let mut command = std::process::Command::new(cmd);
+    for arg in argv {
+        match arg {
+            Object::String(arg) => command.arg(arg),
+            o => {
+                return Object::Error(format!("arguments to `exec` must be strings. got {o}"));
+            }
+        };
+    }
+    let output = match command.output() {
+        Ok(output) => output,
+        Err(err) => return Object::Error(format!("exec {cmd:?} failed: {err}")),
+    };
+
+    let mut result = HashMap::new();
+    result.insert(
+        Object::String(String::from("code")),
+        output
+            .status
+            .code()
+            .map_or(Object::Null, |code| Object::Int(code.into())),
+    );
+    for (key, bytes) in [("stdout", output.stdout), ("stderr", output.stderr)] {
+        result.insert(
+            Object::String(String::from(key)),
+            Object::String(String::from_utf8_lossy(&bytes).into_owned()),
+        );
+    }
+    Object::Hash(result.into())
+}
+
 fn her_atoi(args: Vec<Object>) -> Object {
     match &args[..] {
         [Object::String(s)] => s.parse().map(Object::Int).unwrap_or_else(|_| {
@@ -142,3 +1041,278 @@ fn her_atoi(args: Vec<Object>) -> Object {
         )),
     }
 }
+
+fn her_parse_float(args: Vec<Object>) -> Object {
+    match &args[..] {
+        [Object::String(s)] => match s.trim().parse::<f64>() {
+            Ok(f) if f.is_finite() => Object::Float(f),
+            _ => Object::Error(format!(
+                "argument to `parse_float` must be a valid number. got {s:?}"
+            )),
+        },
+        _ => Object::Error(format!(
+            "illegal argument to `parse_float` (want 1 string, got {args:?}"
+        )),
+    }
+}
+
+/// `to_fixed(x, digits)` writes `x` with exactly `digits` decimals, rounding
+/// half to even like Rust's `{:.N}`.
+fn her_to_fixed(args: Vec<Object>) -> Object {
+    let digits = match &args[1] {
+        Object::Int(d @ 0..=100) => *d as usize,
+        o => {
+            return Object::Error(format!(
+                "digits of `to_fixed` must be int between 0 and 100. got {o}"
+            ));
+        }
+    };
+    match &args[0] {
+        Object::Int(n) if digits == 0 => Object::String(n.to_string()),
+        Object::Int(n) => Object::String(format!("{n}.{}", "0".repeat(digits))),
+        Object::Float(f) => Object::String(format!("{f:.digits$}")),
+        o => Object::Error(format!("argument to `to_fixed` must be number. got {o}")),
+    }
+}
+
+fn radix(name: &str, base: &Object) -> Result<u32, Object> {
+    match base {
+        Object::Int(b @ 2..=36) => Ok(*b as u32),
+        o => Err(Object::Error(format!(
+            "base of `{name}` must be int between 2 and 36. got {o}"
+        ))),
+    }
+}
+
+/// `to_base(n, base)` writes `n` in `base` with lowercase letters for
+/// digits past 9.
+fn her_to_base(args: Vec<Object>) -> Object {
+    let base = match radix("to_base", &args[1]) {
+        Ok(base) => base,
+        Err(err) => return err,
+    };
+    let n = match &args[0] {
+        Object::Int(n) => *n,
+        o => return Object::Error(format!("argument to `to_base` must be int. got {o}")),
+    };
+
+    let mut rest = n.unsigned_abs();
+    let mut digits = Vec::new();
+    loop {
+        let digit = (rest % base as u64) as u32;
+        digits.push(char::from_digit(digit, base).unwrap());
+        rest /= base as u64;
+        if rest == 0 {
+            break;
+        }
+    }
+    if n < 0 {
+        digits.push('-');
+    }
+    Object::String(digits.into_iter().rev().collect())
+}
+
+fn her_from_base(args: Vec<Object>) -> Object {
+    let base = match radix("from_base", &args[1]) {
+        Ok(base) => base,
+        Err(err) => return err,
+    };
+    match &args[0] {
+        Object::String(s) => i64::from_str_radix(s, base)
+            .map(Object::Int)
+            .unwrap_or_else(|_| {
+                Object::Error(format!(
+                    "argument to `from_base` must be valid base {base} digits. got {s:?}"
+                ))
+            }),
+        o => Object::Error(format!("argument to `from_base` must be string. got {o}")),
+    }
+}
+
+/// `chr(n)` is the one-character string for Unicode scalar value `n`.
+fn her_chr(args: Vec<Object>) -> Object {
+    match &args[0] {
+        Object::Int(n) => match u32::try_from(*n).ok().and_then(char::from_u32) {
+            Some(c) => Object::String(c.to_string()),
+            None => Object::Error(format!("{n} is not a Unicode scalar value")),
+        },
+        o => Object::Error(format!("argument to `chr` must be int. got {o}")),
+    }
+}
+
+/// `ord(s)` is the Unicode scalar value of the one character in `s`.
+fn her_ord(args: Vec<Object>) -> Object {
+    match &args[0] {
+        Object::String(s) => {
+            let mut chars = s.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) => Object::Int(c as i64),
+                _ => Object::Error(format!(
+                    "argument to `ord` must be a single character. got {s:?}"
+                )),
+            }
+        }
+        o => Object::Error(format!("argument to `ord` must be string. got {o}")),
+    }
+}
+
+/// `template(text, data)` is `text` with each `{key}` replaced by the value
+/// of `key` in `data`. `{{` and `}}` stand for literal braces.
+fn her_template(args: Vec<Object>) -> Object {
+    let (text, data) = match (&args[0], &args[1]) {
+        (Object::String(text), Object::Hash(data)) => (text, data),
+        (Object::String(_), o) => {
+            return Object::Error(format!(
+                "second argument to `template` must be hash. got {o}"
+            ));
+        }
+        (o, _) => {
+            return Object::Error(format!("argument to `template` must be string. got {o}"));
+        }
+    };
+
+    let mut result = String::new();
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.as_str().starts_with('{') => {
+                chars.next();
+                result.push('{');
+            }
+            '}' if chars.as_str().starts_with('}') => {
+                chars.next();
+                result.push('}');
+            }
+            '{' => {
+                let Some((key, rest)) = chars.as_str().split_once('}') else {
+                    return Object::Error(String::from("unclosed `{` in template"));
+                };
+                match data.get(&Object::String(key.trim().to_string())) {
+                    Some(value) => result.push_str(&display(value)),
+                    None => {
+                        return Object::Error(format!("template key not found: {}", key.trim()));
+                    }
+                }
+                chars = rest.chars();
+            }
+            c => result.push(c),
+        }
+    }
+    Object::String(result)
+}
+
+/// `json_parse(text)` reads strict JSON; `json_parse(text, {"loose": true})`
+/// also takes trailing commas, single quotes, comments and bare keys.
+fn her_json_parse(args: Vec<Object>) -> Object {
+    let (text, loose) = match &args[..] {
+        [Object::String(text)] => (text, false),
+        [Object::String(text), Object::Hash(options)] => {
+            match options.get(&Object::String(String::from("loose"))) {
+                Some(Object::Bool(loose)) => (text, *loose),
+                Some(o) => {
+                    return Object::Error(format!(
+                        "option \"loose\" of `json_parse` must be bool. got {o}"
+                    ));
+                }
+                None => (text, false),
+            }
+        }
+        _ => {
+            return Object::Error(format!(
+                "illegal argument to `json_parse` (want string and options hash, got {})",
+                args.iter()
+                    .map(|o| o.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ));
+        }
+    };
+    json::parse(text, loose).unwrap_or_else(Object::Error)
+}
+
+fn her_json_stringify(args: Vec<Object>) -> Object {
+    json::stringify(&args[0])
+        .map(Object::String)
+        .unwrap_or_else(Object::Error)
+}
+
+const SMALL_DIGITS: [char; 10] = ['零', '一', '二', '三', '四', '五', '六', '七', '八', '九'];
+const SMALL_UNITS: [&str; 3] = ["十", "百", "千"];
+const CAPITAL_DIGITS: [char; 10] = ['零', '壹', '贰', '叁', '肆', '伍', '陆', '柒', '捌', '玖'];
+const CAPITAL_UNITS: [&str; 3] = ["拾", "佰", "仟"];
+/// Units of each group of four digits, enough for any `i64`.
+const GROUP_UNITS: [&str; 5] = ["", "万", "亿", "万亿", "亿亿"];
+
+/// `n` in Chinese numerals, with one `零` for each run of zeros that has
+/// digits on both sides. A leading `一十` is shortened to `十` for `small`.
+fn chinese_numerals(n: i64, digits: [char; 10], units: [&str; 3], small: bool) -> String {
+    if n == 0 {
+        return digits[0].to_string();
+    }
+
+    let mut groups = vec![];
+    let mut rest = n.unsigned_abs();
+    while rest > 0 {
+        groups.push((rest % 10000) as usize);
+        rest /= 10000;
+    }
+
+    let mut out = String::new();
+    let mut zero = false;
+    for (i, &group) in groups.iter().enumerate().rev() {
+        if group == 0 {
+            zero = true;
+            continue;
+        }
+        if !out.is_empty() && (zero || group < 1000) {
+            out.push(digits[0]);
+        }
+        zero = false;
+
+        let mut gap = false;
+        for (place, digit) in [group / 1000, group / 100 % 10, group / 10 % 10, group % 10]
+            .into_iter()
+            .enumerate()
+            .skip_while(|(_, digit)| *digit == 0)
+        {
+            if digit == 0 {
+                gap = true;
+                continue;
+            }
+            if gap {
+                out.push(digits[0]);
+                gap = false;
+            }
+            if !(small && out.is_empty() && digit == 1 && place == 2) {
+                out.push(digits[digit]);
+            }
+            if place < 3 {
+                out.push_str(units[2 - place]);
+            }
+        }
+        out.push_str(GROUP_UNITS[i]);
+    }
+
+    if n < 0 {
+        out.insert(0, '负');
+    }
+    out
+}
+
+fn her_chinese(args: Vec<Object>) -> Object {
+    match &args[0] {
+        Object::Int(n) => Object::String(chinese_numerals(*n, SMALL_DIGITS, SMALL_UNITS, true)),
+        o => Object::Error(format!("argument to `中文数字` must be int. got {o}")),
+    }
+}
+
+/// An amount of yuan the way it is written on cheques and receipts.
+fn her_amount(args: Vec<Object>) -> Object {
+    match &args[0] {
+        Object::Int(n) => Object::String(format!(
+            "{}元整",
+            chinese_numerals(*n, CAPITAL_DIGITS, CAPITAL_UNITS, false)
+        )),
+        o => Object::Error(format!("argument to `大写金额` must be int. got {o}")),
+    }
+}
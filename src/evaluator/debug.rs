@@ -0,0 +1,198 @@
+//! Statement-by-statement stepping for the playground.
+//!
+//! The evaluator is recursive and cannot be paused halfway, so a session runs
+//! the whole program once with tracing on and then replays the recorded steps.
+//! Output is kept on the step that printed it, to show as the steps replay.
+
+use crate::evaluator::Evaluator;
+use crate::evaluator::builtins::{new_builtins, sleep_millis};
+use crate::evaluator::env::Env;
+use crate::evaluator::object::Object;
+use crate::evaluator::shared::Shared;
+use crate::json;
+use crate::lexer::Lexer;
+use crate::parser::{ParseErrors, Parser};
+use crate::span::Span;
+
+/// Stops traced runs that would otherwise never end.
+pub const MAX_STEPS: usize = 10_000;
+
+/// What a traced run evaluates to once it hits `MAX_STEPS`.
+pub const TOO_MANY_STEPS: &str = "步数太多了，姐妹歇歇";
+
+/// What a `BreakpointHook` wants to happen next.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resume {
+    /// Run on until the next `breakpoint()`.
+    Continue,
+    /// Stop again before the next statement.
+    Step,
+}
+
+/// Called by `breakpoint()`, and before every statement while stepping,
+/// with the state of the program at that point.
+pub type BreakpointHook = fn(&Step) -> Resume;
+
+/// The state right before a statement runs.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Step {
+    pub span: Span,
+    /// Number of user function calls on the stack.
+    pub depth: usize,
+    /// Bindings of the innermost scope, sorted by name, builtins left out.
+    pub locals: Vec<(String, Object)>,
+    /// What the statement printed before the next step, in traced runs.
+    pub output: String,
+}
+
+impl Step {
+    pub fn to_json(&self) -> String {
+        let locals = json::array(self.locals.iter().map(|(name, value)| {
+            format!(
+                "{{\"name\":{},\"value\":{}}}",
+                json::string(name),
+                json::string(&value.to_string())
+            )
+        }));
+
+        format!(
+            "{{\"range\":{},\"depth\":{},\"locals\":{},\"output\":{}}}",
+            json::span(&self.span),
+            self.depth,
+            locals,
+            json::string(&self.output)
+        )
+    }
+}
+
+#[derive(Debug)]
+pub struct DebugSession {
+    steps: Vec<Step>,
+    next: usize,
+    result: Option<Object>,
+}
+
+impl DebugSession {
+    pub fn new(input: &str) -> Result<Self, ParseErrors> {
+        Self::with(
+            Parser::new(Lexer::new(input)),
+            Evaluator::new(Shared::new(Env::from(new_builtins()))),
+        )
+    }
+
+    /// Like `new`, with a parser and an evaluator the host has set up, e.g.
+    /// `with_max_steps` or `with_interrupt`. Output is kept on the steps.
+    pub fn with(parser: Parser, evaluator: Evaluator) -> Result<Self, ParseErrors> {
+        let mut parser = parser.with_positions();
+        let program = parser.parse();
+        let errors = parser.get_errors();
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+
+        // Steps are recorded up front and replayed, so waiting now would
+        // only hold up the first one.
+        for name in ["sleep", "躺平"] {
+            evaluator
+                .env
+                .borrow_mut()
+                .define(String::from(name), &Object::Builtin(1, skip_sleep));
+        }
+        let mut evaluator = evaluator.with_trace();
+        let result = evaluator.eval(&program);
+
+        Ok(DebugSession {
+            steps: evaluator.take_trace(),
+            next: 0,
+            result,
+        })
+    }
+
+    /// Moves to the next statement, or returns `None` once the program is done.
+    pub fn step(&mut self) -> Option<&Step> {
+        let step = self.steps.get(self.next)?;
+        self.next += 1;
+        Some(step)
+    }
+
+    /// The step `step` returned last, whose statement runs before the next
+    /// one: its `output` is what to show when moving on.
+    pub fn current(&self) -> Option<&Step> {
+        self.steps.get(self.next.checked_sub(1)?)
+    }
+
+    /// What the whole program evaluated to.
+    pub fn result(&self) -> Option<&Object> {
+        self.result.as_ref()
+    }
+}
+
+fn skip_sleep(args: Vec<Object>) -> Object {
+    sleep_millis(&args[0]).map_or_else(|err| err, |_| Object::Null)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::evaluator::debug::*;
+
+    #[test]
+    fn test_debug_session() {
+        let mut session = DebugSession::new(
+            "宝宝你是一个 a = 1;\n宝宝你是一个 f = 想要你一个态度(x) { x + a };\nf(2);",
+        )
+        .unwrap();
+
+        let expect = vec![
+            (1, 0, vec![]),
+            (2, 0, vec!["a"]),
+            (3, 0, vec!["a", "f"]),
+            (2, 1, vec!["x"]),
+        ];
+
+        for (line, depth, locals) in expect {
+            let step = session.step().unwrap();
+            assert_eq!(line, step.span.start.line);
+            assert_eq!(depth, step.depth);
+            assert_eq!(
+                locals,
+                step.locals
+                    .iter()
+                    .map(|(name, _)| name.as_str())
+                    .collect::<Vec<_>>()
+            );
+        }
+
+        assert_eq!(None, session.step());
+        assert_eq!(Some(&Object::Int(3)), session.result());
+    }
+
+    #[test]
+    fn test_debug_session_output() {
+        let (session, printed) = crate::evaluator::builtins::capture_output(|| {
+            DebugSession::new("print(1);\nlet f = fn() { print(2); 3 };\nprint(f());").unwrap()
+        });
+        assert_eq!("", printed);
+
+        let steps = session
+            .steps
+            .iter()
+            .map(|step| (step.span.start.line, step.output.as_str()))
+            .collect::<Vec<_>>();
+        // `print(f())` prints after its last step, the one in `f`.
+        assert_eq!(
+            vec![(1, "1\n"), (2, ""), (3, ""), (2, "2\n"), (2, "3\n")],
+            steps
+        );
+    }
+
+    #[test]
+    fn test_too_many_steps() {
+        let mut session = DebugSession::new("while (true) { 1; }").unwrap();
+
+        assert_eq!(
+            Some(&Object::Error(String::from(TOO_MANY_STEPS))),
+            session.result()
+        );
+        assert!(session.step().is_some());
+    }
+}
@@ -1,12 +1,11 @@
 use crate::evaluator::object::*;
-use std::cell::RefCell;
+use crate::evaluator::shared::Shared;
 use std::collections::HashMap;
-use std::rc::Rc;
 
 #[derive(PartialEq, Clone, Debug)]
 pub struct Env {
     pub store: HashMap<String, Object>,
-    outer: Option<Rc<RefCell<Env>>>,
+    outer: Option<Shared<Env>>,
 }
 
 impl Default for Env {
@@ -27,7 +26,7 @@ impl Env {
         Env { store, outer: None }
     }
 
-    pub fn new_with_outer(outer: Rc<RefCell<Env>>) -> Self {
+    pub fn new_with_outer(outer: Shared<Env>) -> Self {
         Env {
             store: HashMap::new(),
             outer: Some(outer),
@@ -44,7 +43,27 @@ impl Env {
         }
     }
 
-    pub fn set(&mut self, name: String, value: &Object) {
+    /// Binds `name` in this scope, shadowing any outer binding.
+    pub fn define(&mut self, name: String, value: &Object) {
         self.store.insert(name, value.clone());
     }
+
+    /// Rebinds `name` in the nearest scope that has it. Returns `false`,
+    /// binding nothing, when no scope does.
+    pub fn assign(&mut self, name: String, value: &Object) -> bool {
+        if let Some(slot) = self.store.get_mut(&name) {
+            *slot = value.clone();
+            return true;
+        }
+
+        match self.outer {
+            Some(ref outer) => outer.borrow_mut().assign(name, value),
+            None => false,
+        }
+    }
+
+    /// Unbinds `name` in this scope only; outer scopes are left alone.
+    pub fn remove(&mut self, name: &str) -> Option<Object> {
+        self.store.remove(name)
+    }
 }
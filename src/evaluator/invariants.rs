@@ -0,0 +1,79 @@
+//! Self-checks for the evaluator, built with the `invariants` feature.
+//!
+//! After every expression the evaluator hands its value to `check`, which
+//! panics as soon as the value is in a state no operation should leave it
+//! in, so a bug shows up where it happens and not much later.
+
+use crate::evaluator::object::*;
+
+/// Panics with `context` when `obj`, or anything in it, breaks one of the
+/// invariants below.
+pub fn check(obj: &Object, context: impl Fn() -> String) {
+    if let Err(violation) = find_violation(obj, true) {
+        panic!(
+            "invariant violated: {violation}\n  value: {obj:?}\n  {}",
+            context()
+        );
+    }
+}
+
+/// `top` is whether `obj` is the value itself rather than an item in it;
+/// only the value itself may be a control-flow object.
+fn find_violation(obj: &Object, top: bool) -> Result<(), String> {
+    match obj {
+        Object::ReturnValue(value) => {
+            if !top {
+                return Err("return value stored inside another value".to_string());
+            }
+            if matches!(**value, Object::ReturnValue(_)) {
+                return Err("return value wrapping another return value".to_string());
+            }
+            find_violation(value, false)
+        }
+        Object::BreakStatement | Object::ContinueStatement | Object::Exit(_) if !top => {
+            Err(format!("{obj:?} stored inside another value"))
+        }
+        Object::Quantity(_, unit) if unit.is_empty() => Err("quantity without a unit".to_string()),
+        Object::Array(items) => items
+            .iter()
+            .try_for_each(|item| find_violation(item, false)),
+        Object::Hash(hash) => {
+            for (key, value) in hash.iter() {
+                // Other keys all hash alike and are told apart by `==`,
+                // which a `NaN` key never passes.
+                let hashable = matches!(key, Object::Int(_) | Object::Bool(_) | Object::String(_));
+                if hashable && !hash.contains_key(key) {
+                    return Err(format!("hash key {key} can't be looked up"));
+                }
+                find_violation(key, false)?;
+                find_violation(value, false)?;
+            }
+            Ok(())
+        }
+        Object::Iter(iter) => match &*iter.borrow() {
+            Iter::Items { items, pos } => {
+                if *pos > items.len() {
+                    return Err(format!("iterator at {pos} of {} items", items.len()));
+                }
+                items
+                    .iter()
+                    .try_for_each(|item| find_violation(item, false))
+            }
+            Iter::Range { step: 0, .. } => Err(String::from("range with a step of 0")),
+            Iter::Range { .. } => Ok(()),
+        },
+        Object::Async(func) if !matches!(**func, Object::Func(..)) => {
+            Err(format!("async wrapping a {}", func.type_name()))
+        }
+        Object::Task(task) => match &*task.borrow() {
+            // A task's result can be the task itself, so it isn't walked.
+            Task::Done(
+                result @ (Object::ReturnValue(_)
+                | Object::BreakStatement
+                | Object::ContinueStatement),
+            ) => Err(format!("task done with {result:?}")),
+            _ => Ok(()),
+        },
+        _ => Ok(()),
+    }
+}
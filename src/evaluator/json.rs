@@ -0,0 +1,349 @@
+//! Reading JSON into values and writing values back, for `json_parse` and
+//! `json_stringify`.
+//!
+//! The loose reader also takes what herlang's own literals look like:
+//! trailing commas, `'single quoted'` strings, comments and bare keys.
+
+use crate::evaluator::builtins::sorted_entries;
+use crate::evaluator::object::Object;
+use crate::json;
+use std::collections::HashMap;
+
+/// How deep arrays and objects may nest. Each level is a call in the
+/// reader, so without a cap a long run of `[` overflows the stack.
+pub const MAX_DEPTH: usize = 512;
+
+/// The value `text` stands for. Objects become hashes with string keys,
+/// numbers without a fraction or exponent that fit become ints.
+pub fn parse(text: &str, loose: bool) -> Result<Object, String> {
+    let mut reader = Reader {
+        chars: text.chars().collect(),
+        pos: 0,
+        depth: 0,
+        loose,
+    };
+
+    reader.skip_blank()?;
+    let value = reader.value()?;
+    reader.skip_blank()?;
+    match reader.peek() {
+        None => Ok(value),
+        Some(c) => Err(reader.error(&format!("unexpected `{c}` after the value"))),
+    }
+}
+
+/// `value` as compact JSON. Hash keys are written as strings, in the order
+/// `keys` returns them.
+pub fn stringify(value: &Object) -> Result<String, String> {
+    match value {
+        Object::Null => Ok(String::from("null")),
+        Object::Bool(b) => Ok(b.to_string()),
+        Object::Int(n) => Ok(n.to_string()),
+        Object::Float(n) if n.is_finite() => Ok(value.to_string()),
+        Object::String(s) => Ok(json::string(s)),
+        Object::Array(items) => Ok(json::array(
+            items.iter().map(stringify).collect::<Result<Vec<_>, _>>()?,
+        )),
+        Object::Hash(hash) => {
+            let entries = sorted_entries(hash)
+                .into_iter()
+                .map(|(key, value)| {
+                    let key = match key {
+                        Object::String(s) => s.clone(),
+                        key => key.to_string(),
+                    };
+                    Ok(format!("{}:{}", json::string(&key), stringify(value)?))
+                })
+                .collect::<Result<Vec<_>, String>>()?;
+            Ok(format!("{{{}}}", entries.join(",")))
+        }
+        Object::Float(n) => Err(format!("{n} can't be written as JSON")),
+        o => Err(format!("a {} can't be written as JSON", o.type_name())),
+    }
+}
+
+struct Reader {
+    chars: Vec<char>,
+    pos: usize,
+    /// Arrays and objects open around `pos`.
+    depth: usize,
+    loose: bool,
+}
+
+impl Reader {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn starts_with(&self, s: &str) -> bool {
+        s.chars()
+            .enumerate()
+            .all(|(i, c)| self.chars.get(self.pos + i) == Some(&c))
+    }
+
+    fn error(&self, msg: &str) -> String {
+        let before = &self.chars[..self.pos.min(self.chars.len())];
+        let line = before.iter().filter(|&&c| c == '\n').count() + 1;
+        let column = before.iter().rev().take_while(|&&c| c != '\n').count() + 1;
+        format!("invalid JSON at {line}:{column}: {msg}")
+    }
+
+    fn expect(&mut self, want: char) -> Result<(), String> {
+        match self.peek() {
+            Some(c) if c == want => {
+                self.pos += 1;
+                Ok(())
+            }
+            Some(c) => Err(self.error(&format!("expected `{want}`, got `{c}`"))),
+            None => Err(self.error(&format!("expected `{want}`, got the end"))),
+        }
+    }
+
+    /// Whitespace, and comments when loose.
+    fn skip_blank(&mut self) -> Result<(), String> {
+        loop {
+            match self.peek() {
+                Some(' ' | '\t' | '\n' | '\r') => self.pos += 1,
+                _ if self.loose && self.starts_with("//") => {
+                    while !matches!(self.peek(), None | Some('\n')) {
+                        self.pos += 1;
+                    }
+                }
+                _ if self.loose && self.starts_with("/*") => {
+                    let start = self.pos;
+                    self.pos += 2;
+                    while !self.starts_with("*/") {
+                        if self.peek().is_none() {
+                            self.pos = start;
+                            return Err(self.error("unterminated comment"));
+                        }
+                        self.pos += 1;
+                    }
+                    self.pos += 2;
+                }
+                _ => return Ok(()),
+            }
+        }
+    }
+
+    fn value(&mut self) -> Result<Object, String> {
+        match self.peek() {
+            Some('{' | '[') if self.depth >= MAX_DEPTH => {
+                Err(self.error(&format!("nested too deep, the limit is {MAX_DEPTH}")))
+            }
+            Some(open @ ('{' | '[')) => {
+                self.depth += 1;
+                let value = if open == '{' {
+                    self.object()
+                } else {
+                    self.array()
+                };
+                self.depth -= 1;
+                value
+            }
+            Some('"') => self.string().map(Object::String),
+            Some('\'') if self.loose => self.string().map(Object::String),
+            Some('-' | '0'..='9') => self.number(),
+            Some(c) if c.is_alphabetic() => match self.word().as_str() {
+                "true" => Ok(Object::Bool(true)),
+                "false" => Ok(Object::Bool(false)),
+                "null" => Ok(Object::Null),
+                word => {
+                    self.pos -= word.chars().count();
+                    Err(self.error(&format!("unexpected `{word}`")))
+                }
+            },
+            Some(c) => Err(self.error(&format!("unexpected `{c}`"))),
+            None => Err(self.error("expected a value, got the end")),
+        }
+    }
+
+    fn word(&mut self) -> String {
+        let start = self.pos;
+        while self
+            .peek()
+            .is_some_and(|c| c.is_alphanumeric() || c == '_' || c == '$')
+        {
+            self.pos += 1;
+        }
+        self.chars[start..self.pos].iter().collect()
+    }
+
+    /// Items up to `close`, separated by commas, with a trailing comma when
+    /// loose.
+    fn items(
+        &mut self,
+        close: char,
+        mut item: impl FnMut(&mut Self) -> Result<(), String>,
+    ) -> Result<(), String> {
+        self.pos += 1;
+        self.skip_blank()?;
+        if self.peek() == Some(close) {
+            self.pos += 1;
+            return Ok(());
+        }
+
+        loop {
+            item(self)?;
+            self.skip_blank()?;
+            if self.peek() == Some(close) {
+                self.pos += 1;
+                return Ok(());
+            }
+            self.expect(',')?;
+            self.skip_blank()?;
+            if self.loose && self.peek() == Some(close) {
+                self.pos += 1;
+                return Ok(());
+            }
+        }
+    }
+
+    fn array(&mut self) -> Result<Object, String> {
+        let mut items = vec![];
+        self.items(']', |reader| {
+            items.push(reader.value()?);
+            Ok(())
+        })?;
+        Ok(Object::Array(items.into()))
+    }
+
+    #[allow(clippy::mutable_key_type)]
+    fn object(&mut self) -> Result<Object, String> {
+        let mut hash = HashMap::new();
+        self.items('}', |reader| {
+            let key = match reader.peek() {
+                Some('"') => reader.string()?,
+                Some('\'') if reader.loose => reader.string()?,
+                Some(c) if reader.loose && (c.is_alphabetic() || c == '_' || c == '$') => {
+                    reader.word()
+                }
+                Some(c) => return Err(reader.error(&format!("expected a key, got `{c}`"))),
+                None => return Err(reader.error("expected a key, got the end")),
+            };
+            reader.skip_blank()?;
+            reader.expect(':')?;
+            reader.skip_blank()?;
+            hash.insert(Object::String(key), reader.value()?);
+            Ok(())
+        })?;
+        Ok(Object::Hash(hash.into()))
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        let quote = self.chars[self.pos];
+        let start = self.pos;
+        self.pos += 1;
+        let mut result = String::new();
+
+        loop {
+            let Some(c) = self.peek() else {
+                self.pos = start;
+                return Err(self.error("unterminated string"));
+            };
+            self.pos += 1;
+            match c {
+                c if c == quote => return Ok(result),
+                '\\' => result.push(self.escape()?),
+                c if (c as u32) < 0x20 => {
+                    self.pos -= 1;
+                    return Err(self.error("control character in string"));
+                }
+                c => result.push(c),
+            }
+        }
+    }
+
+    fn escape(&mut self) -> Result<char, String> {
+        let c = self.peek();
+        self.pos += 1;
+        match c {
+            Some('"') => Ok('"'),
+            Some('\\') => Ok('\\'),
+            Some('/') => Ok('/'),
+            Some('b') => Ok('\u{8}'),
+            Some('f') => Ok('\u{c}'),
+            Some('n') => Ok('\n'),
+            Some('r') => Ok('\r'),
+            Some('t') => Ok('\t'),
+            Some('\'') if self.loose => Ok('\''),
+            Some('u') => {
+                let high = self.hex4()?;
+                if !(0xd800..0xdc00).contains(&high) {
+                    return char::from_u32(high).ok_or_else(|| self.error("lone surrogate"));
+                }
+                if !self.starts_with("\\u") {
+                    return Err(self.error("lone surrogate"));
+                }
+                self.pos += 2;
+                let low = self.hex4()?;
+                if !(0xdc00..0xe000).contains(&low) {
+                    return Err(self.error("lone surrogate"));
+                }
+                char::from_u32(0x10000 + ((high - 0xd800) << 10) + (low - 0xdc00))
+                    .ok_or_else(|| self.error("lone surrogate"))
+            }
+            _ => {
+                self.pos -= 1;
+                Err(self.error("unknown escape"))
+            }
+        }
+    }
+
+    fn hex4(&mut self) -> Result<u32, String> {
+        let digits = self
+            .chars
+            .get(self.pos..self.pos + 4)
+            .map(|digits| digits.iter().collect::<String>())
+            .filter(|digits| digits.chars().all(|c| c.is_ascii_hexdigit()))
+            .ok_or_else(|| self.error("`\\u` needs four hex digits"))?;
+        self.pos += 4;
+        Ok(u32::from_str_radix(&digits, 16).unwrap_or_default())
+    }
+
+    fn number(&mut self) -> Result<Object, String> {
+        let start = self.pos;
+        let digits = |reader: &mut Self| {
+            let from = reader.pos;
+            while reader.peek().is_some_and(|c| c.is_ascii_digit()) {
+                reader.pos += 1;
+            }
+            reader.pos - from
+        };
+
+        if self.peek() == Some('-') {
+            self.pos += 1;
+        }
+        let int_start = self.pos;
+        if digits(self) == 0 || (self.chars[int_start] == '0' && self.pos - int_start > 1) {
+            self.pos = start;
+            return Err(self.error("invalid number"));
+        }
+        let mut is_float = false;
+        if self.peek() == Some('.') {
+            self.pos += 1;
+            is_float = true;
+            if digits(self) == 0 {
+                self.pos = start;
+                return Err(self.error("invalid number"));
+            }
+        }
+        if matches!(self.peek(), Some('e' | 'E')) {
+            self.pos += 1;
+            is_float = true;
+            if matches!(self.peek(), Some('+' | '-')) {
+                self.pos += 1;
+            }
+            if digits(self) == 0 {
+                self.pos = start;
+                return Err(self.error("invalid number"));
+            }
+        }
+
+        let text = self.chars[start..self.pos].iter().collect::<String>();
+        match text.parse::<i64>() {
+            Ok(n) if !is_float => Ok(Object::Int(n)),
+            _ => Ok(Object::Float(text.parse().unwrap_or_default())),
+        }
+    }
+}
@@ -1,53 +1,465 @@
 pub mod builtins;
+pub mod debug;
 pub mod env;
+#[cfg(feature = "invariants")]
+mod invariants;
+pub mod json;
 pub mod object;
+pub mod sandbox;
+pub mod shared;
 
 use crate::ast::*;
+use crate::diagnostic::{DiagnosticCode, Lang};
+use crate::evaluator::builtins::{
+    method, new_builtins, new_easter_eggs, set_interrupt, set_output_sink, sorted_entries,
+};
+use crate::evaluator::debug::{BreakpointHook, MAX_STEPS, Resume, Step, TOO_MANY_STEPS};
 use crate::evaluator::env::*;
 use crate::evaluator::object::*;
-use std::cell::RefCell;
+use crate::evaluator::sandbox::{NO_PERMISSION, SandboxConfig};
+use crate::evaluator::shared::Shared;
+use crate::herc;
+use crate::lexer::{LANGUAGE_VERSION, Lexer};
+use crate::parser::{Parser, desugar_c_for};
+use crate::span::Span;
 use std::collections::HashMap;
-use std::rc::Rc;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// What a run stopped by the host evaluates to.
+pub const INTERRUPTED: &str = "被姐妹叫停";
+
+/// What a call nested deeper than `Evaluator::with_max_depth` evaluates to.
+pub const TOO_DEEP: &str = "套娃太深了，姐妹歇歇";
+
+/// Default for `Evaluator::with_max_depth`, which fits in the 8 MiB main
+/// thread stack of a release build.
+pub const MAX_DEPTH: usize = 1_000;
+
+/// How one `考一考` block went.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TestResult {
+    pub name: String,
+    /// The error (or `quit` with a nonzero code) it stopped with, `None` when
+    /// it passed.
+    pub failure: Option<Object>,
+}
+
+/// How the host asks a running program to stop, see `with_interrupt` and
+/// `with_stop_check`.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Interrupt {
+    flag: Option<Arc<AtomicBool>>,
+    check: Option<fn() -> bool>,
+}
+
+impl Interrupt {
+    pub(crate) fn is_set(&self) -> bool {
+        self.flag
+            .as_ref()
+            .is_some_and(|flag| flag.load(Ordering::Relaxed))
+            || self.check.is_some_and(|check| check())
+    }
+}
+
+/// What the host set up through the `with_*` methods. `引入`ed modules are
+/// run with the same options as the program importing them.
+#[derive(Debug, Clone)]
+struct Options {
+    /// Set by the host to ask a running program to stop.
+    interrupt: Interrupt,
+    /// Check values against type annotations as they are bound and returned.
+    strict: bool,
+    /// Values for names the program never binds, see `new_easter_eggs`.
+    easter_eggs: HashMap<String, Object>,
+    /// What the program is allowed to do.
+    sandbox: SandboxConfig,
+    /// Globals from the host that the program may not rebind.
+    constants: HashMap<String, Object>,
+    /// Language version `引入`ed modules are parsed as, unless they have a
+    /// `#她版本` header.
+    version: u32,
+    /// How much of a value error messages show.
+    repr_limit: ReprLimit,
+    /// What `breakpoint()` calls; without one it does nothing.
+    breakpoint: Option<BreakpointHook>,
+    /// Language of runtime errors that have a `DiagnosticCode`.
+    lang: Lang,
+    /// Most user function calls on the stack at once.
+    max_depth: usize,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            interrupt: Interrupt::default(),
+            strict: false,
+            easter_eggs: new_easter_eggs(),
+            sandbox: SandboxConfig::default(),
+            constants: HashMap::new(),
+            version: LANGUAGE_VERSION,
+            repr_limit: ReprLimit::default(),
+            breakpoint: None,
+            lang: Lang::default(),
+            max_depth: MAX_DEPTH,
+        }
+    }
+}
 
 #[derive(Debug)]
 pub struct Evaluator {
-    pub env: Rc<RefCell<Env>>,
+    pub env: Shared<Env>,
+    /// Directory that `引入` paths are resolved against.
+    /// `None` means there is no current file (REPL, wasm), so the cwd is used.
+    base_dir: Option<PathBuf>,
+    /// Modules currently being loaded, used to catch import cycles.
+    importing: Vec<PathBuf>,
+    options: Options,
+    /// Steps recorded at every `Stmt::Mark`, when tracing.
+    trace: Option<Vec<Step>>,
+    /// Output of a traced run not yet put on its step.
+    printed: Shared<String>,
+    /// Number of user function calls on the stack.
+    depth: usize,
+    /// Span of the statement being run, from the last `Stmt::Mark`.
+    position: Option<Span>,
+    /// Tasks in the order they were started, see `run_tasks`.
+    tasks: Vec<Shared<Task>>,
+    /// The top-level scope, which `env` is outside of function calls.
+    globals: Shared<Env>,
+    /// Whether the breakpoint hook asked to stop again at the next statement.
+    stepping: bool,
 }
 
 impl Evaluator {
-    pub fn new(env: Rc<RefCell<Env>>) -> Self {
-        Evaluator { env }
+    pub fn new(env: Shared<Env>) -> Self {
+        Evaluator {
+            globals: Shared::clone(&env),
+            env,
+            base_dir: None,
+            importing: vec![],
+            options: Options::default(),
+            trace: None,
+            printed: Shared::new(String::new()),
+            depth: 0,
+            position: None,
+            tasks: vec![],
+            stepping: false,
+        }
     }
 
-    fn is_truthy(obj: Object) -> bool {
-        match obj {
-            Object::Null | Object::Bool(false) | Object::Int(325) => false,
-            _ => true,
+    /// Takes away the capabilities `sandbox` doesn't allow; denied builtins
+    /// and `引入` fail with `NO_PERMISSION`.
+    pub fn with_sandbox(mut self, sandbox: SandboxConfig) -> Self {
+        sandbox.restrict(&mut self.globals.borrow_mut());
+        self.options.sandbox = sandbox;
+        self
+    }
+
+    /// Adds an easter egg, or replaces one with the same name.
+    pub fn with_easter_egg(mut self, name: impl Into<String>, value: Object) -> Self {
+        self.options.easter_eggs.insert(name.into(), value);
+        self
+    }
+
+    /// Binds `name` to `value` at the top level. A `let`, parameter or
+    /// `del` of the same name, in any scope, is an error.
+    pub fn with_constant(mut self, name: impl Into<String>, value: Object) -> Self {
+        let name = name.into();
+        self.globals.borrow_mut().define(name.clone(), &value);
+        self.options.constants.insert(name, value);
+        self
+    }
+
+    pub fn is_constant(&self, name: &str) -> bool {
+        self.options.constants.contains_key(name)
+    }
+
+    fn check_not_constant(&self, name: &str) -> Result<(), Object> {
+        if self.is_constant(name) {
+            return Err(Self::error(format!("cannot assign to constant {name}")));
+        }
+        Ok(())
+    }
+
+    /// Parses `引入`ed modules as language `version`, see `Lexer::with_version`.
+    pub fn with_language_version(mut self, version: u32) -> Self {
+        self.options.version = version;
+        self
+    }
+
+    /// Cuts values short in error messages, see `ReprLimit`.
+    pub fn with_repr_limit(mut self, limit: ReprLimit) -> Self {
+        self.options.repr_limit = limit;
+        self
+    }
+
+    /// Words runtime errors in `lang`, like diagnostics.
+    pub fn with_lang(mut self, lang: Lang) -> Self {
+        self.options.lang = lang;
+        self
+    }
+
+    fn repr(&self, obj: &Object) -> String {
+        obj.repr(self.options.repr_limit)
+    }
+
+    /// Lets the host cancel evaluation by setting `flag`, e.g. from another
+    /// thread or through shared memory. It is checked before every loop
+    /// iteration and function call, and the run ends with an error.
+    pub fn with_interrupt(mut self, flag: Arc<AtomicBool>) -> Self {
+        self.options.interrupt.flag = Some(flag);
+        self
+    }
+
+    /// Like `with_interrupt`, for hosts that can't share a flag, like a wasm
+    /// module without shared memory: `check` is called at the same points,
+    /// and the run stops once it returns true.
+    pub fn with_stop_check(mut self, check: fn() -> bool) -> Self {
+        self.options.interrupt.check = Some(check);
+        self
+    }
+
+    /// Fails calls nested deeper than `depth` with `TOO_DEEP` instead of
+    /// overflowing the stack. Each level takes a few KiB of stack in release
+    /// builds and more in debug ones, so hosts evaluating on small threads
+    /// should lower it, or give the thread a bigger stack.
+    pub fn with_max_depth(mut self, depth: usize) -> Self {
+        self.options.max_depth = depth;
+        self
+    }
+
+    fn is_interrupted(&self) -> bool {
+        self.options.interrupt.is_set()
+    }
+
+    /// Records a `Step` at every statement of a program parsed
+    /// `with_positions`. Runs longer than `MAX_STEPS` end with an error.
+    /// Output goes on the step of the statement that printed it instead of
+    /// the output sink.
+    pub fn with_trace(mut self) -> Self {
+        self.trace = Some(vec![]);
+        self
+    }
+
+    /// Makes `breakpoint()` call `hook`, which can look around and then let
+    /// the program continue or step through it statement by statement.
+    /// Positions are only known for programs parsed `with_positions`.
+    pub fn with_breakpoint(mut self, hook: BreakpointHook) -> Self {
+        self.options.breakpoint = Some(hook);
+        self
+    }
+
+    fn hit_breakpoint(&mut self) {
+        if let Some(hook) = self.options.breakpoint {
+            let step = self.current_step(self.position.unwrap_or_default());
+            self.stepping = hook(&step) == Resume::Step;
+        }
+    }
+
+    /// Turns on runtime checks of annotated `let`s, parameters and return
+    /// values. Errors carry the statement position when the program was
+    /// parsed `with_positions`.
+    pub fn with_strict_types(mut self) -> Self {
+        self.options.strict = true;
+        self
+    }
+
+    /// `Ok` unless strict mode is on and `value` doesn't fit `ty`.
+    fn check_type(
+        &self,
+        what: &str,
+        ty: &Option<TypeAnnotation>,
+        value: &Object,
+    ) -> Result<(), Object> {
+        let ty = match ty {
+            Some(ty) if self.options.strict => ty,
+            _ => return Ok(()),
+        };
+
+        let at = match self.position {
+            Some(span) => format!(" at {span}"),
+            None => String::new(),
+        };
+
+        match ty.resolve() {
+            Some("any") => Ok(()),
+            Some(name) if name == value.type_name() => Ok(()),
+            Some(_) => Err(Self::error(format!(
+                "type error{at}: {what} expects {}, got {}",
+                ty.0,
+                value.type_name()
+            ))),
+            None => Err(Self::error(format!(
+                "type error{at}: unknown type {}",
+                ty.0
+            ))),
+        }
+    }
+
+    pub fn take_trace(&mut self) -> Vec<Step> {
+        self.trace.take().unwrap_or_default()
+    }
+
+    fn record_step(&mut self, span: Span) -> Option<Object> {
+        let steps = self.trace.as_ref()?.len();
+        if steps >= MAX_STEPS {
+            her_log!(warn, "stopped after {MAX_STEPS} steps");
+            return Some(Self::error(String::from(TOO_MANY_STEPS)));
+        }
+
+        self.file_printed();
+        let step = self.current_step(span);
+        self.trace.as_mut()?.push(step);
+        None
+    }
+
+    /// Puts what was printed since the last step on that step.
+    fn file_printed(&mut self) {
+        let printed = std::mem::take(&mut *self.printed.borrow_mut());
+        if let Some(step) = self.trace.as_mut().and_then(|trace| trace.last_mut()) {
+            step.output.push_str(&printed);
         }
     }
 
+    fn current_step(&self, span: Span) -> Step {
+        let mut locals = self
+            .env
+            .borrow()
+            .store
+            .iter()
+            .filter(|(_, value)| !matches!(value, Object::Builtin(..) | Object::EnvBuiltin(_)))
+            .map(|(name, value)| (name.clone(), value.clone()))
+            .collect::<Vec<_>>();
+        locals.sort_by(|a, b| a.0.cmp(&b.0));
+
+        Step {
+            span,
+            depth: self.depth,
+            locals,
+            output: String::new(),
+        }
+    }
+
+    pub fn with_base_dir(mut self, base_dir: impl Into<PathBuf>) -> Self {
+        self.base_dir = Some(base_dir.into());
+        self
+    }
+
+    fn is_truthy(obj: Object) -> bool {
+        obj.is_truthy()
+    }
+
     fn error(msg: String) -> Object {
         Object::Error(msg)
     }
 
+    /// The error for `code`, in the evaluator's language.
+    fn fail(&self, code: DiagnosticCode) -> Object {
+        Object::Error(code.message(self.options.lang))
+    }
+
+    fn type_mismatch(&self, infix: &Infix, left: &Object, right: &Object) -> Object {
+        self.fail(DiagnosticCode::TypeMismatch {
+            left: self.repr(left),
+            op: infix.to_string(),
+            right: self.repr(right),
+        })
+    }
+
+    /// Errors and `quit` both stop the run.
     fn is_error(obj: &Object) -> bool {
         match obj {
-            Object::Error(_) => true,
+            Object::Error(_) | Object::Exit(_) => true,
             _ => false,
         }
     }
 
+    /// Runs `program`, then the tasks it started but never awaited. An error
+    /// in either ends the run and drops the tasks left.
     pub fn eval(&mut self, program: &Program) -> Option<Object> {
+        // Builtins that take long check the flag too.
+        let outer = set_interrupt(self.options.interrupt.clone());
+        let result = match self.trace {
+            Some(_) => {
+                let printed = Shared::clone(&self.printed);
+                let outer = set_output_sink(Box::new(move |text: &str| {
+                    printed.borrow_mut().push_str(text)
+                }));
+                let result = self.eval_with_tasks(program);
+                set_output_sink(outer);
+                self.file_printed();
+                result
+            }
+            None => self.eval_with_tasks(program),
+        };
+        set_interrupt(outer);
+        result
+    }
+
+    fn eval_with_tasks(&mut self, program: &Program) -> Option<Object> {
+        let result = self.eval_program(program);
+        if matches!(result, Some(Object::Error(_) | Object::Exit(_))) {
+            self.tasks.clear();
+            Self::log_end(&result);
+            return result;
+        }
+
+        let result = self.run_tasks().or(result);
+        Self::log_end(&result);
+        result
+    }
+
+    /// Runs `program`, then each of its top-level `考一考` blocks in a scope
+    /// of its own, so what one test binds the next doesn't see. A program
+    /// that fails before getting to the tests gives that error instead.
+    pub fn run_tests(&mut self, program: &Program) -> Result<Vec<TestResult>, Object> {
+        if let Some(err) = self.eval(program).filter(Self::is_error) {
+            return Err(err);
+        }
+
+        let globals = self.env.clone();
+        let mut results = vec![];
+        for stmt in program {
+            let Stmt::Test { name, body } = stmt else {
+                continue;
+            };
+
+            self.env = Shared::new(Env::new_with_outer(globals.clone()));
+            let failure = match self.eval(body) {
+                Some(Object::Exit(0)) => None,
+                result => result.filter(Self::is_error),
+            };
+            results.push(TestResult {
+                name: name.clone(),
+                failure,
+            });
+        }
+        self.env = globals;
+
+        Ok(results)
+    }
+
+    fn log_end(result: &Option<Object>) {
+        match result {
+            Some(Object::Error(msg)) => her_log!(debug, "run failed: {msg}"),
+            Some(Object::Exit(code)) => her_log!(debug, "run exited with code {code}"),
+            _ => {}
+        }
+    }
+
+    fn eval_program(&mut self, program: &Program) -> Option<Object> {
         let mut result = None;
 
         for stmt in program {
-            if *stmt == Stmt::Blank {
+            if matches!(stmt, Stmt::Blank | Stmt::Comment(_)) {
                 continue;
             }
 
             match self.eval_stmt(stmt) {
                 Some(Object::ReturnValue(value)) => return Some(*value),
-                Some(Object::Error(msg)) => return Some(Object::Error(msg)),
+                Some(obj @ (Object::Error(_) | Object::Exit(_))) => return Some(obj),
                 obj => result = obj,
             }
         }
@@ -55,17 +467,55 @@ impl Evaluator {
         result
     }
 
+    /// The scheduler: runs pending tasks one at a time, oldest first,
+    /// including ones they start, until one fails.
+    fn run_tasks(&mut self) -> Option<Object> {
+        while !self.tasks.is_empty() {
+            let task = self.tasks.remove(0);
+            if !matches!(*task.borrow(), Task::Pending(..)) {
+                continue;
+            }
+
+            her_log!(trace, "running task, {} more pending", self.tasks.len());
+            let result = self.eval_await(Object::Task(task));
+            if Self::is_error(&result) {
+                self.tasks.clear();
+                return Some(result);
+            }
+        }
+
+        None
+    }
+
+    /// Runs a pending task to the end and gives its result; other values
+    /// are their own result.
+    fn eval_await(&mut self, value: Object) -> Object {
+        let Object::Task(task) = value else {
+            return value;
+        };
+
+        let state = std::mem::replace(&mut *task.borrow_mut(), Task::Running);
+        let result = match state {
+            Task::Pending(func, args) => self.apply_func(Some(func), args),
+            Task::Running => Self::error(String::from("task is waiting for itself")),
+            Task::Done(result) => result,
+        };
+
+        *task.borrow_mut() = Task::Done(result.clone());
+        result
+    }
+
     fn eval_block_stmt(&mut self, stmts: &BlockStmt) -> Option<Object> {
         let mut result = None;
 
         for stmt in stmts {
-            if *stmt == Stmt::Blank {
+            if matches!(stmt, Stmt::Blank | Stmt::Comment(_)) {
                 continue;
             }
 
             match self.eval_stmt(stmt) {
                 Some(Object::ReturnValue(value)) => return Some(Object::ReturnValue(value)),
-                Some(Object::Error(msg)) => return Some(Object::Error(msg)),
+                Some(obj @ (Object::Error(_) | Object::Exit(_))) => return Some(obj),
                 obj => result = obj,
             }
         }
@@ -80,7 +530,7 @@ impl Evaluator {
         let mut result = None;
 
         for stmt in stmts {
-            if *stmt == Stmt::Blank {
+            if matches!(stmt, Stmt::Blank | Stmt::Comment(_)) {
                 continue;
             }
 
@@ -88,7 +538,7 @@ impl Evaluator {
                 Some(Object::ReturnValue(value)) => return Some(Object::ReturnValue(value)),
                 Some(Object::BreakStatement) => return Some(Object::BreakStatement),
                 Some(Object::ContinueStatement) => return Some(Object::ContinueStatement),
-                Some(Object::Error(msg)) => return Some(Object::Error(msg)),
+                Some(obj @ (Object::Error(_) | Object::Exit(_))) => return Some(obj),
                 obj => result = obj,
             }
         }
@@ -98,18 +548,48 @@ impl Evaluator {
 
     fn eval_stmt(&mut self, stmt: &Stmt) -> Option<Object> {
         match stmt {
-            Stmt::Let(ident, expr) => {
+            Stmt::Let(ident, ty, expr) => {
                 let value = self.eval_expr(expr)?;
                 if Self::is_error(&value) {
                     Some(value)
                 } else {
                     let Ident(name) = ident;
-                    self.env.borrow_mut().set(name.clone(), &value);
+                    if let Err(err) = self.check_not_constant(name) {
+                        return Some(err);
+                    }
+                    if let Err(err) = self.check_type(&format!("variable {name}"), ty, &value) {
+                        return Some(err);
+                    }
+                    self.env.borrow_mut().define(name.clone(), &value);
                     None
                 }
             }
+            Stmt::Assign(Ident(name), expr) => {
+                if let Err(err) = self.check_not_constant(name) {
+                    return Some(err);
+                }
+                if self.env.borrow_mut().get(name.clone()).is_none() {
+                    return Some(
+                        self.fail(DiagnosticCode::UnknownIdentifier { name: name.clone() }),
+                    );
+                }
+                let value = self.eval_expr(expr)?;
+                if Self::is_error(&value) {
+                    return Some(value);
+                }
+                self.env.borrow_mut().assign(name.clone(), &value);
+                None
+            }
             Stmt::Break => Some(Object::BreakStatement),
             Stmt::Continue => Some(Object::ContinueStatement),
+            Stmt::Import(path) => self.eval_import_stmt(path),
+            Stmt::Mark(span) => {
+                self.position = Some(*span);
+                if self.stepping {
+                    self.hit_breakpoint();
+                }
+                self.record_step(*span)
+            }
             Stmt::Expr(expr) => self.eval_expr(expr),
             Stmt::Return(expr) => {
                 let value = self.eval_expr(expr)?;
@@ -123,13 +603,118 @@ impl Evaluator {
         }
     }
 
+    fn resolve_import_path(&self, path: &str) -> PathBuf {
+        let path = Path::new(path);
+
+        if path.is_absolute() {
+            return path.to_path_buf();
+        }
+
+        match &self.base_dir {
+            Some(dir) => dir.join(path),
+            None => std::env::current_dir()
+                .map(|cwd| cwd.join(path))
+                .unwrap_or_else(|_| path.to_path_buf()),
+        }
+    }
+
+    /// Runs the module in its own scope and copies its top-level bindings
+    /// into the current environment.
+    fn eval_import_stmt(&mut self, path: &str) -> Option<Object> {
+        if !self.options.sandbox.filesystem {
+            return Some(Self::error(String::from(NO_PERMISSION)));
+        }
+
+        let full_path = self.resolve_import_path(path);
+        let full_path = full_path.canonicalize().unwrap_or(full_path);
+        her_log!(debug, "importing {}", full_path.display());
+
+        if self.importing.contains(&full_path) {
+            return Some(Self::error(format!("circular import: {path}")));
+        }
+
+        let program = match herc::load(&full_path, self.options.version) {
+            Some(program) => program,
+            None => match self.parse_module(path, &full_path) {
+                Ok(program) => program,
+                Err(err) => return Some(err),
+            },
+        };
+
+        // Constants are bound outside the module's own scope, so they can be
+        // read there but aren't exported back.
+        let mut builtins = Env::from(new_builtins());
+        self.options.sandbox.restrict(&mut builtins);
+        for (name, value) in &self.options.constants {
+            builtins.define(name.clone(), value);
+        }
+        let module_env = Shared::new(Env::new_with_outer(Shared::new(builtins)));
+        let mut module = Evaluator::new(Shared::clone(&module_env));
+        module.options = self.options.clone();
+        module.depth = self.depth;
+        module.base_dir = full_path.parent().map(Path::to_path_buf);
+        module.importing = self.importing.clone();
+        module.importing.push(full_path);
+
+        if let Some(obj @ (Object::Error(_) | Object::Exit(_))) = module.eval(&program) {
+            return Some(obj);
+        }
+
+        for (name, value) in module_env.borrow().store.iter() {
+            if let Err(err) = self.check_not_constant(name) {
+                return Some(err);
+            }
+            self.env.borrow_mut().define(name.clone(), value);
+        }
+
+        None
+    }
+
+    fn parse_module(&self, path: &str, full_path: &Path) -> Result<Program, Object> {
+        let source = std::fs::read_to_string(full_path)
+            .map_err(|err| Self::error(format!("cannot import {path}: {err}")))?;
+
+        let mut parser = Parser::new(Lexer::new(&source).with_version(self.options.version));
+        let program = parser.parse();
+        let errors = parser.get_errors();
+        if !errors.is_empty() {
+            let msg = errors
+                .iter()
+                .map(|e| e.to_string())
+                .collect::<Vec<_>>()
+                .join("\n");
+            return Err(Self::error(format!("cannot import {path}: {msg}")));
+        }
+        Ok(program)
+    }
+
     fn eval_expr(&mut self, expr: &Expr) -> Option<Object> {
+        let result = self.eval_expr_unchecked(expr);
+        #[cfg(feature = "invariants")]
+        if let Some(obj) = &result {
+            invariants::check(obj, || {
+                format!("after: {expr:?}\n  at: {:?}", self.position)
+            });
+        }
+        result
+    }
+
+    fn eval_expr_unchecked(&mut self, expr: &Expr) -> Option<Object> {
         match expr {
             Expr::Ident(ident) => Some(self.eval_ident(ident)),
             Expr::Literal(literal) => Some(self.eval_literal(literal)),
             Expr::Prefix(prefix, right_expr) => self
                 .eval_expr(right_expr)
                 .map(|right| self.eval_prefix_expr(prefix, right)),
+            Expr::Infix(infix @ (Infix::And | Infix::Or), left_expr, right_expr) => {
+                self.eval_logical_expr(infix, left_expr, right_expr)
+            }
+            Expr::Infix(Infix::Coalesce, left_expr, right_expr) => {
+                match self.eval_expr(left_expr)? {
+                    Object::Null => self.eval_expr(right_expr),
+                    left => Some(left),
+                }
+            }
             Expr::Infix(infix, left_expr, right_expr) => {
                 if let (Some(left), Some(right)) =
                     (self.eval_expr(left_expr), self.eval_expr(right_expr))
@@ -151,21 +736,86 @@ impl Evaluator {
                 alternative,
             } => self.eval_if_expr(cond, consequence, alternative),
             Expr::While { cond, consequence } => self.eval_while_expr(cond, consequence),
-            Expr::Func { params, body } => Some(Object::Func(
-                params.clone(),
-                body.clone(),
-                Rc::clone(&self.env),
+            Expr::DoWhile { consequence, cond } => self.eval_do_while_expr(consequence, cond),
+            Expr::Match { subject, arms } => self.eval_match_expr(subject, arms),
+            Expr::For {
+                vars,
+                iterable,
+                body,
+            } => self.eval_for_expr(vars, iterable, body),
+            Expr::CFor {
+                init,
+                cond,
+                step,
+                body,
+            } => self.eval_expr(&desugar_c_for(
+                init.as_deref(),
+                cond.as_deref(),
+                step.as_deref(),
+                body,
+                None,
             )),
+            Expr::Func {
+                params,
+                return_ty,
+                body,
+                is_async,
+            } => {
+                let func = Object::Func(
+                    params.clone(),
+                    return_ty.clone(),
+                    body.clone(),
+                    Shared::clone(&self.env),
+                );
+
+                Some(match is_async {
+                    true => Object::Async(Box::new(func)),
+                    false => func,
+                })
+            }
             Expr::Call { func, args } => Some(self.eval_call_expr(func, args)),
         }
     }
 
+    /// The callee of `receiver.name(args)`. A hash entry called `name` wins;
+    /// otherwise a builtin method takes `receiver` as its first argument.
+    fn eval_method(
+        &mut self,
+        receiver: &Expr,
+        name: &str,
+        args: &mut Vec<Object>,
+    ) -> Option<Object> {
+        let receiver = self.eval_expr(receiver)?;
+        let key = Object::String(String::from(name));
+
+        if Self::is_error(&receiver) {
+            return Some(receiver);
+        }
+        if let Object::Hash(ref hash) = receiver
+            && hash.contains_key(&key)
+        {
+            return Some(self.eval_index_expr(receiver, key));
+        }
+
+        match method(receiver.type_name(), name) {
+            Some(method) => {
+                args.insert(0, receiver);
+                Some(method)
+            }
+            None => Some(self.eval_index_expr(receiver, key)),
+        }
+    }
+
     fn eval_ident(&mut self, ident: &Ident) -> Object {
         let Ident(name) = ident;
 
-        match self.env.borrow_mut().get(name.clone()) {
-            Some(value) => value,
-            None => Object::Error(format!("identifier not found: {name}")),
+        if let Some(value) = self.env.borrow_mut().get(name.clone()) {
+            return value;
+        }
+
+        match self.options.easter_eggs.get(name) {
+            Some(value) => value.clone(),
+            None => self.fail(DiagnosticCode::UnknownIdentifier { name: name.clone() }),
         }
     }
 
@@ -174,6 +824,7 @@ impl Evaluator {
             Prefix::Not => self.eval_not_op_expr(right),
             Prefix::Minus => self.eval_minus_prefix_op_expr(right),
             Prefix::Plus => self.eval_plus_prefix_op_expr(right),
+            Prefix::Await => self.eval_await(right),
         }
     }
 
@@ -188,45 +839,160 @@ impl Evaluator {
 
     fn eval_minus_prefix_op_expr(&mut self, right: Object) -> Object {
         match right {
+            Object::Int(value) | Object::Quantity(value, _) if value == i64::MIN => {
+                self.fail(DiagnosticCode::IntegerOverflow {
+                    expr: format!("-({})", self.repr(&right)),
+                })
+            }
             Object::Int(value) => Object::Int(-value),
-            _ => Self::error(format!("unknown operator: -{right}")),
+            Object::Float(value) => Object::Float(-value),
+            Object::Quantity(value, unit) => Object::Quantity(-value, unit),
+            _ => self.fail(DiagnosticCode::UnknownPrefixOperator {
+                op: String::from("-"),
+                right: self.repr(&right),
+            }),
         }
     }
 
     fn eval_plus_prefix_op_expr(&mut self, right: Object) -> Object {
         match right {
             Object::Int(value) => Object::Int(value),
-            _ => Self::error(format!("unknown operator: {right}")),
+            Object::Float(_) | Object::Quantity(..) => right,
+            _ => self.fail(DiagnosticCode::UnknownPrefixOperator {
+                op: String::new(),
+                right: self.repr(&right),
+            }),
         }
     }
 
     fn eval_infix_expr(&mut self, infix: &Infix, left: Object, right: Object) -> Object {
+        let is_number = |obj: &Object| {
+            matches!(
+                obj,
+                Object::Int(_) | Object::Float(_) | Object::Quantity(..)
+            )
+        };
+        if !(is_number(&left) && is_number(&right)) && infix.is_comparison() {
+            return self.eval_compare_expr(infix, left, right);
+        }
+
+        match (&left, &right) {
+            (Object::Float(left), Object::Float(right)) => {
+                return self.eval_infix_float_expr(infix, *left, *right);
+            }
+            (Object::Int(left), Object::Float(right)) => {
+                return self.eval_infix_float_expr(infix, *left as f64, *right);
+            }
+            (Object::Float(left), Object::Int(right)) => {
+                return self.eval_infix_float_expr(infix, *left, *right as f64);
+            }
+            (Object::Float(_), _) => {
+                return self.type_mismatch(infix, &left, &right);
+            }
+            _ => {}
+        }
+
         match left {
-            Object::Int(left_value) => {
-                if let Object::Int(right_value) = right {
+            Object::Int(left_value) => match right {
+                Object::Int(right_value) => {
                     self.eval_infix_int_expr(infix, left_value, right_value)
-                } else {
-                    Self::error(format!("type mismatch: {left} {infix} {right}"))
                 }
-            }
+                Object::Quantity(right_value, unit) if *infix == Infix::Multiply => {
+                    match self.eval_infix_int_expr(infix, left_value, right_value) {
+                        Object::Int(value) => Object::Quantity(value, unit),
+                        error => error,
+                    }
+                }
+                _ => self.type_mismatch(infix, &left, &right),
+            },
+            Object::Quantity(left_value, ref unit) => match right {
+                Object::Quantity(right_value, ref right_unit) if unit == right_unit => {
+                    self.eval_infix_quantity_expr(infix, left_value, unit, right_value)
+                }
+                Object::Int(right_value) if matches!(infix, Infix::Multiply | Infix::Divide) => {
+                    match self.eval_infix_int_expr(infix, left_value, right_value) {
+                        Object::Int(value) => Object::Quantity(value, unit.clone()),
+                        error => error,
+                    }
+                }
+                _ => self.type_mismatch(infix, &left, &right),
+            },
             Object::String(left_value) => {
                 if let Object::String(right_value) = right {
                     self.eval_infix_string_expr(infix, left_value, right_value)
                 } else {
-                    Self::error(format!("type mismatch: {left_value} {infix} {right}"))
+                    self.fail(DiagnosticCode::TypeMismatch {
+                        left: left_value,
+                        op: infix.to_string(),
+                        right: self.repr(&right),
+                    })
                 }
             }
-            _ => Self::error(format!("unknown operator: {left} {infix} {right}")),
+            _ => self.fail(DiagnosticCode::UnknownInfixOperator {
+                left: self.repr(&left),
+                op: infix.to_string(),
+                right: self.repr(&right),
+            }),
+        }
+    }
+
+    /// `==` and `!=` between any values, see `Object::deep_eq`. Strings,
+    /// bools and arrays are also ordered among themselves by
+    /// `Object::cmp_total`.
+    fn eval_compare_expr(&self, infix: &Infix, left: Object, right: Object) -> Object {
+        if Self::is_error(&left) {
+            return left;
+        }
+        if Self::is_error(&right) {
+            return right;
+        }
+
+        let ordered =
+            |obj: &Object| matches!(obj, Object::String(_) | Object::Bool(_) | Object::Array(_));
+        let order = left.cmp_total(&right);
+        match infix {
+            Infix::Equal => Object::Bool(left.deep_eq(&right)),
+            Infix::NotEqual => Object::Bool(!left.deep_eq(&right)),
+            _ if !ordered(&left) || left.type_name() != right.type_name() => {
+                self.type_mismatch(infix, &left, &right)
+            }
+            Infix::LessThan => Object::Bool(order.is_lt()),
+            Infix::LessThanEqual => Object::Bool(order.is_le()),
+            Infix::GreaterThan => Object::Bool(order.is_gt()),
+            _ => Object::Bool(order.is_ge()),
+        }
+    }
+
+    /// `&&` and `||` only evaluate the right side when it decides the result.
+    fn eval_logical_expr(&mut self, infix: &Infix, left: &Expr, right: &Expr) -> Option<Object> {
+        let left = self.eval_expr(left)?;
+        if Self::is_error(&left) {
+            return Some(left);
+        }
+
+        let left = Self::is_truthy(left);
+        if (*infix == Infix::And) != left {
+            return Some(Object::Bool(left));
+        }
+
+        let right = self.eval_expr(right)?;
+        if Self::is_error(&right) {
+            return Some(right);
         }
+
+        Some(Object::Bool(Self::is_truthy(right)))
     }
 
     fn eval_index_expr(&mut self, left: Object, index: Object) -> Object {
         match left {
             Object::Array(ref array) => {
                 if let Object::Int(i) = index {
-                    self.eval_array_index_expr(array.clone(), i)
+                    self.eval_array_index_expr(array, i)
                 } else {
-                    Self::error(format!("index operator not supported: {left}"))
+                    self.fail(DiagnosticCode::IndexNotSupported {
+                        left: self.repr(&left),
+                        index: None,
+                    })
                 }
             }
             Object::Hash(ref hash) => match index {
@@ -234,14 +1000,19 @@ impl Evaluator {
                     Some(o) => o.clone(),
                     None => Object::Null,
                 },
-                Object::Error(_) => index,
-                _ => Self::error(format!("unusable as hash key: {index}")),
+                Object::Error(_) | Object::Exit(_) => index,
+                _ => self.fail(DiagnosticCode::UnusableHashKey {
+                    key: self.repr(&index),
+                }),
             },
-            _ => Self::error(format!("uknown operator: {left} {index}")),
+            _ => self.fail(DiagnosticCode::IndexNotSupported {
+                left: self.repr(&left),
+                index: Some(self.repr(&index)),
+            }),
         }
     }
 
-    fn eval_array_index_expr(&mut self, array: Vec<Object>, index: i64) -> Object {
+    fn eval_array_index_expr(&mut self, array: &[Object], index: i64) -> Object {
         let max = array.len() as i64;
 
         if index < 0 || index > max {
@@ -255,17 +1026,66 @@ impl Evaluator {
     }
 
     fn eval_infix_int_expr(&mut self, infix: &Infix, left: i64, right: i64) -> Object {
+        let checked = |value: Option<i64>| match value {
+            Some(value) => Object::Int(value),
+            None => self.fail(DiagnosticCode::IntegerOverflow {
+                expr: format!("{left} {infix} {right}"),
+            }),
+        };
+
+        match infix {
+            Infix::Plus => checked(left.checked_add(right)),
+            Infix::Minus => checked(left.checked_sub(right)),
+            Infix::Multiply => checked(left.checked_mul(right)),
+            Infix::Divide if right == 0 => self.fail(DiagnosticCode::DivisionByZero),
+            Infix::Divide => checked(left.checked_div(right)),
+            Infix::LessThan => Object::Bool(left < right),
+            Infix::LessThanEqual => Object::Bool(left <= right),
+            Infix::GreaterThan => Object::Bool(left > right),
+            Infix::GreaterThanEqual => Object::Bool(left >= right),
+            Infix::Equal => Object::Bool(left == right),
+            Infix::NotEqual => Object::Bool(left != right),
+            Infix::And | Infix::Or | Infix::Coalesce => unreachable!("handled by eval_expr"),
+        }
+    }
+
+    /// Ints mixed with floats are widened first, so `1 / 2.0` is `0.5`.
+    fn eval_infix_float_expr(&mut self, infix: &Infix, left: f64, right: f64) -> Object {
         match infix {
-            Infix::Plus => Object::Int(left + right),
-            Infix::Minus => Object::Int(left - right),
-            Infix::Multiply => Object::Int(left * right),
-            Infix::Divide => Object::Int(left / right),
+            Infix::Plus => Object::Float(left + right),
+            Infix::Minus => Object::Float(left - right),
+            Infix::Multiply => Object::Float(left * right),
+            Infix::Divide if right == 0.0 => self.fail(DiagnosticCode::DivisionByZero),
+            Infix::Divide => Object::Float(left / right),
             Infix::LessThan => Object::Bool(left < right),
             Infix::LessThanEqual => Object::Bool(left <= right),
             Infix::GreaterThan => Object::Bool(left > right),
             Infix::GreaterThanEqual => Object::Bool(left >= right),
             Infix::Equal => Object::Bool(left == right),
             Infix::NotEqual => Object::Bool(left != right),
+            Infix::And | Infix::Or | Infix::Coalesce => unreachable!("handled by eval_expr"),
+        }
+    }
+
+    /// Same units add up, compare, and divide into a plain ratio.
+    fn eval_infix_quantity_expr(
+        &mut self,
+        infix: &Infix,
+        left: i64,
+        unit: &str,
+        right: i64,
+    ) -> Object {
+        match infix {
+            Infix::Plus | Infix::Minus => match self.eval_infix_int_expr(infix, left, right) {
+                Object::Int(value) => Object::Quantity(value, String::from(unit)),
+                error => error,
+            },
+            Infix::Multiply => self.fail(DiagnosticCode::UnknownInfixOperator {
+                left: format!("{left}{unit}"),
+                op: infix.to_string(),
+                right: format!("{right}{unit}"),
+            }),
+            _ => self.eval_infix_int_expr(infix, left, right),
         }
     }
 
@@ -279,6 +1099,8 @@ impl Evaluator {
     fn eval_literal(&mut self, literal: &Literal) -> Object {
         match literal {
             Literal::Int(value) => Object::Int(*value),
+            Literal::Float(value) => Object::Float(*value),
+            Literal::Quantity(value, unit) => Object::Quantity(*value, unit.clone()),
             Literal::Bool(value) => Object::Bool(*value),
             Literal::String(value) => Object::String(value.clone()),
             Literal::Array(objects) => self.eval_array_literal(objects),
@@ -291,7 +1113,8 @@ impl Evaluator {
             objects
                 .iter()
                 .map(|e| self.eval_expr(&e.clone()).unwrap_or(Object::Null))
-                .collect::<Vec<_>>(),
+                .collect::<Vec<_>>()
+                .into(),
         )
     }
 
@@ -313,7 +1136,7 @@ impl Evaluator {
             hash.insert(key, value);
         }
 
-        Object::Hash(hash)
+        Object::Hash(hash.into())
     }
 
     fn eval_if_expr(
@@ -333,10 +1156,89 @@ impl Evaluator {
         }
     }
 
+    fn eval_match_expr(&mut self, subject: &Expr, arms: &[MatchArm]) -> Option<Object> {
+        let subject = self.eval_expr(subject)?;
+        if Self::is_error(&subject) {
+            return Some(subject);
+        }
+
+        for MatchArm { pattern, body } in arms {
+            let mut bindings = vec![];
+            if !self.match_pattern(pattern, &subject, &mut bindings) {
+                continue;
+            }
+
+            for (name, value) in bindings {
+                if let Err(err) = self.check_not_constant(&name) {
+                    return Some(err);
+                }
+                self.env.borrow_mut().define(name, &value);
+            }
+            return self.eval_block_stmt(body);
+        }
+
+        Some(Object::Null)
+    }
+
+    /// Whether `value` matches `pattern`, pushing what its names bind to onto
+    /// `bindings`. Nothing is defined here, so a pattern that fails halfway
+    /// leaves the environment alone.
+    fn match_pattern(
+        &mut self,
+        pattern: &Pattern,
+        value: &Object,
+        bindings: &mut Vec<(String, Object)>,
+    ) -> bool {
+        match (pattern, value) {
+            (Pattern::Wildcard, _) => true,
+            (Pattern::Literal(literal), _) => {
+                let literal = self.eval_literal(literal);
+                self.eval_infix_expr(&Infix::Equal, value.clone(), literal) == Object::Bool(true)
+            }
+            (Pattern::Binding(Ident(name)), _) => {
+                bindings.push((name.clone(), value.clone()));
+                true
+            }
+            (Pattern::Array(patterns, rest), Object::Array(items)) => {
+                let fits = match rest {
+                    Some(_) => items.len() >= patterns.len(),
+                    None => items.len() == patterns.len(),
+                };
+                if !fits
+                    || !patterns
+                        .iter()
+                        .zip(items.iter())
+                        .all(|(pattern, item)| self.match_pattern(pattern, item, bindings))
+                {
+                    return false;
+                }
+                if let Some(Ident(rest)) = rest {
+                    let others = items[patterns.len()..].to_vec().into();
+                    bindings.push((rest.clone(), Object::Array(others)));
+                }
+                true
+            }
+            (Pattern::Hash(entries), Object::Hash(hash)) => entries.iter().all(|(key, pattern)| {
+                hash.get(&Object::String(key.clone()))
+                    .is_some_and(|value| self.match_pattern(pattern, value, bindings))
+            }),
+            (Pattern::Array(..) | Pattern::Hash(_), _) => false,
+        }
+    }
+
     fn eval_while_expr(&mut self, cond: &Expr, consequence: &BlockStmt) -> Option<Object> {
         let mut result: Option<Object> = None;
 
         while let Some(cond_result) = self.eval_expr(cond) {
+            if self.is_interrupted() {
+                her_log!(debug, "interrupted");
+                return Some(Self::error(String::from(INTERRUPTED)));
+            }
+
+            if Self::is_error(&cond_result) {
+                return Some(cond_result);
+            }
+
             if !Self::is_truthy(cond_result.clone()) {
                 break;
             }
@@ -352,6 +1254,7 @@ impl Evaluator {
                     continue;
                 }
                 Some(Object::ReturnValue(value)) => return Some(Object::ReturnValue(value)),
+                Some(obj @ (Object::Error(_) | Object::Exit(_))) => return Some(obj),
                 _ => {}
             }
         }
@@ -359,68 +1262,345 @@ impl Evaluator {
         result
     }
 
-    fn eval_call_expr(&mut self, func: &Expr, args: &[Expr]) -> Object {
-        let args = args
-            .iter()
-            .map(|e| self.eval_expr(e).unwrap_or(Object::Null))
-            .collect::<Vec<_>>();
+    /// Like `eval_while_expr`, but checks `cond` after each run of the body,
+    /// `continue` included.
+    fn eval_do_while_expr(&mut self, consequence: &BlockStmt, cond: &Expr) -> Option<Object> {
+        loop {
+            if self.is_interrupted() {
+                her_log!(debug, "interrupted");
+                return Some(Self::error(String::from(INTERRUPTED)));
+            }
 
-        let (params, body, env) = match self.eval_expr(func) {
-            Some(Object::Func(params, body, env)) => (params, body, env),
-            Some(Object::Builtin(expect_param_num, f)) => {
-                if expect_param_num < 0 || expect_param_num == args.len() as i32 {
-                    return f(args);
-                } else {
-                    return Self::error(format!(
-                        "wrong number of arguments. got={}, want={}",
-                        args.len(),
-                        expect_param_num,
-                    ));
+            let result = match self.eval_block_stmt_with_continue_and_break_statement(consequence) {
+                Some(Object::BreakStatement) => return Some(Object::Null),
+                Some(Object::ContinueStatement) => Some(Object::Null),
+                Some(Object::ReturnValue(value)) => return Some(Object::ReturnValue(value)),
+                Some(obj @ (Object::Error(_) | Object::Exit(_))) => return Some(obj),
+                result => result,
+            };
+
+            let cond_result = self.eval_expr(cond)?;
+            if Self::is_error(&cond_result) {
+                return Some(cond_result);
+            }
+
+            if !Self::is_truthy(cond_result) {
+                return result;
+            }
+        }
+    }
+
+    /// Binds each item of an array, string (by char) or iterator, or each key
+    /// of a hash, to `vars[0]`. A second var gets the item and the first the
+    /// index (or key) instead. Iterators are pulled one item at a time
+    /// through `next()`, as a program would; a hash with a `next` function
+    /// counts as one, and is pulled until it says `done`.
+    fn eval_for_expr(
+        &mut self,
+        vars: &[Ident],
+        iterable: &Expr,
+        body: &BlockStmt,
+    ) -> Option<Object> {
+        let iterable = self.eval_expr(iterable)?;
+        // The `next` to call for each item and its arguments.
+        let source = match &iterable {
+            Object::Iter(_) => {
+                method("iterator", "next").map(|next| (next, vec![iterable.clone()]))
+            }
+            Object::Hash(hash) => hash
+                .get(&Object::String(String::from("next")))
+                .filter(|next| next.type_name() == "function")
+                .map(|next| (next.clone(), vec![])),
+            _ => None,
+        };
+        let entries = match &iterable {
+            _ if source.is_some() => vec![],
+            Object::Array(_) => vec![],
+            Object::String(s) => s
+                .chars()
+                .enumerate()
+                .map(|(i, c)| (Object::Int(i as i64), Object::String(c.to_string())))
+                .collect(),
+            Object::Hash(hash) => sorted_entries(hash)
+                .into_iter()
+                .map(|(key, value)| (key.clone(), value.clone()))
+                .collect(),
+            Object::Error(_) | Object::Exit(_) => return Some(iterable),
+            o => {
+                return Some(Self::error(format!(
+                    "for-in not supported: {}",
+                    self.repr(o)
+                )));
+            }
+        };
+        let pull = |this: &mut Self, index: usize| {
+            let key = Object::Int(index as i64);
+            match (&iterable, &source) {
+                (_, Some((next, args))) => {
+                    let step = this.apply_func(Some(next.clone()), args.clone());
+                    this.step_value(step)
+                        .map(|value| value.map(|value| (key, value)))
+                }
+                (Object::Array(items), _) => Ok(items.get(index).map(|item| (key, item.clone()))),
+                _ => Ok(entries.get(index).cloned()),
+            }
+        };
+
+        let mut result = None;
+        let mut index = 0;
+
+        loop {
+            let (key, value) = match pull(self, index) {
+                Ok(Some(item)) => item,
+                Ok(None) => break,
+                Err(err) => return Some(err),
+            };
+            index += 1;
+
+            if self.is_interrupted() {
+                her_log!(debug, "interrupted");
+                return Some(Self::error(String::from(INTERRUPTED)));
+            }
+
+            if let Some(err) = vars
+                .iter()
+                .find_map(|Ident(name)| self.check_not_constant(name).err())
+            {
+                return Some(err);
+            }
+
+            match vars {
+                [Ident(name)] if matches!(iterable, Object::Hash(_)) && source.is_none() => {
+                    self.env.borrow_mut().define(name.clone(), &key);
+                }
+                [Ident(name)] => self.env.borrow_mut().define(name.clone(), &value),
+                [Ident(key_name), Ident(name), ..] => {
+                    let mut env = self.env.borrow_mut();
+                    env.define(key_name.clone(), &key);
+                    env.define(name.clone(), &value);
+                }
+                [] => {}
+            }
+
+            result = self.eval_block_stmt_with_continue_and_break_statement(body);
+            match result {
+                Some(Object::BreakStatement) => {
+                    result = Some(Object::Null);
+                    break;
+                }
+                Some(Object::ContinueStatement) => {
+                    result = Some(Object::Null);
+                    continue;
+                }
+                Some(Object::ReturnValue(value)) => return Some(Object::ReturnValue(value)),
+                Some(obj @ (Object::Error(_) | Object::Exit(_))) => return Some(obj),
+                _ => {}
+            }
+        }
+
+        result
+    }
+
+    /// The value of a `{"value", "done"}` step that `next()` returned, or
+    /// `None` once it's done.
+    fn step_value(&self, step: Object) -> Result<Option<Object>, Object> {
+        match step {
+            Object::Hash(hash) => {
+                let get = |name: &str| hash.get(&Object::String(String::from(name))).cloned();
+                match get("done") {
+                    Some(done) if done.is_truthy() => Ok(None),
+                    _ => Ok(Some(get("value").unwrap_or(Object::Null))),
+                }
+            }
+            err @ (Object::Error(_) | Object::Exit(_)) => Err(err),
+            o => Err(Self::error(format!(
+                "next() must return {{\"value\", \"done\"}}, got {}",
+                self.repr(&o)
+            ))),
+        }
+    }
+
+    fn eval_call_expr(&mut self, func: &Expr, args: &[Expr]) -> Object {
+        let mut args = args
+            .iter()
+            .map(|e| self.eval_expr(e).unwrap_or(Object::Null))
+            .collect::<Vec<_>>();
+
+        let func = match func.as_dot_access() {
+            Some((receiver, name)) => self.eval_method(receiver, name, &mut args),
+            None => self.eval_expr(func),
+        };
+
+        self.apply_func(func, args)
+    }
+
+    fn eval_env_builtin(&mut self, builtin: EnvBuiltin, args: Vec<Object>) -> Object {
+        match (builtin, &args[..]) {
+            (EnvBuiltin::Globals, _) => Self::snapshot(&self.globals),
+            (EnvBuiltin::Locals, _) => Self::snapshot(&self.env),
+            (EnvBuiltin::Del, [Object::String(name)]) => {
+                if let Err(err) = self.check_not_constant(name) {
+                    return err;
+                }
+                match self.env.borrow_mut().remove(name) {
+                    Some(value) => value,
+                    None => Self::error(format!("cannot delete {name}: not defined in this scope")),
+                }
+            }
+            (EnvBuiltin::Del, _) => {
+                Self::error(format!("argument to `del` must be string. got {}", args[0]))
+            }
+            (EnvBuiltin::Breakpoint, _) => {
+                self.hit_breakpoint();
+                Object::Null
+            }
+        }
+    }
+
+    /// A scope as a hash from name to value, builtins left out.
+    #[allow(clippy::mutable_key_type)]
+    fn snapshot(env: &Shared<Env>) -> Object {
+        Object::Hash(
+            env.borrow()
+                .store
+                .iter()
+                .filter(|(_, value)| !matches!(value, Object::Builtin(..) | Object::EnvBuiltin(_)))
+                .map(|(name, value)| (Object::String(name.clone()), value.clone()))
+                .collect::<HashMap<_, _>>()
+                .into(),
+        )
+    }
+
+    fn apply_func(&mut self, func: Option<Object>, args: Vec<Object>) -> Object {
+        let (params, return_ty, body, env) = match func {
+            Some(Object::Func(params, return_ty, body, env)) => (params, return_ty, body, env),
+            Some(Object::Builtin(expect_param_num, f)) => {
+                if expect_param_num < 0 || expect_param_num == args.len() as i32 {
+                    return f(args);
+                } else {
+                    return self.fail(DiagnosticCode::WrongArgumentCount {
+                        want: expect_param_num.to_string(),
+                        got: args.len(),
+                    });
                 }
             }
-            Some(o) => return Self::error(format!("{o} is not valid function")),
+            Some(Object::EnvBuiltin(builtin)) if builtin.arity() != args.len() => {
+                return self.fail(DiagnosticCode::WrongArgumentCount {
+                    want: builtin.arity().to_string(),
+                    got: args.len(),
+                });
+            }
+            Some(Object::EnvBuiltin(builtin)) => return self.eval_env_builtin(builtin, args),
+            Some(Object::Async(func)) => {
+                let task = Shared::new(Task::Pending(*func, args));
+                self.tasks.push(Shared::clone(&task));
+                return Object::Task(task);
+            }
+            Some(o) => {
+                return self.fail(DiagnosticCode::NotAFunction {
+                    func: self.repr(&o),
+                });
+            }
             None => return Object::Null,
         };
 
-        if params.len() != args.len() {
-            return Self::error(format!(
-                "wrong number of arguments: {} expected but {} given",
-                params.len(),
-                args.len()
-            ));
+        if self.is_interrupted() {
+            her_log!(debug, "interrupted");
+            return Self::error(String::from(INTERRUPTED));
         }
 
-        let current_env = Rc::clone(&self.env);
-        let mut scoped_env = Env::new_with_outer(Rc::clone(&env));
-        let list = params.iter().zip(args.iter());
-        for (ident, o) in list {
-            let Ident(name) = ident.clone();
-            scoped_env.set(name, o);
+        if self.depth >= self.options.max_depth {
+            her_log!(debug, "call depth over {}", self.options.max_depth);
+            return Self::error(String::from(TOO_DEEP));
         }
 
-        self.env = Rc::new(RefCell::new(scoped_env));
+        let required = required_params(&params);
+        if args.len() < required || args.len() > params.len() {
+            let expected = if required == params.len() {
+                required.to_string()
+            } else {
+                format!("{required} to {}", params.len())
+            };
+            return self.fail(DiagnosticCode::WrongArgumentCount {
+                want: expected,
+                got: args.len(),
+            });
+        }
 
-        let object = self.eval_block_stmt(&body);
+        if let Some(err) = params
+            .iter()
+            .find_map(|param| self.check_not_constant(&param.ident.0).err())
+        {
+            return err;
+        }
 
-        self.env = current_env;
+        let current_env = Shared::clone(&self.env);
+        let position = self.position;
+        self.env = Shared::new(Env::new_with_outer(Shared::clone(&env)));
+        let mut args = args.into_iter();
+        for Param { ident, ty, default } in params.iter() {
+            let Ident(name) = ident.clone();
+            // Defaults see the parameters before them.
+            let o = match (args.next(), default) {
+                (Some(o), _) => o,
+                (None, Some(default)) => match self.eval_expr(default) {
+                    Some(o) if Self::is_error(&o) => {
+                        self.env = current_env;
+                        return o;
+                    }
+                    o => o.unwrap_or(Object::Null),
+                },
+                (None, None) => unreachable!("checked against required_params"),
+            };
+            if let Err(err) = self.check_type(&format!("parameter {name}"), ty, &o) {
+                self.env = current_env;
+                return err;
+            }
+            self.env.borrow_mut().define(name, &o);
+        }
+
+        self.depth += 1;
+        her_log!(
+            trace,
+            "calling fn({}) at depth {}",
+            params
+                .iter()
+                .map(|p| p.ident.0.as_str())
+                .collect::<Vec<_>>()
+                .join(", "),
+            self.depth
+        );
 
-        match object {
+        let object = match self.eval_block_stmt(&body) {
             Some(Object::ReturnValue(o)) => *o,
             Some(o) => o,
             None => Object::Null,
-        }
+        };
+        let object = match self.check_type("return value", &return_ty, &object) {
+            Err(err) if !Self::is_error(&object) => err,
+            _ => object,
+        };
+
+        self.depth -= 1;
+        self.env = current_env;
+        self.position = position;
+
+        object
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::evaluator::builtins::new_builtins;
+    use crate::evaluator::builtins::{
+        BuiltinRegistry, capture_output, new_builtins, set_output_sink,
+    };
+    use crate::evaluator::shared::Rc;
     use crate::evaluator::*;
     use crate::lexer::Lexer;
     use crate::parser::Parser;
 
     fn eval(input: &str) -> Option<Object> {
-        Evaluator::new(Rc::new(RefCell::new(Env::from(new_builtins()))))
+        Evaluator::new(Shared::new(Env::from(new_builtins())))
             .eval(&Parser::new(Lexer::new(input)).parse())
     }
 
@@ -446,10 +1626,62 @@ mod tests {
             ("3 * 3 * 3 + 10", Some(Object::Int(37))),
             ("3 * (3 * 3) + 10", Some(Object::Int(37))),
             ("(5 + 10 * 2 + 15 / 3) * 2 + -10", Some(Object::Int(50))),
+            (
+                "9223372036854775807 + 1",
+                Some(Object::Error(String::from(
+                    "integer overflow: 9223372036854775807 + 1",
+                ))),
+            ),
+            (
+                "(-9223372036854775807 - 1) / -1",
+                Some(Object::Error(String::from(
+                    "integer overflow: -9223372036854775808 / -1",
+                ))),
+            ),
+            (
+                "-(-9223372036854775807 - 1)",
+                Some(Object::Error(String::from(
+                    "integer overflow: -(-9223372036854775808)",
+                ))),
+            ),
+            (
+                "let i = 9223372036854775807; i++; i",
+                Some(Object::Error(String::from(
+                    "integer overflow: 9223372036854775807 + 1",
+                ))),
+            ),
         ];
 
         for (input, expect) in tests {
-            assert_eq!(expect, eval(input));
+            assert_eq!(expect, eval(input), "{input}");
+        }
+    }
+
+    #[test]
+    fn test_float_expr() {
+        let tests = vec![
+            ("2.5", Some(Object::Float(2.5))),
+            ("-2.5", Some(Object::Float(-2.5))),
+            ("1.5 + 1.25", Some(Object::Float(2.75))),
+            ("1 + 0.5", Some(Object::Float(1.5))),
+            ("0.5 * 4", Some(Object::Float(2.0))),
+            ("1 / 2.0", Some(Object::Float(0.5))),
+            ("1 / 2", Some(Object::Int(0))),
+            ("1 < 1.5", Some(Object::Bool(true))),
+            ("2.0 >= 2", Some(Object::Bool(true))),
+            ("1 == 1.0", Some(Object::Bool(true))),
+            (
+                "1.0 / 0",
+                Some(Object::Error(String::from("division by zero"))),
+            ),
+            (
+                "1.5 + 1元",
+                Some(Object::Error(String::from("type mismatch: 1.5 + 1元"))),
+            ),
+        ];
+
+        for (input, expect) in tests {
+            assert_eq!(expect, eval(input), "{input}");
         }
     }
 
@@ -503,11 +1735,9 @@ mod tests {
         let input = "[1, 2 * 2, 3 + 3]";
 
         assert_eq!(
-            Some(Object::Array(vec![
-                Object::Int(1),
-                Object::Int(4),
-                Object::Int(6),
-            ])),
+            Some(Object::Array(
+                vec![Object::Int(1), Object::Int(4), Object::Int(6),].into()
+            )),
             eval(input),
         );
     }
@@ -530,6 +1760,8 @@ mod tests {
             ),
             ("[1, 2, 3][3]", Some(Object::Null)),
             ("[1, 2, 3][-1]", Some(Object::Null)),
+            ("let 清单 = [1, 2, 3]; 清单 的 第3个", Some(Object::Int(3))),
+            ("[1, 2, 3][第一个]", Some(Object::Int(1))),
         ];
 
         for (input, expect) in tests {
@@ -538,6 +1770,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::mutable_key_type)]
     fn test_hash_literal() {
         let input = r#"
 let two = "two";
@@ -559,7 +1792,7 @@ let two = "two";
         hash.insert(Object::Bool(true), Object::Int(5));
         hash.insert(Object::Bool(false), Object::Int(6));
 
-        assert_eq!(Some(Object::Hash(hash)), eval(input),);
+        assert_eq!(Some(Object::Hash(hash.into())), eval(input),);
     }
 
     #[test]
@@ -596,6 +1829,53 @@ let two = "two";
         }
     }
 
+    #[test]
+    fn test_logical_expr() {
+        let tests = vec![
+            ("true && 1", Some(Object::Bool(true))),
+            ("1 && 325", Some(Object::Bool(false))),
+            ("false || 325", Some(Object::Bool(false))),
+            ("325 或者 0", Some(Object::Bool(true))),
+            ("并不是 true 而且 nope", Some(Object::Bool(false))),
+            ("true || nope", Some(Object::Bool(true))),
+            (
+                "false || nope",
+                Some(Object::Error(String::from("identifier not found: nope"))),
+            ),
+            (
+                "nope && true",
+                Some(Object::Error(String::from("identifier not found: nope"))),
+            ),
+        ];
+
+        for (input, expect) in tests {
+            assert_eq!(expect, eval(input), "{input}");
+        }
+    }
+
+    #[test]
+    fn test_coalesce() {
+        let tests = vec![
+            ("{\"a\": 1}[\"b\"] ?? 2", Some(Object::Int(2))),
+            ("{\"a\": 1}[\"a\"] ?? 2", Some(Object::Int(1))),
+            ("false ?? 2", Some(Object::Bool(false))),
+            ("0 ?? nope", Some(Object::Int(0))),
+            ("puts() ?? puts() ?? 3", Some(Object::Int(3))),
+            (
+                "puts() ?? nope",
+                Some(Object::Error(String::from("identifier not found: nope"))),
+            ),
+            (
+                "nope ?? 1",
+                Some(Object::Error(String::from("identifier not found: nope"))),
+            ),
+        ];
+
+        for (input, expect) in tests {
+            assert_eq!(expect, eval(input), "{input}");
+        }
+    }
+
     #[test]
     fn test_if_else_expr() {
         let tests = vec![
@@ -610,6 +1890,11 @@ let two = "two";
             ("if (1 >= 2) { 10 }", None),
             ("if (1 >= 2) { 10 } else { 20 }", Some(Object::Int(20))),
             ("if (1 <= 2) { 10 } else { 20 }", Some(Object::Int(10))),
+            (
+                "if (1 > 2) { 10 } else if (2 > 2) { 20 } else if (3 > 2) { 30 } else { 40 }",
+                Some(Object::Int(30)),
+            ),
+            ("if (1 > 2) { 10 } else if (2 > 2) { 20 }", None),
         ];
 
         for (input, expect) in tests {
@@ -617,6 +1902,82 @@ let two = "two";
         }
     }
 
+    #[test]
+    fn test_match_expr() {
+        let f = r#"let f = fn(x) {
+            下头菜单 x {
+                1 => "one",
+                2.5 => "float",
+                "a" => { let y = 2; y * 3 },
+                true => "yes",
+                _ => "other",
+            }
+        };"#;
+        let tests = vec![
+            ("f(1)", Some(Object::String(String::from("one")))),
+            ("f(1.0)", Some(Object::String(String::from("one")))),
+            ("f(2.5)", Some(Object::String(String::from("float")))),
+            ("f(\"a\")", Some(Object::Int(6))),
+            ("f(true)", Some(Object::String(String::from("yes")))),
+            ("f([1])", Some(Object::String(String::from("other")))),
+            ("match 3 { 1 => 2 }", Some(Object::Null)),
+            ("match 1 { _ => 1, 1 => 2 }", Some(Object::Int(1))),
+            (
+                "match y { _ => 1 }",
+                Some(Object::Error(String::from("identifier not found: y"))),
+            ),
+        ];
+
+        for (input, expect) in tests {
+            assert_eq!(expect, eval(&format!("{f} {input}")), "{input}");
+        }
+    }
+
+    #[test]
+    fn test_match_structural_patterns() {
+        let f = r#"let f = fn(x) {
+            match x {
+                [] => "empty",
+                [0, ...其余] => 其余,
+                [老大] => 老大,
+                [a, [b, c]] => a + b + c,
+                {名字, 年龄: 18} => 名字 + " is 18",
+                {名字} => 名字,
+                n => n,
+            }
+        };"#;
+        let tests = vec![
+            ("f([])", r#""empty""#),
+            ("f([0, 1, 2])", "[1, 2]"),
+            ("f([0])", "[]"),
+            ("f([7])", "7"),
+            ("f([1, [2, 3]])", "6"),
+            ("f([1, [2]])", "[1, [2]]"),
+            (r#"f({"名字": "a", "年龄": 18})"#, r#""a is 18""#),
+            (r#"f({"名字": "b", "年龄": 19})"#, r#""b""#),
+            (r#"f({"年龄": 18})"#, r#"{"年龄": 18}"#),
+            ("f(5)", "5"),
+            // A failed arm binds nothing.
+            ("let a = 0; match [1, 2] { [a, 3] => 1, _ => a }", "0"),
+            ("match [1, 2] { [a, b] => a }; b", "2"),
+        ];
+
+        for (input, expect) in tests {
+            let result = eval(&format!("{f} {input}")).map(|o| o.to_string());
+            assert_eq!(Some(String::from(expect)), result, "{input}");
+        }
+
+        let mut evaluator = Evaluator::new(Shared::new(Env::from(new_builtins())))
+            .with_constant("version", Object::Int(2));
+        let program = Parser::new(Lexer::new("match [1] { [version] => version }")).parse();
+        assert_eq!(
+            Some(Object::Error(String::from(
+                "cannot assign to constant version"
+            ))),
+            evaluator.eval(&program)
+        );
+    }
+
     #[test]
     fn test_while_expr() {
         let tests = vec![
@@ -641,6 +2002,14 @@ let two = "two";
                 Some(Object::Int(3)),
             ),
             ("你再说一遍 (4 < 3) { 3 };", None),
+            (
+                "let i = 0; while (i < 3) { let i = i + 1; i + true; }; i;",
+                Some(Object::Error(String::from("type mismatch: 1 + true"))),
+            ),
+            (
+                "while (nope) { 1 }",
+                Some(Object::Error(String::from("identifier not found: nope"))),
+            ),
             (
                 "宝宝你是一个 i = 1; 你再说一遍 (i < 3) { 宝宝你是一个 i = i+1; i}",
                 Some(Object::Int(3)),
@@ -656,6 +2025,113 @@ let two = "two";
         }
     }
 
+    #[test]
+    fn test_strict_types() {
+        let strict = |input: &str| {
+            Evaluator::new(Shared::new(Env::from(new_builtins())))
+                .with_strict_types()
+                .eval(&Parser::new(Lexer::new(input)).with_positions().parse())
+        };
+
+        let tests = vec![
+            ("let x: 整数 = 1; x", Object::Int(1)),
+            (
+                "let f = fn(x: 整数, y: 任意) -> 字符串 { str(x) }; f(1, [])",
+                Object::String(String::from("1")),
+            ),
+            (
+                "let x: 整数 = \"1\";",
+                Object::Error(String::from(
+                    "type error at 1:1: variable x expects 整数, got string",
+                )),
+            ),
+            (
+                "let f = fn(x: 整数) { x };\nf(true);",
+                Object::Error(String::from(
+                    "type error at 2:1: parameter x expects 整数, got bool",
+                )),
+            ),
+            (
+                "let f = fn(x) -> 整数 {\n  x\n};\nf(\"a\");",
+                Object::Error(String::from(
+                    "type error at 2:3: return value expects 整数, got string",
+                )),
+            ),
+            (
+                "let x: 整數 = 1;",
+                Object::Error(String::from("type error at 1:1: unknown type 整數")),
+            ),
+        ];
+
+        for (input, expect) in tests {
+            assert_eq!(Some(expect), strict(input), "{input}");
+        }
+
+        // Annotations are ignored outside strict mode.
+        assert_eq!(Some(Object::Bool(true)), eval("let x: 整数 = true; x"));
+    }
+
+    #[test]
+    fn test_interrupt() {
+        let flag = Arc::new(AtomicBool::new(false));
+        let mut evaluator = Evaluator::new(Shared::new(Env::from(new_builtins())))
+            .with_interrupt(Arc::clone(&flag));
+
+        flag.store(true, Ordering::Relaxed);
+
+        let tests = vec![
+            "你再说一遍 (那么普通却那么自信) { 1; }",
+            "let f = fn() { f() }; f();",
+            "for (i in range(1000000000)) { i }",
+            "unique([1, 2]);",
+        ];
+
+        for input in &tests {
+            assert_eq!(
+                Some(Object::Error(String::from(INTERRUPTED))),
+                evaluator.eval(&Parser::new(Lexer::new(input)).parse())
+            );
+        }
+
+        let mut evaluator =
+            Evaluator::new(Shared::new(Env::from(new_builtins()))).with_stop_check(|| true);
+        for input in &tests {
+            assert_eq!(
+                Some(Object::Error(String::from(INTERRUPTED))),
+                evaluator.eval(&Parser::new(Lexer::new(input)).parse())
+            );
+        }
+
+        let mut evaluator =
+            Evaluator::new(Shared::new(Env::from(new_builtins()))).with_stop_check(|| false);
+        assert_eq!(
+            Some(Object::Int(3)),
+            evaluator.eval(&Parser::new(Lexer::new("1 + 2")).parse())
+        );
+    }
+
+    #[test]
+    fn test_max_depth() {
+        let mut evaluator =
+            Evaluator::new(Shared::new(Env::from(new_builtins()))).with_max_depth(50);
+
+        assert_eq!(
+            Some(Object::Error(String::from(TOO_DEEP))),
+            evaluator.eval(&Parser::new(Lexer::new("let f = fn() { f() }; f();")).parse())
+        );
+
+        let input = "let g = fn(n) { if (n == 0) { n } else { g(n - 1) } };";
+        evaluator.eval(&Parser::new(Lexer::new(input)).parse());
+        assert_eq!(
+            Some(Object::Int(0)),
+            evaluator.eval(&Parser::new(Lexer::new("g(49);")).parse())
+        );
+        assert_eq!(
+            Some(Object::Error(String::from(TOO_DEEP))),
+            evaluator.eval(&Parser::new(Lexer::new("g(50);")).parse())
+        );
+    }
+
     #[test]
     fn test_return_stmt() {
         let tests = vec![
@@ -680,6 +2156,20 @@ if (10 > 1) {
         }
     }
 
+    #[test]
+    fn test_comment() {
+        let tests = vec![
+            ("let a = 5; a; // 五", Some(Object::Int(5))),
+            ("let a = 10 // 十\n / 2; a", Some(Object::Int(5))),
+            ("吐槽 什么都没有", None),
+            ("fn() { 1; 吐槽 一\n }()", Some(Object::Int(1))),
+        ];
+
+        for (input, expect) in tests {
+            assert_eq!(expect, eval(input), "{input}");
+        }
+    }
+
     #[test]
     fn test_let_stmt() {
         let tests = vec![
@@ -699,46 +2189,341 @@ if (10 > 1) {
     }
 
     #[test]
-    fn test_blank_stmt() {
+    fn test_method_call() {
         let tests = vec![
+            ("\"abc\".len()", "3"),
+            ("[1, 2].push(3)", "[1, 2, 3]"),
+            ("[1, 2].push(3).last()", "3"),
+            ("{\"b\": 2, \"a\": 1}.keys()", "[\"a\", \"b\"]"),
+            ("{\"b\": 2, \"a\": 1}.values()", "[1, 2]"),
+            ("\"42\".atoi() + 1", "43"),
+            ("5.str()", "\"5\""),
+            ("let h = {\"len\": fn() { 99 }}; h.len()", "99"),
             (
-                r#"5;
-
-
-"#,
-                Some(Object::Int(5)),
-            ),
-            (
-                r#"let identity = fn (x) {
-  x;
-
-}
-
-identity(100);
-
-"#,
-                Some(Object::Int(100)),
+                "[1].push()",
+                "啊啊啊啊啊啊啊啊(wrong number of arguments: 2 expected but 1 given)",
             ),
         ];
 
         for (input, expect) in tests {
-            assert_eq!(expect, eval(input));
+            assert_eq!(
+                Some(String::from(expect)),
+                eval(input).map(|o| o.to_string())
+            );
         }
     }
 
     #[test]
-    fn test_fn_object() {
-        let input = "fn(x) { x + 2; };";
+    fn test_reflection() {
+        let tests = vec![
+            ("let x = 1; globals().keys()", "[\"x\"]"),
+            ("let x = 1; globals()[\"x\"]", "1"),
+            ("let x = 1; let f = fn(y) { locals() }; f(2)", "{\"y\": 2}"),
+            (
+                "let x = 1; let f = fn(y) { let z = 3; globals().keys() }; f(2)",
+                "[\"f\", \"x\"]",
+            ),
+            (
+                "locals(1)",
+                "啊啊啊啊啊啊啊啊(wrong number of arguments: 0 expected but 1 given)",
+            ),
+            (
+                "[callable(len), callable(fn() {}), callable(async fn() {}), callable(1)]",
+                "[true, true, true, false]",
+            ),
+            (
+                "[arity(fn(a, b) {}), arity(push), arity(puts), arity(globals)]",
+                "[2, 2, null, 0]",
+            ),
+            (
+                "arity(1)",
+                "啊啊啊啊啊啊啊啊(argument to `arity` must be function. got 1)",
+            ),
+        ];
+
+        for (input, expect) in tests {
+            assert_eq!(
+                Some(String::from(expect)),
+                eval(input).map(|o| o.to_string())
+            );
+        }
+    }
+
+    #[test]
+    fn test_del() {
+        let tests = vec![
+            ("let x = 1; del(\"x\")", "1"),
+            (
+                "let x = 1; del(\"x\"); x",
+                "啊啊啊啊啊啊啊啊(identifier not found: x)",
+            ),
+            ("let x = 1; let f = fn(x) { del(\"x\"); x }; f(2)", "1"),
+            (
+                "let x = 1; let f = fn() { del(\"x\") }; f()",
+                "啊啊啊啊啊啊啊啊(cannot delete x: not defined in this scope)",
+            ),
+            (
+                "del(1)",
+                "啊啊啊啊啊啊啊啊(argument to `del` must be string. got 1)",
+            ),
+        ];
+
+        for (input, expect) in tests {
+            assert_eq!(
+                Some(String::from(expect)),
+                eval(input).map(|o| o.to_string())
+            );
+        }
+    }
+
+    #[test]
+    fn test_breakpoint() {
+        use std::cell::RefCell;
+
+        thread_local! {
+            static HITS: RefCell<Vec<(usize, Vec<String>)>> = const { RefCell::new(vec![]) };
+        }
+
+        fn hook(step: &Step) -> Resume {
+            HITS.with_borrow_mut(|hits| {
+                let names = step.locals.iter().map(|(name, _)| name.clone());
+                hits.push((step.span.start.line, names.collect()));
+                // Step once after the first stop, then run on.
+                if hits.len() == 1 {
+                    Resume::Step
+                } else {
+                    Resume::Continue
+                }
+            })
+        }
+
+        let input = "let a = 1;\nbreakpoint();\nlet b = 2;\nlet c = 3;\n先别卷了();\na + b + c";
+        let program = Parser::new(Lexer::new(input)).with_positions().parse();
+        let mut evaluator =
+            Evaluator::new(Shared::new(Env::from(new_builtins()))).with_breakpoint(hook);
+        assert_eq!(Some(Object::Int(6)), evaluator.eval(&program));
+
+        let hits = HITS.take();
+        let expect = vec![(2, vec!["a"]), (3, vec!["a"]), (5, vec!["a", "b", "c"])];
+        assert_eq!(expect.len(), hits.len());
+        for ((line, names), (hit_line, hit_names)) in expect.into_iter().zip(hits) {
+            assert_eq!(line, hit_line);
+            assert_eq!(names, hit_names);
+        }
+
+        // Without a hook it does nothing.
+        assert_eq!(Some(Object::Null), eval("breakpoint()"));
+    }
+
+    #[test]
+    fn test_env_define_assign() {
+        let globals = Shared::new(Env::new());
+        globals
+            .borrow_mut()
+            .define(String::from("x"), &Object::Int(1));
+        let mut scope = Env::new_with_outer(Shared::clone(&globals));
+
+        assert!(scope.assign(String::from("x"), &Object::Int(2)));
+        assert_eq!(None, scope.store.get("x"));
+        assert_eq!(
+            Some(Object::Int(2)),
+            globals.borrow_mut().get(String::from("x"))
+        );
+
+        scope.define(String::from("x"), &Object::Int(3));
+        assert!(scope.assign(String::from("x"), &Object::Int(4)));
+        assert_eq!(Some(Object::Int(4)), scope.get(String::from("x")));
+        assert_eq!(
+            Some(Object::Int(2)),
+            globals.borrow_mut().get(String::from("x"))
+        );
+
+        assert!(!scope.assign(String::from("y"), &Object::Int(5)));
+        assert_eq!(None, scope.get(String::from("y")));
+    }
+
+    #[test]
+    fn test_async() {
+        let tests = vec![
+            ("let f = async fn(x) { x * 2 }; await f(21)", "42"),
+            (
+                "let f = 慢慢来 fn(x) { x * 2 }; 等它一下 f(1) + 等它一下 f(2)",
+                "6",
+            ),
+            ("let f = async fn() { 1 }; f()", "[task]"),
+            (
+                "let f = async fn() { 1 }; let t = f(); await t + await t",
+                "2",
+            ),
+            ("await 5", "5"),
+            ("async fn(x) { x }", "async fn(x) { ... }"),
+            (
+                "let f = async fn() { 1 + true }; f(); 2",
+                "啊啊啊啊啊啊啊啊(type mismatch: 1 + true)",
+            ),
+            (
+                "let f = async fn() { await t }; let t = f(); await t",
+                "啊啊啊啊啊啊啊啊(task is waiting for itself)",
+            ),
+        ];
+
+        for (input, expect) in tests {
+            assert_eq!(
+                Some(String::from(expect)),
+                eval(input).map(|o| o.to_string())
+            );
+        }
+
+        // Tasks nobody awaited run after the program, oldest first.
+        let mut evaluator = Evaluator::new(Shared::new(Env::from(new_builtins())));
+        let input = "let log = []; let f = async fn(x) { let log = push(log, x); log }; \
+                     let a = f(1); let b = f(2); await b";
+        assert_eq!(
+            Some(String::from("[2]")),
+            evaluator
+                .eval(&Parser::new(Lexer::new(input)).parse())
+                .map(|o| o.to_string())
+        );
+        assert_eq!(
+            Some(String::from("[1]")),
+            evaluator
+                .eval(&Parser::new(Lexer::new("await a")).parse())
+                .map(|o| o.to_string())
+        );
+    }
+
+    #[test]
+    fn test_iter() {
+        let sum = |source: &str| {
+            format!(
+                "let it = {source}; let total = \"\"; let s = it.next();
+                 while (!s.done) {{ let total = total + str(s.value); let s = it.next(); }};
+                 total"
+            )
+        };
+        let doubled = "let doubled = fn(it) {
+            {\"next\": fn() {
+                let s = it.next();
+                if (s.done) { s } else { {\"done\": false, \"value\": s.value * 2} }
+            }}
+        };";
+
+        let tests = vec![
+            (sum("[1, 2, 3].iter()"), "\"123\""),
+            (sum("iter(\"她说\")"), "\"她说\""),
+            (sum("{\"b\": 1, \"a\": 2}.iter()"), "\"ab\""),
+            (sum("iter(iter([]))"), "\"\""),
+            (
+                format!("{doubled} {}", sum("doubled([1, 2, 3].iter())")),
+                "\"246\"",
+            ),
+            (
+                String::from("let it = iter([1]); let copy = it; copy.next(); it.next().done"),
+                "true",
+            ),
+            (String::from("iter([]).next().value"), "null"),
+            (
+                String::from("next([1])"),
+                "啊啊啊啊啊啊啊啊(argument to `next` must be iterator. got [1])",
+            ),
+        ];
+
+        for (input, expect) in tests {
+            assert_eq!(
+                Some(String::from(expect)),
+                eval(&input).map(|o| o.to_string())
+            );
+        }
+    }
+
+    #[test]
+    fn test_quantity() {
+        let tests = vec![
+            ("5元", "5元"),
+            ("5元 + 3元 - 1元", "7元"),
+            ("3 * 5元", "15元"),
+            ("10元 / 3", "3元"),
+            ("10元 / 5元", "2"),
+            ("-2斤", "-2斤"),
+            ("5元 > 3元", "true"),
+            ("5元 == 5斤", "啊啊啊啊啊啊啊啊(type mismatch: 5元 == 5斤)"),
+            ("5元 + 1", "啊啊啊啊啊啊啊啊(type mismatch: 5元 + 1)"),
+            ("5元 * 2元", "啊啊啊啊啊啊啊啊(unknown operator: 5元 * 2元)"),
+            ("1元 / 0", "啊啊啊啊啊啊啊啊(division by zero)"),
+            (
+                "4611686018427387904 * 2元",
+                "啊啊啊啊啊啊啊啊(integer overflow: 4611686018427387904 * 2)",
+            ),
+            ("str(2个)", "\"2个\""),
+        ];
+
+        for (input, expect) in tests {
+            assert_eq!(
+                Some(String::from(expect)),
+                eval(input).map(|o| o.to_string())
+            );
+        }
+    }
+
+    #[test]
+    fn test_easter_eggs() {
+        let tests = vec![
+            ("宝宝你是一个 weight = 微胖; weight", "\"180kg\""),
+            ("let 微胖 = 90; 微胖", "90"),
+            ("打工人", "\"996\""),
+        ];
+
+        for (input, expect) in tests {
+            let mut evaluator = Evaluator::new(Shared::new(Env::from(new_builtins())))
+                .with_easter_egg("打工人", Object::String(String::from("996")));
+            let result = evaluator.eval(&Parser::new(Lexer::new(input)).parse());
+
+            assert_eq!(Some(String::from(expect)), result.map(|o| o.to_string()));
+        }
+    }
+
+    #[test]
+    fn test_blank_stmt() {
+        let tests = vec![
+            (
+                r#"5;
+
+
+"#,
+                Some(Object::Int(5)),
+            ),
+            (
+                r#"let identity = fn (x) {
+  x;
+
+}
+
+identity(100);
+
+"#,
+                Some(Object::Int(100)),
+            ),
+        ];
+
+        for (input, expect) in tests {
+            assert_eq!(expect, eval(input));
+        }
+    }
+
+    #[test]
+    fn test_fn_object() {
+        let input = "fn(x) { x + 2; };";
 
         assert_eq!(
             Some(Object::Func(
-                vec![Ident(String::from("x"))],
+                vec![Param::from(Ident(String::from("x")))],
+                None,
                 vec![Stmt::Expr(Expr::Infix(
                     Infix::Plus,
                     Box::new(Expr::Ident(Ident(String::from("x")))),
                     Box::new(Expr::Literal(Literal::Int(2))),
                 ))],
-                Rc::new(RefCell::new(Env::from(new_builtins()))),
+                Shared::new(Env::from(new_builtins())),
             )),
             eval(input),
         );
@@ -792,10 +2577,223 @@ identity(100);
                 "let divise = fn(x, y) { x / y; }; divise(10, 5);",
                 Some(Object::Int(2)),
             ),
+            (
+                "let add = fn(x, y = 10) { x + y; }; [add(1), add(1, 2)];",
+                Some(Object::Array(Rc::new(vec![
+                    Object::Int(11),
+                    Object::Int(3),
+                ]))),
+            ),
+            // Defaults run at each call, in the function's scope, after the
+            // parameters before them are bound.
+            (
+                "let n = 1; let f = fn(x, y = x + n, z = [y]) { z }; let n = 2; f(1);",
+                Some(Object::Array(Rc::new(vec![Object::Int(3)]))),
+            ),
+            (
+                "let f = fn(xs = []) { xs.push(1) }; f(); f();",
+                Some(Object::Array(Rc::new(vec![Object::Int(1)]))),
+            ),
+            (
+                "let f = fn(x, y = 2) { x }; f();",
+                Some(Object::Error(String::from(
+                    "wrong number of arguments: 1 to 2 expected but 0 given",
+                ))),
+            ),
+            (
+                "let f = fn(x, y = 2) { x }; f(1, 2, 3);",
+                Some(Object::Error(String::from(
+                    "wrong number of arguments: 1 to 2 expected but 3 given",
+                ))),
+            ),
+            (
+                "let f = fn(x = nope) { x }; f();",
+                Some(Object::Error(String::from("identifier not found: nope"))),
+            ),
         ];
 
         for (input, expect) in tests {
-            assert_eq!(expect, eval(input));
+            assert_eq!(expect, eval(input), "{input}");
+        }
+    }
+
+    #[test]
+    fn test_import_stmt() {
+        let dir = std::env::temp_dir().join("herlang_test_import_stmt");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("utils.her"),
+            "引入 \"consts.her\"; 宝宝你是一个 double = fn(x) { x * two };",
+        )
+        .unwrap();
+        std::fs::write(dir.join("consts.her"), "宝宝你是一个 two = 2;").unwrap();
+        std::fs::write(dir.join("loop.her"), "引入 \"loop.her\";").unwrap();
+
+        let tests = vec![
+            ("引入 \"utils.her\"; double(21);", Some(Object::Int(42))),
+            ("import \"consts.her\"; two;", Some(Object::Int(2))),
+            (
+                "引入 \"loop.her\";",
+                Some(Object::Error(String::from("circular import: loop.her"))),
+            ),
+        ];
+
+        for (input, expect) in tests {
+            let mut evaluator =
+                Evaluator::new(Shared::new(Env::from(new_builtins()))).with_base_dir(&dir);
+            assert_eq!(
+                expect,
+                evaluator.eval(&Parser::new(Lexer::new(input)).parse())
+            );
+        }
+
+        std::fs::write(dir.join("v1.her"), "#她版本 1\n宝宝你是一个 在 = 1;").unwrap();
+        std::fs::write(dir.join("old.her"), "宝宝你是一个 的 = 2;").unwrap();
+        let program = Parser::new(Lexer::new("引入 \"v1.her\"; 引入 \"old.her\";")).parse();
+        let mut evaluator = Evaluator::new(Shared::new(Env::from(new_builtins())))
+            .with_base_dir(&dir)
+            .with_language_version(1);
+        assert_eq!(None, evaluator.eval(&program));
+        assert_eq!(
+            Some(Object::Int(1)),
+            evaluator.env.borrow_mut().get(String::from("在"))
+        );
+        let mut evaluator =
+            Evaluator::new(Shared::new(Env::from(new_builtins()))).with_base_dir(&dir);
+        assert!(matches!(evaluator.eval(&program), Some(Object::Error(_))));
+    }
+
+    #[test]
+    fn test_import_herc_cache() {
+        let dir = std::env::temp_dir().join("herlang_test_import_herc_cache");
+        std::fs::create_dir_all(&dir).unwrap();
+        let source = dir.join("cached.her");
+        std::fs::write(&source, "宝宝你是一个 x = 1;").unwrap();
+        let cached = Parser::new(Lexer::new("宝宝你是一个 x = 2;")).parse();
+        std::fs::write(
+            herc::cache_path(&source),
+            herc::encode(&cached, LANGUAGE_VERSION),
+        )
+        .unwrap();
+
+        let set_modified = |path: &Path, time| {
+            let file = std::fs::File::options().write(true).open(path).unwrap();
+            file.set_modified(time).unwrap();
+        };
+        let now = std::time::SystemTime::now();
+        let later = now + std::time::Duration::from_secs(10);
+
+        let tests = vec![
+            // The cache is newer, so it's what runs.
+            (now, later, LANGUAGE_VERSION, 2),
+            // The source changed since the cache was built.
+            (later, now, LANGUAGE_VERSION, 1),
+            // The cache was parsed as another language version.
+            (now, later, 1, 1),
+        ];
+
+        let program = Parser::new(Lexer::new("引入 \"cached.her\"; x")).parse();
+        for (source_time, cache_time, version, expect) in tests {
+            set_modified(&source, source_time);
+            set_modified(&herc::cache_path(&source), cache_time);
+            let mut evaluator = Evaluator::new(Shared::new(Env::from(new_builtins())))
+                .with_base_dir(&dir)
+                .with_language_version(version);
+            assert_eq!(Some(Object::Int(expect)), evaluator.eval(&program));
+        }
+    }
+
+    #[test]
+    fn test_import_options() {
+        let dir = std::env::temp_dir().join("herlang_test_import_options");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("read.her"), "宝宝你是一个 twice = version * 2;").unwrap();
+        std::fs::write(dir.join("rebind.her"), "宝宝你是一个 version = 0;").unwrap();
+        std::fs::write(dir.join("long.her"), "-[1, 2, 3, 4];").unwrap();
+
+        let tests = vec![
+            ("引入 \"read.her\"; twice", "4"),
+            (
+                "引入 \"rebind.her\"; version",
+                "啊啊啊啊啊啊啊啊(cannot assign to constant version)",
+            ),
+            (
+                "引入 \"long.her\";",
+                "啊啊啊啊啊啊啊啊(unknown operator: -[1, 2, ……共 4 项])",
+            ),
+        ];
+
+        for (input, expect) in tests {
+            let mut evaluator = Evaluator::new(Shared::new(Env::from(new_builtins())))
+                .with_base_dir(&dir)
+                .with_constant("version", Object::Int(2))
+                .with_repr_limit(ReprLimit {
+                    max_items: Some(2),
+                    max_len: None,
+                });
+            let result = evaluator.eval(&Parser::new(Lexer::new(input)).parse());
+            assert_eq!(Some(String::from(expect)), result.map(|o| o.to_string()));
+        }
+    }
+
+    #[test]
+    fn test_sandbox() {
+        let dir = std::env::temp_dir().join("herlang_test_sandbox");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("bye.her"), "宝宝你是一个 bye = fn() { 哼(1) };").unwrap();
+
+        let no_exit = SandboxConfig {
+            exit: false,
+            ..SandboxConfig::default()
+        };
+        let denied = Some(Object::Error(String::from(NO_PERMISSION)));
+        let tests = vec![
+            (SandboxConfig::locked(), "quit(1);", denied.clone()),
+            (SandboxConfig::locked(), "引入 \"bye.her\";", denied.clone()),
+            (SandboxConfig::locked(), "len(\"ok\")", Some(Object::Int(2))),
+            (SandboxConfig::locked(), "躺平(1000)", denied.clone()),
+            (SandboxConfig::locked(), "exec(\"true\")", denied.clone()),
+            (no_exit, "sleep(1)", Some(Object::Null)),
+            (no_exit, "引入 \"bye.her\"; bye();", denied.clone()),
+            (no_exit, "let 哈 = 1; 哈", Some(Object::Int(1))),
+        ];
+
+        for (sandbox, input, expect) in tests {
+            let mut evaluator = Evaluator::new(Shared::new(Env::from(new_builtins())))
+                .with_base_dir(&dir)
+                .with_sandbox(sandbox);
+            assert_eq!(
+                expect,
+                evaluator.eval(&Parser::new(Lexer::new(input)).parse()),
+                "{input}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_quit() {
+        let tests = vec![
+            ("quit(); 1;", Some(Object::Exit(0))),
+            ("哼(3); 1;", Some(Object::Exit(3))),
+            (
+                "let f = fn() { while (true) { 哈(2); } }; f(); 1;",
+                Some(Object::Exit(2)),
+            ),
+            ("{\"a\": 1}[quit(4)]; 1;", Some(Object::Exit(4))),
+            (
+                "let t = 慢慢来 fn() { quit(5) }; t(); let x = 1;",
+                Some(Object::Exit(5)),
+            ),
+            (
+                "quit(\"bye\")",
+                Some(Object::Error(String::from(
+                    "argument to `quit` must be int. got \"bye\"",
+                ))),
+            ),
+        ];
+
+        for (input, expect) in tests {
+            assert_eq!(expect, eval(input), "{input}");
         }
     }
 
@@ -830,7 +2828,7 @@ addTwo(2);
             (
                 "len(\"one\", \"two\")",
                 Some(Object::Error(String::from(
-                    "wrong number of arguments. got=2, want=1",
+                    "wrong number of arguments: 1 expected but 2 given",
                 ))),
             ),
             // first
@@ -839,7 +2837,7 @@ addTwo(2);
             (
                 "first([], [])",
                 Some(Object::Error(String::from(
-                    "wrong number of arguments. got=2, want=1",
+                    "wrong number of arguments: 1 expected but 2 given",
                 ))),
             ),
             (
@@ -860,7 +2858,7 @@ addTwo(2);
             (
                 "last([], [])",
                 Some(Object::Error(String::from(
-                    "wrong number of arguments. got=2, want=1",
+                    "wrong number of arguments: 1 expected but 2 given",
                 ))),
             ),
             (
@@ -878,22 +2876,20 @@ addTwo(2);
             // rest
             (
                 "rest([1, 2, 3, 4])",
-                Some(Object::Array(vec![
-                    Object::Int(2),
-                    Object::Int(3),
-                    Object::Int(4),
-                ])),
+                Some(Object::Array(
+                    vec![Object::Int(2), Object::Int(3), Object::Int(4)].into(),
+                )),
             ),
             (
                 "rest([2, 3, 4])",
-                Some(Object::Array(vec![Object::Int(3), Object::Int(4)])),
+                Some(Object::Array(vec![Object::Int(3), Object::Int(4)].into())),
             ),
-            ("rest([4])", Some(Object::Array(vec![]))),
+            ("rest([4])", Some(Object::Array(vec![].into()))),
             ("rest([])", Some(Object::Null)),
             (
                 "rest([], [])",
                 Some(Object::Error(String::from(
-                    "wrong number of arguments. got=2, want=1",
+                    "wrong number of arguments: 1 expected but 2 given",
                 ))),
             ),
             (
@@ -911,22 +2907,28 @@ addTwo(2);
             // push
             (
                 "push([1, 2, 3], 4)",
-                Some(Object::Array(vec![
-                    Object::Int(1),
-                    Object::Int(2),
-                    Object::Int(3),
-                    Object::Int(4),
-                ])),
+                Some(Object::Array(
+                    vec![
+                        Object::Int(1),
+                        Object::Int(2),
+                        Object::Int(3),
+                        Object::Int(4),
+                    ]
+                    .into(),
+                )),
+            ),
+            (
+                "push([], 1)",
+                Some(Object::Array(vec![Object::Int(1)].into())),
             ),
-            ("push([], 1)", Some(Object::Array(vec![Object::Int(1)]))),
             (
                 "let a = [1]; push(a, 2); a",
-                Some(Object::Array(vec![Object::Int(1)])),
+                Some(Object::Array(vec![Object::Int(1)].into())),
             ),
             (
                 "push([], [], [])",
                 Some(Object::Error(String::from(
-                    "wrong number of arguments. got=3, want=2",
+                    "wrong number of arguments: 2 expected but 3 given",
                 ))),
             ),
             (
@@ -949,25 +2951,712 @@ addTwo(2);
     }
 
     #[test]
-    fn test_error_handling() {
+    fn test_compare() {
         let tests = vec![
+            ("\"a\" == \"a\"", "true"),
+            ("true != false", "true"),
+            ("[1, [2, \"3\"]] == [1, [2, \"3\"]]", "true"),
+            ("[1, 2] == [2, 1]", "false"),
+            ("{\"a\": 1, \"b\": [2]} == {\"b\": [2], \"a\": 1}", "true"),
+            ("{\"a\": 1} == {\"a\": 2}", "false"),
+            ("1 == \"1\"", "false"),
+            ("1 != true", "true"),
+            ("puts() == puts()", "true"),
+            ("let f = fn() { 1 }; f == f", "true"),
+            ("fn() { 1 } == fn() { 1 }", "true"),
+            ("fn() { 1 } == fn() { 2 }", "false"),
+            ("\"abc\" < \"abd\"", "true"),
+            ("[1, 2] < [1, 2, 0]", "true"),
+            ("[2] >= [1, 9]", "true"),
+            ("false < true", "true"),
             (
-                "5 + true",
-                Some(Object::Error(String::from("type mismatch: 5 + true"))),
+                "sort([3, \"b\", puts(), [1], 1, \"a\", true, 2元])",
+                "[null, true, 1, 3, 2元, \"a\", \"b\", [1]]",
             ),
+            ("[[2], [1, 2], [1]].sort()", "[[1], [1, 2], [2]]"),
+            ("\"a\" < 1", "啊啊啊啊啊啊啊啊(type mismatch: \"a\" < 1)"),
+            ("{} < {}", "啊啊啊啊啊啊啊啊(type mismatch: {} < {})"),
+            ("y == 1", "啊啊啊啊啊啊啊啊(identifier not found: y)"),
             (
-                "5 + true; 5;",
-                Some(Object::Error(String::from("type mismatch: 5 + true"))),
+                "sort(1)",
+                "啊啊啊啊啊啊啊啊(argument to `sort` must be array. got 1)",
             ),
+        ];
+
+        for (input, expect) in tests {
+            assert_eq!(
+                Some(String::from(expect)),
+                eval(input).map(|o| o.to_string()),
+                "{input}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_sleep() {
+        let start = std::time::Instant::now();
+        assert_eq!(Some(Object::Null), eval("sleep(20)"));
+        assert!(start.elapsed() >= std::time::Duration::from_millis(20));
+
+        assert_eq!(Some(Object::Null), eval("躺平(0)"));
+        assert_eq!(
+            Some(Object::Error(String::from(
+                "argument to `sleep` must be non-negative int. got -1"
+            ))),
+            eval("sleep(-1)")
+        );
+    }
+
+    #[test]
+    fn test_step_stmt() {
+        let tests = vec![
+            ("let i = 0; while (i < 5) { i++ }; i", "5"),
+            ("let n = 3; --n; n--; n", "1"),
+            ("let x = 1; let f = fn() { x++; x }; [f(), x]", "[2, 2]"),
             (
-                "-true",
-                Some(Object::Error(String::from("unknown operator: -true"))),
+                "let counter = fn() { let n = 0; fn() { n++; n } }; let c = counter(); c(); c(); c()",
+                "3",
             ),
+            ("let i = 0; if (true) { i++; }; i", "1"),
+            ("n++;", "啊啊啊啊啊啊啊啊(identifier not found: n)"),
+        ];
+        for (input, expect) in tests {
+            assert_eq!(
+                Some(String::from(expect)),
+                eval(input).map(|o| o.to_string()),
+                "{input}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_macro() {
+        let tests = vec![
             (
-                "5; true + false; 5;",
-                Some(Object::Error(String::from(
-                    "unknown operator: true + false",
-                ))),
+                "造个句式 unless(c, x) => { if (!c) { x } } unless(1 > 2, \"ok\")",
+                "\"ok\"",
+            ),
+            (
+                "造个句式 swap(a, b) => { let t = a; [b, t] } let t = 1; let u = 2; [swap(t, u), t]",
+                "[[2, 1], 1]",
+            ),
+            // Arguments are code, not values: one the template drops never runs.
+            ("造个句式 first(a, b) => { a } first(1, quit(3))", "1"),
+            (
+                "造个句式 pair(a, b = a + 1) => { [a, b] } [pair(1), pair(1, 5)]",
+                "[[1, 2], [1, 5]]",
+            ),
+        ];
+        for (input, expect) in tests {
+            assert_eq!(
+                Some(String::from(expect)),
+                eval(input).map(|o| o.to_string()),
+                "{input}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_number_conversions() {
+        let tests = vec![
+            (r#"parse_float(" 2.5 ")"#, "2.5"),
+            (r#""-3".parse_float()"#, "-3.0"),
+            (r#"parse_float("1e3")"#, "1000.0"),
+            (
+                r#"parse_float("inf")"#,
+                r#"啊啊啊啊啊啊啊啊(argument to `parse_float` must be a valid number. got "inf")"#,
+            ),
+            ("to_fixed(2.345, 1)", r#""2.3""#),
+            ("to_fixed(2.5, 0)", r#""2""#),
+            ("7.to_fixed(2)", r#""7.00""#),
+            (
+                "to_fixed(1, -1)",
+                "啊啊啊啊啊啊啊啊(digits of `to_fixed` must be int between 0 and 100. got -1)",
+            ),
+            ("to_base(255, 16)", r#""ff""#),
+            ("(-5).to_base(2)", r#""-101""#),
+            ("to_base(0, 36)", r#""0""#),
+            (
+                "to_base(-9223372036854775807 - 1, 2)",
+                r#""-1000000000000000000000000000000000000000000000000000000000000000""#,
+            ),
+            (
+                "to_base(1, 37)",
+                "啊啊啊啊啊啊啊啊(base of `to_base` must be int between 2 and 36. got 37)",
+            ),
+            (r#"from_base("ff", 16)"#, "255"),
+            (r#""-101".from_base(2)"#, "-5"),
+            (r#"ord("她")"#, "22905"),
+            (r#""😀".ord()"#, "128512"),
+            ("chr(22905)", r#""她""#),
+            ("65.chr()", r#""A""#),
+            (
+                r#"ord("ab")"#,
+                r#"啊啊啊啊啊啊啊啊(argument to `ord` must be a single character. got "ab")"#,
+            ),
+            (
+                "chr(55296)",
+                "啊啊啊啊啊啊啊啊(55296 is not a Unicode scalar value)",
+            ),
+            (
+                r#"from_base("12", 2)"#,
+                r#"啊啊啊啊啊啊啊啊(argument to `from_base` must be valid base 2 digits. got "12")"#,
+            ),
+        ];
+        for (input, expect) in tests {
+            assert_eq!(
+                Some(String::from(expect)),
+                eval(input).map(|o| o.to_string()),
+                "{input}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_template() {
+        let tests = vec![
+            (
+                r#"模板("亲爱的{名字}：你已经 { 天数 } 天没理我了", {"名字": "宝", "天数": 3})"#,
+                r#""亲爱的宝：你已经 3 天没理我了""#,
+            ),
+            (
+                r#""{{她}} 说 {话}".template({"话": [1, "二"]})"#,
+                r#""{她} 说 [1, \"二\"]""#,
+            ),
+            (
+                r#"let 信 = """
+                    亲爱的{名字}：
+                      见字如面。
+                    """;
+                template(信, {"名字": "宝"})"#,
+                r#""亲爱的宝：\n  见字如面。""#,
+            ),
+            (
+                r#"template("{名字}", {})"#,
+                "啊啊啊啊啊啊啊啊(template key not found: 名字)",
+            ),
+            (
+                r#"template("{名字", {})"#,
+                "啊啊啊啊啊啊啊啊(unclosed `{` in template)",
+            ),
+            (
+                r#"template("{名字}", ["宝"])"#,
+                r#"啊啊啊啊啊啊啊啊(second argument to `template` must be hash. got ["宝"])"#,
+            ),
+        ];
+        for (input, expect) in tests {
+            assert_eq!(
+                Some(String::from(expect)),
+                eval(input).map(|o| o.to_string()),
+                "{input}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_json() {
+        let tests = vec![
+            (
+                r#"json_parse("[1, -2.5, 1e2, true, null, \"她\\n\"]")"#,
+                r#"[1, -2.5, 100.0, true, null, "她\n"]"#,
+            ),
+            (r#"json_parse("{\"a\": {\"b\": []}}")["a"]["b"]"#, "[]"),
+            (r#""\"\\ud83d\\ude00\"".json_parse()"#, r#""😀""#),
+            (
+                r#"json_parse("99999999999999999999")"#,
+                "100000000000000000000.0",
+            ),
+            (
+                r#"let 她 = json_parse("
+                    {
+                        // 她的资料
+                        名字: '宝',
+                        'tags': [1, 2,], /* 完 */
+                    }
+                ", {"loose": true});
+                [她["名字"], 她["tags"], len(keys(她))]"#,
+                r#"["宝", [1, 2], 2]"#,
+            ),
+            (
+                r#"json_parse("[1, 2,]")"#,
+                "啊啊啊啊啊啊啊啊(invalid JSON at 1:7: unexpected `]`)",
+            ),
+            (
+                r#"json_parse("{'a': 1}")"#,
+                "啊啊啊啊啊啊啊啊(invalid JSON at 1:2: expected a key, got `'`)",
+            ),
+            (
+                r#"json_parse("[01]")"#,
+                "啊啊啊啊啊啊啊啊(invalid JSON at 1:2: invalid number)",
+            ),
+            (
+                r#"json_parse("1 2")"#,
+                "啊啊啊啊啊啊啊啊(invalid JSON at 1:3: unexpected `2` after the value)",
+            ),
+            (
+                r#"json_parse("\"abc")"#,
+                "啊啊啊啊啊啊啊啊(invalid JSON at 1:1: unterminated string)",
+            ),
+            (
+                r#"let s = "["; for (_ in range(10)) { let s = s + s }; json_parse(s)"#,
+                "啊啊啊啊啊啊啊啊(invalid JSON at 1:513: nested too deep, the limit is 512)",
+            ),
+            (
+                r#"let s = "["; for (_ in range(22)) { let s = s + s }; json_parse(s)"#,
+                "啊啊啊啊啊啊啊啊(invalid JSON at 1:513: nested too deep, the limit is 512)",
+            ),
+            (r#"len(json_parse("[[[[[[[[[[1]]]]]]]]]]"))"#, "1"),
+            (
+                r#"json_parse("1", {"loose": 1})"#,
+                r#"啊啊啊啊啊啊啊啊(option "loose" of `json_parse` must be bool. got 1)"#,
+            ),
+            (
+                r#"json_stringify({"b": [1, 2.5, "她\"", 那咋了], "a": {}, 3: puts()})"#,
+                r#""{\"a\":{},\"b\":[1,2.5,\"她\\\"\",false],\"3\":null}""#,
+            ),
+            (
+                r#"json_parse(json_stringify({"x": [1, "y"]}))["x"]"#,
+                r#"[1, "y"]"#,
+            ),
+            (
+                "json_stringify(fn() {})",
+                "啊啊啊啊啊啊啊啊(a function can't be written as JSON)",
+            ),
+        ];
+        for (input, expect) in tests {
+            assert_eq!(
+                Some(String::from(expect)),
+                eval(input).map(|o| o.to_string()),
+                "{input}"
+            );
+        }
+    }
+
+    #[cfg(all(feature = "os", unix))]
+    #[test]
+    fn test_exec() {
+        let tests = vec![
+            (r#"exec("echo", ["hi", "她"])["stdout"]"#, r#""hi 她\n""#),
+            (r#"exec("echo")["code"]"#, "0"),
+            (r#"摇人("sh", ["-c", "echo no >&2; exit 3"])["code"]"#, "3"),
+            (
+                r#"exec("sh", ["-c", "echo no >&2"])["stderr"]"#,
+                r#""no\n""#,
+            ),
+            (
+                r#"exec("echo", [1])"#,
+                "啊啊啊啊啊啊啊啊(arguments to `exec` must be strings. got 1)",
+            ),
+        ];
+        for (input, expect) in tests {
+            assert_eq!(
+                Some(String::from(expect)),
+                eval(input).map(|o| o.to_string()),
+                "{input}"
+            );
+        }
+        assert!(matches!(
+            eval(r#"exec("herlang-no-such-command")"#),
+            Some(Object::Error(msg)) if msg.starts_with("exec \"herlang-no-such-command\" failed")
+        ));
+    }
+
+    #[test]
+    fn test_do_while_expr() {
+        let tests = vec![
+            ("let i = 0; do { let i = i + 1; } while (false); i;", "1"),
+            (
+                "let i = 0; 先干再说 { let i = i + 1; } 你再说一遍 (i < 3); i;",
+                "3",
+            ),
+            (
+                "let i = 0; let s = 0; do { let i = i + 1; if (i == 2) { continue; } if (i == 4) { break; } let s = s + i; } while (i < 10); s;",
+                "4",
+            ),
+            ("do { 5 } while (false)", "5"),
+            ("let f = fn() { do { return 9; } while (true) }; f();", "9"),
+            (
+                "do { 1 } while (nope)",
+                "啊啊啊啊啊啊啊啊(identifier not found: nope)",
+            ),
+        ];
+
+        for (input, expect) in tests {
+            assert_eq!(
+                expect,
+                eval(input).map_or(String::from("None"), |o| o.to_string()),
+                "{input}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_for_expr() {
+        let tests = vec![
+            (
+                "let s = 0; for (x in [1, 2, 3]) { let s = s + x; }; s;",
+                "6",
+            ),
+            (
+                "let s = \"\"; for (i, x in [\"a\", \"b\"]) { let s = s + str(i) + x; }; s;",
+                "\"0a1b\"",
+            ),
+            (
+                "let s = \"\"; for (k in {\"b\": 2, \"a\": 1}) { let s = s + k; }; s;",
+                "\"ab\"",
+            ),
+            (
+                "let s = 0; for (k, v in {\"b\": 2, \"a\": 1}) { let s = s * 10 + v; }; s;",
+                "12",
+            ),
+            (
+                "let s = \"\"; 挨个盘点 (c 在 \"好的\") { let s = c + s; }; s;",
+                "\"的好\"",
+            ),
+            (
+                "let s = 0; for (x in range(1, 10)) { if (x == 2) { continue; } if (x == 4) { break; } let s = s + x; }; s;",
+                "4",
+            ),
+            (
+                "let it = iter([1, 2, 3]); for (x in it) { if (x == 2) { break; } }; next(it)[\"value\"];",
+                "3",
+            ),
+            (
+                "let s = \"\"; for (x in range(0, 10, 3)) { let s = s + str(x); }; for (x in range(3, 0, -2)) { let s = s + str(x); }; s;",
+                "\"036931\"",
+            ),
+            (
+                "let s = 0; for (i, x in range(1000000000000)) { if (i == 3) { break; } let s = s + x; }; s;",
+                "3",
+            ),
+            ("for (x in range(3, 0)) { x }", "None"),
+            (
+                "let f = fn() { for (x in [7, 8]) { return x; } }; f();",
+                "7",
+            ),
+            ("for (x in []) { x }", "None"),
+            (
+                "let doubled = fn(it) { {\"next\": fn() { let s = it.next(); if (s.done) { s } else { {\"done\": false, \"value\": s.value * 2} } }} };
+                 let s = \"\"; for (i, x in doubled(iter([1, 2, 3]))) { let s = s + str(i) + str(x); }; s;",
+                "\"021426\"",
+            ),
+            (
+                "let it = iter([1, 2, 3]); let h = {\"next\": fn() { it.next() }};
+                 for (x in h) { if (x == 2) { break; } }; it.next().value;",
+                "3",
+            ),
+            (
+                "let s = \"\"; for (k in {\"next\": 1}) { let s = s + k; }; s;",
+                "\"next\"",
+            ),
+            (
+                "for (x in {\"next\": fn() { 1 }}) { x }",
+                "啊啊啊啊啊啊啊啊(next() must return {\"value\", \"done\"}, got 1)",
+            ),
+            (
+                "for (x in {\"next\": fn() { 1 / 0 }}) { x }",
+                "啊啊啊啊啊啊啊啊(division by zero)",
+            ),
+            (
+                "for (x in 3) { x }",
+                "啊啊啊啊啊啊啊啊(for-in not supported: 3)",
+            ),
+            (
+                "for (x in [1]) { x + true }",
+                "啊啊啊啊啊啊啊啊(type mismatch: 1 + true)",
+            ),
+        ];
+
+        for (input, expect) in tests {
+            assert_eq!(
+                expect,
+                eval(input).map_or(String::from("None"), |o| o.to_string()),
+                "{input}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_c_for_expr() {
+        let tests = vec![
+            (
+                "let s = 0; for (let i = 0; i < 5; i++) { if (i == 1) { continue; } if (i == 3) { break; } let s = s + i; }; s;",
+                "2",
+            ),
+            ("let i = 7; for (let i = 0; i < 3; i++) { i }; i;", "7"),
+            (
+                "let f = fn() { for (let i = 0; ; i++) { if (i == 4) { return i; } } }; f();",
+                "4",
+            ),
+            (
+                "let n = 0; 挨个盘点 (; n < 3; let n = n + 1) { n }; n;",
+                "3",
+            ),
+            (
+                "let s = 0; for (let i = 0; i < 3; i++) { 下头菜单 i { 1 => { continue; }, _ => { let s = s + i; } }; }; s;",
+                "2",
+            ),
+        ];
+
+        for (input, expect) in tests {
+            assert_eq!(
+                expect,
+                eval(input).map_or(String::from("None"), |o| o.to_string()),
+                "{input}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_run_tests() {
+        let input = r#"
+let x = 1;
+puts(x);
+考一考 "a" { let y = 2; assert(x == 1); }
+考一考 "b" { 必须的(y == 2, "y is " + str(y)) }
+考一考 "c" { return 1; assert(false) }
+考一考 "d" { quit(3) }
+"#;
+        let program = Parser::new(Lexer::new(input)).parse();
+        let mut evaluator = Evaluator::new(Shared::new(Env::from(new_builtins())));
+
+        let (results, output) = capture_output(|| evaluator.run_tests(&program));
+        assert_eq!("1\n", output);
+        assert_eq!(
+            vec![
+                None,
+                Some(Object::Error(String::from("identifier not found: y"))),
+                None,
+                Some(Object::Exit(3)),
+            ],
+            results
+                .unwrap()
+                .into_iter()
+                .map(|result| result.failure)
+                .collect::<Vec<_>>()
+        );
+
+        assert_eq!(Some(Object::Int(1)), eval("考一考 \"a\" { quit(1) } 1"));
+        assert_eq!(
+            Some(Object::Error(String::from("assertion failed: no"))),
+            eval("assert(325, \"no\")")
+        );
+    }
+
+    #[test]
+    fn test_builtin_registry() {
+        let registry = BuiltinRegistry::new();
+        let builtins = new_builtins();
+
+        for def in registry.iter() {
+            for name in std::iter::once(&def.name).chain(def.aliases) {
+                match &builtins[*name] {
+                    Object::Builtin(arity, _) => assert_eq!(def.arity, *arity, "{name}"),
+                    Object::EnvBuiltin(_) => {}
+                    o => panic!("{name} is bound to {o}"),
+                }
+            }
+        }
+        assert_eq!(
+            builtins.len(),
+            registry
+                .iter()
+                .map(|def| 1 + def.aliases.len())
+                .sum::<usize>()
+        );
+
+        assert_eq!(Some("puts"), registry.get("家人们").map(|def| def.name));
+        assert_eq!(
+            Some(String::from("push(数组, 值)")),
+            registry.get("push").map(|def| def.signature())
+        );
+        assert!(registry.get("nope").is_none());
+
+        assert_eq!(
+            Some("len"),
+            registry.method("string", "len").map(|def| def.name)
+        );
+        assert!(registry.method("int", "len").is_none());
+        assert!(registry.method("array", "去重").is_none());
+        assert_eq!(
+            Some("repr"),
+            registry.method("function", "repr").map(|def| def.name)
+        );
+        assert!(registry.method("string", "puts").is_none());
+    }
+
+    #[test]
+    fn test_array_helpers() {
+        let tests = vec![
+            ("flat([1, [2, [3]], [], 4])", "[1, 2, [3], 4]"),
+            ("zip([1, 2, 3], [\"a\", \"b\"])", "[[1, \"a\"], [2, \"b\"]]"),
+            ("[5, 6].enumerate()", "[[0, 5], [1, 6]]"),
+            (
+                "unique([1, [2], 1, [2], \"1\", 2元])",
+                "[1, [2], \"1\", 2元]",
+            ),
+            ("组CP(拍平([[1], [2]]), [3, 4])", "[[1, 3], [2, 4]]"),
+            ("报数(去重([]))", "[]"),
+            ("range(3)", "[iterator]"),
+            (
+                "let r = 数到(-1, 1); [r.next().value, r.next().value, r.next().done]",
+                "[-1, 0, true]",
+            ),
+            (
+                "range(1, \"a\")",
+                "啊啊啊啊啊啊啊啊(illegal argument to `range` (want end, or start, end and step ints, got 1, \"a\"))",
+            ),
+            (
+                "range(0, 5, 0)",
+                "啊啊啊啊啊啊啊啊(step of `range` must not be 0)",
+            ),
+            (
+                "zip([], 1)",
+                "啊啊啊啊啊啊啊啊(argument to `zip` must be array. got 1)",
+            ),
+            (
+                "unique(\"aa\")",
+                "啊啊啊啊啊啊啊啊(argument to `unique` must be array. got \"aa\")",
+            ),
+        ];
+
+        for (input, expect) in tests {
+            assert_eq!(
+                Some(String::from(expect)),
+                eval(input).map(|o| o.to_string()),
+                "{input}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_print() {
+        let tests = vec![
+            ("print(\"hi\")", "hi\n"),
+            ("聚焦(1, \"a\", [\"b\"], 那咋了)", "1 a [\"b\"] false\n"),
+            ("print()", "\n"),
+            ("print(1, 2, {\"sep\": \", \"}); print(3)", "1, 2\n3\n"),
+            (
+                "print(\"a\", {\"end\": \"\"}); print(\"b\", {\"sep\": \"-\", \"end\": \"!\"})",
+                "ab!",
+            ),
+            ("print({\"a\": 1})", "{\"a\": 1}\n"),
+            (
+                "print({\"c\": 3, \"a\": 1, \"d\": 4, \"b\": 2})",
+                "{\"a\": 1, \"b\": 2, \"c\": 3, \"d\": 4}\n",
+            ),
+        ];
+
+        for (input, expect) in tests {
+            let (result, output) = capture_output(|| eval(input));
+            assert_eq!(Some(Object::Null), result);
+            assert_eq!(expect, output);
+        }
+
+        assert_eq!(
+            Some(Object::Error(String::from(
+                "option \"end\" of `print` must be string. got 1"
+            ))),
+            eval("print(1, {\"end\": 1})")
+        );
+    }
+
+    #[test]
+    fn test_copy_on_write() {
+        let mut evaluator = Evaluator::new(Shared::new(Env::from(new_builtins())));
+        let program = Parser::new(Lexer::new(
+            "let a = [1, 2]; let b = a; let c = push(b, 3); let h = {1: a};",
+        ))
+        .parse();
+        evaluator.eval(&program);
+
+        let get = |name: &str| evaluator.env.borrow_mut().get(String::from(name));
+        let (Some(Object::Array(a)), Some(Object::Array(b)), Some(Object::Array(c))) =
+            (get("a"), get("b"), get("c"))
+        else {
+            panic!("expected arrays");
+        };
+        assert!(Rc::ptr_eq(&a, &b));
+        assert_eq!(vec![Object::Int(1), Object::Int(2)], *a);
+        assert_eq!(vec![Object::Int(1), Object::Int(2), Object::Int(3)], *c);
+        let Some(Object::Hash(h)) = get("h") else {
+            panic!("expected a hash");
+        };
+        assert!(
+            matches!(h.get(&Object::Int(1)), Some(Object::Array(item)) if Rc::ptr_eq(item, &a))
+        );
+    }
+
+    #[test]
+    fn test_output_sink() {
+        let writes = Shared::new(vec![]);
+        let sink = Shared::clone(&writes);
+        let outer = set_output_sink(Box::new(move |text: &str| {
+            sink.borrow_mut().push(text.to_string())
+        }));
+        eval("小作文(1, \"a\"); puts(2); print(\"b\", {\"end\": \"\"})");
+        set_output_sink(outer);
+
+        assert_eq!(vec!["1\n", "\"a\"\n", "2\n", "b"], *writes.borrow());
+    }
+
+    #[test]
+    fn test_chinese_numerals() {
+        let tests = vec![
+            ("中文数字(0)", "零"),
+            ("中文数字(10)", "十"),
+            ("中文数字(15)", "十五"),
+            ("中文数字(123)", "一百二十三"),
+            ("中文数字(1005)", "一千零五"),
+            ("中文数字(1050)", "一千零五十"),
+            ("中文数字(10086)", "一万零八十六"),
+            ("中文数字(110000)", "十一万"),
+            ("中文数字(100010)", "十万零一十"),
+            ("中文数字(100000001)", "一亿零一"),
+            ("中文数字(1000000000000)", "一万亿"),
+            ("中文数字(-20)", "负二十"),
+            ("大写金额(0)", "零元整"),
+            ("大写金额(10)", "壹拾元整"),
+            ("大写金额(10086)", "壹万零捌拾陆元整"),
+            ("大写金额(3000400)", "叁佰万零肆佰元整"),
+        ];
+
+        for (input, expect) in tests {
+            assert_eq!(Some(Object::String(String::from(expect))), eval(input));
+        }
+
+        assert_eq!(
+            Some(Object::Error(String::from(
+                "argument to `大写金额` must be int. got \"1\"",
+            ))),
+            eval("大写金额(\"1\")")
+        );
+    }
+
+    #[test]
+    fn test_error_handling() {
+        let tests = vec![
+            (
+                "5 + true",
+                Some(Object::Error(String::from("type mismatch: 5 + true"))),
+            ),
+            (
+                "5 + true; 5;",
+                Some(Object::Error(String::from("type mismatch: 5 + true"))),
+            ),
+            (
+                "-true",
+                Some(Object::Error(String::from("unknown operator: -true"))),
+            ),
+            (
+                "let x = 0; 1 / x",
+                Some(Object::Error(String::from("division by zero"))),
+            ),
+            (
+                "5; true + false; 5;",
+                Some(Object::Error(String::from(
+                    "unknown operator: true + false",
+                ))),
             ),
             (
                 "if (10 > 1) { true + false; }",
@@ -1010,6 +3699,22 @@ if (10 > 1) {
         }
     }
 
+    #[cfg(feature = "invariants")]
+    #[test]
+    #[should_panic(expected = "invariant violated: BreakStatement stored inside another value")]
+    fn test_invariants() {
+        use crate::evaluator::invariants::check;
+
+        // Values the evaluator makes pass.
+        eval("let h = {1: [2.5, 3元], \"a\": iter([1])}; h[1][0] / 0; next(h[\"a\"]); h");
+        check(&Object::ReturnValue(Box::new(Object::Null)), String::new);
+
+        check(
+            &Object::Array(Rc::new(vec![Object::Int(1), Object::BreakStatement])),
+            || "test".to_string(),
+        );
+    }
+
     // FIXME Someday, I want to run Z Combinator...
     //     #[test]
     //     fn test_z_combinator() {
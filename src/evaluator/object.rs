@@ -1,64 +1,210 @@
 #![allow(clippy::derived_hash_with_manual_eq)]
+#![allow(unpredictable_function_pointer_comparisons)]
 
 use crate::ast::*;
+use crate::evaluator::builtins;
 use crate::evaluator::env::*;
+use crate::evaluator::shared::{Rc, Shared};
 use crate::lexer::unescape::escape_str;
-use std::cell::RefCell;
+use std::cmp::Ordering;
 use std::collections::HashMap;
 use std::fmt;
 use std::hash::{Hash, Hasher};
-use std::rc::Rc;
 
 pub type BuiltinFunc = fn(Vec<Object>) -> Object;
 
 #[derive(PartialEq, Clone, Debug)]
 pub enum Object {
     Int(i64),
+    Float(f64),
+    /// A number that remembers its unit, from a literal like `5元`.
+    Quantity(i64, String),
     String(String),
     Bool(bool),
-    Array(Vec<Object>),
-    Hash(HashMap<Object, Object>),
-    Func(Vec<Ident>, BlockStmt, Rc<RefCell<Env>>),
+    /// Copies of an array or hash share its items until one of them is
+    /// changed, see `Rc::make_mut`.
+    Array(Rc<Vec<Object>>),
+    Hash(Rc<HashMap<Object, Object>>),
+    Func(Vec<Param>, Option<TypeAnnotation>, BlockStmt, Shared<Env>),
     Builtin(i32, BuiltinFunc),
+    /// A builtin that works on the evaluator's scopes, so it can't be a
+    /// plain `BuiltinFunc`.
+    EnvBuiltin(EnvBuiltin),
+    /// An `async fn`, wrapping the `Object::Func` that runs as its task.
+    Async(Box<Object>),
+    Task(Shared<Task>),
+    Iter(Shared<Iter>),
     Null,
     ReturnValue(Box<Object>),
     BreakStatement,
     ContinueStatement,
     Error(String),
+    /// `quit(code)`: stops the program like an error does, and asks the host
+    /// to exit with `code`. Only the CLI actually ends the process.
+    Exit(i32),
+}
+
+/// What `iter()` and `range()` hand out. Copies of an iterator share it,
+/// so they advance together.
+#[derive(PartialEq, Clone, Debug)]
+pub enum Iter {
+    /// The values to go through and how many are done.
+    Items { items: Rc<Vec<Object>>, pos: usize },
+    /// Ints from `next` by `step`, up to but not including `end`, made one
+    /// at a time. `step` is never 0.
+    Range { next: i64, end: i64, step: i64 },
+}
+
+impl Iterator for Iter {
+    type Item = Object;
+
+    fn next(&mut self) -> Option<Object> {
+        match self {
+            Iter::Items { items, pos } => {
+                let item = items.get(*pos).cloned();
+                if item.is_some() {
+                    *pos += 1;
+                }
+                item
+            }
+            Iter::Range { next, end, step } => {
+                let left = if *step > 0 { next < end } else { next > end };
+                if !left {
+                    return None;
+                }
+                let item = *next;
+                // Past the ends of i64 there is nothing before `end` either.
+                *next = next.checked_add(*step).unwrap_or(*end);
+                Some(Object::Int(item))
+            }
+        }
+    }
+}
+
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum EnvBuiltin {
+    /// `globals()`: the top-level bindings.
+    Globals,
+    /// `locals()`: the bindings of the innermost scope.
+    Locals,
+    /// `del(name)`: unbinds `name` in the innermost scope.
+    Del,
+    /// `breakpoint()`: hands the current state to the host, see
+    /// `Evaluator::with_breakpoint`.
+    Breakpoint,
+}
+
+impl EnvBuiltin {
+    pub fn arity(self) -> usize {
+        match self {
+            EnvBuiltin::Globals | EnvBuiltin::Locals | EnvBuiltin::Breakpoint => 0,
+            EnvBuiltin::Del => 1,
+        }
+    }
+}
+
+/// A call to an `async fn`, run when awaited or once the program is done.
+#[derive(PartialEq, Clone, Debug)]
+pub enum Task {
+    Pending(Object, Vec<Object>),
+    Running,
+    Done(Object),
+}
+
+/// How much of a value `Object::repr` shows, so printing a huge array
+/// doesn't flood the REPL or an error message. `None` shows everything.
+#[derive(PartialEq, Clone, Copy, Debug, Default)]
+pub struct ReprLimit {
+    /// Items shown of each array or hash, the rest as `……共 N 项`.
+    pub max_items: Option<usize>,
+    /// Chars shown of each string, the rest as `……共 N 字`.
+    pub max_len: Option<usize>,
+}
+
+/// `Object::repr`, as something to `write!`.
+struct Repr<'a>(&'a Object, ReprLimit);
+
+impl fmt::Display for Repr<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt_repr(f, self.1)
+    }
 }
 
 /// This is actually repr
 impl fmt::Display for Object {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.fmt_repr(f, ReprLimit::default())
+    }
+}
+
+impl Object {
+    /// Like `to_string`, but cut short where `limit` says.
+    pub fn repr(&self, limit: ReprLimit) -> String {
+        Repr(self, limit).to_string()
+    }
+
+    fn fmt_repr(&self, f: &mut fmt::Formatter, limit: ReprLimit) -> fmt::Result {
         match *self {
             Object::Int(ref value) => write!(f, "{value}"),
-            Object::String(ref value) => write!(f, "{}", escape_str(value)),
+            Object::Float(value) => write!(f, "{}", format_float(value)),
+            Object::Quantity(ref value, ref unit) => write!(f, "{value}{unit}"),
+            Object::String(ref value) => match limit.max_len {
+                Some(max) if value.chars().count() > max => {
+                    let shown = value.chars().take(max).collect::<String>();
+                    let escaped = escape_str(&shown);
+                    write!(
+                        f,
+                        "{}……共 {} 字\"",
+                        &escaped[..escaped.len() - 1],
+                        value.chars().count()
+                    )
+                }
+                _ => write!(f, "{}", escape_str(value)),
+            },
             Object::Bool(ref value) => write!(f, "{value}"),
             Object::Array(ref objects) => {
-                let mut result = String::new();
-                for (i, obj) in objects.iter().enumerate() {
-                    if i < 1 {
-                        result.push_str(&format!("{obj}"));
-                    } else {
-                        result.push_str(&format!(", {obj}"));
+                let max = limit.max_items.unwrap_or(usize::MAX);
+                write!(f, "[")?;
+                for (i, obj) in objects.iter().take(max).enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
                     }
+                    obj.fmt_repr(f, limit)?;
                 }
-                write!(f, "[{result}]")
+                if objects.len() > max {
+                    let sep = if max > 0 { ", " } else { "" };
+                    write!(f, "{sep}……共 {} 项", objects.len())?;
+                }
+                write!(f, "]")
             }
             Object::Hash(ref hash) => {
-                let mut result = String::new();
-                for (i, (k, v)) in hash.iter().enumerate() {
-                    if i < 1 {
-                        result.push_str(&format!("{k}: {v}"));
-                    } else {
-                        result.push_str(&format!(", {k}: {v}"));
+                let max = limit.max_items.unwrap_or(usize::MAX);
+                write!(f, "{{")?;
+                for (i, (k, v)) in builtins::sorted_entries(hash)
+                    .into_iter()
+                    .take(max)
+                    .enumerate()
+                {
+                    if i > 0 {
+                        write!(f, ", ")?;
                     }
+                    write!(f, "{}: {}", Repr(k, limit), Repr(v, limit))?;
+                }
+                if hash.len() > max {
+                    let sep = if max > 0 { ", " } else { "" };
+                    write!(f, "{sep}……共 {} 项", hash.len())?;
                 }
-                write!(f, "{{{result}}}")
+                write!(f, "}}")
             }
-            Object::Func(ref params, _, _) => {
+            Object::Func(ref params, ..) => {
                 let mut result = String::new();
-                for (i, Ident(s)) in params.iter().enumerate() {
+                for (
+                    i,
+                    Param {
+                        ident: Ident(s), ..
+                    },
+                ) in params.iter().enumerate()
+                {
                     if i < 1 {
                         result.push_str(&s.to_string());
                     } else {
@@ -67,14 +213,83 @@ impl fmt::Display for Object {
                 }
                 write!(f, "fn({result}) {{ ... }}")
             }
-            Object::Builtin(_, _) => write!(f, "[builtin function]"),
+            Object::Builtin(_, _) | Object::EnvBuiltin(_) => write!(f, "[builtin function]"),
+            Object::Async(ref func) => write!(f, "async {}", Repr(func, limit)),
+            Object::Task(_) => write!(f, "[task]"),
+            Object::Iter(_) => write!(f, "[iterator]"),
             Object::Null => write!(f, "null"),
             Object::BreakStatement => write!(f, "[break statement]"),
             Object::ContinueStatement => write!(f, "[continue statement]"),
-            Object::ReturnValue(ref value) => write!(f, "ReturnValue({value})"),
+            Object::ReturnValue(ref value) => write!(f, "ReturnValue({})", Repr(value, limit)),
             Object::Error(ref value) => write!(f, "啊啊啊啊啊啊啊啊({value})"),
+            Object::Exit(code) => write!(f, "[exit {code}]"),
+        }
+    }
+}
+
+/// Type names accepted in annotations, with the name `Object::type_name` uses.
+pub const TYPE_NAMES: &[(&str, &str)] = &[
+    ("整数", "int"),
+    ("int", "int"),
+    ("小数", "float"),
+    ("float", "float"),
+    ("数量", "quantity"),
+    ("quantity", "quantity"),
+    ("字符串", "string"),
+    ("string", "string"),
+    ("布尔", "bool"),
+    ("bool", "bool"),
+    ("数组", "array"),
+    ("array", "array"),
+    ("哈希", "hash"),
+    ("hash", "hash"),
+    ("函数", "function"),
+    ("function", "function"),
+    ("迭代器", "iterator"),
+    ("iterator", "iterator"),
+    ("空", "null"),
+    ("null", "null"),
+    ("任意", "any"),
+    ("any", "any"),
+];
+
+impl Object {
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Object::Int(_) => "int",
+            Object::Float(_) => "float",
+            Object::Quantity(..) => "quantity",
+            Object::String(_) => "string",
+            Object::Bool(_) => "bool",
+            Object::Array(_) => "array",
+            Object::Hash(_) => "hash",
+            Object::Func(..) | Object::Builtin(..) | Object::EnvBuiltin(_) | Object::Async(_) => {
+                "function"
+            }
+            Object::Task(_) => "task",
+            Object::Iter(_) => "iterator",
+            Object::Null => "null",
+            Object::ReturnValue(value) => value.type_name(),
+            Object::BreakStatement | Object::ContinueStatement => "statement",
+            Object::Error(_) => "error",
+            Object::Exit(_) => "exit",
         }
     }
+
+    /// Everything but `null`, `false` and 325 counts as true.
+    pub fn is_truthy(&self) -> bool {
+        !matches!(self, Object::Null | Object::Bool(false) | Object::Int(325))
+    }
+}
+
+impl TypeAnnotation {
+    /// The `Object::type_name` this annotation stands for, `any` included.
+    pub fn resolve(&self) -> Option<&'static str> {
+        TYPE_NAMES
+            .iter()
+            .find(|(name, _)| *name == self.0)
+            .map(|(_, type_name)| *type_name)
+    }
 }
 
 impl Eq for Object {}
@@ -89,3 +304,104 @@ impl Hash for Object {
         }
     }
 }
+
+impl Object {
+    /// Equality as `==` sees it: arrays element by element, hashes by their
+    /// entries whatever the order, functions when they have the same code
+    /// and scope. Ints and floats compare by value, so `1 == 1.0`; other
+    /// values of different types are never equal, so an int never equals a
+    /// quantity, and `1` doesn't equal `true`.
+    #[allow(clippy::mutable_key_type)]
+    pub fn deep_eq(&self, other: &Object) -> bool {
+        match (self, other) {
+            (Object::Array(a), Object::Array(b)) => {
+                a.len() == b.len() && a.iter().zip(b.iter()).all(|(x, y)| x.deep_eq(y))
+            }
+            (Object::Hash(a), Object::Hash(b)) => {
+                a.len() == b.len()
+                    && a.iter()
+                        .all(|(key, x)| b.get(key).is_some_and(|y| x.deep_eq(y)))
+            }
+            (Object::Func(params, ty, body, env), Object::Func(params2, ty2, body2, env2)) => {
+                Shared::ptr_eq(env, env2) && params == params2 && ty == ty2 && body == body2
+            }
+            (Object::Int(a), Object::Float(b)) | (Object::Float(b), Object::Int(a)) => {
+                *a as f64 == *b
+            }
+            (Object::Async(a), Object::Async(b)) => a.deep_eq(b),
+            (Object::Task(a), Object::Task(b)) => Shared::ptr_eq(a, b),
+            (Object::Iter(a), Object::Iter(b)) => Shared::ptr_eq(a, b),
+            (Object::ReturnValue(a), Object::ReturnValue(b)) => a.deep_eq(b),
+            (Object::Func(..), _)
+            | (Object::Async(_), _)
+            | (Object::Task(_), _)
+            | (Object::Iter(_), _)
+            | (Object::ReturnValue(_), _)
+            | (Object::Array(_), _)
+            | (Object::Hash(_), _) => false,
+            _ => self == other,
+        }
+    }
+
+    /// An order over all values, for sorting: first by type (null, bool,
+    /// number, quantity, string, array, hash, then the rest), then by value.
+    /// Ints and floats are ordered together by value.
+    /// Quantities go by unit and then amount, strings by code point, arrays
+    /// element by element, and hashes by their entries sorted by key.
+    /// Values without an order of their own, like functions, tie.
+    pub fn cmp_total(&self, other: &Object) -> Ordering {
+        let rank = |obj: &Object| match obj {
+            Object::Null => 0,
+            Object::Bool(_) => 1,
+            Object::Int(_) | Object::Float(_) => 2,
+            Object::Quantity(..) => 3,
+            Object::String(_) => 4,
+            Object::Array(_) => 5,
+            Object::Hash(_) => 6,
+            _ => 7,
+        };
+
+        match (self, other) {
+            (Object::Bool(a), Object::Bool(b)) => a.cmp(b),
+            (Object::Int(a), Object::Int(b)) => a.cmp(b),
+            (Object::Float(a), Object::Float(b)) => a.total_cmp(b),
+            (Object::Int(a), Object::Float(b)) => (*a as f64).total_cmp(b),
+            (Object::Float(a), Object::Int(b)) => a.total_cmp(&(*b as f64)),
+            (Object::Quantity(a, unit), Object::Quantity(b, unit2)) => {
+                unit.cmp(unit2).then(a.cmp(b))
+            }
+            (Object::String(a), Object::String(b)) => a.cmp(b),
+            (Object::Array(a), Object::Array(b)) => cmp_all(a.iter(), b.iter()),
+            (Object::Hash(a), Object::Hash(b)) => {
+                cmp_all(sorted_entries(a).into_iter(), sorted_entries(b).into_iter())
+            }
+            _ => rank(self).cmp(&rank(other)),
+        }
+    }
+}
+
+/// Keys and values of `hash` in one list, in key order.
+#[allow(clippy::mutable_key_type)]
+fn sorted_entries(hash: &HashMap<Object, Object>) -> Vec<&Object> {
+    let mut entries = hash.iter().collect::<Vec<_>>();
+    entries.sort_by(|x, y| x.0.cmp_total(y.0));
+    entries
+        .into_iter()
+        .flat_map(|(key, value)| [key, value])
+        .collect()
+}
+
+fn cmp_all<'a>(
+    mut a: impl Iterator<Item = &'a Object>,
+    mut b: impl Iterator<Item = &'a Object>,
+) -> Ordering {
+    loop {
+        match (a.next(), b.next()) {
+            (Some(x), Some(y)) => match x.cmp_total(y) {
+                Ordering::Equal => continue,
+                order => return order,
+            },
+            (x, y) => return x.is_some().cmp(&y.is_some()),
+        }
+    }
+}
@@ -0,0 +1,67 @@
+use crate::evaluator::env::Env;
+use crate::evaluator::object::Object;
+
+/// What a program that isn't allowed something gets back.
+pub const NO_PERMISSION: &str = "没权限，想peach";
+
+/// Which capabilities a program may use. Everything is allowed by default;
+/// `locked` turns it all off for running code nobody has looked at.
+///
+/// Builtins for randomness or the network get a switch here when they are
+/// added.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SandboxConfig {
+    /// Reading files, i.e. `引入`.
+    pub filesystem: bool,
+    /// Stopping the program with `quit`, `哼` or `哈`.
+    pub exit: bool,
+    /// Waiting with `sleep` or `躺平`.
+    pub time: bool,
+    /// Running other programs with `exec` or `摇人`.
+    pub process: bool,
+}
+
+impl Default for SandboxConfig {
+    fn default() -> Self {
+        SandboxConfig {
+            filesystem: true,
+            exit: true,
+            time: true,
+            process: true,
+        }
+    }
+}
+
+impl SandboxConfig {
+    pub fn locked() -> Self {
+        SandboxConfig {
+            filesystem: false,
+            exit: false,
+            time: false,
+            process: false,
+        }
+    }
+
+    /// Shadows the builtins `env` may not use with ones that only fail.
+    pub(crate) fn restrict(&self, env: &mut Env) {
+        if !self.exit {
+            for name in ["quit", "哼", "哈"] {
+                env.define(String::from(name), &Object::Builtin(-1, denied));
+            }
+        }
+        if !self.time {
+            for name in ["sleep", "躺平"] {
+                env.define(String::from(name), &Object::Builtin(1, denied));
+            }
+        }
+        if !self.process {
+            for name in ["exec", "摇人"] {
+                env.define(String::from(name), &Object::Builtin(-1, denied));
+            }
+        }
+    }
+}
+
+fn denied(_: Vec<Object>) -> Object {
+    Object::Error(String::from(NO_PERMISSION))
+}
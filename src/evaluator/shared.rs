@@ -0,0 +1,106 @@
+//! The handle scopes, tasks and iterators are shared through.
+//!
+//! By default it is an `Rc<RefCell<T>>`. With the `sync` feature it is an
+//! `Arc<RwLock<T>>` instead, which makes values, the `Evaluator` and the
+//! `Engine` `Send`, so a warmed up engine can be moved to another thread.
+
+#[cfg(not(feature = "sync"))]
+mod inner {
+    pub type Cell<T> = std::rc::Rc<std::cell::RefCell<T>>;
+    pub type Ref<'a, T> = std::cell::Ref<'a, T>;
+    pub type RefMut<'a, T> = std::cell::RefMut<'a, T>;
+
+    pub fn new<T>(value: T) -> Cell<T> {
+        std::rc::Rc::new(std::cell::RefCell::new(value))
+    }
+
+    pub fn borrow<T>(cell: &Cell<T>) -> Ref<'_, T> {
+        cell.borrow()
+    }
+
+    pub fn borrow_mut<T>(cell: &Cell<T>) -> RefMut<'_, T> {
+        cell.borrow_mut()
+    }
+
+    pub fn ptr_eq<T>(a: &Cell<T>, b: &Cell<T>) -> bool {
+        std::rc::Rc::ptr_eq(a, b)
+    }
+}
+
+#[cfg(feature = "sync")]
+mod inner {
+    use std::sync::{Arc, PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+    pub type Cell<T> = Arc<RwLock<T>>;
+    pub type Ref<'a, T> = RwLockReadGuard<'a, T>;
+    pub type RefMut<'a, T> = RwLockWriteGuard<'a, T>;
+
+    pub fn new<T>(value: T) -> Cell<T> {
+        Arc::new(RwLock::new(value))
+    }
+
+    // A panic while holding the lock leaves the value as it was written so
+    // far, same as a `RefCell` would.
+    pub fn borrow<T>(cell: &Cell<T>) -> Ref<'_, T> {
+        cell.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    pub fn borrow_mut<T>(cell: &Cell<T>) -> RefMut<'_, T> {
+        cell.write().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    pub fn ptr_eq<T>(a: &Cell<T>, b: &Cell<T>) -> bool {
+        Arc::ptr_eq(a, b)
+    }
+}
+
+pub use inner::{Ref, RefMut};
+
+/// The pointer arrays and hashes are shared through: `Rc`, or `Arc` with
+/// the `sync` feature.
+#[cfg(not(feature = "sync"))]
+pub type Rc<T> = std::rc::Rc<T>;
+#[cfg(feature = "sync")]
+pub type Rc<T> = std::sync::Arc<T>;
+
+/// A shared, mutable `T`. Clones point at the same value.
+pub struct Shared<T>(inner::Cell<T>);
+
+impl<T> Shared<T> {
+    pub fn new(value: T) -> Self {
+        Shared(inner::new(value))
+    }
+
+    /// Panics (or, with `sync`, blocks) while the value is borrowed mutably.
+    pub fn borrow(&self) -> Ref<'_, T> {
+        inner::borrow(&self.0)
+    }
+
+    /// Panics (or, with `sync`, blocks) while the value is borrowed.
+    pub fn borrow_mut(&self) -> RefMut<'_, T> {
+        inner::borrow_mut(&self.0)
+    }
+
+    /// Whether `a` and `b` are the same value, not just equal ones.
+    pub fn ptr_eq(a: &Self, b: &Self) -> bool {
+        inner::ptr_eq(&a.0, &b.0)
+    }
+}
+
+impl<T> Clone for Shared<T> {
+    fn clone(&self) -> Self {
+        Shared(self.0.clone())
+    }
+}
+
+impl<T: PartialEq> PartialEq for Shared<T> {
+    fn eq(&self, other: &Self) -> bool {
+        Shared::ptr_eq(self, other) || *self.borrow() == *other.borrow()
+    }
+}
+
+impl<T: std::fmt::Debug> std::fmt::Debug for Shared<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        self.borrow().fmt(f)
+    }
+}
@@ -0,0 +1,195 @@
+//! `herlang fix`: rewrites a source written for an older language version so
+//! it means the same under `LANGUAGE_VERSION`.
+//!
+//! Names that have since become keywords get a trailing `_` (more if that is
+//! taken too), and a `#她版本` header is bumped. Changes are made at the
+//! spans the lexer reports, so the rest of the source stays as it was.
+
+use crate::lexer::{LANGUAGE_VERSION, Lexer, VERSION_HEADER};
+use crate::span::Span;
+use crate::token::Token;
+use std::collections::{HashMap, HashSet};
+
+/// Replace `span` of the source with `replacement`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Edit {
+    pub span: Span,
+    pub replacement: String,
+}
+
+/// What to change in `src` for it to run the same under the current version.
+/// `from` is the version `src` was written for, unless it has a header.
+pub fn fixes(src: &str, from: u32) -> Vec<Edit> {
+    let mut lexer = Lexer::new(src).with_version(from);
+    let mut tokens = vec![];
+    loop {
+        let (tok, span) = lexer.next_token_with_span();
+        if tok == Token::Eof {
+            break;
+        }
+        tokens.push((tok, span));
+    }
+
+    let names = tokens
+        .iter()
+        .filter_map(|(tok, _)| match tok {
+            Token::Ident(name) => Some(name.as_str()),
+            _ => None,
+        })
+        .collect::<HashSet<_>>();
+    let mut renamed = HashMap::new();
+    let mut edits = vec![];
+
+    for (tok, span) in &tokens {
+        match tok {
+            Token::Version(version) if *version < LANGUAGE_VERSION => edits.push(Edit {
+                span: *span,
+                replacement: format!("{VERSION_HEADER} {LANGUAGE_VERSION}"),
+            }),
+            Token::Ident(name) if is_keyword(name) => {
+                let replacement = renamed
+                    .entry(name.as_str())
+                    .or_insert_with(|| fresh_name(name, &names))
+                    .clone();
+                edits.push(Edit {
+                    span: *span,
+                    replacement,
+                });
+            }
+            _ => {}
+        }
+    }
+
+    edits
+}
+
+/// Whether the current version reads `name` as something other than a name.
+fn is_keyword(name: &str) -> bool {
+    Lexer::new(name).next_token() != Token::Ident(name.to_string())
+}
+
+fn fresh_name(name: &str, taken: &HashSet<&str>) -> String {
+    let mut fresh = format!("{name}_");
+    while taken.contains(fresh.as_str()) || is_keyword(&fresh) {
+        fresh.push('_');
+    }
+    fresh
+}
+
+/// `src` with `edits`, which must not overlap, made.
+pub fn apply(src: &str, edits: &[Edit]) -> String {
+    let chars = src.chars().collect::<Vec<_>>();
+    let mut edits = edits.iter().collect::<Vec<_>>();
+    edits.sort_by_key(|edit| edit.span.start.offset);
+
+    let mut out = String::new();
+    let mut pos = 0;
+    for edit in edits {
+        out.extend(&chars[pos..edit.span.start.offset]);
+        out.push_str(&edit.replacement);
+        pos = edit.span.end.offset;
+    }
+    out.extend(&chars[pos..]);
+    out
+}
+
+/// The changed lines between `old` and `new`, as a unified diff without
+/// context (like `diff -U0`). Edits from `fixes` never add or remove a line,
+/// so lines are compared one to one.
+pub fn diff(path: &str, old: &str, new: &str) -> String {
+    let old = old.lines().collect::<Vec<_>>();
+    let new = new.lines().collect::<Vec<_>>();
+    let mut out = String::new();
+    let mut i = 0;
+
+    while i < old.len().max(new.len()) {
+        if old.get(i) == new.get(i) {
+            i += 1;
+            continue;
+        }
+
+        let start = i;
+        while i < old.len().max(new.len()) && old.get(i) != new.get(i) {
+            i += 1;
+        }
+        let removed = &old[start.min(old.len())..i.min(old.len())];
+        let added = &new[start.min(new.len())..i.min(new.len())];
+
+        if out.is_empty() {
+            out.push_str(&format!("--- a/{path}\n+++ b/{path}\n"));
+        }
+        out.push_str(&format!(
+            "@@ -{} +{} @@\n",
+            hunk_range(start, removed.len()),
+            hunk_range(start, added.len())
+        ));
+        for line in removed {
+            out.push_str(&format!("-{line}\n"));
+        }
+        for line in added {
+            out.push_str(&format!("+{line}\n"));
+        }
+    }
+
+    out
+}
+
+/// `start,len` of a hunk, with `start` counted from 1 (or the line before,
+/// when the hunk is empty on this side).
+fn hunk_range(start: usize, len: usize) -> String {
+    match len {
+        0 => format!("{start},0"),
+        1 => format!("{}", start + 1),
+        _ => format!("{},{len}", start + 1),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::fix::*;
+    use crate::parser::Parser;
+
+    fn fix(src: &str, from: u32) -> String {
+        apply(src, &fixes(src, from))
+    }
+
+    #[test]
+    fn test_fixes() {
+        let src = "let loop = 1;\nlet loop_ = 2;\nputs(loop + loop_);\nlet x = 在;";
+        let fixed = fix(src, 1);
+        assert_eq!(
+            "let loop__ = 1;\nlet loop_ = 2;\nputs(loop__ + loop_);\nlet x = 在_;",
+            fixed
+        );
+
+        let mut parser = Parser::new(Lexer::new(&fixed));
+        parser.parse();
+        assert!(parser.get_errors().is_empty());
+
+        // Already current: nothing to do.
+        assert_eq!(Vec::<Edit>::new(), fixes("puts(loop_);", 1));
+        assert_eq!(Vec::<Edit>::new(), fixes("let x = 1; // loop", 1));
+    }
+
+    #[test]
+    fn test_version_header() {
+        assert_eq!(
+            "#她版本 2\nlet match_ = 一直摆烂_;",
+            fix("#她版本 1\nlet match = 一直摆烂;", 2)
+        );
+        assert_eq!("#她版本 2\n1", fix("#她版本 2\n1", 1));
+    }
+
+    #[test]
+    fn test_diff() {
+        let old = "#她版本 1\nlet a = 1;\nlet for = 2;\nputs(for);\n";
+        let new = fix(old, 1);
+        assert_eq!(
+            "--- a/main.her\n+++ b/main.her\n\
+             @@ -1 +1 @@\n-#她版本 1\n+#她版本 2\n\
+             @@ -3,2 +3,2 @@\n-let for = 2;\n-puts(for);\n+let for_ = 2;\n+puts(for_);\n",
+            diff("main.her", old, &new)
+        );
+        assert_eq!("", diff("main.her", old, old));
+    }
+}
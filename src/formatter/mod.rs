@@ -1,16 +1,36 @@
 #![allow(clippy::if_same_then_else)]
 use crate::ast::*;
 use crate::lexer::unescape::escape_str;
+use crate::lexer::{KEYWORDS, LANGUAGE_VERSION, Lexer, SLANG_COMMENT, is_keyword_in};
+use crate::parser::{ParseError, ParseErrors, Parser};
+use crate::span::Span;
+use crate::token::Token;
+use std::collections::HashSet;
 
 struct FormatConfig {
     max_line_length: usize,
+    /// Longer arrays and hashes get one item per line.
+    max_array_oneline: usize,
     max_hash_oneline: usize,
 }
 
+/// Which spelling of each keyword the formatter writes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum KeywordStyle {
+    /// `let`, `fn`, `while`, ...
+    #[default]
+    English,
+    /// `宝宝你是一个`, `想要你一个态度`, `你再说一遍`, ...
+    AbaAba,
+}
+
 pub struct Formatter {
     indent: usize,
     column: usize,
     config: FormatConfig,
+    keyword_style: KeywordStyle,
+    /// Language version the output has to parse as.
+    version: u32,
 }
 
 impl Default for Formatter {
@@ -26,8 +46,44 @@ impl Formatter {
             column: 1,
             config: FormatConfig {
                 max_line_length: 80,
+                max_array_oneline: 8,
                 max_hash_oneline: 3,
             },
+            keyword_style: KeywordStyle::default(),
+            version: LANGUAGE_VERSION,
+        }
+    }
+
+    /// Writes code for language `version`, never using keywords it reads
+    /// as names. A `#她版本` header on the program takes precedence.
+    pub fn with_version(mut self, version: u32) -> Self {
+        self.version = version;
+        self
+    }
+
+    pub fn with_keyword_style(mut self, style: KeywordStyle) -> Self {
+        self.keyword_style = style;
+        self
+    }
+
+    /// The first spelling of `tok` in `KEYWORDS` for the keyword style.
+    /// `tok` in the chosen style, or its only spelling if it has just one.
+    fn keyword(&self, tok: Token) -> &'static str {
+        let english = self.keyword_style == KeywordStyle::English;
+        let mut spellings = KEYWORDS
+            .iter()
+            .filter(|(spelling, t)| *t == tok && is_keyword_in(spelling, self.version));
+        spellings
+            .clone()
+            .find(|(spelling, _)| spelling.is_ascii() == english)
+            .or_else(|| spellings.next())
+            .map_or("", |(spelling, _)| spelling)
+    }
+
+    fn comment_marker(&self) -> &'static str {
+        match self.keyword_style {
+            KeywordStyle::English => "//",
+            KeywordStyle::AbaAba => SLANG_COMMENT,
         }
     }
 
@@ -38,6 +94,9 @@ impl Formatter {
             Infix::LessThan | Infix::LessThanEqual => Precedence::LessGreater,
             Infix::GreaterThan | Infix::GreaterThanEqual => Precedence::LessGreater,
             Infix::Equal | Infix::NotEqual => Precedence::Equals,
+            Infix::And => Precedence::And,
+            Infix::Or => Precedence::Or,
+            Infix::Coalesce => Precedence::Coalesce,
         }
     }
 
@@ -48,12 +107,16 @@ impl Formatter {
                 consequence: _,
                 alternative: _,
             }
-            | &Expr::Func { params: _, body: _ } => true,
+            | &Expr::Match { .. }
+            | &Expr::Func { .. } => true,
             _ => false,
         }
     }
 
     pub fn format(&mut self, program: Program) -> String {
+        if let Some(Stmt::Comment(Comment::Version(version))) = program.first() {
+            self.version = *version;
+        }
         self.format_block_stmt(program)
     }
 
@@ -65,6 +128,11 @@ impl Formatter {
     }
 
     fn normalize_block_stmt(stmts: BlockStmt) -> BlockStmt {
+        let stmts = stmts
+            .into_iter()
+            .filter(|x| !matches!(x, Stmt::Mark(_)))
+            .collect::<Vec<_>>();
+
         stmts
             .iter()
             .enumerate()
@@ -85,6 +153,8 @@ impl Formatter {
     fn format_block_stmt(&mut self, stmts: BlockStmt) -> String {
         let mut result = String::new();
         let list = Self::normalize_block_stmt(stmts);
+        // End of an `if` or `fn` statement printed without its `;`.
+        let mut unterminated: Option<usize> = None;
 
         for (i, stmt) in list.into_iter().enumerate() {
             self.column = self.indent * 2 + 1;
@@ -99,7 +169,22 @@ impl Formatter {
                 self.indent_str(0)
             };
 
-            result.push_str(&format!("{}{}", indent_str, self.format_stmt(stmt)));
+            let is_comment = matches!(stmt, Stmt::Comment(_));
+            let stmt_str = self.format_stmt(stmt);
+
+            // `fn() {}` followed by `(x)`, `[x]`, `-x` or `+x` would read as
+            // a call, index or infix, so that one keeps its `;`.
+            if let Some(end) = unterminated
+                && stmt_str.starts_with(['(', '[', '-', '+'])
+            {
+                result.insert(end, ';');
+            }
+            if !stmt_str.is_empty() && !is_comment {
+                unterminated = (!stmt_str.ends_with(';'))
+                    .then_some(result.len() + indent_str.len() + stmt_str.len());
+            }
+
+            result.push_str(&format!("{indent_str}{stmt_str}"));
         }
 
         result
@@ -107,10 +192,16 @@ impl Formatter {
 
     fn format_stmt(&mut self, stmt: Stmt) -> String {
         match stmt {
-            Stmt::Let(ident, expr) => self.format_let_stmt(ident, expr),
+            Stmt::Let(ident, ty, expr) => self.format_let_stmt(ident, ty, expr),
+            Stmt::Assign(ident, expr) => self.format_assign_stmt(ident, expr),
             Stmt::Return(expr) => self.format_return_stmt(expr),
-            Stmt::Break => String::from("break;"),
-            Stmt::Continue => String::from("continue;"),
+            Stmt::Break => format!("{};", self.keyword(Token::Break)),
+            Stmt::Continue => format!("{};", self.keyword(Token::Continue)),
+            Stmt::Import(path) => {
+                format!("{} {};", self.keyword(Token::Import), escape_str(&path))
+            }
+            Stmt::Macro { name, params, body } => self.format_macro_stmt(name, params, body),
+            Stmt::Test { name, body } => self.format_test_stmt(name, body),
             Stmt::Expr(expr) => {
                 if Self::ignore_semicolon_expr(&expr) {
                     self.format_expr(expr, Precedence::Lowest)
@@ -118,13 +209,49 @@ impl Formatter {
                     format!("{};", self.format_expr(expr, Precedence::Lowest))
                 }
             }
-            Stmt::Blank => String::new(),
+            Stmt::Comment(Comment::Line(text)) => format!("{}{text}", self.comment_marker()),
+            Stmt::Comment(Comment::Block(text)) => format!("/*{text}*/"),
+            Stmt::Comment(Comment::Version(version)) => format!("#她版本 {version}"),
+            Stmt::Blank | Stmt::Mark(_) => String::new(),
         }
     }
 
-    fn format_let_stmt(&mut self, ident: Ident, expr: Expr) -> String {
+    fn format_test_stmt(&mut self, name: String, body: BlockStmt) -> String {
+        self.indent += 1;
+        let body_str = self.format_block_stmt(body);
+        self.indent -= 1;
+
+        format!(
+            "{} {} {{\n{body_str}\n{}}}",
+            self.keyword(Token::Test),
+            escape_str(&name),
+            self.indent_str(0)
+        )
+    }
+
+    fn format_macro_stmt(&mut self, name: Ident, params: Vec<Param>, body: BlockStmt) -> String {
+        let params_str = params
+            .into_iter()
+            .map(|param| self.format_param(param))
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        self.indent += 1;
+        let body_str = self.format_block_stmt(body);
+        self.indent -= 1;
+
+        format!(
+            "{} {}({params_str}) => {{\n{body_str}\n{}}}",
+            self.keyword(Token::Macro),
+            self.format_ident_expr(name),
+            self.indent_str(0)
+        )
+    }
+
+    fn format_let_stmt(&mut self, ident: Ident, ty: Option<TypeAnnotation>, expr: Expr) -> String {
         let ident_str = self.format_ident_expr(ident);
-        let result = format!("let {ident_str} = ");
+        let ty_str = Self::format_type_annotation(": ", ty);
+        let result = format!("{} {ident_str}{ty_str} = ", self.keyword(Token::Let));
 
         self.column += result.len();
 
@@ -133,8 +260,34 @@ impl Formatter {
         format!("{result}{expr_str};")
     }
 
+    fn format_assign_stmt(&mut self, ident: Ident, expr: Expr) -> String {
+        let step = match &expr {
+            Expr::Infix(infix, left, right)
+                if **left == Expr::Ident(ident.clone())
+                    && **right == Expr::Literal(Literal::Int(1)) =>
+            {
+                match infix {
+                    Infix::Plus => Some(Token::Increment),
+                    Infix::Minus => Some(Token::Decrement),
+                    _ => None,
+                }
+            }
+            _ => None,
+        };
+        let ident_str = self.format_ident_expr(ident);
+
+        match step {
+            Some(step) => format!("{ident_str}{step};"),
+            // Only `++` and `--` parse to an assignment.
+            None => format!(
+                "{ident_str} = {};",
+                self.format_expr(expr, Precedence::Lowest)
+            ),
+        }
+    }
+
     fn format_return_stmt(&mut self, expr: Expr) -> String {
-        let result = String::from("return ");
+        let result = format!("{} ", self.keyword(Token::Return));
 
         self.column += result.len();
 
@@ -145,7 +298,7 @@ impl Formatter {
         match expr {
             Expr::Ident(ident) => self.format_ident_expr(ident),
             Expr::Literal(literal) => self.format_literal(literal),
-            Expr::Prefix(prefix, right) => self.format_prefix_expr(prefix, *right),
+            Expr::Prefix(prefix, right) => self.format_prefix_expr(prefix, *right, precedence),
             Expr::Infix(infix, left, right) => {
                 self.format_infix_expr(infix, *left, *right, precedence)
             }
@@ -156,7 +309,38 @@ impl Formatter {
                 alternative,
             } => self.format_if_expr(*cond, consequence, alternative),
             Expr::While { cond, consequence } => self.format_while_expr(*cond, consequence),
-            Expr::Func { params, body } => self.format_func_expr(params, body),
+            Expr::DoWhile { consequence, cond } => self.format_do_while_expr(consequence, *cond),
+            Expr::Match { subject, arms } => self.format_match_expr(*subject, arms),
+            Expr::For {
+                vars,
+                iterable,
+                body,
+            } => self.format_for_expr(vars, *iterable, body),
+            Expr::CFor {
+                init,
+                cond,
+                step,
+                body,
+            } => self.format_c_for_expr(
+                init.map(|init| *init),
+                cond.map(|cond| *cond),
+                step.map(|step| *step),
+                body,
+            ),
+            Expr::Func {
+                params,
+                return_ty,
+                body,
+                is_async,
+            } => {
+                let prefix = if is_async {
+                    format!("{} ", self.keyword(Token::Async))
+                } else {
+                    String::new()
+                };
+                self.column += prefix.len();
+                format!("{prefix}{}", self.format_func_expr(params, return_ty, body))
+            }
             Expr::Call { func, args } => self.format_call_expr(*func, args),
         }
     }
@@ -170,10 +354,20 @@ impl Formatter {
     fn format_literal(&mut self, literal: Literal) -> String {
         match literal {
             Literal::Int(value) => self.format_int_literal(value),
+            Literal::Float(value) => {
+                let result = format_float(value);
+                self.column += result.len();
+                result
+            }
+            Literal::Quantity(value, unit) => {
+                let result = format!("{value}{unit}");
+                self.column += result.len();
+                result
+            }
             Literal::String(value) => self.format_string_literal(value),
             Literal::Bool(value) => self.format_bool_literal(value),
-            Literal::Array(value) => self.format_array_literal(value, false),
-            Literal::Hash(value) => self.format_hash_literal(value, false),
+            Literal::Array(value) => self.format_array_literal(value),
+            Literal::Hash(value) => self.format_hash_literal(value),
         }
     }
 
@@ -190,103 +384,73 @@ impl Formatter {
     }
 
     fn format_bool_literal(&mut self, value: bool) -> String {
-        let result = value.to_string();
+        let result = self.keyword(Token::Bool(value)).to_string();
         self.column += result.len();
         result
     }
 
-    fn format_array_literal(&mut self, arr: Vec<Expr>, wrap: bool) -> String {
-        let mut result = String::new();
-        let original = arr.clone();
-        let total = original.len();
-
-        if wrap {
-            self.indent += 1;
-        }
-
-        for (i, expr) in arr.into_iter().enumerate() {
-            let expr_str = self.format_expr(expr, Precedence::Lowest);
-
-            if wrap {
-                if i == 0 {
-                    result.push('\n');
-                } else {
-                    result.push_str(",\n");
-                }
-
-                result.push_str(&format!("{}{}", self.indent_str(0), expr_str));
-
-                if i + 1 == total {
-                    self.indent -= 1;
-                    result.push_str(&format!("\n{}", self.indent_str(0)));
-                }
-            } else if i > 0 {
-                result.push_str(&format!(", {expr_str}"));
-            } else {
-                result.push_str(&expr_str);
-            }
-        }
-
-        if !wrap && self.column + result.len() + 2 > self.config.max_line_length {
-            return self.format_array_literal(original, true);
-        }
-
-        format!("[{result}]")
+    fn format_array_literal(&mut self, arr: Vec<Expr>) -> String {
+        let wrap = arr.len() > self.config.max_array_oneline || arr.iter().any(is_collection);
+        self.format_collection(("[", "]", ""), arr, wrap, |formatter, expr| {
+            formatter.format_expr(expr, Precedence::Lowest)
+        })
     }
 
-    fn format_hash_literal(&mut self, hash: Vec<(Expr, Expr)>, wrap: bool) -> String {
-        let mut result = String::new();
-        let original = hash.clone();
-        let total = original.len();
-
-        if !wrap && total > self.config.max_hash_oneline {
-            return self.format_hash_literal(original, true);
-        }
+    fn format_hash_literal(&mut self, hash: Vec<(Expr, Expr)>) -> String {
+        let wrap = hash.len() > self.config.max_hash_oneline
+            || hash
+                .iter()
+                .any(|(key, value)| is_collection(key) || is_collection(value));
+        self.format_collection(("{", "}", " "), hash, wrap, |formatter, (key, value)| {
+            let key = formatter.format_expr(key, Precedence::Lowest);
+            formatter.column += 2;
+            let value = formatter.format_expr(value, Precedence::Lowest);
+            format!("{key}: {value}")
+        })
+    }
 
-        if wrap {
-            self.indent += 1;
+    /// `items` between `open` and `close`, padded with `pad`, on one line.
+    /// When `wrap` is set, or that doesn't fit, one item per line, each
+    /// with a trailing comma.
+    fn format_collection<T: Clone>(
+        &mut self,
+        (open, close, pad): (&str, &str, &str),
+        items: Vec<T>,
+        wrap: bool,
+        format_item: impl Fn(&mut Self, T) -> String,
+    ) -> String {
+        let start = self.column;
+        if items.is_empty() {
+            self.column += open.len() + close.len();
+            return format!("{open}{close}");
         }
 
-        for (i, (key, value)) in hash.into_iter().enumerate() {
-            let key_str = self.format_expr(key, Precedence::Lowest);
-            let value_str = self.format_expr(value, Precedence::Lowest);
-
-            if wrap {
-                if i > 0 {
-                    result.push(',');
-                }
-
-                result.push_str(&format!(
-                    "\n{}{}: {}",
-                    self.indent_str(0),
-                    key_str,
-                    value_str
-                ));
-
-                if i + 1 == total {
-                    self.indent -= 1;
-                    result.push_str(&format!("\n{}", self.indent_str(0)));
-                }
-            } else {
-                if i == 0 {
-                    result.push(' ');
-                } else {
-                    result.push_str(", ");
-                }
-
-                result.push_str(&format!("{key_str}: {value_str}"));
-
-                if i + 1 == total {
-                    result.push(' ');
-                }
+        if !wrap {
+            self.column += open.len() + pad.len();
+            let mut strs = vec![];
+            for item in items.iter().cloned() {
+                strs.push(format_item(self, item));
+                self.column += 2;
+            }
+            let line = format!("{open}{pad}{}{pad}{close}", strs.join(", "));
+            let end = start + line.chars().count();
+            if !line.contains('\n') && end <= self.config.max_line_length + 1 {
+                self.column = end;
+                return line;
             }
         }
 
-        if !wrap && self.column + result.len() > self.config.max_line_length {
-            return self.format_hash_literal(original, true);
+        self.indent += 1;
+        let mut result = format!("{open}\n");
+        for item in items {
+            self.column = self.indent * 2 + 1;
+            let item = format_item(self, item);
+            result.push_str(&format!("{}{item},\n", self.indent_str(0)));
         }
-
-        format!("{{{result}}}")
+        self.indent -= 1;
+        result.push_str(&format!("{}{close}", self.indent_str(0)));
+        self.column = self.indent * 2 + 1 + close.len();
+        result
     }
 
     fn format_infix_expr(
@@ -297,8 +461,18 @@ impl Formatter {
         precedence: Precedence,
     ) -> String {
         let current_precedence = Self::infix_to_precedence(&infix);
+        // Operators are left-associative, so `a - (b - c)` keeps its parens.
+        let right_grouped = matches!(
+            &right,
+            Expr::Infix(right_infix, ..) if Self::infix_to_precedence(right_infix) == current_precedence
+        );
         let left_str = self.format_expr(left, current_precedence.clone());
         let right_str = self.format_expr(right, current_precedence.clone());
+        let right_str = if right_grouped {
+            format!("({right_str})")
+        } else {
+            right_str
+        };
 
         if precedence > current_precedence {
             format!("({left_str} {infix} {right_str})")
@@ -307,14 +481,32 @@ impl Formatter {
         }
     }
 
-    fn format_prefix_expr(&mut self, prefix: Prefix, right: Expr) -> String {
+    fn format_prefix_expr(
+        &mut self,
+        prefix: Prefix,
+        right: Expr,
+        precedence: Precedence,
+    ) -> String {
         let right_str = self.format_expr(right, Precedence::Prefix);
+        let result = match prefix {
+            Prefix::Await => format!("{} {right_str}", self.keyword(Token::Await)),
+            // `- -x`, not `--x`, which is a decrement.
+            Prefix::Plus | Prefix::Minus if right_str.starts_with(['+', '-']) => {
+                format!("{prefix} {right_str}")
+            }
+            _ => format!("{prefix}{right_str}"),
+        };
 
-        format!("{prefix}{right_str}")
+        // `(-f)(x)`: without the parens the call would bind first.
+        if precedence > Precedence::Prefix {
+            format!("({result})")
+        } else {
+            result
+        }
     }
 
     fn format_index_expr(&mut self, left: Expr, index: Expr) -> String {
-        let left_str = self.format_expr(left, Precedence::Lowest);
+        let left_str = self.format_expr(left, Precedence::Index);
         let index_str = self.format_expr(index, Precedence::Lowest);
 
         format!("{left_str}[{index_str}]")
@@ -332,17 +524,39 @@ impl Formatter {
 
         let consequence_str = self.format_block_stmt(consequence);
 
+        let if_str = self.keyword(Token::If);
         let result = match alternative {
+            Some(mut alternative_expr)
+                if matches!(alternative_expr[..], [Stmt::Expr(Expr::If { .. })]) =>
+            {
+                let Some(Stmt::Expr(Expr::If {
+                    cond,
+                    consequence,
+                    alternative,
+                })) = alternative_expr.pop()
+                else {
+                    unreachable!()
+                };
+                let indent_str = self.indent_str(-1);
+                let else_str = self.keyword(Token::Else);
+                self.indent -= 1;
+                let else_if_str = self.format_if_expr(*cond, consequence, alternative);
+                self.indent += 1;
+                format!(
+                    "{if_str} ({cond_str}) {{\n{consequence_str}\n{indent_str}}} {else_str} {else_if_str}",
+                )
+            }
             Some(alternative_expr) => {
                 let alternative_str = self.format_block_stmt(alternative_expr);
                 let indent_str = self.indent_str(-1);
+                let else_str = self.keyword(Token::Else);
                 format!(
-                    "if ({cond_str}) {{\n{consequence_str}\n{indent_str}}} else {{\n{alternative_str}\n{indent_str}}}",
+                    "{if_str} ({cond_str}) {{\n{consequence_str}\n{indent_str}}} {else_str} {{\n{alternative_str}\n{indent_str}}}",
                 )
             }
             None => {
                 let indent_str = self.indent_str(-1);
-                format!("if ({cond_str}) {{\n{consequence_str}\n{indent_str}}}")
+                format!("{if_str} ({cond_str}) {{\n{consequence_str}\n{indent_str}}}")
             }
         };
 
@@ -351,19 +565,191 @@ impl Formatter {
         result
     }
 
+    /// `while (true)` comes out as `loop`, which parses to the same thing,
+    /// from the version that has `loop` on.
     fn format_while_expr(&mut self, cond: Expr, consequence: BlockStmt) -> String {
-        let cond_str = self.format_expr(cond, Precedence::Lowest);
+        let head = match cond {
+            Expr::Literal(Literal::Bool(true)) if self.version >= 2 => {
+                self.keyword(Token::Loop).to_string()
+            }
+            cond => format!(
+                "{} ({})",
+                self.keyword(Token::While),
+                self.format_expr(cond, Precedence::Lowest)
+            ),
+        };
         self.indent += 1;
 
         let consequence_str = self.format_block_stmt(consequence);
         let indent_str = self.indent_str(-1);
         self.indent -= 1;
 
-        let result = format!("while ({cond_str}) {{\n{consequence_str}\n{indent_str}}}");
+        format!("{head} {{\n{consequence_str}\n{indent_str}}}")
+    }
+
+    fn format_do_while_expr(&mut self, consequence: BlockStmt, cond: Expr) -> String {
+        self.indent += 1;
+        let consequence_str = self.format_block_stmt(consequence);
+        let indent_str = self.indent_str(-1);
+        self.indent -= 1;
+
+        let cond_str = self.format_expr(cond, Precedence::Lowest);
+        format!(
+            "{} {{\n{consequence_str}\n{indent_str}}} {} ({cond_str})",
+            self.keyword(Token::Do),
+            self.keyword(Token::While)
+        )
+    }
+
+    /// One arm per line. An arm that is a single expression is written
+    /// without braces, unless it starts with a hash, which would read as a
+    /// block.
+    fn format_match_expr(&mut self, subject: Expr, arms: Vec<MatchArm>) -> String {
+        let subject_str = self.format_expr(subject, Precedence::Lowest);
+        self.indent += 1;
+        let indent_str = self.indent_str(0);
+
+        let mut arms_str = String::new();
+        for MatchArm { pattern, body } in arms {
+            let pattern_str = self.format_pattern(pattern);
+            let inline = match &body[..] {
+                [Stmt::Expr(expr)] => Some(self.format_expr(expr.clone(), Precedence::Lowest))
+                    .filter(|expr_str| !expr_str.starts_with('{')),
+                _ => None,
+            };
+            let body_str = match inline {
+                Some(expr_str) => expr_str,
+                None => {
+                    self.indent += 1;
+                    let body_str = self.format_block_stmt(body);
+                    self.indent -= 1;
+                    format!("{{\n{body_str}\n{indent_str}}}")
+                }
+            };
+            arms_str.push_str(&format!("{indent_str}{pattern_str} => {body_str},\n"));
+        }
+
+        self.indent -= 1;
+        format!(
+            "{} {subject_str} {{\n{arms_str}{}}}",
+            self.keyword(Token::Match),
+            self.indent_str(0)
+        )
+    }
+
+    /// Always on one line, spaced like the literal it looks like. A hash
+    /// entry that binds its own key is written as just the key.
+    fn format_pattern(&mut self, pattern: Pattern) -> String {
+        match pattern {
+            Pattern::Wildcard => String::from("_"),
+            Pattern::Literal(literal) => {
+                self.format_expr(Expr::Literal(literal), Precedence::Lowest)
+            }
+            Pattern::Binding(ident) => self.format_ident_expr(ident),
+            Pattern::Array(items, rest) => {
+                let items = items
+                    .into_iter()
+                    .map(|item| self.format_pattern(item))
+                    .chain(rest.map(|Ident(name)| format!("...{name}")))
+                    .collect::<Vec<_>>();
+                format!("[{}]", items.join(", "))
+            }
+            Pattern::Hash(entries) if entries.is_empty() => String::from("{}"),
+            Pattern::Hash(entries) => {
+                let entries = entries
+                    .into_iter()
+                    .map(|(key, value)| match value {
+                        Pattern::Binding(Ident(name)) if name == key => key,
+                        value => format!("{key}: {}", self.format_pattern(value)),
+                    })
+                    .collect::<Vec<_>>();
+                format!("{{ {} }}", entries.join(", "))
+            }
+        }
+    }
+
+    fn format_for_expr(&mut self, vars: Vec<Ident>, iterable: Expr, body: BlockStmt) -> String {
+        let vars_str = vars
+            .into_iter()
+            .map(|ident| self.format_ident_expr(ident))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let iterable_str = self.format_expr(iterable, Precedence::Lowest);
+        self.indent += 1;
+
+        let body_str = self.format_block_stmt(body);
+        let indent_str = self.indent_str(-1);
+        self.indent -= 1;
+
+        format!(
+            "{} ({vars_str} {} {iterable_str}) {{\n{body_str}\n{indent_str}}}",
+            self.keyword(Token::For),
+            self.keyword(Token::In)
+        )
+    }
+
+    /// `for (init; cond; step) { body }`, with `for (;;)` when all three
+    /// are left out.
+    fn format_c_for_expr(
+        &mut self,
+        init: Option<Stmt>,
+        cond: Option<Expr>,
+        step: Option<Stmt>,
+        body: BlockStmt,
+    ) -> String {
+        let init_str = match init {
+            Some(init) => {
+                let init_str = self.format_stmt(init);
+                match init_str.ends_with(';') {
+                    true => init_str,
+                    false => format!("{init_str};"),
+                }
+            }
+            None => String::from(";"),
+        };
+        let cond_str = match cond {
+            Some(cond) => format!(" {};", self.format_expr(cond, Precedence::Lowest)),
+            None => String::from(";"),
+        };
+        let step_str = match step {
+            Some(step) => format!(" {}", self.format_stmt(step).trim_end_matches(';')),
+            None => String::new(),
+        };
+        self.indent += 1;
+
+        let body_str = self.format_block_stmt(body);
+        let indent_str = self.indent_str(-1);
+        self.indent -= 1;
+
+        format!(
+            "{} ({init_str}{cond_str}{step_str}) {{\n{body_str}\n{indent_str}}}",
+            self.keyword(Token::For),
+        )
+    }
+
+    fn format_type_annotation(sep: &str, ty: Option<TypeAnnotation>) -> String {
+        match ty {
+            Some(TypeAnnotation(name)) => format!("{sep}{name}"),
+            None => String::new(),
+        }
+    }
+
+    fn format_param(&mut self, param: Param) -> String {
+        let mut result = self.format_ident_expr(param.ident);
+        result.push_str(&Self::format_type_annotation(": ", param.ty));
+        if let Some(default) = param.default {
+            result.push_str(" = ");
+            result.push_str(&self.format_expr(default, Precedence::Lowest));
+        }
         result
     }
 
-    fn format_func_expr(&mut self, params: Vec<Ident>, body: BlockStmt) -> String {
+    fn format_func_expr(
+        &mut self,
+        params: Vec<Param>,
+        return_ty: Option<TypeAnnotation>,
+        body: BlockStmt,
+    ) -> String {
         let mut params_str = String::new();
 
         for (i, param) in params.into_iter().enumerate() {
@@ -371,9 +757,11 @@ impl Formatter {
                 params_str.push_str(", ");
             }
 
-            params_str.push_str(&self.format_ident_expr(param));
+            params_str.push_str(&self.format_param(param));
         }
 
+        let return_ty_str = Self::format_type_annotation(" -> ", return_ty);
+
         self.indent += 1;
 
         let body_str = self.format_block_stmt(body);
@@ -381,15 +769,17 @@ impl Formatter {
         self.indent -= 1;
 
         format!(
-            "fn({}) {{\n{}\n{}}}",
+            "{}({}){} {{\n{}\n{}}}",
+            self.keyword(Token::Func),
             params_str,
+            return_ty_str,
             body_str,
             self.indent_str(0)
         )
     }
 
     fn format_call_expr(&mut self, func: Expr, args: Vec<Expr>) -> String {
-        let func_str = self.format_expr(func, Precedence::Lowest);
+        let func_str = self.format_expr(func, Precedence::Call);
         let mut args_str = String::new();
 
         for (i, arg) in args.into_iter().enumerate() {
@@ -404,14 +794,237 @@ impl Formatter {
     }
 }
 
+/// A non-empty array or hash literal, which puts the one around it on
+/// several lines.
+fn is_collection(expr: &Expr) -> bool {
+    match expr {
+        Expr::Literal(Literal::Array(items)) => !items.is_empty(),
+        Expr::Literal(Literal::Hash(pairs)) => !pairs.is_empty(),
+        _ => false,
+    }
+}
+
+/// Formatter output together with where the cursor ended up.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Formatted {
+    pub code: String,
+    pub cursor: usize,
+}
+
+fn significant_tokens(input: &str) -> Vec<(Token, Span)> {
+    let mut lexer = Lexer::new(input);
+    let mut tokens = vec![];
+
+    loop {
+        match lexer.next_token_with_span() {
+            (Token::Eof, _) => break,
+            (Token::Blank | Token::Comment(_) | Token::BlockComment(_) | Token::Version(_), _) => {}
+            tok => tokens.push(tok),
+        }
+    }
+
+    tokens
+}
+
+/// Tokens the formatter adds or drops on its own.
+fn is_layout_token(tok: &Token) -> bool {
+    matches!(
+        tok,
+        Token::Semicolon | Token::Lparen | Token::Rparen | Token::Comma
+    )
+}
+
+/// Formats `input` and maps the char offset `cursor` onto the formatted code.
+///
+/// The cursor is anchored to the token it sits in (or right after), and the
+/// token streams before and after formatting are matched up to find where
+/// that token went. Keyword spellings may change, so the offset inside the
+/// token is clamped to the new token's length.
+pub fn format_with_cursor(input: &str, cursor: usize) -> Result<Formatted, ParseErrors> {
+    let mut parser = Parser::new(Lexer::new(input)).without_macro_expansion();
+    let program = parser.parse();
+    let errors = parser.get_errors();
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+
+    let code = Formatter::new().format(program);
+    let cursor = map_cursor(input, &code, cursor);
+    Ok(Formatted { code, cursor })
+}
+
+/// Where the char offset `cursor` in `input` ends up in `code`, the
+/// formatted `input`. See `format_with_cursor`.
+pub fn map_cursor(input: &str, code: &str, cursor: usize) -> usize {
+    let before = significant_tokens(input);
+    let after = significant_tokens(code);
+
+    let anchor = match before
+        .iter()
+        .rposition(|(_, span)| span.start.offset < cursor)
+    {
+        Some(anchor) => anchor,
+        None => return 0,
+    };
+
+    let mut matched = vec![None; before.len()];
+    let (mut i, mut j) = (0, 0);
+    while i < before.len() && j < after.len() {
+        if before[i].0 == after[j].0 {
+            matched[i] = Some(j);
+            i += 1;
+            j += 1;
+        } else if is_layout_token(&before[i].0) {
+            i += 1;
+        } else if is_layout_token(&after[j].0) {
+            j += 1;
+        } else {
+            i += 1;
+            j += 1;
+        }
+    }
+
+    match (0..=anchor).rev().find_map(|i| matched[i].map(|j| (i, j))) {
+        Some((i, j)) => {
+            let (_, old) = before[i];
+            let (_, new) = after[j];
+            let old_len = old.end.offset - old.start.offset;
+            let new_len = new.end.offset - new.start.offset;
+
+            if i == anchor && cursor - old.start.offset < old_len {
+                new.start.offset + (cursor - old.start.offset).min(new_len)
+            } else {
+                new.end.offset
+            }
+        }
+        None => 0,
+    }
+}
+
+/// Formats `input` even when it doesn't parse: runs of top-level statements
+/// that parse are formatted, and the lines touched by a parse error are
+/// copied through as they are, so a half-written file loses nothing.
+/// Returns the parse errors along with the code.
+pub fn format_lenient(input: &str) -> (String, ParseErrors) {
+    // Runs after the first are parsed on their own, without the header.
+    let mut header = Lexer::new(input);
+    header.next_token();
+    let version = header.version();
+    let mut parser = Parser::new(Lexer::new(input)).without_macro_expansion();
+    let program = parser.parse();
+    let errors = parser.get_errors();
+    if errors.is_empty() {
+        return (Formatter::new().format(program), errors);
+    }
+    if errors
+        .iter()
+        .any(|err| matches!(err, ParseError::TooLong { .. }))
+    {
+        return (input.to_string(), errors);
+    }
+
+    let program = Parser::new(Lexer::new(input))
+        .without_macro_expansion()
+        .with_positions()
+        .parse();
+    let parsed = program
+        .iter()
+        .filter_map(|stmt| match stmt {
+            Stmt::Mark(span) => Some(*span),
+            _ => None,
+        })
+        .filter(|span| {
+            !errors.iter().any(|err| {
+                let offset = err.span().start.offset;
+                span.start.offset <= offset && offset <= span.end.offset
+            })
+        })
+        .collect::<Vec<_>>();
+
+    // Lines with tokens no good statement took, or with an error on them.
+    let mut broken = significant_tokens(input)
+        .into_iter()
+        .filter(|(_, token)| {
+            !parsed.iter().any(|span| {
+                span.start.offset <= token.start.offset && token.end.offset <= span.end.offset
+            })
+        })
+        .flat_map(|(_, token)| token.start.line..=token.end.line)
+        .chain(errors.iter().map(|err| err.span().start.line))
+        .collect::<HashSet<_>>();
+    // A statement sharing a line with broken code is kept as is too.
+    loop {
+        let spread = parsed
+            .iter()
+            .filter(|span| (span.start.line..=span.end.line).any(|line| broken.contains(&line)))
+            .flat_map(|span| span.start.line..=span.end.line)
+            .filter(|line| !broken.contains(line))
+            .collect::<Vec<_>>();
+        if spread.is_empty() {
+            break;
+        }
+        broken.extend(spread);
+    }
+
+    let lines = input.split('\n').collect::<Vec<_>>();
+    let mut pieces: Vec<(String, bool)> = vec![];
+    let mut blank_before = false;
+    let mut start = 0;
+    while start < lines.len() {
+        let is_broken = broken.contains(&(start + 1));
+        let end = (start..lines.len())
+            .find(|&i| broken.contains(&(i + 1)) != is_broken)
+            .unwrap_or(lines.len());
+        let run = &lines[start..end];
+        start = end;
+
+        let text = run.join("\n");
+        let piece = if is_broken {
+            text.trim_matches('\n').trim_end().to_string()
+        } else {
+            let lexer = Lexer::new(&text).with_version(version);
+            let mut parser = Parser::new(lexer).without_macro_expansion();
+            let program = parser.parse();
+            match parser.get_errors().is_empty() {
+                true => Formatter::new().with_version(version).format(program),
+                false => text.trim_matches('\n').trim_end().to_string(),
+            }
+        };
+
+        let leading = run.iter().take_while(|line| line.trim().is_empty()).count();
+        let trailing = run
+            .iter()
+            .rev()
+            .take_while(|line| line.trim().is_empty())
+            .count();
+        if piece.trim().is_empty() {
+            blank_before |= leading > 0;
+            continue;
+        }
+        pieces.push((piece, blank_before || leading > 0));
+        blank_before = trailing > 0;
+    }
+
+    let mut code = String::new();
+    for (i, (piece, blank_before)) in pieces.into_iter().enumerate() {
+        if i > 0 {
+            code.push_str(if blank_before { "\n\n" } else { "\n" });
+        }
+        code.push_str(&piece);
+    }
+    (code, errors)
+}
+
 #[cfg(test)]
 mod tests {
     use crate::formatter::*;
-    use crate::lexer::*;
-    use crate::parser::*;
 
     fn format(input: &str) -> String {
-        Formatter::new().format(Parser::new(Lexer::new(input)).parse())
+        Formatter::new().format(
+            Parser::new(Lexer::new(input))
+                .without_macro_expansion()
+                .parse(),
+        )
     }
 
     #[test]
@@ -458,10 +1071,62 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_test_stmt() {
+        assert_eq!(
+            "考一考 \"a\" {\n  assert(1);\n}",
+            format("考一考   \"a\"{assert(1)}")
+        );
+    }
+
+    #[test]
+    fn test_macro() {
+        let input = "造个句式 twice(x) => { x * x }\ntwice(2);";
+        let program = Parser::new(Lexer::new(input))
+            .without_macro_expansion()
+            .parse();
+
+        let code = Formatter::new().format(program.clone());
+        assert_eq!("造个句式 twice(x) => {\n  x * x;\n}\ntwice(2);", code);
+        assert_eq!(
+            program,
+            Parser::new(Lexer::new(&code))
+                .without_macro_expansion()
+                .parse()
+        );
+    }
+
+    #[test]
+    fn test_step_stmt() {
+        assert_eq!("i++;\nj--;\n- -x;\n+ -y;", format("i++; --j; - -x; +-y"));
+    }
+
+    #[test]
+    fn test_comment() {
+        let input = "// 开头\nfn() { x }; 吐槽 中间\n-1;\nif (x) { x; // 里面\n}";
+
+        assert_eq!(
+            "// 开头\nfn() {\n  x;\n};\n// 中间\n-1;\nif (x) {\n  x;\n  // 里面\n}",
+            format(input)
+        );
+        assert_eq!(
+            "/* 多行\n   注释 */\nif (x) {\n  /**/\n  x;\n}",
+            format("/* 多行\n   注释 */ if (x) { /**/ x }")
+        );
+        assert_eq!(
+            "吐槽 开头\n1;",
+            Formatter::new()
+                .with_keyword_style(KeywordStyle::AbaAba)
+                .format(Parser::new(Lexer::new("// 开头\n1;")).parse())
+        );
+    }
+
     #[test]
     fn test_literal() {
         let tests = vec![
             ("1000", "1000;"),
+            ("1.50", "1.5;"),
+            ("2.0", "2.0;"),
             ("\"foo\"", "\"foo\";"),
             ("true", "true;"),
             ("false", "false;"),
@@ -480,13 +1145,13 @@ mod tests {
   123456789,
   123456789,
   123456789,
-  123456789
+  123456789,
 ];"#,
             ),
             (
                 "[\"124567890124567890124567890124567890124567890124567890124567890124567890124567890\"]",
                 r#"[
-  "124567890124567890124567890124567890124567890124567890124567890124567890124567890"
+  "124567890124567890124567890124567890124567890124567890124567890124567890124567890",
 ];"#,
             ),
             ("{      \"key\"   : \"value\"}", "{ \"key\": \"value\" };"),
@@ -497,13 +1162,13 @@ mod tests {
   1: 1,
   2: 2,
   3: 3,
-  4: 4
+  4: 4,
 };"#,
             ),
             (
                 "{\"123456789123456789123456789123456789123456789123456789123456789123456789\": true}",
                 r#"{
-  "123456789123456789123456789123456789123456789123456789123456789123456789": true
+  "123456789123456789123456789123456789123456789123456789123456789123456789": true,
 };"#,
             ),
         ];
@@ -513,6 +1178,71 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_collection_layout() {
+        let tests = vec![
+            ("[1,2,3,4,5,6,7,8]", "[1, 2, 3, 4, 5, 6, 7, 8];"),
+            (
+                "[1,2,3,4,5,6,7,8,9]",
+                "[\n  1,\n  2,\n  3,\n  4,\n  5,\n  6,\n  7,\n  8,\n  9,\n];",
+            ),
+            ("[[], {}]", "[[], {}];"),
+            (
+                "[[1, 2], [3, 4]]",
+                r#"[
+  [1, 2],
+  [3, 4],
+];"#,
+            ),
+            (
+                "{\"a\": [1], b: {\"c\": {}},}",
+                r#"{
+  "a": [1],
+  b: { "c": {} },
+};"#,
+            ),
+            (
+                "[fn(x) { x }]",
+                r#"[
+  fn(x) {
+    x;
+  },
+];"#,
+            ),
+        ];
+
+        for (input, expect) in tests {
+            assert_eq!(String::from(expect), format(input));
+        }
+    }
+
+    #[test]
+    fn test_collection_round_trip() {
+        // Every literal shape in the parser tests.
+        let inputs = [
+            "[1, 2 * 2, 3 + 3]",
+            "{}",
+            "{\"one\": 1, \"two\": 2, \"three\": 3}",
+            "{\"one\": 0 + 1, \"two\": 10 - 8, \"three\": 15 / 5}",
+            "{key: \"value\"}",
+            "[sum(1, 2), sum(3, 4)]",
+            "a * [1, 2, 3, 4][b * c] * d",
+            "add(a * b[2], b[1], 2 * [1, 2][1])",
+            "let x = [1, 2, 3];",
+            "[[1, [2, {\"a\": [3, 4,],}]], {1: [], 2: {3: 4}},]",
+        ];
+
+        for input in inputs {
+            let once = format(input);
+            assert_eq!(
+                Parser::new(Lexer::new(input)).parse(),
+                Parser::new(Lexer::new(&once)).parse(),
+                "{once}"
+            );
+            assert_eq!(once, format(&once));
+        }
+    }
+
     #[test]
     fn test_let_stmt() {
         let tests = vec![
@@ -531,13 +1261,13 @@ mod tests {
   12345678,
   12345678,
   12345678,
-  1234
+  1234,
 ];"#,
             ),
             (
                 "let aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa = {\"fooo\": \"abcdefg\"};",
                 r#"let aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa = {
-  "fooo": "abcdefg"
+  "fooo": "abcdefg",
 };"#,
             ),
         ];
@@ -555,7 +1285,7 @@ mod tests {
             (
                 "return [\"aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa\"]",
                 r#"return [
-  "aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa"
+  "aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa",
 ];"#,
             ),
         ];
@@ -565,6 +1295,30 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_import_stmt() {
+        let tests = vec![
+            ("import   \"utils.her\"", "import \"utils.her\";"),
+            ("引入 \"姐妹.her\";", "import \"姐妹.her\";"),
+        ];
+
+        for (input, expect) in tests {
+            assert_eq!(String::from(expect), format(input));
+        }
+    }
+
+    #[test]
+    fn test_type_annotations() {
+        let tests = vec![
+            ("let 年龄 :整数=18", "let 年龄: 整数 = 18;"),
+            ("fn(x:整数,y)->整数{x}", "fn(x: 整数, y) -> 整数 {\n  x;\n}"),
+        ];
+
+        for (input, expect) in tests {
+            assert_eq!(String::from(expect), format(input));
+        }
+    }
+
     #[test]
     fn test_operator() {
         let tests = vec![
@@ -574,11 +1328,20 @@ mod tests {
             ("(2 + 2)   * 5", "(2 + 2) * 5;"),
             ("2/(5+5  )", "2 / (5 + 5);"),
             ("2   / 5+5  ", "2 / 5 + 5;"),
+            ("a 或者 b 而且 c==d", "a || b && c == d;"),
+            ("(a||b)&&c", "(a || b) && c;"),
+            ("a??b||c", "a ?? b || c;"),
+            ("(a ?? b) || c", "(a ?? b) || c;"),
+            ("1 - (2 - 3)", "1 - (2 - 3);"),
+            ("a < (b > c)", "a < (b > c);"),
             // prefix
             ("-  5", "-5;"),
             ("! true", "!true;"),
             ("-(  4*    5   )", "-(4 * 5);"),
             ("!((10-2)  / 4)", "!((10 - 2) / 4);"),
+            ("并不是 true", "!true;"),
+            ("等它一下  f()", "await f();"),
+            ("(-f)(x)", "(-f)(x);"),
         ];
 
         for (input, expect) in tests {
@@ -592,6 +1355,7 @@ mod tests {
             ("foo[ 0  ]", "foo[0];"),
             ("foo[   1*2 ]", "foo[1 * 2];"),
             ("foo [   \"key\" ]", "foo[\"key\"];"),
+            ("(a + b)[0]", "(a + b)[0];"),
         ];
 
         for (input, expect) in tests {
@@ -615,6 +1379,28 @@ mod tests {
 } else {
   false;
 }"#,
+            ),
+            (
+                "if(x){1}else if(y){2}else{if(z){3}}",
+                r#"if (x) {
+  1;
+} else if (y) {
+  2;
+} else if (z) {
+  3;
+}"#,
+            ),
+            (
+                "let f = fn() { if(x){1}else if(y){2} else {3} };",
+                r#"let f = fn() {
+  if (x) {
+    1;
+  } else if (y) {
+    2;
+  } else {
+    3;
+  }
+};"#,
             ),
             (
                 r#"if (x) {
@@ -629,10 +1415,10 @@ mod tests {
     123456789,
     123456789,
     123456789,
-    123456789
+    123456789,
   ];
   let obj = {
-    "keeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeey": "valueeeeeeeeeeeeeeeeeeeeeeee"
+    "keeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeey": "valueeeeeeeeeeeeeeeeeeeeeeee",
   };
 }"#,
             ),
@@ -657,21 +1443,15 @@ if(         y){if(z)        { z; }}
     if (z) {
       z;
     }
-  } else {
-    if (z) {
-      z;
-    }
+  } else if (z) {
+    z;
   }
-} else {
-  if (y) {
-    if (z) {
-      z;
-    }
-  } else {
-    if (z) {
-      z;
-    }
+} else if (y) {
+  if (z) {
+    z;
   }
+} else if (z) {
+  z;
 }"#,
             ),
         ];
@@ -698,6 +1478,18 @@ fn    (y) {y;}
   fn(y) {
     y;
   }
+}"#,
+            ),
+            (
+                "慢慢来 fn() { 1 }",
+                r#"async fn() {
+  1;
+}"#,
+            ),
+            (
+                "fn(x,y:整数=x+1,  z = [ ]) {x}",
+                r#"fn(x, y: 整数 = x + 1, z = []) {
+  x;
 }"#,
             ),
         ];
@@ -724,6 +1516,63 @@ continue
   continue;
 };"#,
             ),
+            (
+                "while (那么普通却那么自信) { 下头 }",
+                r#"loop {
+  break;
+};"#,
+            ),
+            (
+                "一直摆烂{下头}",
+                r#"loop {
+  break;
+};"#,
+            ),
+            (
+                "反复横跳 (x) { 退退退; 接着奏乐接着舞 }",
+                r#"while (x) {
+  break;
+  continue;
+};"#,
+            ),
+        ];
+
+        for (input, expect) in tests {
+            assert_eq!(String::from(expect), format(input));
+        }
+    }
+
+    #[test]
+    fn test_match_expr() {
+        let tests = vec![
+            (
+                "let r = match  x{1=>\"a\",-2=>{puts(x);x},_=>{{\"k\": 1}}}",
+                r#"let r = match x {
+  1 => "a",
+  -2 => {
+    puts(x);
+    x;
+  },
+  _ => {
+    { "k": 1 };
+  },
+};"#,
+            ),
+            (
+                "分情况讨论 x { 那么普通却那么自信 => 1 }",
+                r#"match x {
+  true => 1,
+}"#,
+            ),
+            (
+                "match x{[a,0,...b]=>a,{名字,年龄:[_]}=>名字,{}=>1,n=>n}",
+                r#"match x {
+  [a, 0, ...b] => a,
+  { 名字, 年龄: [_] } => 名字,
+  {} => 1,
+  n => n,
+}"#,
+            ),
         ];
 
         for (input, expect) in tests {
@@ -731,6 +1580,108 @@ continue
         }
     }
 
+    #[test]
+    fn test_do_while_expr() {
+        assert_eq!(
+            "do {\n  break;\n} while (x);",
+            format("先干再说{下头}你再说一遍(  x )")
+        );
+    }
+
+    #[test]
+    fn test_for_expr() {
+        let tests = vec![
+            (
+                "for(  x in  xs ){  puts(x)}",
+                r#"for (x in xs) {
+  puts(x);
+};"#,
+            ),
+            (
+                "挨个盘点 (k,v 在 {\"a\": 1}) { 下头 }",
+                r#"for (k, v in { "a": 1 }) {
+  break;
+};"#,
+            ),
+        ];
+
+        for (input, expect) in tests {
+            assert_eq!(String::from(expect), format(input));
+        }
+    }
+
+    #[test]
+    fn test_c_for_expr() {
+        let tests = vec![
+            (
+                "for(let i=0;i<3;i++){if (i == 1) { continue; } puts(i)}",
+                r#"for (let i = 0; i < 3; i++) {
+  if (i == 1) {
+    continue;
+  }
+  puts(i);
+};"#,
+            ),
+            ("for (;;) { break }", "for (;;) {\n  break;\n};"),
+            ("for (puts(1); x; ) { 1 }", "for (puts(1); x;) {\n  1;\n};"),
+        ];
+
+        for (input, expect) in tests {
+            assert_eq!(String::from(expect), format(input), "{input}");
+        }
+    }
+
+    #[test]
+    fn test_version_header() {
+        assert_eq!(
+            "#她版本 1\nlet do = 1;\nfor(do);",
+            format("#她版本   1\nlet  do=1;for( do )")
+        );
+        assert_eq!(
+            "#她版本 1\nwhile (true) {\n  break;\n};",
+            format("#她版本 1\nwhile (true) { break; }")
+        );
+        assert_eq!(
+            "while (true) {\n  break;\n};",
+            Formatter::new()
+                .with_version(1)
+                .format(Parser::new(Lexer::new("while (true) { break; }")).parse())
+        );
+        assert_eq!("loop {\n  break;\n};", format("while (true) { break; }"));
+    }
+
+    #[test]
+    fn test_keyword_style() {
+        let input = r#"
+import "a.her";
+let f = async fn(x) { if (x) { return await g(); } else { false } };
+while (true) { break; continue; }
+for (k, v in h) { k }
+"#;
+        let expect = r#"引入 "a.her";
+宝宝你是一个 f = 慢慢来 想要你一个态度(x) {
+  姐妹们觉得呢 (x) {
+    反手举报 等它一下 g();
+  } 那能一样吗 {
+    那咋了;
+  }
+};
+一直摆烂 {
+  下头;
+  接着奏乐接着舞;
+};
+挨个盘点 (k, v 在 h) {
+  k;
+};"#;
+
+        let program = Parser::new(Lexer::new(input)).parse();
+        let code = Formatter::new()
+            .with_keyword_style(KeywordStyle::AbaAba)
+            .format(program.clone());
+        assert_eq!(expect, code);
+        assert_eq!(program, Parser::new(Lexer::new(&code)).parse());
+    }
+
     #[test]
     fn test_call_expr() {
         let tests = vec![
@@ -739,6 +1690,7 @@ continue
             ("foo((2 * 2 * 2))", "foo(2 * 2 * 2);"),
             ("foo(x,y,z)", "foo(x, y, z);"),
             ("arr[  \"hoge\" ](x,y,z)", "arr[\"hoge\"](x, y, z);"),
+            ("(a == b)(x)", "(a == b)(x);"),
         ];
 
         for (input, expect) in tests {
@@ -748,15 +1700,104 @@ continue
 
     #[test]
     fn test_block_stmt() {
-        let tests = vec![(
-            "1000; 1000; 1000;",
-            r#"1000;
+        let tests = vec![
+            (
+                "1000; 1000; 1000;",
+                r#"1000;
 1000;
 1000;"#,
-        )];
+            ),
+            (
+                "fn() { x }; -1; fn() { x } f(1);",
+                r#"fn() {
+  x;
+};
+-1;
+fn() {
+  x;
+}
+f(1);"#,
+            ),
+        ];
 
         for (input, expect) in tests {
             assert_eq!(String::from(expect), format(input));
         }
     }
+
+    #[test]
+    fn test_format_with_cursor() {
+        let tests = vec![
+            // (input, cursor, expected code, expected cursor)
+            ("let   x=1", 4, "let x = 1;", 3),
+            ("let   x=1", 7, "let x = 1;", 5),
+            ("let   x=1", 9, "let x = 1;", 9),
+            ("宝宝你是一个 x = 1;", 3, "let x = 1;", 3),
+            ("宝宝你是一个 x = 1;", 8, "let x = 1;", 5),
+            ("if(x){foo( 1 ,2)}", 12, "if (x) {\n  foo(1, 2);\n}", 16),
+            ("  1", 0, "1;", 0),
+        ];
+
+        for (input, cursor, code, expect) in tests {
+            let formatted = format_with_cursor(input, cursor).unwrap();
+            assert_eq!(code, formatted.code);
+            assert_eq!(expect, formatted.cursor, "{input:?} @ {cursor}");
+        }
+
+        assert!(format_with_cursor("let = ;", 0).is_err());
+    }
+
+    #[test]
+    fn test_format_lenient() {
+        let tests = vec![
+            (
+                "let a   =1;\nlet b=[1,2];",
+                "let a = 1;\nlet b = [1, 2];",
+                0,
+            ),
+            (
+                "let a   =1;\nlet = ;\nlet b=[1,2];",
+                "let a = 1;\nlet = ;\nlet b = [1, 2];",
+                2,
+            ),
+            (
+                r#"let a=1;
+
+
+let f = fn(x) {
+    x +
+};
+
+// 吐槽
+let c={"a":1}
+"#,
+                r#"let a = 1;
+
+let f = fn(x) {
+    x +
+};
+
+// 吐槽
+let c = { "a": 1 };"#,
+                1,
+            ),
+            (
+                "let a=1; let = 2;\nlet b = 2\n",
+                "let a=1; let = 2;\nlet b = 2;",
+                1,
+            ),
+            ("let s = \"abc\nlet t=1;", "let s = \"abc\nlet t=1;", 1),
+            (
+                "#她版本 1\nlet = ;\nlet match =1;\nwhile (true) { break; }",
+                "#她版本 1\nlet = ;\nlet match = 1;\nwhile (true) {\n  break;\n};",
+                2,
+            ),
+        ];
+
+        for (input, expected, errors) in tests {
+            let (code, got) = format_lenient(input);
+            assert_eq!(expected, code);
+            assert_eq!(errors, got.len());
+        }
+    }
 }
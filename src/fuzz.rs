@@ -0,0 +1,362 @@
+//! Random programs for fuzzing and round-trip tests. `Unstructured` turns
+//! raw bytes (from a fuzzer, or any RNG) into choices the same way the
+//! `arbitrary` crate does, so the same input always gives the same AST, and
+//! running out of bytes only ever picks the simplest option.
+//!
+//! Every generated program is one the parser can produce: formatting it and
+//! parsing the result gives the same AST back.
+
+use crate::ast::*;
+use crate::formatter::Formatter;
+use crate::lexer::UNITS;
+
+/// How deep expressions and blocks nest before only leaves are picked.
+const MAX_DEPTH: usize = 4;
+
+const IDENTS: &[&str] = &["a", "b", "x", "y", "f", "宝宝", "值"];
+const TYPES: &[&str] = &["整数", "字符串", "布尔", "数组"];
+const STRING_CHARS: &[char] = &['a', 'z', ' ', '"', '\\', '\n', '\t', '中', '😀'];
+
+pub struct Unstructured<'a> {
+    data: &'a [u8],
+    depth: usize,
+}
+
+impl<'a> Unstructured<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Unstructured { data, depth: 0 }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    /// The next byte, `0` once the data is used up.
+    pub fn byte(&mut self) -> u8 {
+        match self.data.split_first() {
+            Some((byte, rest)) => {
+                self.data = rest;
+                *byte
+            }
+            None => 0,
+        }
+    }
+
+    /// A number in `0..n`.
+    pub fn below(&mut self, n: usize) -> usize {
+        if n <= 1 {
+            return 0;
+        }
+        self.byte() as usize % n
+    }
+
+    pub fn choose<T: Copy>(&mut self, items: &[T]) -> T {
+        items[self.below(items.len())]
+    }
+
+    pub fn bool(&mut self) -> bool {
+        self.byte() & 1 == 1
+    }
+
+    /// `f(self)` a number of times between `0` and `max`.
+    pub fn vec<T>(&mut self, max: usize, mut f: impl FnMut(&mut Self) -> T) -> Vec<T> {
+        (0..self.below(max + 1)).map(|_| f(self)).collect()
+    }
+
+    fn nested<T>(&mut self, f: impl FnOnce(&mut Self) -> T) -> T {
+        self.depth += 1;
+        let result = f(self);
+        self.depth -= 1;
+        result
+    }
+
+    fn at_max_depth(&self) -> bool {
+        self.depth >= MAX_DEPTH
+    }
+}
+
+/// A value built from unstructured bytes.
+pub trait Arbitrary: Sized {
+    fn arbitrary(u: &mut Unstructured) -> Self;
+}
+
+impl Arbitrary for Ident {
+    fn arbitrary(u: &mut Unstructured) -> Self {
+        Ident(u.choose(IDENTS).to_string())
+    }
+}
+
+impl Arbitrary for TypeAnnotation {
+    fn arbitrary(u: &mut Unstructured) -> Self {
+        TypeAnnotation(u.choose(TYPES).to_string())
+    }
+}
+
+impl Arbitrary for Param {
+    fn arbitrary(u: &mut Unstructured) -> Self {
+        Param {
+            ident: Ident::arbitrary(u),
+            ty: Option::arbitrary(u),
+            default: None,
+        }
+    }
+}
+
+impl<T: Arbitrary> Arbitrary for Option<T> {
+    fn arbitrary(u: &mut Unstructured) -> Self {
+        u.bool().then(|| T::arbitrary(u))
+    }
+}
+
+impl Arbitrary for Prefix {
+    fn arbitrary(u: &mut Unstructured) -> Self {
+        u.choose(&[&Prefix::Not, &Prefix::Minus, &Prefix::Plus, &Prefix::Await])
+            .clone()
+    }
+}
+
+impl Arbitrary for Infix {
+    fn arbitrary(u: &mut Unstructured) -> Self {
+        use Infix::*;
+        u.choose(&[
+            &Plus,
+            &Minus,
+            &Divide,
+            &Multiply,
+            &Equal,
+            &NotEqual,
+            &GreaterThanEqual,
+            &GreaterThan,
+            &LessThanEqual,
+            &LessThan,
+            &And,
+            &Or,
+            &Coalesce,
+        ])
+        .clone()
+    }
+}
+
+impl Arbitrary for Literal {
+    fn arbitrary(u: &mut Unstructured) -> Self {
+        // Literals are never negative: `-1` parses as a prefix expression.
+        let int = |u: &mut Unstructured| i64::from(u.byte()) * i64::from(u.byte());
+        let leaves = 5;
+        let choice = if u.at_max_depth() {
+            u.below(leaves)
+        } else {
+            u.below(leaves + 2)
+        };
+
+        match choice {
+            0 => Literal::Int(int(u)),
+            1 => Literal::Bool(u.bool()),
+            2 => Literal::String(u.vec(6, |u| u.choose(STRING_CHARS)).into_iter().collect()),
+            3 => Literal::Quantity(int(u), u.choose(UNITS).to_string()),
+            // Eighths print exactly, so they read back as the same float.
+            4 => Literal::Float(f64::from(u.byte()) / 8.0),
+            5 => u.nested(|u| Literal::Array(u.vec(3, Expr::arbitrary))),
+            _ => {
+                u.nested(|u| Literal::Hash(u.vec(3, |u| (Expr::arbitrary(u), Expr::arbitrary(u)))))
+            }
+        }
+    }
+}
+
+impl Arbitrary for Pattern {
+    fn arbitrary(u: &mut Unstructured) -> Self {
+        let leaves = 5;
+        let choice = if u.at_max_depth() {
+            u.below(leaves)
+        } else {
+            u.below(leaves + 2)
+        };
+
+        match choice {
+            0 => Pattern::Wildcard,
+            1 => Pattern::Literal(Literal::Int(i64::from(u.byte()))),
+            2 => Pattern::Literal(Literal::Bool(u.bool())),
+            3 => Pattern::Literal(Literal::String(
+                u.vec(6, |u| u.choose(STRING_CHARS)).into_iter().collect(),
+            )),
+            4 => Pattern::Binding(Ident::arbitrary(u)),
+            5 => u.nested(|u| {
+                let items = u.vec(3, Pattern::arbitrary);
+                let rest = u.bool().then(|| Ident::arbitrary(u));
+                Pattern::Array(items, rest)
+            }),
+            _ => u.nested(|u| {
+                Pattern::Hash(u.vec(3, |u| {
+                    let Ident(key) = Ident::arbitrary(u);
+                    (key, Pattern::arbitrary(u))
+                }))
+            }),
+        }
+    }
+}
+
+impl Arbitrary for MatchArm {
+    fn arbitrary(u: &mut Unstructured) -> Self {
+        MatchArm {
+            pattern: Pattern::arbitrary(u),
+            body: body(u, false),
+        }
+    }
+}
+
+impl Arbitrary for Expr {
+    fn arbitrary(u: &mut Unstructured) -> Self {
+        let leaves = 2;
+        let choice = if u.at_max_depth() {
+            u.below(leaves)
+        } else {
+            u.below(leaves + 10)
+        };
+
+        let boxed = |u: &mut Unstructured| Box::new(Expr::arbitrary(u));
+        u.nested(|u| match choice {
+            0 => Expr::Ident(Ident::arbitrary(u)),
+            1 => Expr::Literal(Literal::arbitrary(u)),
+            2 => Expr::Prefix(Prefix::arbitrary(u), boxed(u)),
+            3 => Expr::Infix(Infix::arbitrary(u), boxed(u), boxed(u)),
+            4 => Expr::Index(boxed(u), boxed(u)),
+            5 => Expr::If {
+                cond: boxed(u),
+                consequence: body(u, false),
+                alternative: u.bool().then(|| body(u, false)),
+            },
+            6 => Expr::While {
+                cond: boxed(u),
+                consequence: body(u, true),
+            },
+            7 => Expr::DoWhile {
+                consequence: body(u, true),
+                cond: boxed(u),
+            },
+            8 => Expr::For {
+                vars: (0..1 + usize::from(u.bool()))
+                    .map(|_| Ident::arbitrary(u))
+                    .collect(),
+                iterable: boxed(u),
+                body: body(u, true),
+            },
+            9 => Expr::Match {
+                subject: boxed(u),
+                arms: u.vec(3, MatchArm::arbitrary),
+            },
+            10 => {
+                let mut params = u.vec(3, Param::arbitrary);
+                // Only the last parameters can have a default.
+                let required = u.below(params.len() + 1);
+                for param in &mut params[required..] {
+                    param.default = Some(Expr::arbitrary(u));
+                }
+                Expr::Func {
+                    params,
+                    return_ty: Option::arbitrary(u),
+                    body: body(u, false),
+                    is_async: u.bool(),
+                }
+            }
+            _ => Expr::Call {
+                func: boxed(u),
+                args: u.vec(3, Expr::arbitrary),
+            },
+        })
+    }
+}
+
+/// Up to `max` statements, with `break` and `continue` only `in_loop`.
+fn block(u: &mut Unstructured, max: usize, in_loop: bool) -> BlockStmt {
+    u.vec(max, |u| match u.below(if in_loop { 5 } else { 3 }) {
+        0 => Stmt::Expr(Expr::arbitrary(u)),
+        1 => Stmt::Let(
+            Ident::arbitrary(u),
+            Option::arbitrary(u),
+            Expr::arbitrary(u),
+        ),
+        2 => Stmt::Return(Expr::arbitrary(u)),
+        3 => Stmt::Break,
+        _ => Stmt::Continue,
+    })
+}
+
+/// A block inside braces. Never empty: `{}` is formatted with a blank line,
+/// which parses back as `Stmt::Blank`.
+fn body(u: &mut Unstructured, in_loop: bool) -> BlockStmt {
+    let mut stmts = block(u, 2, in_loop);
+    stmts.push(Stmt::Expr(Expr::arbitrary(u)));
+    stmts
+}
+
+/// A program of up to three statements.
+pub fn program(data: &[u8]) -> Program {
+    block(&mut Unstructured::new(data), 3, false)
+}
+
+/// The formatted source of `program(data)`.
+pub fn source(data: &[u8]) -> String {
+    Formatter::new().format(program(data))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::formatter::KeywordStyle;
+    use crate::fuzz::*;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+
+    /// Bytes from a xorshift generator, so each seed is a fixed input.
+    fn bytes(seed: u64) -> Vec<u8> {
+        let mut state = seed.wrapping_mul(0x9e3779b97f4a7c15) | 1;
+        (0..256)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                state as u8
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_empty_input() {
+        assert_eq!(Program::new(), program(&[]));
+        assert_eq!(
+            vec![Stmt::Expr(Expr::Ident(Ident(String::from("a"))))],
+            program(&[1])
+        );
+    }
+
+    #[test]
+    fn test_format_round_trip() {
+        for seed in 0..500 {
+            let program = program(&bytes(seed));
+            let source = Formatter::new().format(program.clone());
+
+            let mut parser = Parser::new(Lexer::new(&source));
+            let parsed = parser.parse();
+
+            assert!(parser.get_errors().is_empty(), "{source}");
+            assert_eq!(program, parsed, "{source}");
+
+            let source = Formatter::new()
+                .with_keyword_style(KeywordStyle::AbaAba)
+                .format(program.clone());
+            assert_eq!(
+                program,
+                Parser::new(Lexer::new(&source)).parse(),
+                "{source}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_herc_round_trip() {
+        for seed in 0..500 {
+            let program = program(&bytes(seed));
+            let bytes = crate::herc::encode(&program, 2);
+            assert_eq!(Ok(program), crate::herc::decode(&bytes, 2), "{seed}");
+        }
+    }
+}
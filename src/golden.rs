@@ -0,0 +1,269 @@
+//! Golden-file tests: runs `.her` files and compares what they print with
+//! the `.expected` file next to each one. Files with `考一考` blocks run
+//! those instead.
+
+use crate::ast::Stmt;
+use crate::evaluator::builtins::{capture_output, new_builtins};
+use crate::evaluator::env::Env;
+use crate::evaluator::object::Object;
+use crate::evaluator::shared::Shared;
+use crate::evaluator::{Evaluator, TestResult};
+use crate::lexer::Lexer;
+use crate::parser::Parser;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// What running `src` prints: its output, then the error it stopped with.
+/// A program that doesn't parse gives its parse errors instead.
+pub fn output(src: &str) -> String {
+    let mut parser = Parser::new(Lexer::new(src));
+    let program = parser.parse();
+    let errors = parser.get_errors();
+    if !errors.is_empty() {
+        return errors
+            .iter()
+            .map(|err| format!("{}: {}\n", err.span(), err))
+            .collect();
+    }
+
+    let env = Env::from(new_builtins());
+    let mut evaluator = Evaluator::new(Shared::new(env));
+    let (result, mut output) = capture_output(|| evaluator.eval(&program));
+    if let Some(err @ Object::Error(_)) = result {
+        output.push_str(&format!("{err}\n"));
+    }
+
+    output
+}
+
+/// The results of the `考一考` blocks in `src`, or `None` when it has none
+/// (or doesn't parse). What they print is dropped. A program that fails
+/// before the tests gives one failed result for the top level.
+pub fn tests(src: &str) -> Option<Vec<TestResult>> {
+    let mut parser = Parser::new(Lexer::new(src));
+    let program = parser.parse();
+    if !parser.get_errors().is_empty()
+        || !program.iter().any(|stmt| matches!(stmt, Stmt::Test { .. }))
+    {
+        return None;
+    }
+
+    let env = Env::from(new_builtins());
+    let mut evaluator = Evaluator::new(Shared::new(env));
+    let (results, _) = capture_output(|| evaluator.run_tests(&program));
+
+    Some(results.unwrap_or_else(|err| {
+        vec![TestResult {
+            name: String::from("(top level)"),
+            failure: Some(err),
+        }]
+    }))
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Outcome {
+    Passed,
+    Failed {
+        expected: String,
+        actual: String,
+    },
+    /// No `.expected` file yet.
+    Missing {
+        actual: String,
+    },
+    /// The `.expected` file was rewritten with the new output.
+    Updated,
+    /// The file has `考一考` blocks, which ran instead.
+    Tests(Vec<TestResult>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Case {
+    pub path: PathBuf,
+    pub outcome: Outcome,
+}
+
+#[derive(Default)]
+pub struct Runner {
+    update: bool,
+}
+
+impl Runner {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Writes the actual output to `.expected` files that differ or are
+    /// missing, instead of failing.
+    pub fn with_update(mut self, update: bool) -> Self {
+        self.update = update;
+        self
+    }
+
+    /// The `.expected` file that goes with `path`.
+    pub fn expected_path(path: &Path) -> PathBuf {
+        path.with_extension("expected")
+    }
+
+    pub fn run_file(&self, path: &Path) -> io::Result<Outcome> {
+        let src = fs::read_to_string(path)?;
+        if let Some(results) = tests(&src) {
+            return Ok(Outcome::Tests(results));
+        }
+
+        let actual = output(&src);
+        let expected_path = Self::expected_path(path);
+        let expected = match fs::read_to_string(&expected_path) {
+            Ok(expected) => Some(expected),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => None,
+            Err(err) => return Err(err),
+        };
+
+        Ok(match expected {
+            Some(expected) if expected == actual => Outcome::Passed,
+            _ if self.update => {
+                fs::write(&expected_path, actual)?;
+                Outcome::Updated
+            }
+            Some(expected) => Outcome::Failed { expected, actual },
+            None => Outcome::Missing { actual },
+        })
+    }
+
+    /// Runs `path`, a single `.her` file or a directory of them.
+    pub fn run(&self, path: &Path) -> io::Result<Vec<Case>> {
+        if path.is_dir() {
+            return self.run_dir(path);
+        }
+
+        let outcome = self.run_file(path)?;
+        Ok(vec![Case {
+            path: path.to_path_buf(),
+            outcome,
+        }])
+    }
+
+    /// Runs every `.her` file under `dir`, in path order.
+    pub fn run_dir(&self, dir: &Path) -> io::Result<Vec<Case>> {
+        let mut paths = vec![];
+        collect_her_files(dir, &mut paths)?;
+        paths.sort();
+
+        paths
+            .into_iter()
+            .map(|path| {
+                let outcome = self.run_file(&path)?;
+                Ok(Case { path, outcome })
+            })
+            .collect()
+    }
+}
+
+/// Adds the `.her` files under `dir` to `paths`, in no particular order.
+pub fn collect_her_files(dir: &Path, paths: &mut Vec<PathBuf>) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect_her_files(&path, paths)?;
+        } else if path.extension().is_some_and(|ext| ext == "her") {
+            paths.push(path);
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::golden::*;
+
+    #[test]
+    fn test_output() {
+        assert_eq!(
+            "1\nhello\n",
+            output("小作文(1); 聚焦(\"hello\"); 宝宝你是一个 x = 2;")
+        );
+        assert_eq!(
+            "1\n啊啊啊啊啊啊啊啊(identifier not found: y)\n",
+            output("puts(1); y; puts(2);")
+        );
+        assert!(output("puts(1;").starts_with("1:7: "));
+    }
+
+    #[test]
+    fn test_tests() {
+        assert_eq!(None, tests("puts(1);"));
+
+        let results =
+            tests("puts(0); 考一考 \"a\" { assert(1 == 1) } 考一考 \"b\" { assert(1 == 2) }")
+                .unwrap();
+        assert_eq!(
+            vec![
+                (String::from("a"), None),
+                (
+                    String::from("b"),
+                    Some(Object::Error(String::from("assertion failed")))
+                ),
+            ],
+            results
+                .into_iter()
+                .map(|result| (result.name, result.failure))
+                .collect::<Vec<_>>()
+        );
+
+        let results = tests("nope; 考一考 \"a\" { 1 }").unwrap();
+        assert_eq!("(top level)", results[0].name);
+        assert_eq!(1, results.len());
+    }
+
+    #[test]
+    fn test_runner() {
+        let dir = std::env::temp_dir().join(format!("herlang-golden-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("nested")).unwrap();
+        fs::write(dir.join("a.her"), "puts(1 + 1);").unwrap();
+        fs::write(dir.join("a.expected"), "2\n").unwrap();
+        fs::write(dir.join("nested/b.her"), "puts(\"b\");").unwrap();
+        fs::write(dir.join("c.her"), "puts(3);").unwrap();
+        fs::write(dir.join("c.expected"), "4\n").unwrap();
+
+        let outcomes = |runner: Runner| {
+            runner
+                .run_dir(&dir)
+                .unwrap()
+                .into_iter()
+                .map(|case| case.outcome)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            vec![
+                Outcome::Passed,
+                Outcome::Failed {
+                    expected: String::from("4\n"),
+                    actual: String::from("3\n"),
+                },
+                Outcome::Missing {
+                    actual: String::from("\"b\"\n"),
+                },
+            ],
+            outcomes(Runner::new())
+        );
+        assert_eq!(
+            vec![Outcome::Passed, Outcome::Updated, Outcome::Updated],
+            outcomes(Runner::new().with_update(true))
+        );
+        assert_eq!(vec![Outcome::Passed; 3], outcomes(Runner::new()));
+        assert_eq!(
+            vec![Case {
+                path: dir.join("c.her"),
+                outcome: Outcome::Passed,
+            }],
+            Runner::new().run(&dir.join("c.her")).unwrap()
+        );
+        assert!(Runner::new().run(&dir.join("none.her")).is_err());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,708 @@
+//! Parsed programs saved as `.herc` files, so `引入` can skip lexing and
+//! parsing a module that hasn't changed since `herlang build --target=herc`.
+//!
+//! A file is `HERC`, the format version and the language version the module
+//! was parsed as (both little-endian `u32`), then the program. Nodes are a tag
+//! byte followed by their fields; strings and lists start with their length
+//! as a `u32`, and an `Option` is a `0` or `1` byte before the value.
+
+use crate::ast::*;
+use crate::span::{Position, Span};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const MAGIC: &[u8] = b"HERC";
+
+/// Bumped whenever the AST or its encoding changes, which makes older files
+/// stale.
+pub const FORMAT_VERSION: u32 = 1;
+
+/// Where the cache of the module at `source` lives: next to it, with the
+/// extension changed to `.herc`.
+pub fn cache_path(source: &Path) -> PathBuf {
+    source.with_extension("herc")
+}
+
+/// `program`, parsed as language `version`, as the bytes of a `.herc` file.
+pub fn encode(program: &Program, version: u32) -> Vec<u8> {
+    let mut w = Writer(MAGIC.to_vec());
+    w.u32(FORMAT_VERSION);
+    w.u32(version);
+    w.block(program);
+    w.0
+}
+
+/// Reads back what `encode` wrote for language `version`. Files from another
+/// format or language version are an error, like malformed ones.
+pub fn decode(bytes: &[u8], version: u32) -> Result<Program, String> {
+    let mut r = Reader { bytes, pos: 0 };
+    if r.take(MAGIC.len())? != MAGIC {
+        return Err(String::from("not a .herc file"));
+    }
+    let format = r.u32()?;
+    if format != FORMAT_VERSION {
+        return Err(format!(
+            "format version {format}, expected {FORMAT_VERSION}"
+        ));
+    }
+    let parsed_as = r.u32()?;
+    if parsed_as != version {
+        return Err(format!(
+            "parsed as language version {parsed_as}, expected {version}"
+        ));
+    }
+
+    let program = r.block()?;
+    if r.pos != bytes.len() {
+        return Err(r.error("trailing bytes"));
+    }
+    Ok(program)
+}
+
+/// The cached program for the module at `source`, if its `.herc` file is at
+/// least as new as the source and was parsed as language `version`.
+pub fn load(source: &Path, version: u32) -> Option<Program> {
+    let cache = cache_path(source);
+    let modified = |path: &Path| fs::metadata(path).and_then(|meta| meta.modified()).ok();
+    if modified(&cache)? < modified(source)? {
+        her_log!(debug, "{} is older than its source", cache.display());
+        return None;
+    }
+
+    let bytes = fs::read(&cache).ok()?;
+    match decode(&bytes, version) {
+        Ok(program) => Some(program),
+        Err(_err) => {
+            her_log!(debug, "ignoring {}: {_err}", cache.display());
+            None
+        }
+    }
+}
+
+struct Writer(Vec<u8>);
+
+impl Writer {
+    fn u8(&mut self, n: u8) {
+        self.0.push(n);
+    }
+
+    fn u32(&mut self, n: u32) {
+        self.0.extend_from_slice(&n.to_le_bytes());
+    }
+
+    fn len(&mut self, n: usize) {
+        self.u32(n as u32);
+    }
+
+    fn i64(&mut self, n: i64) {
+        self.0.extend_from_slice(&n.to_le_bytes());
+    }
+
+    fn f64(&mut self, n: f64) {
+        self.0.extend_from_slice(&n.to_le_bytes());
+    }
+
+    fn bool(&mut self, b: bool) {
+        self.u8(b as u8);
+    }
+
+    fn str(&mut self, s: &str) {
+        self.len(s.len());
+        self.0.extend_from_slice(s.as_bytes());
+    }
+
+    fn option<T>(&mut self, value: Option<&T>, f: impl FnOnce(&mut Self, &T)) {
+        match value {
+            Some(value) => {
+                self.u8(1);
+                f(self, value);
+            }
+            None => self.u8(0),
+        }
+    }
+
+    fn list<T>(&mut self, items: &[T], mut f: impl FnMut(&mut Self, &T)) {
+        self.len(items.len());
+        for item in items {
+            f(self, item);
+        }
+    }
+
+    fn ident(&mut self, ident: &Ident) {
+        self.str(&ident.0);
+    }
+
+    fn ty(&mut self, ty: Option<&TypeAnnotation>) {
+        self.option(ty, |w, ty| w.str(&ty.0));
+    }
+
+    fn param(&mut self, param: &Param) {
+        self.ident(&param.ident);
+        self.ty(param.ty.as_ref());
+        self.option(param.default.as_ref(), Self::expr);
+    }
+
+    fn position(&mut self, position: &Position) {
+        self.len(position.offset);
+        self.len(position.line);
+        self.len(position.column);
+    }
+
+    fn block(&mut self, block: &BlockStmt) {
+        self.list(block, Self::stmt);
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Blank => self.u8(0),
+            Stmt::Comment(Comment::Line(text)) => {
+                self.u8(1);
+                self.str(text);
+            }
+            Stmt::Comment(Comment::Block(text)) => {
+                self.u8(2);
+                self.str(text);
+            }
+            Stmt::Comment(Comment::Version(version)) => {
+                self.u8(3);
+                self.u32(*version);
+            }
+            Stmt::Break => self.u8(4),
+            Stmt::Continue => self.u8(5),
+            Stmt::Let(ident, ty, value) => {
+                self.u8(6);
+                self.ident(ident);
+                self.ty(ty.as_ref());
+                self.expr(value);
+            }
+            Stmt::Assign(ident, value) => {
+                self.u8(7);
+                self.ident(ident);
+                self.expr(value);
+            }
+            Stmt::Return(value) => {
+                self.u8(8);
+                self.expr(value);
+            }
+            Stmt::Import(path) => {
+                self.u8(9);
+                self.str(path);
+            }
+            Stmt::Macro { name, params, body } => {
+                self.u8(10);
+                self.ident(name);
+                self.list(params, Self::param);
+                self.block(body);
+            }
+            Stmt::Test { name, body } => {
+                self.u8(11);
+                self.str(name);
+                self.block(body);
+            }
+            Stmt::Expr(expr) => {
+                self.u8(12);
+                self.expr(expr);
+            }
+            Stmt::Mark(span) => {
+                self.u8(13);
+                self.position(&span.start);
+                self.position(&span.end);
+            }
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Ident(ident) => {
+                self.u8(0);
+                self.ident(ident);
+            }
+            Expr::Literal(literal) => {
+                self.u8(1);
+                self.literal(literal);
+            }
+            Expr::Prefix(prefix, right) => {
+                self.u8(2);
+                self.u8(match prefix {
+                    Prefix::Plus => 0,
+                    Prefix::Minus => 1,
+                    Prefix::Not => 2,
+                    Prefix::Await => 3,
+                });
+                self.expr(right);
+            }
+            Expr::Infix(infix, left, right) => {
+                self.u8(3);
+                self.u8(INFIXES.iter().position(|i| i == infix).unwrap() as u8);
+                self.expr(left);
+                self.expr(right);
+            }
+            Expr::Index(left, index) => {
+                self.u8(4);
+                self.expr(left);
+                self.expr(index);
+            }
+            Expr::If {
+                cond,
+                consequence,
+                alternative,
+            } => {
+                self.u8(5);
+                self.expr(cond);
+                self.block(consequence);
+                self.option(alternative.as_ref(), Self::block);
+            }
+            Expr::While { cond, consequence } => {
+                self.u8(6);
+                self.expr(cond);
+                self.block(consequence);
+            }
+            Expr::DoWhile { consequence, cond } => {
+                self.u8(7);
+                self.block(consequence);
+                self.expr(cond);
+            }
+            Expr::Match { subject, arms } => {
+                self.u8(8);
+                self.expr(subject);
+                self.list(arms, |w, arm| {
+                    w.pattern(&arm.pattern);
+                    w.block(&arm.body);
+                });
+            }
+            Expr::For {
+                vars,
+                iterable,
+                body,
+            } => {
+                self.u8(9);
+                self.list(vars, Self::ident);
+                self.expr(iterable);
+                self.block(body);
+            }
+            Expr::CFor {
+                init,
+                cond,
+                step,
+                body,
+            } => {
+                self.u8(10);
+                self.option(init.as_deref(), Self::stmt);
+                self.option(cond.as_deref(), Self::expr);
+                self.option(step.as_deref(), Self::stmt);
+                self.block(body);
+            }
+            Expr::Func {
+                params,
+                return_ty,
+                body,
+                is_async,
+            } => {
+                self.u8(11);
+                self.list(params, Self::param);
+                self.ty(return_ty.as_ref());
+                self.block(body);
+                self.bool(*is_async);
+            }
+            Expr::Call { func, args } => {
+                self.u8(12);
+                self.expr(func);
+                self.list(args, Self::expr);
+            }
+        }
+    }
+
+    fn literal(&mut self, literal: &Literal) {
+        match literal {
+            Literal::Int(n) => {
+                self.u8(0);
+                self.i64(*n);
+            }
+            Literal::Float(n) => {
+                self.u8(1);
+                self.f64(*n);
+            }
+            Literal::Quantity(n, unit) => {
+                self.u8(2);
+                self.i64(*n);
+                self.str(unit);
+            }
+            Literal::String(s) => {
+                self.u8(3);
+                self.str(s);
+            }
+            Literal::Bool(b) => {
+                self.u8(4);
+                self.bool(*b);
+            }
+            Literal::Array(items) => {
+                self.u8(5);
+                self.list(items, Self::expr);
+            }
+            Literal::Hash(pairs) => {
+                self.u8(6);
+                self.list(pairs, |w, (key, value)| {
+                    w.expr(key);
+                    w.expr(value);
+                });
+            }
+        }
+    }
+
+    fn pattern(&mut self, pattern: &Pattern) {
+        match pattern {
+            Pattern::Wildcard => self.u8(0),
+            Pattern::Literal(literal) => {
+                self.u8(1);
+                self.literal(literal);
+            }
+            Pattern::Binding(ident) => {
+                self.u8(2);
+                self.ident(ident);
+            }
+            Pattern::Array(items, rest) => {
+                self.u8(3);
+                self.list(items, Self::pattern);
+                self.option(rest.as_ref(), Self::ident);
+            }
+            Pattern::Hash(entries) => {
+                self.u8(4);
+                self.list(entries, |w, (key, pattern)| {
+                    w.str(key);
+                    w.pattern(pattern);
+                });
+            }
+        }
+    }
+}
+
+/// Infix operators by their tag.
+const INFIXES: &[Infix] = &[
+    Infix::Plus,
+    Infix::Minus,
+    Infix::Divide,
+    Infix::Multiply,
+    Infix::Equal,
+    Infix::NotEqual,
+    Infix::GreaterThanEqual,
+    Infix::GreaterThan,
+    Infix::LessThanEqual,
+    Infix::LessThan,
+    Infix::And,
+    Infix::Or,
+    Infix::Coalesce,
+];
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn error(&self, what: &str) -> String {
+        format!("{what} at byte {}", self.pos)
+    }
+
+    fn take(&mut self, n: usize) -> Result<&'a [u8], String> {
+        let end = self
+            .pos
+            .checked_add(n)
+            .filter(|&end| end <= self.bytes.len())
+            .ok_or_else(|| self.error("unexpected end of file"))?;
+        let bytes = &self.bytes[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], String> {
+        Ok(self.take(N)?.try_into().unwrap())
+    }
+
+    fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        Ok(u32::from_le_bytes(self.array()?))
+    }
+
+    fn len(&mut self) -> Result<usize, String> {
+        Ok(self.u32()? as usize)
+    }
+
+    fn i64(&mut self) -> Result<i64, String> {
+        Ok(i64::from_le_bytes(self.array()?))
+    }
+
+    fn f64(&mut self) -> Result<f64, String> {
+        Ok(f64::from_le_bytes(self.array()?))
+    }
+
+    fn bool(&mut self) -> Result<bool, String> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(self.error("invalid bool")),
+        }
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        let len = self.len()?;
+        let bytes = self.take(len)?;
+        String::from_utf8(bytes.to_vec()).map_err(|_| self.error("invalid UTF-8"))
+    }
+
+    fn option<T>(
+        &mut self,
+        f: impl FnOnce(&mut Self) -> Result<T, String>,
+    ) -> Result<Option<T>, String> {
+        match self.bool()? {
+            true => f(self).map(Some),
+            false => Ok(None),
+        }
+    }
+
+    fn list<T>(
+        &mut self,
+        mut f: impl FnMut(&mut Self) -> Result<T, String>,
+    ) -> Result<Vec<T>, String> {
+        let len = self.len()?;
+        // Every item takes at least a byte, so a bad length can't make this
+        // reserve more than the file's size.
+        let mut items = Vec::with_capacity(len.min(self.bytes.len() - self.pos));
+        for _ in 0..len {
+            items.push(f(self)?);
+        }
+        Ok(items)
+    }
+
+    fn ident(&mut self) -> Result<Ident, String> {
+        self.string().map(Ident)
+    }
+
+    fn ty(&mut self) -> Result<Option<TypeAnnotation>, String> {
+        self.option(|r| r.string().map(TypeAnnotation))
+    }
+
+    fn param(&mut self) -> Result<Param, String> {
+        Ok(Param {
+            ident: self.ident()?,
+            ty: self.ty()?,
+            default: self.option(Self::expr)?,
+        })
+    }
+
+    fn position(&mut self) -> Result<Position, String> {
+        Ok(Position {
+            offset: self.len()?,
+            line: self.len()?,
+            column: self.len()?,
+        })
+    }
+
+    fn block(&mut self) -> Result<BlockStmt, String> {
+        self.list(Self::stmt)
+    }
+
+    fn stmt(&mut self) -> Result<Stmt, String> {
+        Ok(match self.u8()? {
+            0 => Stmt::Blank,
+            1 => Stmt::Comment(Comment::Line(self.string()?)),
+            2 => Stmt::Comment(Comment::Block(self.string()?)),
+            3 => Stmt::Comment(Comment::Version(self.u32()?)),
+            4 => Stmt::Break,
+            5 => Stmt::Continue,
+            6 => Stmt::Let(self.ident()?, self.ty()?, self.expr()?),
+            7 => Stmt::Assign(self.ident()?, self.expr()?),
+            8 => Stmt::Return(self.expr()?),
+            9 => Stmt::Import(self.string()?),
+            10 => Stmt::Macro {
+                name: self.ident()?,
+                params: self.list(Self::param)?,
+                body: self.block()?,
+            },
+            11 => Stmt::Test {
+                name: self.string()?,
+                body: self.block()?,
+            },
+            12 => Stmt::Expr(self.expr()?),
+            13 => Stmt::Mark(Span {
+                start: self.position()?,
+                end: self.position()?,
+            }),
+            _ => return Err(self.error("invalid statement")),
+        })
+    }
+
+    fn boxed(&mut self) -> Result<Box<Expr>, String> {
+        self.expr().map(Box::new)
+    }
+
+    fn expr(&mut self) -> Result<Expr, String> {
+        Ok(match self.u8()? {
+            0 => Expr::Ident(self.ident()?),
+            1 => Expr::Literal(self.literal()?),
+            2 => {
+                let prefix = match self.u8()? {
+                    0 => Prefix::Plus,
+                    1 => Prefix::Minus,
+                    2 => Prefix::Not,
+                    3 => Prefix::Await,
+                    _ => return Err(self.error("invalid prefix operator")),
+                };
+                Expr::Prefix(prefix, self.boxed()?)
+            }
+            3 => {
+                let infix = INFIXES
+                    .get(self.u8()? as usize)
+                    .ok_or_else(|| self.error("invalid infix operator"))?;
+                Expr::Infix(infix.clone(), self.boxed()?, self.boxed()?)
+            }
+            4 => Expr::Index(self.boxed()?, self.boxed()?),
+            5 => Expr::If {
+                cond: self.boxed()?,
+                consequence: self.block()?,
+                alternative: self.option(Self::block)?,
+            },
+            6 => Expr::While {
+                cond: self.boxed()?,
+                consequence: self.block()?,
+            },
+            7 => Expr::DoWhile {
+                consequence: self.block()?,
+                cond: self.boxed()?,
+            },
+            8 => Expr::Match {
+                subject: self.boxed()?,
+                arms: self.list(|r| {
+                    Ok(MatchArm {
+                        pattern: r.pattern()?,
+                        body: r.block()?,
+                    })
+                })?,
+            },
+            9 => Expr::For {
+                vars: self.list(Self::ident)?,
+                iterable: self.boxed()?,
+                body: self.block()?,
+            },
+            10 => Expr::CFor {
+                init: self.option(|r| r.stmt().map(Box::new))?,
+                cond: self.option(Self::boxed)?,
+                step: self.option(|r| r.stmt().map(Box::new))?,
+                body: self.block()?,
+            },
+            11 => Expr::Func {
+                params: self.list(Self::param)?,
+                return_ty: self.ty()?,
+                body: self.block()?,
+                is_async: self.bool()?,
+            },
+            12 => Expr::Call {
+                func: self.boxed()?,
+                args: self.list(Self::expr)?,
+            },
+            _ => return Err(self.error("invalid expression")),
+        })
+    }
+
+    fn literal(&mut self) -> Result<Literal, String> {
+        Ok(match self.u8()? {
+            0 => Literal::Int(self.i64()?),
+            1 => Literal::Float(self.f64()?),
+            2 => Literal::Quantity(self.i64()?, self.string()?),
+            3 => Literal::String(self.string()?),
+            4 => Literal::Bool(self.bool()?),
+            5 => Literal::Array(self.list(Self::expr)?),
+            6 => Literal::Hash(self.list(|r| Ok((r.expr()?, r.expr()?)))?),
+            _ => return Err(self.error("invalid literal")),
+        })
+    }
+
+    fn pattern(&mut self) -> Result<Pattern, String> {
+        Ok(match self.u8()? {
+            0 => Pattern::Wildcard,
+            1 => Pattern::Literal(self.literal()?),
+            2 => Pattern::Binding(self.ident()?),
+            3 => Pattern::Array(self.list(Self::pattern)?, self.option(Self::ident)?),
+            4 => Pattern::Hash(self.list(|r| Ok((r.string()?, r.pattern()?)))?),
+            _ => return Err(self.error("invalid pattern")),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::herc::*;
+    use crate::lexer::{LANGUAGE_VERSION, Lexer};
+    use crate::parser::Parser;
+
+    fn parse(input: &str) -> Program {
+        let mut parser = Parser::new(Lexer::new(input))
+            .without_macro_expansion()
+            .with_positions();
+        let program = parser.parse();
+        assert!(parser.get_errors().is_empty(), "{input}");
+        program
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let program = parse(
+            r#"#她版本 2
+// 注释
+/* 块 */
+let a: 整数 = -1 + 2 * 3 ?? 4;
+let f = async fn(x, y: 字符串 = "默认") -> 整数 { return await x[0]; };
+a++;
+if (!a) { 1.5 } else { 5元 };
+while (true) { break; }
+do { continue; } while (a < 3 && a >= 0 || a != 1);
+for (i, x in [1, {"k": true}]) { x }
+for (let i = 0; i < 3; i++) { i }
+for (;;) { break; }
+match a { 1 => "一", [b, ...rest] => rest, {k, v: _} => k, c => c };
+引入 "utils.her";
+造个句式 twice(x) => { x + x }
+考一考 "加法" { 1 + 1 }
+"#,
+        );
+
+        let bytes = encode(&program, LANGUAGE_VERSION);
+        assert_eq!(Ok(program), decode(&bytes, LANGUAGE_VERSION));
+    }
+
+    #[test]
+    fn test_decode_errors() {
+        let program = parse("let a = 1;");
+        let bytes = encode(&program, 2);
+
+        assert_eq!(
+            Err(String::from("parsed as language version 2, expected 1")),
+            decode(&bytes, 1)
+        );
+        assert_eq!(
+            Err(String::from("not a .herc file")),
+            decode(b"HERB\x01\0\0\0", 2)
+        );
+
+        let mut stale = bytes.clone();
+        stale[4] = 0;
+        assert_eq!(
+            Err(format!("format version 0, expected {FORMAT_VERSION}")),
+            decode(&stale, 2)
+        );
+
+        // Cut anywhere, the file is an error rather than a panic.
+        for len in 0..bytes.len() {
+            assert!(decode(&bytes[..len], 2).is_err(), "{len}");
+        }
+
+        let mut longer = bytes.clone();
+        longer.push(0);
+        assert_eq!(
+            Err(format!("trailing bytes at byte {}", bytes.len())),
+            decode(&longer, 2)
+        );
+    }
+}
@@ -0,0 +1,210 @@
+//! Token classification straight from the lexer, so editors highlight
+//! exactly what the language accepts instead of guessing with regexes.
+
+use crate::json;
+use crate::lexer::Lexer;
+use crate::span::Span;
+use crate::token::{Token, TokenCategory};
+use std::str::FromStr;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct HighlightToken {
+    pub category: TokenCategory,
+    pub span: Span,
+}
+
+pub fn tokens(input: &str) -> Vec<HighlightToken> {
+    let mut lexer = Lexer::new(input);
+    let mut result = vec![];
+
+    loop {
+        let (tok, span) = lexer.next_token_with_span();
+        if tok == Token::Eof {
+            break;
+        }
+
+        if let Some(category) = tok.category() {
+            result.push(HighlightToken { category, span });
+        }
+    }
+
+    result
+}
+
+pub fn to_json(tokens: &[HighlightToken]) -> String {
+    json::array(tokens.iter().map(|t| {
+        format!(
+            "{{\"category\":{},\"range\":{}}}",
+            json::string(t.category.as_str()),
+            json::span(&t.span)
+        )
+    }))
+}
+
+/// Colours for `to_html`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Theme {
+    #[default]
+    Light,
+    Dark,
+}
+
+impl FromStr for Theme {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "light" => Ok(Theme::Light),
+            "dark" => Ok(Theme::Dark),
+            _ => Err(format!("unknown theme {s:?}, want light or dark")),
+        }
+    }
+}
+
+impl Theme {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Theme::Light => "light",
+            Theme::Dark => "dark",
+        }
+    }
+
+    /// Stylesheet for the classes `to_html` puts out, scoped to this theme
+    /// so pages can mix both.
+    pub fn css(&self) -> String {
+        let (background, text, colors) = match self {
+            Theme::Light => (
+                "#f6f8fa",
+                "#24292e",
+                [
+                    ("keyword", "#d73a49"),
+                    ("number", "#005cc5"),
+                    ("string", "#032f62"),
+                    ("boolean", "#005cc5"),
+                    ("operator", "#d73a49"),
+                    ("comment", "#6a737d"),
+                    ("illegal", "#b31d28"),
+                ],
+            ),
+            Theme::Dark => (
+                "#0d1117",
+                "#c9d1d9",
+                [
+                    ("keyword", "#ff7b72"),
+                    ("number", "#79c0ff"),
+                    ("string", "#a5d6ff"),
+                    ("boolean", "#79c0ff"),
+                    ("operator", "#ff7b72"),
+                    ("comment", "#8b949e"),
+                    ("illegal", "#f85149"),
+                ],
+            ),
+        };
+        let theme = self.as_str();
+
+        let mut css = format!(
+            ".herlang-{theme} {{ background: {background}; color: {text}; padding: 1em; }}\n"
+        );
+        for (category, color) in colors {
+            let underline = match category {
+                "illegal" => " text-decoration: wavy underline;",
+                _ => "",
+            };
+            css.push_str(&format!(
+                ".herlang-{theme} .her-{category} {{ color: {color};{underline} }}\n"
+            ));
+        }
+
+        css
+    }
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// `src` as a `<pre>` block with every token in a `<span class="her-…">`
+/// named after its category. Spacing between tokens is kept as is; pair it
+/// with `theme.css()`.
+pub fn to_html(src: &str, theme: Theme) -> String {
+    let chars = src.chars().collect::<Vec<_>>();
+    let text =
+        |start: usize, end: usize| escape_html(&chars[start..end].iter().collect::<String>());
+
+    let mut result = format!("<pre class=\"herlang herlang-{}\"><code>", theme.as_str());
+    let mut pos = 0;
+
+    for tok in tokens(src) {
+        let (start, end) = (tok.span.start.offset, tok.span.end.offset);
+        result.push_str(&text(pos, start));
+        result.push_str(&format!(
+            "<span class=\"her-{}\">{}</span>",
+            tok.category.as_str(),
+            text(start, end)
+        ));
+        pos = end;
+    }
+    result.push_str(&text(pos, chars.len()));
+    result.push_str("</code></pre>\n");
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::highlight::*;
+
+    #[test]
+    fn test_tokens() {
+        let input = "宝宝你是一个 x = \"a\";\n\n姐妹们觉得呢 (x 我接受 那咋了) {}";
+
+        let tests = vec![
+            (TokenCategory::Keyword, 0, 6),
+            (TokenCategory::Identifier, 7, 8),
+            (TokenCategory::Operator, 9, 10),
+            (TokenCategory::String, 11, 14),
+            (TokenCategory::Punctuation, 14, 15),
+            (TokenCategory::Keyword, 17, 23),
+            (TokenCategory::Punctuation, 24, 25),
+            (TokenCategory::Identifier, 25, 26),
+            (TokenCategory::Operator, 27, 30),
+            (TokenCategory::Boolean, 31, 34),
+            (TokenCategory::Punctuation, 34, 35),
+            (TokenCategory::Punctuation, 36, 37),
+            (TokenCategory::Punctuation, 37, 38),
+        ];
+
+        let tokens = tokens(input);
+        assert_eq!(tests.len(), tokens.len());
+
+        for ((category, start, end), tok) in tests.into_iter().zip(tokens) {
+            assert_eq!(category, tok.category);
+            assert_eq!((start, end), (tok.span.start.offset, tok.span.end.offset));
+        }
+    }
+
+    #[test]
+    fn test_to_html() {
+        assert_eq!(
+            "<pre class=\"herlang herlang-dark\"><code><span class=\"her-keyword\">宝宝你是一个</span> \
+             <span class=\"her-identifier\">x</span> <span class=\"her-operator\">=</span> \
+             <span class=\"her-string\">&quot;&lt;b&gt;&quot;</span><span class=\"her-punctuation\">;</span>\n\
+             </code></pre>\n",
+            to_html("宝宝你是一个 x = \"<b>\";\n", Theme::Dark)
+        );
+        assert!(Theme::Light.css().contains(".herlang-light .her-keyword"));
+        assert_eq!(Ok(Theme::Dark), "dark".parse());
+        assert!("solarized".parse::<Theme>().is_err());
+    }
+
+    #[test]
+    fn test_to_json() {
+        assert_eq!(
+            r#"[{"category":"number","range":{"start":{"offset":0,"line":1,"column":1},"end":{"offset":2,"line":1,"column":3}}}]"#,
+            to_json(&tokens("42"))
+        );
+    }
+}
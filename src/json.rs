@@ -0,0 +1,44 @@
+//! Just enough JSON writing for the wasm and tooling APIs.
+
+use crate::span::{Position, Span};
+
+/// Quotes and escapes `s` as a JSON string.
+pub fn string(s: &str) -> String {
+    let mut result = String::with_capacity(s.len() + 2);
+
+    result.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => result.push_str("\\\""),
+            '\\' => result.push_str("\\\\"),
+            '\n' => result.push_str("\\n"),
+            '\r' => result.push_str("\\r"),
+            '\t' => result.push_str("\\t"),
+            c if (c as u32) < 0x20 => result.push_str(&format!("\\u{:04x}", c as u32)),
+            c => result.push(c),
+        }
+    }
+    result.push('"');
+
+    result
+}
+
+pub fn position(pos: &Position) -> String {
+    format!(
+        "{{\"offset\":{},\"line\":{},\"column\":{}}}",
+        pos.offset, pos.line, pos.column
+    )
+}
+
+pub fn span(span: &Span) -> String {
+    format!(
+        "{{\"start\":{},\"end\":{}}}",
+        position(&span.start),
+        position(&span.end)
+    )
+}
+
+/// Joins already encoded JSON values into an array.
+pub fn array(items: impl IntoIterator<Item = String>) -> String {
+    format!("[{}]", items.into_iter().collect::<Vec<_>>().join(","))
+}
@@ -2,10 +2,64 @@ extern crate unicode_normalization;
 /// Unicode lexer for the HER language.
 /// Some functions taken from `rust/compiler/rustc_lexer/src/lib.rs`.
 extern crate unicode_xid;
+use crate::span::{LineIndex, Span};
 use crate::token::Token;
+use std::fmt;
+use unescape::EscapeError;
 
 pub mod unescape;
 
+/// Input the lexer still made a token of, but which isn't valid.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LexError {
+    /// A bad escape in a string literal, like `\q` or `\u{110000}`.
+    InvalidEscape {
+        escape: String,
+        error: EscapeError,
+        span: Span,
+    },
+    /// A string literal that runs to the end of the input.
+    UnterminatedString { span: Span },
+    /// A `/*` comment without its `*/`.
+    UnterminatedComment { span: Span },
+    /// A `#她版本` header, or `Lexer::with_version`, asking for a language
+    /// version that doesn't exist.
+    UnknownVersion { version: String, span: Span },
+    /// An integer literal that doesn't fit in 64 bits.
+    IntegerTooLarge { literal: String, span: Span },
+}
+
+impl LexError {
+    pub fn span(&self) -> Span {
+        match self {
+            LexError::InvalidEscape { span, .. } => *span,
+            LexError::UnterminatedString { span } => *span,
+            LexError::UnterminatedComment { span } => *span,
+            LexError::UnknownVersion { span, .. } => *span,
+            LexError::IntegerTooLarge { span, .. } => *span,
+        }
+    }
+}
+
+impl fmt::Display for LexError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LexError::InvalidEscape { escape, error, .. } => {
+                write!(f, "invalid escape in string: {escape} ({error})")
+            }
+            LexError::UnterminatedString { .. } => write!(f, "unterminated string"),
+            LexError::UnterminatedComment { .. } => write!(f, "unterminated block comment"),
+            LexError::UnknownVersion { version, .. } => write!(
+                f,
+                "unknown language version {version}, expected 1 to {LANGUAGE_VERSION}"
+            ),
+            LexError::IntegerTooLarge { literal, .. } => {
+                write!(f, "integer literal too large: {literal}")
+            }
+        }
+    }
+}
+
 /// All variable names are nfc-normaized.
 pub fn nfc_normalize(string: &str) -> String {
     use self::unicode_normalization::{IsNormalized, UnicodeNormalization, is_nfc_quick};
@@ -47,10 +101,13 @@ pub fn is_whitespace(c: char) -> bool {
     )
 }
 
+/// Emoji that are operators rather than part of a name: `➕ ➖ ✖️ ➗ ❗`.
+const EMOJI_OPERATORS: [char; 5] = ['➕', '➖', '✖', '➗', '❗'];
+
 /// Decide whether character may show up in emoji.
 /// We cannot validate the entire sequence given the current architecture.
 fn is_emoji_like(c: char) -> bool {
-    if c < '\x7f' {
+    if c < '\x7f' || EMOJI_OPERATORS.contains(&c) {
         false
     } else {
         // ZWJ
@@ -86,7 +143,7 @@ fn is_id_start(c: char) -> bool {
 
 /// True if `c` is valid as a non-first character of an identifier.
 /// Compared to Rust, we additionally allow $ and ¥.
-fn is_id_continue(c: char) -> bool {
+pub fn is_id_continue(c: char) -> bool {
     c.is_ascii_lowercase()
         || c.is_ascii_uppercase()
         || c.is_ascii_digit()
@@ -97,21 +154,182 @@ fn is_id_continue(c: char) -> bool {
         || is_emoji_like(c)
 }
 
+/// Every spelling the lexer turns into a keyword or keyword-like operator.
+pub static KEYWORDS: &[(&str, Token)] = &[
+    // Monkey keywords
+    ("fn", Token::Func),
+    ("let", Token::Let),
+    ("true", Token::Bool(true)),
+    ("false", Token::Bool(false)),
+    ("if", Token::If),
+    ("while", Token::While),
+    ("do", Token::Do),
+    ("loop", Token::Loop),
+    ("match", Token::Match),
+    ("for", Token::For),
+    ("in", Token::In),
+    ("break", Token::Break),
+    ("continue", Token::Continue),
+    ("else", Token::Else),
+    ("return", Token::Return),
+    ("import", Token::Import),
+    ("async", Token::Async),
+    ("await", Token::Await),
+    // HER Aba-aba keywords
+    ("想要你一个态度", Token::Func),
+    ("宝宝你是一个", Token::Let),
+    ("那么普通却那么自信", Token::Bool(true)),
+    ("那咋了", Token::Bool(false)),
+    ("姐妹们觉得呢", Token::If),
+    ("抛开事实不谈", Token::If),
+    ("那能一样吗", Token::Else),
+    ("我接受不等于我同意", Token::Else),
+    ("你再说一遍", Token::While),
+    ("反复横跳", Token::While),
+    ("先干再说", Token::Do),
+    ("一直摆烂", Token::Loop),
+    ("分情况讨论", Token::Match),
+    ("下头菜单", Token::Match),
+    ("挨个盘点", Token::For),
+    ("在", Token::In),
+    ("下头", Token::Break),
+    ("退退退", Token::Break),
+    ("接着奏乐接着舞", Token::Continue),
+    ("而且", Token::And),
+    ("或者", Token::Or),
+    ("并非", Token::Bang),
+    ("并不是", Token::Bang),
+    ("的", Token::Of),
+    ("反手举报", Token::Return),
+    ("引入", Token::Import),
+    ("慢慢来", Token::Async),
+    ("等它一下", Token::Await),
+    ("造个句式", Token::Macro),
+    ("考一考", Token::Test),
+    ("我同意", Token::Equal),
+    ("我接受", Token::Equal),
+    ("拼单", Token::Plus),
+    ("接", Token::Plus),
+    ("差异", Token::Minus),
+    ("种草", Token::Asterisk),
+    ("踩雷", Token::Slash),
+    ("避雷", Token::Slash),
+];
+
+/// Starts a line comment, like `//`. Names can't begin with it.
+pub const SLANG_COMMENT: &str = "吐槽";
+
+/// The language version a source gets unless it asks for another.
+pub const LANGUAGE_VERSION: u32 = 2;
+
+/// Whether `spelling`, one of `KEYWORDS`, is a keyword in language `version`
+/// rather than a plain name.
+pub fn is_keyword_in(spelling: &str, version: u32) -> bool {
+    version >= 2 || !NEW_IN_V2.contains(&spelling)
+}
+
+/// `#她版本 1` as the very first line pins a source to a language version.
+pub const VERSION_HEADER: &str = "#她版本";
+
+/// Spellings in `KEYWORDS` that version 1, the language the first
+/// playground ran, reads as plain names.
+static NEW_IN_V2: &[&str] = &[
+    "do",
+    "loop",
+    "match",
+    "for",
+    "in",
+    "import",
+    "async",
+    "await",
+    "反复横跳",
+    "先干再说",
+    "一直摆烂",
+    "分情况讨论",
+    "下头菜单",
+    "挨个盘点",
+    "在",
+    "退退退",
+    "接着奏乐接着舞",
+    "而且",
+    "或者",
+    "并非",
+    "并不是",
+    "的",
+    "引入",
+    "慢慢来",
+    "等它一下",
+    "造个句式",
+    "考一考",
+];
+
+/// Measure words that can follow a number, longest first so `3公斤` isn't
+/// read as `3公` and `斤`.
+pub static UNITS: &[&str] = &[
+    "公斤", "分钟", "小时", "个", "元", "块", "毛", "斤", "两", "克", "只", "件", "本", "次", "天",
+    "岁", "杯", "张", "条", "米", "人", "年", "月", "周",
+];
+
+/// `第3个`, `第３个` or `第三个` to 3. Chinese numerals go up to 九十九.
+fn parse_ordinal(literal: &str) -> Option<i64> {
+    let n = literal.strip_prefix('第')?.strip_suffix('个')?;
+
+    if !n.is_empty() && n.chars().all(|c| matches!(c, '0'..='9' | '０'..='９')) {
+        return n
+            .chars()
+            .map(|c| c.to_digit(10).unwrap_or_else(|| c as u32 - '０' as u32))
+            .try_fold(0i64, |n, d| n.checked_mul(10)?.checked_add(d as i64));
+    }
+
+    let digit = |s: &str| {
+        let mut chars = s.chars();
+        match (chars.next(), chars.next()) {
+            (Some(c), None) => "一二三四五六七八九"
+                .chars()
+                .position(|d| d == c)
+                .map(|i| i as i64 + 1),
+            _ => None,
+        }
+    };
+
+    match n.split_once('十') {
+        None => digit(n),
+        Some((tens, ones)) => {
+            let tens = if tens.is_empty() { 1 } else { digit(tens)? };
+            let ones = if ones.is_empty() { 0 } else { digit(ones)? };
+            Some(tens * 10 + ones)
+        }
+    }
+}
+
 pub struct Lexer {
     input: Vec<char>,
     pos: usize,
     next_pos: usize,
     ch: char,
+    token_start: usize,
+    lines: LineIndex,
+    /// Extra spellings from `with_keyword_set`, looked up before `KEYWORDS`.
+    keywords: Vec<(String, Token)>,
+    errors: Vec<LexError>,
+    /// Language version, see `LANGUAGE_VERSION`.
+    version: u32,
 }
 
 impl Lexer {
     pub fn new(origin_input: &str) -> Self {
         let input = origin_input.chars().collect::<Vec<char>>();
+        let lines = LineIndex::new(&input);
         let mut lexer = Lexer {
             input,
             pos: 0,
             next_pos: 0,
             ch: '\0',
+            token_start: 0,
+            lines,
+            keywords: vec![],
+            errors: vec![],
+            version: LANGUAGE_VERSION,
         };
 
         lexer.read_char();
@@ -119,6 +337,36 @@ impl Lexer {
         lexer
     }
 
+    /// Adds keyword aliases, e.g. for a dialect, on top of `KEYWORDS`.
+    /// An alias takes precedence over a built-in spelling, so an existing
+    /// keyword can also be given a different meaning.
+    pub fn with_keyword_set(mut self, aliases: &[(&str, Token)]) -> Self {
+        self.keywords.extend(
+            aliases
+                .iter()
+                .map(|(alias, tok)| (alias.to_string(), tok.clone())),
+        );
+        self
+    }
+
+    /// Lexes as language `version` unless the source starts with a
+    /// `#她版本` header of its own.
+    pub fn with_version(mut self, version: u32) -> Self {
+        if (1..=LANGUAGE_VERSION).contains(&version) {
+            self.version = version;
+        } else {
+            self.errors.push(LexError::UnknownVersion {
+                version: version.to_string(),
+                span: Span::default(),
+            });
+        }
+        self
+    }
+
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
     fn read_char(&mut self) {
         if self.next_pos >= self.input.len() {
             self.ch = '\0';
@@ -141,6 +389,16 @@ impl Lexer {
         self.nextch() == ch
     }
 
+    fn rest_starts_with(&self, s: &str) -> bool {
+        let rest = self.input.get(self.pos..).unwrap_or_default();
+        s.chars().count() <= rest.len() && s.chars().zip(rest).all(|(a, b)| a == *b)
+    }
+
+    /// What was wrong in the input lexed so far, leaving none behind.
+    pub fn take_errors(&mut self) -> Vec<LexError> {
+        std::mem::take(&mut self.errors)
+    }
+
     fn skip_whitespace(&mut self) {
         loop {
             if is_whitespace(self.ch) {
@@ -151,20 +409,57 @@ impl Lexer {
         }
     }
 
+    /// Like `next_token`, but also returns where the token sits in the input.
+    /// The source text `span` covers.
+    pub fn text(&self, span: Span) -> String {
+        self.input
+            .get(span.start.offset..span.end.offset)
+            .map_or_else(String::new, |chars| chars.iter().collect())
+    }
+
+    pub fn next_token_with_span(&mut self) -> (Token, Span) {
+        let tok = self.next_token();
+        let end = self.pos.min(self.input.len());
+        let start = self.token_start.min(end);
+
+        (tok, self.lines.span(start, end))
+    }
+
     pub fn next_token(&mut self) -> Token {
         self.skip_whitespace();
+        self.token_start = self.pos;
 
         let tok = match self.ch {
             '=' => {
                 if self.nextch_is('=') {
                     self.read_char();
                     Token::Equal
+                } else if self.nextch_is('>') {
+                    self.read_char();
+                    Token::FatArrow
                 } else {
                     Token::Assign
                 }
             }
-            '+' => Token::Plus,
-            '-' => Token::Minus,
+            '+' => {
+                if self.nextch_is('+') {
+                    self.read_char();
+                    Token::Increment
+                } else {
+                    Token::Plus
+                }
+            }
+            '-' => {
+                if self.nextch_is('-') {
+                    self.read_char();
+                    Token::Decrement
+                } else if self.nextch_is('>') {
+                    self.read_char();
+                    Token::Arrow
+                } else {
+                    Token::Minus
+                }
+            }
             '!' => {
                 if self.nextch_is('=') {
                     self.read_char();
@@ -173,8 +468,61 @@ impl Lexer {
                     Token::Bang
                 }
             }
-            '/' => Token::Slash,
+            '&' => {
+                if self.nextch_is('&') {
+                    self.read_char();
+                    Token::And
+                } else {
+                    Token::Illegal
+                }
+            }
+            '|' => {
+                if self.nextch_is('|') {
+                    self.read_char();
+                    Token::Or
+                } else {
+                    Token::Illegal
+                }
+            }
+            '?' => {
+                if self.nextch_is('?') {
+                    self.read_char();
+                    Token::Coalesce
+                } else {
+                    Token::Illegal
+                }
+            }
+            '#' if self.pos == 0 && self.rest_starts_with(VERSION_HEADER) => {
+                return self.consume_version_header();
+            }
+            '/' => {
+                if self.nextch_is('/') {
+                    return self.consume_comment(2);
+                }
+                if self.nextch_is('*') {
+                    return self.consume_block_comment();
+                }
+                Token::Slash
+            }
             '*' => Token::Asterisk,
+            '➕' | '➖' | '✖' | '➗' | '❗' => {
+                let c = self.ch;
+                // `✖️` is usually typed with the emoji variation selector.
+                if self.nextch_is('\u{fe0f}') {
+                    self.read_char();
+                }
+                match c {
+                    '➕' => Token::Plus,
+                    '➖' => Token::Minus,
+                    '✖' => Token::Asterisk,
+                    '➗' => Token::Slash,
+                    _ if self.nextch_is('=') => {
+                        self.read_char();
+                        Token::NotEqual
+                    }
+                    _ => Token::Bang,
+                }
+            }
             '<' => {
                 if self.nextch_is('=') {
                     self.read_char();
@@ -201,11 +549,20 @@ impl Lexer {
             ',' => Token::Comma,
             ';' => Token::Semicolon,
             ':' => Token::Colon,
-            '0'..='9' => {
+            '0'..='9' | '０'..='９' => {
                 return self.consume_number();
             }
+            '"' if self.rest_starts_with("\"\"\"") => {
+                return self.consume_text_block();
+            }
             '"' => {
-                return self.consume_string();
+                return self.consume_string('"', '"');
+            }
+            '「' => {
+                return self.consume_string('「', '」');
+            }
+            '『' => {
+                return self.consume_string('『', '』');
             }
             '\n' => {
                 if self.nextch_is('\n') {
@@ -217,7 +574,9 @@ impl Lexer {
             }
             '\0' => Token::Eof,
             _ => {
-                if is_id_start(self.ch) {
+                if self.rest_starts_with(SLANG_COMMENT) {
+                    return self.consume_comment(SLANG_COMMENT.chars().count());
+                } else if is_id_start(self.ch) {
                     return self.consume_identifier();
                 } else {
                     Token::Illegal
@@ -230,6 +589,90 @@ impl Lexer {
         tok
     }
 
+    /// Skips the `marker_len` chars of the marker and takes the rest of the
+    /// line, leaving the newline for `next_token`.
+    fn consume_comment(&mut self, marker_len: usize) -> Token {
+        for _ in 0..marker_len {
+            self.read_char();
+        }
+
+        let start_pos = self.pos;
+        while !matches!(self.ch, '\n' | '\0') {
+            self.read_char();
+        }
+
+        let text = self.input[start_pos..self.pos].iter().collect::<String>();
+        Token::Comment(String::from(text.trim_end()))
+    }
+
+    /// `#她版本 n` up to the end of the line. An unknown version is an error
+    /// and leaves the version as it was.
+    fn consume_version_header(&mut self) -> Token {
+        let start = self.pos;
+        for _ in 0..VERSION_HEADER.chars().count() {
+            self.read_char();
+        }
+
+        let text_start = self.pos;
+        while !matches!(self.ch, '\n' | '\0') {
+            self.read_char();
+        }
+
+        let text = self.input[text_start..self.pos].iter().collect::<String>();
+        let text = text.trim();
+        match text.parse::<u32>() {
+            Ok(version) if (1..=LANGUAGE_VERSION).contains(&version) => {
+                self.version = version;
+                Token::Version(version)
+            }
+            _ => {
+                self.errors.push(LexError::UnknownVersion {
+                    version: text.to_string(),
+                    span: self.lines.span(start, self.pos),
+                });
+                Token::Blank
+            }
+        }
+    }
+
+    /// `/* ... */`, where each `/*` inside needs its own `*/`.
+    fn consume_block_comment(&mut self) -> Token {
+        let start = self.pos;
+        self.read_char();
+        self.read_char();
+
+        let text_start = self.pos;
+        let mut depth = 1;
+        loop {
+            let next = self.nextch();
+            match (self.ch, next) {
+                ('\0', _) => {
+                    self.errors.push(LexError::UnterminatedComment {
+                        span: self.lines.span(start, self.pos),
+                    });
+                    let text = self.input[text_start..self.pos].iter().collect();
+                    return Token::BlockComment(text);
+                }
+                ('/', '*') => {
+                    depth += 1;
+                    self.read_char();
+                }
+                ('*', '/') => {
+                    depth -= 1;
+                    if depth == 0 {
+                        let text = self.input[text_start..self.pos].iter().collect();
+                        self.read_char();
+                        self.read_char();
+                        return Token::BlockComment(text);
+                    }
+                    self.read_char();
+                }
+                _ => {}
+            }
+            self.read_char();
+        }
+    }
+
     fn consume_identifier(&mut self) -> Token {
         let start_pos = self.pos;
 
@@ -243,87 +686,198 @@ impl Lexer {
 
         let literal = self.input[start_pos..self.pos].iter().collect::<String>();
 
-        match literal.as_str() {
-            // Monkey keywords
-            "fn" => Token::Func,
-            "let" => Token::Let,
-            "true" => Token::Bool(true),
-            "false" => Token::Bool(false),
-            "if" => Token::If,
-            "while" => Token::While,
-            "break" => Token::Break,
-            "continue" => Token::Continue,
-            "else" => Token::Else,
-            "return" => Token::Return,
-            // HER Aba-aba keywords
-            "想要你一个态度" => Token::Func,
-            "宝宝你是一个" => Token::Let,
-            "那么普通却那么自信" => Token::Bool(true),
-            "那咋了" => Token::Bool(false),
-            "姐妹们觉得呢" => Token::If,
-            "抛开事实不谈" => Token::If,
-            "那能一样吗" => Token::Else,
-            "我接受不等于我同意" => Token::Else,
-            "你再说一遍" => Token::While,
-            "下头" => Token::Break,
-            "反手举报" => Token::Return,
-            "我同意" => Token::Equal,
-            "我接受" => Token::Equal,
-            "拼单" => Token::Plus,
-            "接" => Token::Plus,
-            "差异" => Token::Minus,
-            "种草" => Token::Asterisk,
-            "踩雷" => Token::Slash,
-            "避雷" => Token::Slash,
-            "微胖" => Token::String(String::from("180kg")),
-            _ => Token::Ident(nfc_normalize(&literal)),
+        if let Some((_, tok)) = self
+            .keywords
+            .iter()
+            .find(|(keyword, _)| *keyword == literal)
+        {
+            return tok.clone();
         }
+
+        if let Some((_, tok)) = KEYWORDS
+            .iter()
+            .find(|(keyword, _)| *keyword == literal && is_keyword_in(keyword, self.version))
+        {
+            return tok.clone();
+        }
+
+        if let Some(n) = parse_ordinal(&literal) {
+            return Token::Ordinal(n);
+        }
+
+        Token::Ident(nfc_normalize(&literal))
     }
 
     fn consume_number(&mut self) -> Token {
         let start_pos = self.pos;
 
-        while let '0'..='9' = self.ch {
+        let is_digit = |c: char| matches!(c, '0'..='9' | '０'..='９');
+        while is_digit(self.ch) {
             self.read_char();
         }
 
-        let literal = &self.input[start_pos..self.pos].iter().collect::<String>();
+        // `3.14`, but not `3.` or `3.len()`.
+        let fraction = self.ch == '.' && is_digit(self.nextch());
+        if fraction {
+            self.read_char();
+            while is_digit(self.ch) {
+                self.read_char();
+            }
+        }
+
+        // Full-width digits count the same as ASCII ones.
+        let literal = &self.input[start_pos..self.pos]
+            .iter()
+            .map(|&c| match c {
+                '０'..='９' => char::from(b'0' + (c as u32 - '０' as u32) as u8),
+                c => c,
+            })
+            .collect::<String>();
+
+        if fraction {
+            return Token::Float(literal.parse().unwrap());
+        }
 
-        Token::Int(literal.parse::<i64>().unwrap())
+        // Too large for 64 bits: an error, and 0 so parsing goes on.
+        let value = literal.parse::<i64>().unwrap_or_else(|_| {
+            self.errors.push(LexError::IntegerTooLarge {
+                literal: literal.clone(),
+                span: self.lines.span(start_pos, self.pos),
+            });
+            0
+        });
+
+        match self.consume_unit() {
+            Some(unit) => Token::Quantity(value, unit),
+            None => Token::Int(value),
+        }
     }
 
-    fn consume_string(&mut self) -> Token {
+    /// Takes one of `UNITS` if it comes next and doesn't start a longer
+    /// name, so `3个数` stays `3` followed by `个数`.
+    fn consume_unit(&mut self) -> Option<String> {
+        let rest = &self.input[self.pos..];
+        let unit = UNITS.iter().find(|unit| {
+            let len = unit.chars().count();
+            rest.len() >= len
+                && rest.iter().zip(unit.chars()).all(|(&a, b)| a == b)
+                && !rest.get(len).is_some_and(|&c| is_id_continue(c))
+        })?;
+
+        for _ in unit.chars() {
+            self.read_char();
+        }
+
+        Some(String::from(*unit))
+    }
+
+    /// A string between `open` and `close`. Corner brackets nest, so
+    /// `「他说「好」」` is one string; `"` can't, since it closes itself.
+    fn consume_string(&mut self, open: char, close: char) -> Token {
         self.read_char();
 
         let start_pos = self.pos;
         let mut bs = false;
+        let mut depth = 0;
 
         while self.ch != '\0' {
             if bs {
                 bs = false;
-            } else {
-                match self.ch {
-                    '"' => {
-                        let literal = self.input[start_pos..self.pos].iter().collect::<String>();
-                        self.read_char();
-                        return Token::String(unescape::unescape_str_or_byte_str_all(&literal));
-                    }
-                    '\\' => {
-                        bs = true;
-                    }
-                    _ => (),
+            } else if self.ch == close && depth == 0 {
+                let value = self.unescape(start_pos);
+                self.read_char();
+                return Token::String(value);
+            } else if self.ch == close {
+                depth -= 1;
+            } else if self.ch == open {
+                depth += 1;
+            } else if self.ch == '\\' {
+                bs = true;
+            }
+            self.read_char();
+        }
+
+        let value = self.unescape(start_pos);
+        self.errors.push(LexError::UnterminatedString {
+            span: self.lines.span(start_pos - 1, self.pos),
+        });
+        Token::String(value)
+    }
+
+    /// A `"""` string: its lines, less the opening line break and the
+    /// indentation of the closing `"""`, which sits on a line of its own.
+    fn consume_text_block(&mut self) -> Token {
+        for _ in 0..3 {
+            self.read_char();
+        }
+
+        let start_pos = self.pos;
+        let mut bs = false;
+
+        while self.ch != '\0' {
+            if bs {
+                bs = false;
+            } else if self.rest_starts_with("\"\"\"") {
+                let value = dedent(&self.unescape(start_pos));
+                for _ in 0..3 {
+                    self.read_char();
                 }
+                return Token::String(value);
+            } else if self.ch == '\\' {
+                bs = true;
             }
             self.read_char();
         }
-        // FIXME: Make Lexer faliable
-        Token::String("<Lexer error: string: premature EOF>".to_string())
+
+        let value = dedent(&self.unescape(start_pos));
+        self.errors.push(LexError::UnterminatedString {
+            span: self.lines.span(start_pos - 3, self.pos),
+        });
+        Token::String(value)
+    }
+
+    /// The string literal from `start_pos` up to here, with any bad escape
+    /// left out and recorded.
+    fn unescape(&mut self, start_pos: usize) -> String {
+        let literal = self.input[start_pos..self.pos].iter().collect::<String>();
+        let (value, errors) = unescape::unescape_str(&literal);
+
+        let offset = |byte: usize| start_pos + literal[..byte].chars().count();
+        for (range, error) in errors {
+            self.errors.push(LexError::InvalidEscape {
+                escape: literal[range.clone()].to_string(),
+                error,
+                span: self.lines.span(offset(range.start), offset(range.end)),
+            });
+        }
+
+        value
     }
 }
 
+/// `text` without its leading line break, and, when its last line is only
+/// spaces and tabs, without that line and its indentation on every line.
+fn dedent(text: &str) -> String {
+    let text = text.strip_prefix('\n').unwrap_or(text);
+    let (body, indent) = match text.rsplit_once('\n') {
+        Some((body, last)) if last.chars().all(|c| c == ' ' || c == '\t') => (body, last),
+        _ => return text.to_string(),
+    };
+
+    body.split('\n')
+        .map(|line| match line.strip_prefix(indent) {
+            Some(line) => line,
+            None if line.trim().is_empty() => "",
+            None => line,
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::lexer::Lexer;
+    use crate::lexer::unescape::{self, EscapeError};
+    use crate::lexer::{LANGUAGE_VERSION, LexError, Lexer};
     use crate::token::Token;
 
     #[test]
@@ -336,7 +890,7 @@ let add = fn(x, y) {
 };
 
 let result = add(five, ten);
-!-/*5;
+!-/ *5;
 5 < 10 > 5;
 
 if (5 < 10) {
@@ -475,6 +1029,30 @@ if (5 < 10) {
         }
     }
 
+    #[test]
+    fn test_next_token_with_span() {
+        let input = "宝宝你是一个 x = 10;\n  \"ab\"";
+
+        let tests = vec![
+            (Token::Let, (1, 1), (1, 7)),
+            (Token::Ident(String::from("x")), (1, 8), (1, 9)),
+            (Token::Assign, (1, 10), (1, 11)),
+            (Token::Int(10), (1, 12), (1, 14)),
+            (Token::Semicolon, (1, 14), (1, 15)),
+            (Token::String(String::from("ab")), (2, 3), (2, 7)),
+            (Token::Eof, (2, 7), (2, 7)),
+        ];
+
+        let mut lexer = Lexer::new(input);
+
+        for (expect, start, end) in tests {
+            let (tok, span) = lexer.next_token_with_span();
+            assert_eq!(expect, tok);
+            assert_eq!(start, (span.start.line, span.start.column));
+            assert_eq!(end, (span.end.line, span.end.column));
+        }
+    }
+
     #[test]
     fn test_cjk_next_token() {
         let input = r#"
@@ -513,15 +1091,15 @@ fib(10);
     }
 
     #[test]
-    fn test_fat_literal() {
+    fn test_female_keyword() {
         let input = r#"
-            宝宝你是一个 weight = 微胖;
+            宝宝你是一个 her = 微胖;
         "#;
         let tokens = vec![
             Token::Let,
-            Token::Ident(String::from("weight")),
+            Token::Ident(String::from("her")),
             Token::Assign,
-            Token::String(String::from("180kg")),
+            Token::Ident(String::from("微胖")),
         ];
 
         let mut lexer = Lexer::new(input);
@@ -533,18 +1111,457 @@ fib(10);
     }
 
     #[test]
-    fn test_female_keyword() {
+    fn test_full_width_digits() {
+        let input = "宝宝你是一个 年龄 = １８; 2０2５";
+        let tokens = vec![
+            Token::Let,
+            Token::Ident(String::from("年龄")),
+            Token::Assign,
+            Token::Int(18),
+            Token::Semicolon,
+            Token::Int(2025),
+        ];
+
+        let mut lexer = Lexer::new(input);
+
+        for expect in tokens {
+            let tok = lexer.next_token();
+            assert_eq!(expect, tok);
+        }
+    }
+
+    #[test]
+    fn test_integer_too_large() {
+        let mut lexer = Lexer::new("9223372036854775807 9223372036854775808元");
+
+        assert_eq!(Token::Int(i64::MAX), lexer.next_token());
+        assert_eq!(Token::Quantity(0, String::from("元")), lexer.next_token());
+        assert_eq!(Token::Eof, lexer.next_token());
+
+        let errors = lexer.take_errors();
+        assert_eq!(1, errors.len());
+        assert_eq!(
+            "integer literal too large: 9223372036854775808",
+            errors[0].to_string()
+        );
+        assert_eq!(
+            (20, 39),
+            (errors[0].span().start.offset, errors[0].span().end.offset)
+        );
+    }
+
+    #[test]
+    fn test_quantity() {
+        let input = "5元 + ３公斤 * 3个数 2斤x";
+        let tokens = vec![
+            Token::Quantity(5, String::from("元")),
+            Token::Plus,
+            Token::Quantity(3, String::from("公斤")),
+            Token::Asterisk,
+            Token::Int(3),
+            Token::Ident(String::from("个数")),
+            Token::Int(2),
+            Token::Ident(String::from("斤x")),
+            Token::Eof,
+        ];
+
+        let mut lexer = Lexer::new(input);
+
+        for expect in tokens {
+            assert_eq!(expect, lexer.next_token());
+        }
+    }
+
+    #[test]
+    fn test_string_escapes() {
+        let tests = vec![
+            (r#""a\nb""#, "a\nb"),
+            (r#""\x41\0""#, "A\0"),
+            (r#""\u{4F60}\u{1F600}""#, "你😀"),
+            (r#""\"\\\'""#, "\"\\'"),
+        ];
+
+        for (input, expect) in tests {
+            let mut lexer = Lexer::new(input);
+            assert_eq!(Token::String(String::from(expect)), lexer.next_token());
+            assert_eq!(Vec::<LexError>::new(), lexer.take_errors());
+        }
+
+        for s in ["\0\u{1b}\u{7f}\r\t\n\"\\'", "\u{200e}你\u{301}"] {
+            let mut lexer = Lexer::new(&unescape::escape_str(s));
+            assert_eq!(Token::String(String::from(s)), lexer.next_token(), "{s:?}");
+            assert_eq!(Vec::<LexError>::new(), lexer.take_errors());
+        }
+    }
+
+    #[test]
+    fn test_string_errors() {
+        let mut lexer = Lexer::new(r#""a\qb\xFF" "c"#);
+
+        assert_eq!(Token::String(String::from("ab")), lexer.next_token());
+        assert_eq!(Token::String(String::from("c")), lexer.next_token());
+        assert_eq!(
+            vec![
+                (String::from("\\q"), EscapeError::InvalidEscape, 2, 4),
+                (
+                    String::from("\\xFF"),
+                    EscapeError::OutOfRangeHexEscape,
+                    5,
+                    9
+                ),
+            ],
+            lexer
+                .take_errors()
+                .into_iter()
+                .filter_map(|err| match err {
+                    LexError::InvalidEscape {
+                        escape,
+                        error,
+                        span,
+                    } => Some((escape, error, span.start.offset, span.end.offset)),
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+        );
+
+        let mut lexer = Lexer::new("x = \"abc");
+        while lexer.next_token() != Token::Eof {}
+        let errors = lexer.take_errors();
+        assert_eq!(1, errors.len());
+        assert_eq!(
+            (4, 8),
+            (errors[0].span().start.offset, errors[0].span().end.offset)
+        );
+    }
+
+    #[test]
+    fn test_emoji_operators() {
+        let mut lexer = Lexer::new("a➕b➖2✖\u{fe0f}c➗d ❗e ❗\u{fe0f}= 🐶✖3");
+        let expect = vec![
+            Token::Ident(String::from("a")),
+            Token::Plus,
+            Token::Ident(String::from("b")),
+            Token::Minus,
+            Token::Int(2),
+            Token::Asterisk,
+            Token::Ident(String::from("c")),
+            Token::Slash,
+            Token::Ident(String::from("d")),
+            Token::Bang,
+            Token::Ident(String::from("e")),
+            Token::NotEqual,
+            Token::Ident(String::from("🐶")),
+            Token::Asterisk,
+            Token::Int(3),
+            Token::Eof,
+        ];
+        for tok in expect {
+            assert_eq!(tok, lexer.next_token());
+        }
+    }
+
+    #[test]
+    fn test_corner_quotes() {
+        let tests = vec![
+            ("「你好」", "你好"),
+            ("『嵌套』", "嵌套"),
+            ("「他说『好』」", "他说『好』"),
+            ("「他说「好」吧」", "他说「好」吧"),
+            (r#"『\n"』"#, "\n\""),
+            ("「」", ""),
+        ];
+        for (input, expect) in tests {
+            let mut lexer = Lexer::new(input);
+            assert_eq!(
+                Token::String(String::from(expect)),
+                lexer.next_token(),
+                "{input}"
+            );
+            assert_eq!(Token::Eof, lexer.next_token(), "{input}");
+            assert!(lexer.take_errors().is_empty(), "{input}");
+        }
+
+        let mut lexer = Lexer::new("「没写完」");
+        lexer.next_token();
+        assert_eq!(Token::Eof, lexer.next_token());
+        let mut lexer = Lexer::new("「「没写完」");
+        lexer.next_token();
+        assert!(matches!(
+            lexer.take_errors()[..],
+            [LexError::UnterminatedString { .. }]
+        ));
+    }
+
+    #[test]
+    fn test_text_block() {
+        let tests = vec![
+            ("\"\"\"\"\"\"", ""),
+            ("\"\"\"一行\"\"\"", "一行"),
+            (
+                "\"\"\"\n    亲爱的{名字}：\n\n      见字如面。\n    \"\"\"",
+                "亲爱的{名字}：\n\n  见字如面。",
+            ),
+            ("\"\"\"\n  她说\\n\"好\"\n  \"\"\"", "她说\n\"好\""),
+            ("\"\"\"\n  a\n b\n  \"\"\"", "a\n b"),
+            ("\"\"\"\n  末尾\"\"\"", "  末尾"),
+        ];
+        for (input, expect) in tests {
+            let mut lexer = Lexer::new(input);
+            assert_eq!(
+                Token::String(String::from(expect)),
+                lexer.next_token(),
+                "{input}"
+            );
+            assert_eq!(Token::Eof, lexer.next_token(), "{input}");
+            assert!(lexer.take_errors().is_empty(), "{input}");
+        }
+
+        let mut lexer = Lexer::new("\"\"\"没写完\"\"");
+        lexer.next_token();
+        assert!(matches!(
+            lexer.take_errors()[..],
+            [LexError::UnterminatedString { .. }]
+        ));
+    }
+
+    #[test]
+    fn test_increment() {
+        let mut lexer = Lexer::new("i++ --j a - -b");
+        let tokens = vec![
+            Token::Ident(String::from("i")),
+            Token::Increment,
+            Token::Decrement,
+            Token::Ident(String::from("j")),
+            Token::Ident(String::from("a")),
+            Token::Minus,
+            Token::Minus,
+            Token::Ident(String::from("b")),
+            Token::Eof,
+        ];
+        for expect in tokens {
+            assert_eq!(expect, lexer.next_token());
+        }
+    }
+
+    #[test]
+    fn test_coalesce() {
+        let mut lexer = Lexer::new("a ?? b ?");
+        let tokens = vec![
+            Token::Ident(String::from("a")),
+            Token::Coalesce,
+            Token::Ident(String::from("b")),
+            Token::Illegal,
+            Token::Eof,
+        ];
+        for expect in tokens {
+            assert_eq!(expect, lexer.next_token());
+        }
+    }
+
+    #[test]
+    fn test_for_in() {
+        let mut lexer = Lexer::new("for (x in xs) 挨个盘点 (y 在 ys) inx");
+        let tokens = vec![
+            Token::For,
+            Token::Lparen,
+            Token::Ident(String::from("x")),
+            Token::In,
+            Token::Ident(String::from("xs")),
+            Token::Rparen,
+            Token::For,
+            Token::Lparen,
+            Token::Ident(String::from("y")),
+            Token::In,
+            Token::Ident(String::from("ys")),
+            Token::Rparen,
+            Token::Ident(String::from("inx")),
+            Token::Eof,
+        ];
+        for expect in tokens {
+            assert_eq!(expect, lexer.next_token());
+        }
+    }
+
+    #[test]
+    fn test_token_display() {
+        let tests = vec![
+            (Token::Bool(false), "那咋了"),
+            (Token::Func, "想要你一个态度"),
+            (Token::Of, "的"),
+            (Token::Lbrace, "{"),
+            (Token::NotEqual, "!="),
+            (Token::FatArrow, "=>"),
+            (Token::String(String::from("她\n")), r#""她\n""#),
+            (Token::Float(2.0), "2.0"),
+            (Token::Ordinal(3), "第3个"),
+            (Token::Quantity(5, String::from("元")), "5元"),
+            (Token::Eof, "end of input"),
+        ];
+        for (tok, expect) in tests {
+            assert_eq!(expect, tok.to_string());
+        }
+
+        let mut lexer = Lexer::new(r#""\x80""#);
+        lexer.next_token();
+        assert_eq!(
+            r"invalid escape in string: \x80 (`\x` goes up to `\x7F`)",
+            lexer.take_errors()[0].to_string()
+        );
+    }
+
+    #[test]
+    fn test_comment() {
+        let input = "1 // one  \n2 / 3 吐槽这行\n//\n吐槽";
+        let tokens = vec![
+            Token::Int(1),
+            Token::Comment(String::from(" one")),
+            Token::Int(2),
+            Token::Slash,
+            Token::Int(3),
+            Token::Comment(String::from("这行")),
+            Token::Comment(String::new()),
+            Token::Comment(String::new()),
+            Token::Eof,
+        ];
+
+        let mut lexer = Lexer::new(input);
+
+        for expect in tokens {
+            assert_eq!(expect, lexer.next_token());
+        }
+    }
+
+    #[test]
+    fn test_block_comment() {
+        let input = "1 /* a /* b */\n*/ 2 */ 3 /**/ / /* open";
+        let tokens = vec![
+            Token::Int(1),
+            Token::BlockComment(String::from(" a /* b */\n")),
+            Token::Int(2),
+            Token::Asterisk,
+            Token::Slash,
+            Token::Int(3),
+            Token::BlockComment(String::new()),
+            Token::Slash,
+            Token::BlockComment(String::from(" open")),
+            Token::Eof,
+        ];
+
+        let mut lexer = Lexer::new(input);
+
+        for expect in tokens {
+            assert_eq!(expect, lexer.next_token());
+        }
+
+        let errors = lexer.take_errors();
+        assert_eq!(1, errors.len());
+        assert!(matches!(errors[0], LexError::UnterminatedComment { .. }));
+        assert_eq!(
+            (32, 39),
+            (errors[0].span().start.offset, errors[0].span().end.offset)
+        );
+    }
+
+    #[test]
+    fn test_version() {
+        let input = "#她版本 1\nfor 在 do";
+        let mut lexer = Lexer::new(input);
+        for expect in [
+            Token::Version(1),
+            Token::Ident(String::from("for")),
+            Token::Ident(String::from("在")),
+            Token::Ident(String::from("do")),
+            Token::Eof,
+        ] {
+            assert_eq!(expect, lexer.next_token());
+        }
+        assert_eq!(1, lexer.version());
+
+        let mut lexer = Lexer::new("for 在").with_version(1);
+        assert_eq!(Token::Ident(String::from("for")), lexer.next_token());
+        let mut lexer = Lexer::new("for 在");
+        assert_eq!(Token::For, lexer.next_token());
+        assert_eq!(Token::In, lexer.next_token());
+
+        let mut lexer = Lexer::new("#她版本 9\n1");
+        assert_eq!(Token::Blank, lexer.next_token());
+        assert_eq!(Token::Int(1), lexer.next_token());
+        assert!(matches!(
+            lexer.take_errors()[..],
+            [LexError::UnknownVersion { .. }]
+        ));
+        assert_eq!(LANGUAGE_VERSION, lexer.version());
+
+        let mut lexer = Lexer::new("1 #她版本 1");
+        lexer.next_token();
+        assert_eq!(Token::Illegal, lexer.next_token());
+    }
+
+    #[test]
+    fn test_float() {
+        let input = "2.75 ０.５ 5.str() 1.";
+        let tokens = vec![
+            Token::Float(2.75),
+            Token::Float(0.5),
+            Token::Int(5),
+            Token::Dot,
+            Token::Ident(String::from("str")),
+            Token::Lparen,
+            Token::Rparen,
+            Token::Int(1),
+            Token::Dot,
+            Token::Eof,
+        ];
+
+        let mut lexer = Lexer::new(input);
+
+        for expect in tokens {
+            assert_eq!(expect, lexer.next_token());
+        }
+    }
+
+    #[test]
+    fn test_ordinal() {
+        let tests = vec![
+            ("第1个", Token::Ordinal(1)),
+            ("第１０个", Token::Ordinal(10)),
+            ("第一个", Token::Ordinal(1)),
+            ("第十个", Token::Ordinal(10)),
+            ("第十三个", Token::Ordinal(13)),
+            ("第四十个", Token::Ordinal(40)),
+            ("第九十九个", Token::Ordinal(99)),
+            ("第个", Token::Ident(String::from("第个"))),
+            ("第一百个", Token::Ident(String::from("第一百个"))),
+            ("第十十个", Token::Ident(String::from("第十十个"))),
+        ];
+
+        for (input, expect) in tests {
+            assert_eq!(expect, Lexer::new(input).next_token(), "{input}");
+        }
+    }
+
+    #[test]
+    fn test_keyword_set() {
         let input = r#"
-            宝宝你是一个 her = 微胖;
+            整一个 x = 嗯呐;
+            拉倒吧 let;
         "#;
         let tokens = vec![
             Token::Let,
-            Token::Ident(String::from("her")),
+            Token::Ident(String::from("x")),
             Token::Assign,
-            Token::String(String::from("180kg")),
+            Token::Bool(true),
+            Token::Semicolon,
+            Token::Break,
+            Token::Ident(String::from("let")),
         ];
 
-        let mut lexer = Lexer::new(input);
+        let mut lexer = Lexer::new(input).with_keyword_set(&[
+            ("整一个", Token::Let),
+            ("嗯呐", Token::Bool(true)),
+            ("拉倒吧", Token::Break),
+            ("let", Token::Ident(String::from("let"))),
+        ]);
 
         for expect in tokens {
             let tok = lexer.next_token();
@@ -2,11 +2,12 @@
 //! values they represent.
 //! From `rust/compiler/rustc_lexer/src/unescape.rs`.
 
+use std::fmt;
 use std::ops::Range;
 use std::str::Chars;
 
 /// Errors that can occur during string unescaping.
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum EscapeError {
     /// Expected 1 char, but 0 were found.
     ZeroChars,
@@ -56,30 +57,60 @@ pub enum EscapeError {
     NonAsciiCharInByteString,
 }
 
+impl fmt::Display for EscapeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let text = match self {
+            EscapeError::ZeroChars => "no character",
+            EscapeError::MoreThanOneChar => "more than one character",
+            EscapeError::LoneSlash => "`\\` at the end",
+            EscapeError::InvalidEscape => "unknown escape",
+            EscapeError::BareCarriageReturn | EscapeError::BareCarriageReturnInRawString => {
+                "bare carriage return"
+            }
+            EscapeError::EscapeOnlyChar => "character must be escaped",
+            EscapeError::TooShortHexEscape => "`\\x` needs two hex digits",
+            EscapeError::InvalidCharInHexEscape => "not a hex digit",
+            EscapeError::OutOfRangeHexEscape => "`\\x` goes up to `\\x7F`",
+            EscapeError::NoBraceInUnicodeEscape => "`\\u` needs `{`",
+            EscapeError::InvalidCharInUnicodeEscape => "not a hex digit",
+            EscapeError::EmptyUnicodeEscape => "empty `\\u{}`",
+            EscapeError::UnclosedUnicodeEscape => "`\\u{` isn't closed",
+            EscapeError::LeadingUnderscoreUnicodeEscape => "`_` before the digits",
+            EscapeError::OverlongUnicodeEscape => "more than six digits",
+            EscapeError::LoneSurrogateUnicodeEscape => "surrogates aren't characters",
+            EscapeError::OutOfRangeUnicodeEscape => "beyond `\\u{10FFFF}`",
+            EscapeError::UnicodeEscapeInByte
+            | EscapeError::NonAsciiCharInByte
+            | EscapeError::NonAsciiCharInByteString => "not allowed in bytes",
+        };
+
+        write!(f, "{text}")
+    }
+}
+
 /// This totally custom function escapes a string.
 ///
 /// We re-use the Rust escaper because we have the same string syntax! Haha!
+/// It only writes `\"`, `\\`, `\n`, `\r`, `\t`, `\0` and `\u{..}`, all of
+/// which `unescape_str` reads back.
 pub fn escape_str(s: &str) -> String {
     format!("{s:?}")
 }
 
-pub fn unescape_str_or_byte_str_all(s: &str) -> String {
-    if s.contains(&['\\', '\r'][..]) {
-        let mut buf = String::with_capacity(s.len());
-        let mut error = false;
-        unescape_str_or_byte_str(s, &mut |_, unescaped_char| {
-            match unescaped_char {
-                Ok(c) => buf.push(c),
-                Err(e) => {
-                    error = true;
-                    buf = format!("<Lexer error: string: {e:?}>");
-                }
-            };
-        });
-        buf
-    } else {
-        s.to_string()
+/// Unescapes the contents of a string literal (without quotes). Bad escapes
+/// are left out of the string and reported with their byte range in `s`.
+pub fn unescape_str(s: &str) -> (String, Vec<(Range<usize>, EscapeError)>) {
+    let mut errors = vec![];
+    if !s.contains(&['\\', '\r'][..]) {
+        return (s.to_string(), errors);
     }
+
+    let mut buf = String::with_capacity(s.len());
+    unescape_str_or_byte_str(s, &mut |range, unescaped_char| match unescaped_char {
+        Ok(c) => buf.push(c),
+        Err(e) => errors.push((range, e)),
+    });
+    (buf, errors)
 }
 
 fn scan_escape(first_char: char, chars: &mut Chars<'_>) -> Result<char, EscapeError> {
@@ -89,7 +120,7 @@ fn scan_escape(first_char: char, chars: &mut Chars<'_>) -> Result<char, EscapeEr
         return match first_char {
             '\t' | '\n' => Err(EscapeError::EscapeOnlyChar),
             '\r' => Err(EscapeError::BareCarriageReturn),
-            '"' => Err(EscapeError::EscapeOnlyChar),
+            // A bare `"` only gets here inside `「」` or `『』`, where it's fine.
             _ => Ok(first_char),
         };
     }
@@ -118,9 +149,12 @@ fn scan_escape(first_char: char, chars: &mut Chars<'_>) -> Result<char, EscapeEr
 
             let value = hi * 16 + lo;
 
-            let value = value as u8;
+            // Anything past ASCII is written as `\u{..}`, like in Rust.
+            if value > 0x7F {
+                return Err(EscapeError::OutOfRangeHexEscape);
+            }
 
-            value as char
+            value as u8 as char
         }
 
         'u' => {
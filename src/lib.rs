@@ -1,9 +1,54 @@
 #![allow(clippy::match_like_matches_macro, clippy::single_match)]
 
+/// Logs through the `log` crate with the `log` feature on; otherwise only
+/// type-checks its arguments. Targets start with `herlang::`.
+macro_rules! her_log {
+    ($level:ident, $($arg:tt)+) => {{
+        #[cfg(feature = "log")]
+        log::$level!($($arg)+);
+        #[cfg(not(feature = "log"))]
+        let _ = format_args!($($arg)+);
+    }};
+}
+
 pub mod ast;
+#[cfg(feature = "bench")]
+pub mod bench;
+#[cfg(feature = "diagnostics")]
+pub mod callgraph;
+#[cfg(feature = "codegen")]
+pub mod codegen;
+#[cfg(feature = "diagnostics")]
+pub mod completion;
 pub mod constants;
+pub mod diagnostic;
+pub mod engine;
 pub mod evaluator;
+#[cfg(feature = "diagnostics")]
+pub mod fix;
+#[cfg(feature = "formatter")]
 pub mod formatter;
+#[cfg(feature = "fuzz")]
+pub mod fuzz;
+#[cfg(feature = "golden")]
+pub mod golden;
+pub mod herc;
+#[cfg(feature = "diagnostics")]
+pub mod highlight;
+pub mod json;
 pub mod lexer;
+#[cfg(feature = "diagnostics")]
+pub mod lint;
 pub mod parser;
+#[cfg(feature = "serve")]
+pub mod serve;
+#[cfg(feature = "diagnostics")]
+pub mod sexpr;
+#[cfg(feature = "share")]
+pub mod share;
+pub mod span;
+#[cfg(feature = "diagnostics")]
+pub mod stats;
 pub mod token;
+#[cfg(feature = "diagnostics")]
+pub mod typecheck;
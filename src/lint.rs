@@ -0,0 +1,237 @@
+//! Checks that need no types: code that can't run, and constant expressions
+//! that are bound to misbehave. Works on a program parsed `with_positions` so
+//! every diagnostic has a location.
+
+use crate::ast::visit::{Visitor, walk_expr, walk_stmt};
+use crate::ast::*;
+use crate::diagnostic::{Diagnostic, DiagnosticCode};
+use crate::span::Span;
+
+struct Linter {
+    /// Span of the statement being looked at, from the last `Stmt::Mark`.
+    span: Span,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Linter {
+    fn warning(&mut self, code: DiagnosticCode) {
+        self.diagnostics.push(Diagnostic::warning(code, self.span));
+    }
+
+    fn error(&mut self, code: DiagnosticCode) {
+        self.diagnostics.push(Diagnostic::error(code, self.span));
+    }
+
+    /// Reports integer arithmetic on constants that can only fail. Operands
+    /// that already failed fold to `None`, so each problem is reported once.
+    fn check_const_infix(&mut self, infix: &Infix, left: &Expr, right: &Expr) {
+        let right = const_int(right);
+
+        if !matches!(
+            infix,
+            Infix::Plus | Infix::Minus | Infix::Multiply | Infix::Divide
+        ) {
+            return;
+        }
+
+        if *infix == Infix::Divide && right == Some(0) {
+            self.error(DiagnosticCode::DivisionByZero);
+        } else if let (Some(left), Some(right)) = (const_int(left), right)
+            && const_int_infix(infix, left, right).is_none()
+        {
+            self.error(DiagnosticCode::IntegerOverflow {
+                expr: format!("{left} {infix} {right}"),
+            });
+        }
+    }
+
+    fn check_const_index(&mut self, left: &Expr, index: &Expr) {
+        let (Expr::Literal(Literal::Array(items)), Some(index)) = (left, const_int(index)) else {
+            return;
+        };
+
+        if index < 0 || index as usize >= items.len() {
+            self.warning(DiagnosticCode::IndexOutOfBounds {
+                index,
+                len: items.len(),
+            });
+        }
+    }
+}
+
+impl Visitor for Linter {
+    fn visit_block(&mut self, stmts: &BlockStmt) {
+        let outer = self.span;
+        let mut jumped = false;
+        let mut unreachable: Option<Span> = None;
+
+        for stmt in stmts {
+            match stmt {
+                Stmt::Mark(span) => self.span = *span,
+                Stmt::Blank | Stmt::Comment(_) => {}
+                _ if jumped => {
+                    let start = unreachable.map_or(self.span.start, |s| s.start);
+                    unreachable = Some(Span {
+                        start,
+                        end: self.span.end,
+                    });
+                }
+                Stmt::Return(_) | Stmt::Break | Stmt::Continue => jumped = true,
+                _ => {}
+            }
+
+            self.visit_stmt(stmt);
+        }
+
+        if let Some(span) = unreachable {
+            self.diagnostics
+                .push(Diagnostic::warning(DiagnosticCode::UnreachableCode, span));
+        }
+
+        self.span = outer;
+    }
+
+    fn visit_stmt(&mut self, stmt: &Stmt) {
+        match stmt {
+            // Templates are linted where they are expanded.
+            Stmt::Macro { .. } => {}
+            _ => walk_stmt(self, stmt),
+        }
+    }
+
+    fn visit_expr(&mut self, expr: &Expr) {
+        walk_expr(self, expr);
+
+        match expr {
+            Expr::Infix(infix, left, right) => self.check_const_infix(infix, left, right),
+            Expr::Index(left, index) => self.check_const_index(left, index),
+            _ => {}
+        }
+    }
+}
+
+/// Folds integer constant expressions, `None` if `expr` isn't one or overflows.
+fn const_int(expr: &Expr) -> Option<i64> {
+    match expr {
+        Expr::Literal(Literal::Int(value)) => Some(*value),
+        Expr::Prefix(Prefix::Minus, right) => const_int(right)?.checked_neg(),
+        Expr::Prefix(Prefix::Plus, right) => const_int(right),
+        Expr::Infix(infix, left, right) => {
+            const_int_infix(infix, const_int(left)?, const_int(right)?)
+        }
+        _ => None,
+    }
+}
+
+fn const_int_infix(infix: &Infix, left: i64, right: i64) -> Option<i64> {
+    match infix {
+        Infix::Plus => left.checked_add(right),
+        Infix::Minus => left.checked_sub(right),
+        Infix::Multiply => left.checked_mul(right),
+        Infix::Divide => left.checked_div(right),
+        _ => None,
+    }
+}
+
+/// Lints a whole program.
+pub fn check_program(program: &Program) -> Vec<Diagnostic> {
+    let mut linter = Linter {
+        span: Span::default(),
+        diagnostics: vec![],
+    };
+    linter.visit_block(program);
+
+    linter.diagnostics
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::diagnostic::{Lang, Severity};
+    use crate::lexer::Lexer;
+    use crate::lint::*;
+    use crate::parser::Parser;
+
+    fn lint(input: &str) -> Vec<Diagnostic> {
+        check_program(&Parser::new(Lexer::new(input)).with_positions().parse())
+    }
+
+    #[test]
+    fn test_unreachable_code() {
+        let diagnostics = lint(
+            "宝宝你是一个 f = 想要你一个态度(x) {\n  反手举报 x;\n  小作文(\"到不了\");\n  x + 1;\n};",
+        );
+
+        assert_eq!(1, diagnostics.len());
+        assert_eq!(Severity::Warning, diagnostics[0].severity);
+        assert_eq!("unreachable code", diagnostics[0].message(Lang::En));
+        assert_eq!(
+            (3, 3, 4, 9),
+            (
+                diagnostics[0].span.start.line,
+                diagnostics[0].span.start.column,
+                diagnostics[0].span.end.line,
+                diagnostics[0].span.end.column,
+            )
+        );
+
+        assert_eq!(1, lint("while (true) { break; 1; }").len());
+        assert_eq!(1, lint("[fn() { return 1; 2; }]").len());
+        assert!(lint("fn(x) { if (x) { return 1; } 2; }").is_empty());
+        assert!(lint("fn(x) { return 1;\n\n}").is_empty());
+    }
+
+    #[test]
+    fn test_const_exprs() {
+        let messages = |input: &str| {
+            lint(input)
+                .into_iter()
+                .map(|d| (d.severity, d.message(Lang::En)))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            vec![(Severity::Error, String::from("division by zero"))],
+            messages("1 / (2 - 2);")
+        );
+        assert_eq!(
+            vec![(Severity::Error, String::from("division by zero"))],
+            messages("fn(x) { x / 0 };")
+        );
+        assert_eq!(
+            vec![(
+                Severity::Error,
+                String::from("integer overflow: 9223372036854775807 + 1")
+            )],
+            messages("9223372036854775807 + 1 + 1;")
+        );
+        assert_eq!(
+            vec![(
+                Severity::Warning,
+                String::from("index 3 out of bounds for array of length 3, always null")
+            )],
+            messages("[1, 2, 3][1 + 2];")
+        );
+        assert_eq!(
+            vec![(Severity::Error, String::from("division by zero"))],
+            messages("fn(x = 1 / 0) { x };")
+        );
+        assert!(messages("[1, 2, 3][-1 + 3]; 6 / 3; let x = 0; 1 / x;").is_empty());
+        assert!(messages("造个句式 f(x) => { x / 0 }").is_empty());
+    }
+
+    #[test]
+    fn test_test_blocks() {
+        let diagnostics = lint("考一考 \"除法\" {\n  let x = 1 / 0;\n  return x;\n  x;\n}");
+
+        assert_eq!(
+            vec![
+                (Severity::Error, String::from("division by zero"), 2),
+                (Severity::Warning, String::from("unreachable code"), 4),
+            ],
+            diagnostics
+                .iter()
+                .map(|d| (d.severity, d.message(Lang::En), d.span.start.line))
+                .collect::<Vec<_>>()
+        );
+    }
+}
@@ -0,0 +1,144 @@
+//! Expanding `造个句式` macros: the template with each parameter replaced by
+//! the argument's syntax tree.
+//!
+//! Names the template binds with `let`, as function parameters or in `match`
+//! patterns are renamed to a spelling no program can write, so they never
+//! capture or clobber the caller's variables. Other names are looked up where
+//! the macro is used.
+
+use crate::ast::visit::{Visitor, VisitorMut, walk_expr_mut, walk_stmt};
+use crate::ast::*;
+use std::collections::{HashMap, HashSet};
+
+#[derive(Debug, Clone)]
+pub(crate) struct Macro {
+    pub params: Vec<Param>,
+    pub body: BlockStmt,
+}
+
+impl Macro {
+    /// The expansion of a call with `args`, the defaults standing in for
+    /// any left out. `id` tells this expansion's renamed bindings apart from
+    /// other expansions'.
+    pub fn expand(&self, args: Vec<Expr>, id: usize) -> Expr {
+        let mut args = args.into_iter();
+        let mut substitutes = HashMap::new();
+        for param in &self.params {
+            let arg = match (args.next(), &param.default) {
+                (Some(arg), _) => arg,
+                // Defaults see the parameters before them.
+                (None, Some(default)) => {
+                    let mut default = default.clone();
+                    Substitute(substitutes.clone()).visit_expr_mut(&mut default);
+                    default
+                }
+                (None, None) => unreachable!("checked against required_params"),
+            };
+            substitutes.insert(param.ident.0.clone(), arg);
+        }
+
+        let params = self
+            .params
+            .iter()
+            .map(|param| param.ident.0.clone())
+            .collect::<Vec<_>>();
+
+        let mut bound = BoundNames::default();
+        bound.visit_block(&self.body);
+        let renames = bound
+            .0
+            .into_iter()
+            .filter(|name| !params.contains(name))
+            .map(|name| {
+                let fresh = fresh_name(&name, id);
+                (name, fresh)
+            })
+            .collect();
+
+        let mut body = self.body.clone();
+        Rename(renames).visit_block_mut(&mut body);
+        Substitute(substitutes).visit_block_mut(&mut body);
+
+        // A template that is one expression stands for it; a longer one
+        // runs as a function called on the spot.
+        let mut stmts = body
+            .iter()
+            .filter(|stmt| !matches!(stmt, Stmt::Blank | Stmt::Comment(_) | Stmt::Mark(_)));
+        if let (Some(Stmt::Expr(expr)), None) = (stmts.next(), stmts.next()) {
+            return expr.clone();
+        }
+
+        Expr::Call {
+            func: Box::new(Expr::Func {
+                params: vec![],
+                return_ty: None,
+                body,
+                is_async: false,
+            }),
+            args: vec![],
+        }
+    }
+}
+
+#[derive(Default)]
+struct BoundNames(HashSet<String>);
+
+impl Visitor for BoundNames {
+    fn visit_stmt(&mut self, stmt: &Stmt) {
+        if let Stmt::Let(Ident(name), ..) = stmt {
+            self.0.insert(name.clone());
+        }
+        walk_stmt(self, stmt);
+    }
+
+    fn visit_pattern(&mut self, pattern: &Pattern) {
+        let names = pattern
+            .bindings()
+            .into_iter()
+            .map(|Ident(name)| name.clone());
+        self.0.extend(names);
+    }
+
+    fn visit_param(&mut self, param: &Param) {
+        self.0.insert(param.ident.0.clone());
+        if let Some(default) = &param.default {
+            self.visit_expr(default);
+        }
+    }
+}
+
+/// A spelling of `name` no program can write.
+fn fresh_name(name: &str, id: usize) -> String {
+    format!("{name}#{id}")
+}
+
+pub(crate) struct Rename(HashMap<String, String>);
+
+impl Rename {
+    /// Renames `name` for expansion (or desugaring) `id`.
+    pub fn one(name: &str, id: usize) -> Self {
+        Rename(HashMap::from([(name.to_string(), fresh_name(name, id))]))
+    }
+}
+
+impl VisitorMut for Rename {
+    fn visit_ident_mut(&mut self, ident: &mut Ident) {
+        if let Some(fresh) = self.0.get(&ident.0) {
+            ident.0 = fresh.clone();
+        }
+    }
+}
+
+struct Substitute(HashMap<String, Expr>);
+
+impl VisitorMut for Substitute {
+    fn visit_expr_mut(&mut self, expr: &mut Expr) {
+        match expr {
+            Expr::Ident(Ident(name)) if self.0.contains_key(name) => {
+                let arg = self.0[name].clone();
+                *expr = arg;
+            }
+            _ => walk_expr_mut(self, expr),
+        }
+    }
+}
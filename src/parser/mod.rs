@@ -1,30 +1,95 @@
+mod macros;
+pub mod syntax;
+
+use crate::ast::visit::VisitorMut;
 use crate::ast::*;
 use crate::constants::HER_KEY_WORDS;
-use crate::lexer::Lexer;
+use crate::lexer::{LexError, Lexer};
+use crate::span::Span;
 use crate::token::Token;
+use macros::{Macro, Rename};
+use std::collections::HashMap;
 use std::fmt;
+use syntax::{ONLY_FORMULAS, SyntaxConfig};
+
+/// What a program gets for being longer than a host allows.
+pub const TOO_LONG: &str = "代码太长，姐妹歇歇";
 
 #[derive(Debug, Clone)]
 pub enum ParseError {
-    UnexpectedToken { want: Option<Token>, got: Token },
-    HerUnexpectedToken { got: String },
+    UnexpectedToken {
+        want: Option<Token>,
+        got: Token,
+        /// `got` as written in the source, like `if` for `Token::If`.
+        text: String,
+        span: Span,
+    },
+    HerUnexpectedToken {
+        rule: HerRule,
+        span: Span,
+    },
+    /// The input is longer than allowed, or took more than
+    /// `Parser::with_max_steps` tokens to parse.
+    TooLong {
+        span: Span,
+    },
+    /// A token the lexer flagged, like a string with a bad escape.
+    Lex(LexError),
+    /// A macro called with a different number of arguments than it has
+    /// parameters.
+    MacroArity {
+        name: String,
+        want: usize,
+        got: usize,
+        span: Span,
+    },
+    /// Syntax `Parser::with_syntax` doesn't allow.
+    NotAllowed {
+        span: Span,
+    },
+}
+
+impl ParseError {
+    pub fn span(&self) -> Span {
+        match self {
+            ParseError::UnexpectedToken { span, .. } => *span,
+            ParseError::HerUnexpectedToken { span, .. } => *span,
+            ParseError::TooLong { span } => *span,
+            ParseError::Lex(err) => err.span(),
+            ParseError::MacroArity { span, .. } => *span,
+            ParseError::NotAllowed { span } => *span,
+        }
+    }
 }
 
 impl fmt::Display for ParseError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
-            ParseError::UnexpectedToken { want: w, got: g } => match w {
+            ParseError::UnexpectedToken {
+                want: w, text: g, ..
+            } => match w {
                 Some(w) => write!(
                     f,
-                    "啊啊啊啊啊啊啊啊啊啊啊啊 Unexpected Token: expected {w:?}, got {g:?}"
+                    "啊啊啊啊啊啊啊啊啊啊啊啊 Unexpected Token: expected `{w}`, got `{g}`"
                 ),
                 None => write!(
                     f,
-                    "啊啊啊啊啊啊啊啊啊啊啊啊 Unexpected Token: no prefix rule for {g:?}"
+                    "啊啊啊啊啊啊啊啊啊啊啊啊 Unexpected Token: no prefix rule for `{g}`"
                 ),
             },
-            ParseError::HerUnexpectedToken { got: g } => {
-                write!(f, "啊啊啊啊啊啊啊啊啊啊啊啊 SyntaxError: {g:?}")
+            ParseError::HerUnexpectedToken { rule, .. } => {
+                write!(f, "啊啊啊啊啊啊啊啊啊啊啊啊 SyntaxError: {rule}")
+            }
+            ParseError::TooLong { .. } => write!(f, "{TOO_LONG}"),
+            ParseError::Lex(err) => write!(f, "{err}"),
+            ParseError::MacroArity {
+                name, want, got, ..
+            } => write!(
+                f,
+                "啊啊啊啊啊啊啊啊啊啊啊啊 MacroError: {name} takes {want} arguments, got {got}"
+            ),
+            ParseError::NotAllowed { .. } => {
+                write!(f, "啊啊啊啊啊啊啊啊啊啊啊啊 SyntaxError: {ONLY_FORMULAS}")
             }
         }
     }
@@ -32,11 +97,67 @@ impl fmt::Display for ParseError {
 
 pub type ParseErrors = Vec<ParseError>;
 
+/// Which of herlang's own syntax rules a program broke, see
+/// `ParseError::HerUnexpectedToken`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HerRule {
+    /// Binding one of `HER_KEY_WORDS`.
+    ProtectedName,
+    NotAPattern {
+        got: String,
+    },
+    MissingDefault {
+        param: String,
+    },
+}
+
+impl fmt::Display for HerRule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            HerRule::ProtectedName => write!(f, "女性是不能被定义的！！！"),
+            HerRule::NotAPattern { got } => write!(
+                f,
+                "`{got}` is not a pattern, expected a number, string, bool, name, array, hash or `_`"
+            ),
+            HerRule::MissingDefault { param } => write!(
+                f,
+                "parameter `{param}` needs a default, like the ones before it"
+            ),
+        }
+    }
+}
+
+/// What to do when a program binds one of `HER_KEY_WORDS`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum HerKeyWordPolicy {
+    #[default]
+    Error,
+    Warning,
+    Off,
+}
+
 pub struct Parser {
     lexer: Lexer,
     current_token: Token,
+    current_span: Span,
     next_token: Token,
+    next_span: Span,
+    /// Comments right before `current_token` and `next_token`. Only the
+    /// ones before a statement end up in the program.
+    current_comments: Vec<Comment>,
+    next_comments: Vec<Comment>,
     errors: ParseErrors,
+    warnings: ParseErrors,
+    positions: bool,
+    her_key_words: HerKeyWordPolicy,
+    /// Tokens consumed so far, counted against `max_steps`.
+    steps: usize,
+    max_steps: Option<usize>,
+    /// Macros defined so far, by name, and whether calls to them expand.
+    macros: HashMap<String, Macro>,
+    expand_macros: bool,
+    expansions: usize,
+    syntax: SyntaxConfig,
 }
 
 impl Parser {
@@ -44,8 +165,21 @@ impl Parser {
         let mut parser = Parser {
             lexer,
             current_token: Token::Eof,
+            current_span: Span::default(),
             next_token: Token::Eof,
+            next_span: Span::default(),
+            current_comments: vec![],
+            next_comments: vec![],
             errors: vec![],
+            warnings: vec![],
+            positions: false,
+            her_key_words: HerKeyWordPolicy::default(),
+            steps: 0,
+            max_steps: None,
+            macros: HashMap::new(),
+            expand_macros: true,
+            expansions: 0,
+            syntax: SyntaxConfig::default(),
         };
 
         parser.bump();
@@ -54,15 +188,54 @@ impl Parser {
         parser
     }
 
+    /// Puts a `Stmt::Mark` with the source span before every statement.
+    pub fn with_positions(mut self) -> Self {
+        self.positions = true;
+        self
+    }
+
+    pub fn with_her_key_word_policy(mut self, policy: HerKeyWordPolicy) -> Self {
+        self.her_key_words = policy;
+        self
+    }
+
+    /// Gives up after `max_steps` tokens, so a huge input can't hang the
+    /// host. The only error is then `ParseError::TooLong`.
+    pub fn with_max_steps(mut self, max_steps: usize) -> Self {
+        self.max_steps = Some(max_steps);
+        self
+    }
+
+    /// Reports syntax `syntax` doesn't allow as `ParseError::NotAllowed`.
+    pub fn with_syntax(mut self, syntax: SyntaxConfig) -> Self {
+        self.syntax = syntax;
+        self
+    }
+
+    /// Leaves calls to `造个句式` macros as they are written, and the names
+    /// a C-style `for` binds as they are, for tools like the formatter that
+    /// give the source back.
+    pub fn without_macro_expansion(mut self) -> Self {
+        self.expand_macros = false;
+        self
+    }
+
+    fn is_over_budget(&self) -> bool {
+        self.max_steps.is_some_and(|max| self.steps > max)
+    }
+
     fn token_to_precedence(tok: &Token) -> Precedence {
         match tok {
+            Token::Coalesce => Precedence::Coalesce,
+            Token::Or => Precedence::Or,
+            Token::And => Precedence::And,
             Token::Equal | Token::NotEqual => Precedence::Equals,
             Token::LessThan | Token::LessThanEqual => Precedence::LessGreater,
             Token::GreaterThan | Token::GreaterThanEqual => Precedence::LessGreater,
             Token::Plus | Token::Minus => Precedence::Sum,
             Token::Slash | Token::Asterisk => Precedence::Product,
             Token::Lbracket => Precedence::Index,
-            Token::Dot => Precedence::Index,
+            Token::Dot | Token::Of => Precedence::Index,
             Token::Lparen => Precedence::Call,
             _ => Precedence::Lowest,
         }
@@ -72,10 +245,34 @@ impl Parser {
         self.errors.clone()
     }
 
+    /// Problems that don't stop the program from running, such as binding
+    /// one of `HER_KEY_WORDS` under `HerKeyWordPolicy::Warning`.
+    pub fn get_warnings(&mut self) -> ParseErrors {
+        self.warnings.clone()
+    }
+
     fn bump(&mut self) {
+        self.steps += 1;
+        if self.is_over_budget() {
+            // Pretend the input ends here, so every loop winds down.
+            self.current_token = Token::Eof;
+            self.next_token = Token::Eof;
+            return;
+        }
+
         // FIXME: Clearly unnecessary clone
         self.current_token = self.next_token.clone();
-        self.next_token = self.lexer.next_token();
+        self.current_span = self.next_span;
+        self.current_comments = std::mem::take(&mut self.next_comments);
+        loop {
+            (self.next_token, self.next_span) = self.lexer.next_token_with_span();
+            match &self.next_token {
+                Token::Comment(text) => self.next_comments.push(Comment::Line(text.clone())),
+                Token::BlockComment(text) => self.next_comments.push(Comment::Block(text.clone())),
+                Token::Version(version) => self.next_comments.push(Comment::Version(*version)),
+                _ => break,
+            }
+        }
     }
 
     fn current_token_is(&mut self, tok: Token) -> bool {
@@ -108,26 +305,54 @@ impl Parser {
         self.errors.push(ParseError::UnexpectedToken {
             want: Some(tok),
             got: self.next_token.clone(),
+            text: self.source_text(&self.next_token, self.next_span),
+            span: self.next_span,
         });
     }
 
     fn error_no_prefix_parser(&mut self) {
         self.errors.push(ParseError::UnexpectedToken {
             want: None,
-            got: self.next_token.clone(),
+            got: self.current_token.clone(),
+            text: self.source_text(&self.current_token, self.current_span),
+            span: self.current_span,
         });
     }
 
+    /// How `tok` at `span` is written, so errors quote `if` where the code
+    /// says `if` and not another spelling of the same keyword.
+    fn source_text(&self, tok: &Token, span: Span) -> String {
+        match self.lexer.text(span) {
+            text if text.is_empty() => tok.to_string(),
+            text => text,
+        }
+    }
+
     pub fn parse(&mut self) -> Program {
         let mut program: Program = vec![];
 
         while !self.current_token_is(Token::Eof) {
-            match self.parse_stmt() {
-                Some(stmt) => program.push(stmt),
-                None => {}
-            }
+            self.parse_stmt_into(&mut program);
             self.bump();
         }
+        self.take_comments_into(&mut program);
+
+        self.errors
+            .extend(self.lexer.take_errors().into_iter().map(ParseError::Lex));
+        self.errors.sort_by_key(|err| err.span().start.offset);
+
+        if self.is_over_budget() {
+            self.errors = vec![ParseError::TooLong {
+                span: self.current_span,
+            }];
+        }
+
+        for err in &self.errors {
+            her_log!(debug, "{}: {err}", err.span());
+        }
+        for warning in &self.warnings {
+            her_log!(debug, "{}: warning: {warning}", warning.span());
+        }
 
         program
     }
@@ -142,27 +367,116 @@ impl Parser {
                 self.error_next_token(Token::Rbrace);
                 return block;
             }
-            match self.parse_stmt() {
-                Some(stmt) => block.push(stmt),
-                None => {}
+            let errors = self.errors.len();
+            self.parse_stmt_into(&mut block);
+            // A statement cut short by the `}` ends the block there, rather
+            // than running on to the end of the input.
+            let cut_short = self.errors[errors..]
+                .iter()
+                .any(|err| err.span() == self.current_span);
+            if cut_short && self.current_token_is(Token::Rbrace) {
+                break;
             }
             self.bump();
         }
+        self.take_comments_into(&mut block);
 
         block
     }
 
+    fn take_comments_into(&mut self, block: &mut BlockStmt) {
+        block.extend(self.current_comments.drain(..).map(Stmt::Comment));
+    }
+
+    fn parse_stmt_into(&mut self, block: &mut BlockStmt) {
+        self.take_comments_into(block);
+        let start = self.current_span.start;
+
+        if let Some(stmt) = self.parse_stmt() {
+            let is_code =
+                |stmt: &Stmt| !matches!(stmt, Stmt::Blank | Stmt::Comment(_) | Stmt::Mark(_));
+            if !self.syntax.statements && is_code(&stmt) && block.iter().any(is_code) {
+                self.errors.push(ParseError::NotAllowed {
+                    span: Span {
+                        start,
+                        end: self.current_span.end,
+                    },
+                });
+            }
+            if self.positions && !matches!(stmt, Stmt::Blank | Stmt::Comment(_)) {
+                block.push(Stmt::Mark(Span {
+                    start,
+                    end: self.current_span.end,
+                }));
+            }
+            block.push(stmt);
+        }
+    }
+
     fn parse_stmt(&mut self) -> Option<Stmt> {
+        let allowed = match self.current_token {
+            Token::Let => self.syntax.bindings,
+            Token::Return | Token::Break | Token::Continue => self.syntax.statements,
+            Token::Import => self.syntax.imports,
+            Token::Macro => self.syntax.functions,
+            Token::Test => self.syntax.tests,
+            _ => true,
+        };
+        self.check_syntax(allowed);
+
         match self.current_token {
             Token::Let => self.parse_let_stmt(),
             Token::Return => self.parse_return_stmt(),
             Token::Blank => Some(Stmt::Blank),
             Token::Break => self.parse_break_stmt(),
             Token::Continue => self.parse_continue_stmt(),
+            Token::Import => self.parse_import_stmt(),
+            Token::Macro => self.parse_macro_stmt(),
+            Token::Test => self.parse_test_stmt(),
+            Token::Increment | Token::Decrement if matches!(self.next_token, Token::Ident(_)) => {
+                self.parse_step_stmt()
+            }
+            Token::Ident(_) if matches!(self.next_token, Token::Increment | Token::Decrement) => {
+                self.parse_step_stmt()
+            }
             _ => self.parse_expr_stmt(),
         }
     }
 
+    /// `i++`, `++i`, `i--` or `--i`, which assign `i + 1` or `i - 1` to `i`.
+    fn parse_step_stmt(&mut self) -> Option<Stmt> {
+        self.check_syntax(self.syntax.bindings);
+        let (op, name, span) = match self.current_token {
+            Token::Ident(_) => {
+                let name = self.parse_ident()?;
+                let span = self.current_span;
+                self.bump();
+                (self.current_token.clone(), name, span)
+            }
+            _ => {
+                let op = self.current_token.clone();
+                self.bump();
+                (op, self.parse_ident()?, self.current_span)
+            }
+        };
+        let infix = match op {
+            Token::Increment => Infix::Plus,
+            _ => Infix::Minus,
+        };
+
+        self.check_binding(&name, span);
+        if self.next_token_is(&Token::Semicolon) {
+            self.bump();
+        }
+
+        let value = Expr::Infix(
+            infix,
+            Box::new(Expr::Ident(name.clone())),
+            Box::new(Expr::Literal(Literal::Int(1))),
+        );
+        Some(Stmt::Assign(name, value))
+    }
+
     fn parse_let_stmt(&mut self) -> Option<Stmt> {
         match &self.next_token {
             Token::Ident(_) => self.bump(),
@@ -170,19 +484,14 @@ impl Parser {
         };
 
         let name = self.parse_ident()?;
+        let name_span = self.current_span;
+        let ty = self.parse_type_annotation(Token::Colon)?;
 
         if !self.expect_next_token(Token::Assign) {
             return None;
         }
 
-        // 女性是不能被定义滴
-        if HER_KEY_WORDS.contains(&name.0.as_str()) {
-            self.errors.push(ParseError::HerUnexpectedToken {
-                got: format!("女性是不能被定义的！！！"),
-            });
-            return None;
-        };
-
+        self.check_binding(&name, name_span);
         self.bump();
 
         let expr = self.parse_expr(Precedence::Lowest)?;
@@ -191,7 +500,35 @@ impl Parser {
             self.bump();
         }
 
-        Some(Stmt::Let(name, expr))
+        Some(Stmt::Let(name, ty, expr))
+    }
+
+    /// Reports the current token, which starts a statement or expression, if
+    /// it isn't `allowed` by `with_syntax`.
+    fn check_syntax(&mut self, allowed: bool) {
+        if !allowed {
+            self.errors.push(ParseError::NotAllowed {
+                span: self.current_span,
+            });
+        }
+    }
+
+    /// Every syntax that introduces a name goes through here.
+    fn check_binding(&mut self, name: &Ident, span: Span) {
+        // 女性是不能被定义滴
+        if !HER_KEY_WORDS.contains(&name.0.as_str()) {
+            return;
+        }
+
+        let err = ParseError::HerUnexpectedToken {
+            rule: HerRule::ProtectedName,
+            span,
+        };
+        match self.her_key_words {
+            HerKeyWordPolicy::Error => self.errors.push(err),
+            HerKeyWordPolicy::Warning => self.warnings.push(err),
+            HerKeyWordPolicy::Off => {}
+        }
     }
 
     fn parse_return_stmt(&mut self) -> Option<Stmt> {
@@ -207,8 +544,6 @@ impl Parser {
     }
 
     fn parse_break_stmt(&mut self) -> Option<Stmt> {
-        self.bump();
-
         if self.next_token_is(&Token::Semicolon) {
             self.bump();
         }
@@ -217,13 +552,80 @@ impl Parser {
     }
 
     fn parse_continue_stmt(&mut self) -> Option<Stmt> {
+        if self.next_token_is(&Token::Semicolon) {
+            self.bump();
+        }
+
+        Some(Stmt::Continue)
+    }
+
+    fn parse_import_stmt(&mut self) -> Option<Stmt> {
+        let path = match &self.next_token {
+            Token::String(path) => path.clone(),
+            _ => {
+                self.error_next_token(Token::String(String::new()));
+                return None;
+            }
+        };
+
         self.bump();
 
         if self.next_token_is(&Token::Semicolon) {
             self.bump();
         }
 
-        Some(Stmt::Continue)
+        Some(Stmt::Import(path))
+    }
+
+    fn parse_test_stmt(&mut self) -> Option<Stmt> {
+        let name = match &self.next_token {
+            Token::String(name) => name.clone(),
+            _ => {
+                self.error_next_token(Token::String(String::new()));
+                return None;
+            }
+        };
+        self.bump();
+
+        if !self.expect_next_token(Token::Lbrace) {
+            return None;
+        }
+        let body = self.parse_block_stmt();
+
+        Some(Stmt::Test { name, body })
+    }
+
+    /// `造个句式 name(params) => { template }`. Calls to `name` after this
+    /// are replaced with the template while parsing.
+    fn parse_macro_stmt(&mut self) -> Option<Stmt> {
+        if !matches!(self.next_token, Token::Ident(_)) {
+            self.error_next_token(Token::Ident(String::new()));
+            return None;
+        }
+        self.bump();
+
+        let name = self.parse_ident()?;
+        self.check_binding(&name, self.current_span);
+
+        if !self.expect_next_token(Token::Lparen) {
+            return None;
+        }
+        let params = self.parse_func_params()?;
+
+        if !self.expect_next_token(Token::FatArrow) || !self.expect_next_token(Token::Lbrace) {
+            return None;
+        }
+        let body = self.parse_block_stmt();
+
+        self.macros.insert(
+            name.0.clone(),
+            Macro {
+                params: params.clone(),
+                body: body.clone(),
+            },
+        );
+
+        Some(Stmt::Macro { name, params, body })
     }
 
     fn parse_expr_stmt(&mut self) -> Option<Stmt> {
@@ -239,19 +641,31 @@ impl Parser {
     }
 
     fn parse_expr(&mut self, precedence: Precedence) -> Option<Expr> {
+        let allowed = match self.current_token {
+            Token::While | Token::Do | Token::Loop | Token::For => self.syntax.loops,
+            Token::Func | Token::Async => self.syntax.functions,
+            _ => true,
+        };
+        self.check_syntax(allowed);
+
         // prefix
         let mut left = match self.current_token {
             Token::Ident(_) => self.parse_ident_expr(),
-            Token::Int(_) => self.parse_int_expr(),
+            Token::Int(_) | Token::Float(_) | Token::Quantity(..) => self.parse_int_expr(),
             Token::String(_) => self.parse_string_expr(),
             Token::Bool(_) => self.parse_bool_expr(),
             Token::Lbracket => self.parse_array_expr(),
             Token::Lbrace => self.parse_hash_expr(),
-            Token::Bang | Token::Minus | Token::Plus => self.parse_prefix_expr(),
+            Token::Bang | Token::Minus | Token::Plus | Token::Await => self.parse_prefix_expr(),
             Token::Lparen => self.parse_grouped_expr(),
             Token::If => self.parse_if_expr(),
             Token::While => self.parse_while_expr(),
+            Token::Do => self.parse_do_while_expr(),
+            Token::Loop => self.parse_loop_expr(),
+            Token::Match => self.parse_match_expr(),
+            Token::For => self.parse_for_expr(),
             Token::Func => self.parse_func_expr(),
+            Token::Async => self.parse_async_func_expr(),
             _ => {
                 self.error_no_prefix_parser();
                 return None;
@@ -259,7 +673,10 @@ impl Parser {
         };
 
         // infix
-        while !self.next_token_is(&Token::Semicolon) && precedence < self.next_token_precedence() {
+        while left.is_some()
+            && !self.next_token_is(&Token::Semicolon)
+            && precedence < self.next_token_precedence()
+        {
             match self.next_token {
                 Token::Plus
                 | Token::Minus
@@ -270,7 +687,10 @@ impl Parser {
                 | Token::LessThan
                 | Token::LessThanEqual
                 | Token::GreaterThan
-                | Token::GreaterThanEqual => {
+                | Token::GreaterThanEqual
+                | Token::And
+                | Token::Or
+                | Token::Coalesce => {
                     self.bump();
                     left = self.parse_infix_expr(left.unwrap());
                 }
@@ -282,6 +702,10 @@ impl Parser {
                     self.bump();
                     left = self.parse_dot_access_expr(left.unwrap());
                 }
+                Token::Of => {
+                    self.bump();
+                    left = self.parse_ordinal_access_expr(left.unwrap());
+                }
                 Token::Lparen => {
                     self.bump();
                     left = self.parse_call_expr(left.unwrap());
@@ -307,6 +731,10 @@ impl Parser {
     fn parse_int_expr(&mut self) -> Option<Expr> {
         match self.current_token {
             Token::Int(ref mut int) => Some(Expr::Literal(Literal::Int(*int))),
+            Token::Float(value) => Some(Expr::Literal(Literal::Float(value))),
+            Token::Quantity(value, ref unit) => {
+                Some(Expr::Literal(Literal::Quantity(value, unit.clone())))
+            }
             _ => None,
         }
     }
@@ -377,6 +805,10 @@ impl Parser {
 
         while self.next_token_is(&Token::Comma) {
             self.bump();
+            // A trailing comma.
+            if self.next_token_is(&end) {
+                break;
+            }
             self.bump();
 
             match self.parse_expr(Precedence::Lowest) {
@@ -395,6 +827,7 @@ impl Parser {
     fn parse_prefix_expr(&mut self) -> Option<Expr> {
         let prefix = match self.current_token {
             Token::Bang => Prefix::Not,
+            Token::Await => Prefix::Await,
             Token::Minus => Prefix::Minus,
             Token::Plus => Prefix::Plus,
             _ => return None,
@@ -418,6 +851,9 @@ impl Parser {
             Token::LessThanEqual => Infix::LessThanEqual,
             Token::GreaterThan => Infix::GreaterThan,
             Token::GreaterThanEqual => Infix::GreaterThanEqual,
+            Token::And => Infix::And,
+            Token::Or => Infix::Or,
+            Token::Coalesce => Infix::Coalesce,
             _ => return None,
         };
 
@@ -432,7 +868,10 @@ impl Parser {
     fn parse_index_expr(&mut self, left: Expr) -> Option<Expr> {
         self.bump();
 
-        let index = self.parse_expr(Precedence::Lowest)?;
+        let index = match self.current_token {
+            Token::Ordinal(n) if self.next_token == Token::Rbracket => Self::ordinal_index(n),
+            _ => self.parse_expr(Precedence::Lowest)?,
+        };
 
         if !self.expect_next_token(Token::Rbracket) {
             return None;
@@ -452,6 +891,24 @@ impl Parser {
         })
     }
 
+    /// `清单 的 第1个` is `清单[0]`.
+    fn parse_ordinal_access_expr(&mut self, left: Expr) -> Option<Expr> {
+        let Token::Ordinal(n) = self.next_token else {
+            self.error_next_token(Token::Ordinal(1));
+            return None;
+        };
+        self.bump();
+
+        Some(Expr::Index(
+            Box::new(left),
+            Box::new(Self::ordinal_index(n)),
+        ))
+    }
+
+    fn ordinal_index(n: i64) -> Expr {
+        Expr::Literal(Literal::Int(n - 1))
+    }
+
     fn parse_grouped_expr(&mut self) -> Option<Expr> {
         self.bump();
 
@@ -483,11 +940,17 @@ impl Parser {
         if self.next_token_is(&Token::Else) {
             self.bump();
 
-            if !self.expect_next_token(Token::Lbrace) {
-                return None;
-            }
+            // `else if (...) { ... }` is `else { if (...) { ... } }`.
+            if self.next_token_is(&Token::If) {
+                self.bump();
+                alternative = Some(vec![Stmt::Expr(self.parse_if_expr()?)]);
+            } else {
+                if !self.expect_next_token(Token::Lbrace) {
+                    return None;
+                }
 
-            alternative = Some(self.parse_block_stmt());
+                alternative = Some(self.parse_block_stmt());
+            }
         }
 
         Some(Expr::If {
@@ -518,102 +981,559 @@ impl Parser {
         })
     }
 
-    fn parse_func_expr(&mut self) -> Option<Expr> {
-        if !self.expect_next_token(Token::Lparen) {
-            return None;
-        }
-
-        let params = self.parse_func_params()?;
-
+    /// `loop { ... }` is `while (true) { ... }`.
+    fn parse_loop_expr(&mut self) -> Option<Expr> {
         if !self.expect_next_token(Token::Lbrace) {
             return None;
         }
 
-        Some(Expr::Func {
-            params,
-            body: self.parse_block_stmt(),
+        let consequence = self.parse_block_stmt();
+
+        Some(Expr::While {
+            cond: Box::new(Expr::Literal(Literal::Bool(true))),
+            consequence,
         })
     }
 
-    fn parse_func_params(&mut self) -> Option<Vec<Ident>> {
-        let mut params = vec![];
-
-        if self.next_token_is(&Token::Rparen) {
-            self.bump();
-            return Some(params);
-        }
-
+    fn parse_match_expr(&mut self) -> Option<Expr> {
         self.bump();
+        let subject = self.parse_expr(Precedence::Lowest)?;
 
-        match self.parse_ident() {
-            Some(ident) => params.push(ident),
-            None => return None,
-        };
+        if !self.expect_next_token(Token::Lbrace) {
+            return None;
+        }
 
-        while self.next_token_is(&Token::Comma) {
-            self.bump();
+        let mut arms = vec![];
+        while !self.next_token_is(&Token::Rbrace) {
             self.bump();
+            let pattern = self.parse_pattern()?;
 
-            match self.parse_ident() {
-                Some(ident) => params.push(ident),
-                None => return None,
+            if !self.expect_next_token(Token::FatArrow) {
+                return None;
+            }
+
+            let body = if self.next_token_is(&Token::Lbrace) {
+                self.bump();
+                self.parse_block_stmt()
+            } else {
+                self.bump();
+                vec![Stmt::Expr(self.parse_expr(Precedence::Lowest)?)]
             };
+            arms.push(MatchArm { pattern, body });
+
+            if !self.next_token_is(&Token::Comma) {
+                break;
+            }
+            self.bump();
         }
 
-        if !self.expect_next_token(Token::Rparen) {
+        if !self.expect_next_token(Token::Rbrace) {
             return None;
         }
 
-        Some(params)
+        Some(Expr::Match {
+            subject: Box::new(subject),
+            arms,
+        })
     }
 
-    fn parse_call_expr(&mut self, func: Expr) -> Option<Expr> {
-        let args = self.parse_expr_list(Token::Rparen)?;
+    /// `_`, a number (maybe negative), string or bool, a name, or an array or
+    /// hash of patterns.
+    fn parse_pattern(&mut self) -> Option<Pattern> {
+        let negate = self.current_token_is(Token::Minus);
+        if negate {
+            self.bump();
+        }
 
-        Some(Expr::Call {
-            func: Box::new(func),
-            args,
-        })
-    }
-}
+        let literal = match (&self.current_token, negate) {
+            (Token::Ident(name), false) if name == "_" => return Some(Pattern::Wildcard),
+            (Token::Ident(name), false) => {
+                let name = Ident(name.clone());
+                self.check_binding(&name, self.current_span);
+                return Some(Pattern::Binding(name));
+            }
+            (Token::Lbracket, false) => return self.parse_array_pattern(),
+            (Token::Lbrace, false) => return self.parse_hash_pattern(),
+            (Token::Int(value), _) => Literal::Int(if negate { -value } else { *value }),
+            (Token::Float(value), _) => Literal::Float(if negate { -value } else { *value }),
+            (Token::Quantity(value, unit), _) => {
+                Literal::Quantity(if negate { -value } else { *value }, unit.clone())
+            }
+            (Token::String(value), false) => Literal::String(value.clone()),
+            (Token::Bool(value), false) => Literal::Bool(*value),
+            (tok, _) => {
+                self.errors.push(ParseError::HerUnexpectedToken {
+                    rule: HerRule::NotAPattern {
+                        got: self.source_text(tok, self.current_span),
+                    },
+                    span: self.current_span,
+                });
+                return None;
+            }
+        };
 
-#[cfg(test)]
-mod tests {
-    use crate::ast::*;
-    use crate::lexer::Lexer;
-    use crate::parser::Parser;
+        Some(Pattern::Literal(literal))
+    }
 
-    fn check_parse_errors(parser: &mut Parser) {
-        let errors = parser.get_errors();
+    /// `[a, b]`, or `[a, ...rest]`, where the rest comes last.
+    fn parse_array_pattern(&mut self) -> Option<Pattern> {
+        let mut items = vec![];
 
-        if errors.is_empty() {
-            return;
-        }
+        while !self.next_token_is(&Token::Rbracket) {
+            self.bump();
 
-        println!("\n");
+            if self.current_token_is(Token::Dot) {
+                if !self.expect_next_token(Token::Dot) || !self.expect_next_token(Token::Dot) {
+                    return None;
+                }
+                if !matches!(self.next_token, Token::Ident(_)) {
+                    self.error_next_token(Token::Ident(String::new()));
+                    return None;
+                }
+                self.bump();
+                let rest = self.parse_ident()?;
+                self.check_binding(&rest, self.current_span);
+                if !self.expect_next_token(Token::Rbracket) {
+                    return None;
+                }
+                return Some(Pattern::Array(items, Some(rest)));
+            }
 
-        println!("parser has {} errors", errors.len());
+            items.push(self.parse_pattern()?);
 
-        for err in errors {
-            println!("parse error: {:?}", err);
+            if !self.next_token_is(&Token::Rbracket) && !self.expect_next_token(Token::Comma) {
+                return None;
+            }
         }
+        self.bump();
 
-        println!("\n");
-
-        panic!("failed");
+        Some(Pattern::Array(items, None))
     }
 
-    #[test]
-    fn test_blank() {
-        let input = r#"
-1000;
+    /// `{key: pattern}`, where `{key}` is short for `{key: key}`.
+    fn parse_hash_pattern(&mut self) -> Option<Pattern> {
+        let mut entries = vec![];
 
-1000;
+        while !self.next_token_is(&Token::Rbrace) {
+            if !matches!(self.next_token, Token::Ident(_)) {
+                self.error_next_token(Token::Ident(String::new()));
+                return None;
+            }
+            self.bump();
+            let Ident(key) = self.parse_ident()?;
+
+            let pattern = if self.next_token_is(&Token::Colon) {
+                self.bump();
+                self.bump();
+                self.parse_pattern()?
+            } else {
+                let name = Ident(key.clone());
+                self.check_binding(&name, self.current_span);
+                Pattern::Binding(name)
+            };
+            entries.push((key, pattern));
 
+            if !self.next_token_is(&Token::Rbrace) && !self.expect_next_token(Token::Comma) {
+                return None;
+            }
+        }
+        self.bump();
 
-1000;
+        Some(Pattern::Hash(entries))
+    }
 
-if (x) {
+    fn parse_do_while_expr(&mut self) -> Option<Expr> {
+        if !self.expect_next_token(Token::Lbrace) {
+            return None;
+        }
+
+        let consequence = self.parse_block_stmt();
+
+        if !self.expect_next_token(Token::While) || !self.expect_next_token(Token::Lparen) {
+            return None;
+        }
+
+        self.bump();
+
+        let cond = self.parse_expr(Precedence::Lowest)?;
+
+        if !self.expect_next_token(Token::Rparen) {
+            return None;
+        }
+
+        Some(Expr::DoWhile {
+            consequence,
+            cond: Box::new(cond),
+        })
+    }
+
+    fn parse_for_expr(&mut self) -> Option<Expr> {
+        if !self.expect_next_token(Token::Lparen) {
+            return None;
+        }
+        self.bump();
+
+        if !matches!(self.current_token, Token::Ident(_))
+            || !matches!(self.next_token, Token::In | Token::Comma)
+        {
+            return self.parse_c_for_expr();
+        }
+
+        let mut vars = vec![];
+        loop {
+            let Some(var) = self.parse_ident() else {
+                self.error_no_prefix_parser();
+                return None;
+            };
+            self.check_binding(&var, self.current_span);
+            vars.push(var);
+
+            if vars.len() == 2 || !self.next_token_is(&Token::Comma) {
+                break;
+            }
+            self.bump();
+            self.bump();
+        }
+
+        if !self.expect_next_token(Token::In) {
+            return None;
+        }
+        self.bump();
+
+        let iterable = self.parse_expr(Precedence::Lowest)?;
+
+        if !self.expect_next_token(Token::Rparen) || !self.expect_next_token(Token::Lbrace) {
+            return None;
+        }
+
+        let body = self.parse_block_stmt();
+
+        Some(Expr::For {
+            vars,
+            iterable: Box::new(iterable),
+            body,
+        })
+    }
+
+    /// `for (init; cond; step) { body }`, with the current token just past
+    /// `(`. Unless macros are left unexpanded, it is turned into a `While`
+    /// right away, see `desugar_c_for`.
+    fn parse_c_for_expr(&mut self) -> Option<Expr> {
+        let init = match self.current_token {
+            Token::Semicolon => None,
+            _ => {
+                let init = self.parse_stmt()?;
+                if !self.current_token_is(Token::Semicolon) {
+                    self.error_next_token(Token::Semicolon);
+                    return None;
+                }
+                Some(init)
+            }
+        };
+        self.bump();
+
+        let cond = match self.current_token {
+            Token::Semicolon => None,
+            _ => {
+                let cond = self.parse_expr(Precedence::Lowest)?;
+                if !self.expect_next_token(Token::Semicolon) {
+                    return None;
+                }
+                Some(cond)
+            }
+        };
+        self.bump();
+
+        let step = match self.current_token {
+            Token::Rparen => None,
+            _ => {
+                let step = self.parse_stmt()?;
+                if !self.expect_next_token(Token::Rparen) {
+                    return None;
+                }
+                Some(step)
+            }
+        };
+
+        if !self.expect_next_token(Token::Lbrace) {
+            return None;
+        }
+        let body = self.parse_block_stmt();
+
+        if !self.expand_macros {
+            return Some(Expr::CFor {
+                init: init.map(Box::new),
+                cond: cond.map(Box::new),
+                step: step.map(Box::new),
+                body,
+            });
+        }
+        self.expansions += 1;
+        let id = Some(self.expansions);
+        Some(desugar_c_for(
+            init.as_ref(),
+            cond.as_ref(),
+            step.as_ref(),
+            &body,
+            id,
+        ))
+    }
+
+    fn parse_func_expr(&mut self) -> Option<Expr> {
+        if !self.expect_next_token(Token::Lparen) {
+            return None;
+        }
+
+        let params = self.parse_func_params()?;
+        let return_ty = self.parse_type_annotation(Token::Arrow)?;
+
+        if !self.expect_next_token(Token::Lbrace) {
+            return None;
+        }
+
+        Some(Expr::Func {
+            params,
+            return_ty,
+            body: self.parse_block_stmt(),
+            is_async: false,
+        })
+    }
+
+    fn parse_async_func_expr(&mut self) -> Option<Expr> {
+        if !self.expect_next_token(Token::Func) {
+            return None;
+        }
+
+        let mut func = self.parse_func_expr()?;
+        if let Expr::Func { is_async, .. } = &mut func {
+            *is_async = true;
+        }
+
+        Some(func)
+    }
+
+    /// Parses `<sep> 类型` if the next token is `sep`; `Some(None)` when there is none.
+    fn parse_type_annotation(&mut self, sep: Token) -> Option<Option<TypeAnnotation>> {
+        if !self.next_token_is(&sep) {
+            return Some(None);
+        }
+
+        self.bump();
+        match &self.next_token {
+            Token::Ident(name) => {
+                let ty = TypeAnnotation(name.clone());
+                self.bump();
+                Some(Some(ty))
+            }
+            _ => {
+                self.error_next_token(Token::Ident(String::from("类型")));
+                None
+            }
+        }
+    }
+
+    /// `name`, `name: 类型`, `name = default` or `name: 类型 = default`.
+    /// `needs_default` is set once a parameter before this one had a default.
+    fn parse_param(&mut self, needs_default: bool) -> Option<Param> {
+        let ident = self.parse_ident()?;
+        let span = self.current_span;
+        self.check_binding(&ident, span);
+        let ty = self.parse_type_annotation(Token::Colon)?;
+
+        let default = if self.next_token_is(&Token::Assign) {
+            self.bump();
+            self.bump();
+            Some(self.parse_expr(Precedence::Lowest)?)
+        } else if needs_default {
+            self.errors.push(ParseError::HerUnexpectedToken {
+                rule: HerRule::MissingDefault {
+                    param: ident.0.clone(),
+                },
+                span,
+            });
+            return None;
+        } else {
+            None
+        };
+
+        Some(Param { ident, ty, default })
+    }
+
+    fn parse_func_params(&mut self) -> Option<Vec<Param>> {
+        let mut params = vec![];
+
+        if self.next_token_is(&Token::Rparen) {
+            self.bump();
+            return Some(params);
+        }
+
+        self.bump();
+
+        match self.parse_param(false) {
+            Some(param) => params.push(param),
+            None => return None,
+        };
+
+        while self.next_token_is(&Token::Comma) {
+            self.bump();
+            self.bump();
+
+            let needs_default = params.iter().any(|param| param.default.is_some());
+            match self.parse_param(needs_default) {
+                Some(param) => params.push(param),
+                None => return None,
+            };
+        }
+
+        if !self.expect_next_token(Token::Rparen) {
+            return None;
+        }
+
+        Some(params)
+    }
+
+    fn parse_call_expr(&mut self, func: Expr) -> Option<Expr> {
+        let start = self.current_span.start;
+        let args = self.parse_expr_list(Token::Rparen)?;
+
+        if let Expr::Ident(Ident(name)) = &func
+            && self.expand_macros
+            && let Some(mac) = self.macros.get(name)
+        {
+            let required = required_params(&mac.params);
+            if (required..=mac.params.len()).contains(&args.len()) {
+                self.expansions += 1;
+                return Some(mac.expand(args, self.expansions));
+            }
+
+            self.errors.push(ParseError::MacroArity {
+                name: name.clone(),
+                want: if args.len() < required {
+                    required
+                } else {
+                    mac.params.len()
+                },
+                got: args.len(),
+                span: Span {
+                    start,
+                    end: self.current_span.end,
+                },
+            });
+        }
+
+        Some(Expr::Call {
+            func: Box::new(func),
+            args,
+        })
+    }
+}
+
+/// A C-style `for` as `if (true) { init; while (cond) { body; step } }`,
+/// with `step` also run before each `continue`. With an `id`, a name `init`
+/// binds is renamed like a macro's, so it doesn't outlive the loop.
+pub(crate) fn desugar_c_for(
+    init: Option<&Stmt>,
+    cond: Option<&Expr>,
+    step: Option<&Stmt>,
+    body: &BlockStmt,
+    id: Option<usize>,
+) -> Expr {
+    let mut body = body.clone();
+    if let Some(step) = step {
+        step_before_continue(&mut body, step);
+        body.push(step.clone());
+    }
+    let cond = cond.cloned().unwrap_or(Expr::Literal(Literal::Bool(true)));
+    let mut expr = Expr::While {
+        cond: Box::new(cond),
+        consequence: body,
+    };
+    let Some(mut init) = init.cloned() else {
+        return expr;
+    };
+
+    if let (Some(id), Stmt::Let(Ident(name), ..)) = (id, &init) {
+        let mut rename = Rename::one(name, id);
+        rename.visit_stmt_mut(&mut init);
+        rename.visit_expr_mut(&mut expr);
+    }
+
+    Expr::If {
+        cond: Box::new(Expr::Literal(Literal::Bool(true))),
+        consequence: vec![init, Stmt::Expr(expr)],
+        alternative: None,
+    }
+}
+
+/// Puts `step` before each `continue` of a loop `body`, including those in
+/// `if`s and `match` arms, but not those of nested loops and functions.
+fn step_before_continue(body: &mut BlockStmt, step: &Stmt) {
+    let mut i = 0;
+    while i < body.len() {
+        match &mut body[i] {
+            Stmt::Continue => {
+                body.insert(i, step.clone());
+                i += 1;
+            }
+            Stmt::Expr(Expr::If {
+                consequence,
+                alternative,
+                ..
+            }) => {
+                step_before_continue(consequence, step);
+                if let Some(alternative) = alternative {
+                    step_before_continue(alternative, step);
+                }
+            }
+            Stmt::Expr(Expr::Match { arms, .. }) => {
+                for arm in arms {
+                    step_before_continue(&mut arm.body, step);
+                }
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ast::*;
+    use crate::lexer::Lexer;
+    use crate::parser::syntax::SyntaxConfig;
+    use crate::parser::{HerKeyWordPolicy, Parser, desugar_c_for};
+
+    fn check_parse_errors(parser: &mut Parser) {
+        let errors = parser.get_errors();
+
+        if errors.is_empty() {
+            return;
+        }
+
+        println!("\n");
+
+        println!("parser has {} errors", errors.len());
+
+        for err in errors {
+            println!("parse error: {:?}", err);
+        }
+
+        println!("\n");
+
+        panic!("failed");
+    }
+
+    #[test]
+    fn test_blank() {
+        let input = r#"
+1000;
+
+1000;
+
+
+1000;
+
+if (x) {
 
     x;
 
@@ -647,6 +1567,49 @@ if (x) {
         );
     }
 
+    #[test]
+    fn test_comment() {
+        let input = r#"
+// before
+let x = 1 + /* dropped */ // dropped
+  2; /* after
+  x */
+if (x) {
+  x;
+  // last
+}
+"#;
+
+        let mut parser = Parser::new(Lexer::new(input));
+        let program = parser.parse();
+
+        check_parse_errors(&mut parser);
+        assert_eq!(
+            vec![
+                Stmt::Comment(Comment::Line(String::from(" before"))),
+                Stmt::Let(
+                    Ident(String::from("x")),
+                    None,
+                    Expr::Infix(
+                        Infix::Plus,
+                        Box::new(Expr::Literal(Literal::Int(1))),
+                        Box::new(Expr::Literal(Literal::Int(2))),
+                    ),
+                ),
+                Stmt::Comment(Comment::Block(String::from(" after\n  x "))),
+                Stmt::Expr(Expr::If {
+                    cond: Box::new(Expr::Ident(Ident(String::from("x")))),
+                    consequence: vec![
+                        Stmt::Expr(Expr::Ident(Ident(String::from("x")))),
+                        Stmt::Comment(Comment::Line(String::from(" last"))),
+                    ],
+                    alternative: None,
+                }),
+            ],
+            program,
+        );
+    }
+
     #[test]
     fn test_let_stmt() {
         let input = r#"
@@ -661,10 +1624,19 @@ let foobar = 838383;
         check_parse_errors(&mut parser);
         assert_eq!(
             vec![
-                Stmt::Let(Ident(String::from("x")), Expr::Literal(Literal::Int(5))),
-                Stmt::Let(Ident(String::from("y")), Expr::Literal(Literal::Int(10))),
+                Stmt::Let(
+                    Ident(String::from("x")),
+                    None,
+                    Expr::Literal(Literal::Int(5))
+                ),
+                Stmt::Let(
+                    Ident(String::from("y")),
+                    None,
+                    Expr::Literal(Literal::Int(10))
+                ),
                 Stmt::Let(
                     Ident(String::from("foobar")),
+                    None,
                     Expr::Literal(Literal::Int(838383)),
                 ),
             ],
@@ -694,6 +1666,26 @@ return 993322;
         );
     }
 
+    #[test]
+    fn test_import_stmt() {
+        let input = r#"
+import "utils.her";
+引入 "姐妹/小作文.her"
+        "#;
+
+        let mut parser = Parser::new(Lexer::new(input));
+        let program = parser.parse();
+
+        check_parse_errors(&mut parser);
+        assert_eq!(
+            vec![
+                Stmt::Import(String::from("utils.her")),
+                Stmt::Import(String::from("姐妹/小作文.her")),
+            ],
+            program,
+        );
+    }
+
     #[test]
     fn test_ident_expr() {
         let input = "foobar;";
@@ -719,6 +1711,20 @@ return 993322;
         assert_eq!(vec![Stmt::Expr(Expr::Literal(Literal::Int(5)))], program,);
     }
 
+    #[test]
+    fn test_float_literal_expr() {
+        let input = "2.5;";
+
+        let mut parser = Parser::new(Lexer::new(input));
+        let program = parser.parse();
+
+        check_parse_errors(&mut parser);
+        assert_eq!(
+            vec![Stmt::Expr(Expr::Literal(Literal::Float(2.5)))],
+            program
+        );
+    }
+
     #[test]
     fn test_string_literal_expr() {
         let input = "\"herllo world\";";
@@ -777,6 +1783,39 @@ return 993322;
         );
     }
 
+    #[test]
+    fn test_coalesce() {
+        let tests = vec![
+            ("a ?? b || c", "a ?? (b || c)"),
+            ("a ?? b ?? c", "(a ?? b) ?? c"),
+        ];
+
+        for (input, expect) in tests {
+            let mut parser = Parser::new(Lexer::new(input));
+            let program = parser.parse();
+
+            check_parse_errors(&mut parser);
+            assert_eq!(Parser::new(Lexer::new(expect)).parse(), program);
+        }
+    }
+
+    #[test]
+    fn test_trailing_comma() {
+        let tests = vec![
+            ("[1, 2,]", "[1, 2]"),
+            ("f(1,)", "f(1)"),
+            ("{1: 2,}", "{1: 2}"),
+        ];
+
+        for (input, expect) in tests {
+            let mut parser = Parser::new(Lexer::new(input));
+            let program = parser.parse();
+
+            check_parse_errors(&mut parser);
+            assert_eq!(Parser::new(Lexer::new(expect)).parse(), program);
+        }
+    }
+
     #[test]
     fn test_hash_literal_expr() {
         let tests = vec![
@@ -883,6 +1922,32 @@ return 993322;
         );
     }
 
+    #[test]
+    fn test_ordinal_access_expr() {
+        let index = |left: Expr, n: i64| {
+            Expr::Index(Box::new(left), Box::new(Expr::Literal(Literal::Int(n))))
+        };
+        let list = || Expr::Ident(Ident(String::from("清单")));
+
+        let tests = vec![
+            ("清单 的 第1个", index(list(), 0)),
+            ("清单[第十二个]", index(list(), 11)),
+            ("清单 的 第２个 的 第三个", index(index(list(), 1), 2)),
+        ];
+
+        for (input, expect) in tests {
+            let mut parser = Parser::new(Lexer::new(input));
+            let program = parser.parse();
+
+            check_parse_errors(&mut parser);
+            assert_eq!(vec![Stmt::Expr(expect)], program);
+        }
+
+        let mut parser = Parser::new(Lexer::new("清单 的 x"));
+        parser.parse();
+        assert_eq!(1, parser.get_errors().len());
+    }
+
     #[test]
     fn test_prefix_expr() {
         let tests = vec![
@@ -978,100 +2043,413 @@ return 993322;
                 )),
             ),
             (
-                "5 != 5;",
-                Stmt::Expr(Expr::Infix(
-                    Infix::NotEqual,
-                    Box::new(Expr::Literal(Literal::Int(5))),
-                    Box::new(Expr::Literal(Literal::Int(5))),
-                )),
+                "5 != 5;",
+                Stmt::Expr(Expr::Infix(
+                    Infix::NotEqual,
+                    Box::new(Expr::Literal(Literal::Int(5))),
+                    Box::new(Expr::Literal(Literal::Int(5))),
+                )),
+            ),
+            (
+                "5 >= 5;",
+                Stmt::Expr(Expr::Infix(
+                    Infix::GreaterThanEqual,
+                    Box::new(Expr::Literal(Literal::Int(5))),
+                    Box::new(Expr::Literal(Literal::Int(5))),
+                )),
+            ),
+            (
+                "5 <= 5;",
+                Stmt::Expr(Expr::Infix(
+                    Infix::LessThanEqual,
+                    Box::new(Expr::Literal(Literal::Int(5))),
+                    Box::new(Expr::Literal(Literal::Int(5))),
+                )),
+            ),
+        ];
+
+        for (input, expect) in tests {
+            let mut parser = Parser::new(Lexer::new(input));
+            let program = parser.parse();
+
+            check_parse_errors(&mut parser);
+            assert_eq!(vec![expect], program);
+        }
+    }
+
+    #[test]
+    fn test_if_expr() {
+        let input = "if (x < y) { x }";
+
+        let mut parser = Parser::new(Lexer::new(input));
+        let program = parser.parse();
+
+        check_parse_errors(&mut parser);
+        assert_eq!(
+            vec![Stmt::Expr(Expr::If {
+                cond: Box::new(Expr::Infix(
+                    Infix::LessThan,
+                    Box::new(Expr::Ident(Ident(String::from("x")))),
+                    Box::new(Expr::Ident(Ident(String::from("y")))),
+                )),
+                consequence: vec![Stmt::Expr(Expr::Ident(Ident(String::from("x"))))],
+                alternative: None,
+            })],
+            program,
+        );
+    }
+
+    #[test]
+    fn test_if_else_expr() {
+        let input = "if (x < y) { x } else { y }";
+
+        let mut parser = Parser::new(Lexer::new(input));
+        let program = parser.parse();
+
+        check_parse_errors(&mut parser);
+        assert_eq!(
+            vec![Stmt::Expr(Expr::If {
+                cond: Box::new(Expr::Infix(
+                    Infix::LessThan,
+                    Box::new(Expr::Ident(Ident(String::from("x")))),
+                    Box::new(Expr::Ident(Ident(String::from("y")))),
+                )),
+                consequence: vec![Stmt::Expr(Expr::Ident(Ident(String::from("x"))))],
+                alternative: Some(vec![Stmt::Expr(Expr::Ident(Ident(String::from("y"))))]),
+            })],
+            program,
+        );
+    }
+
+    #[test]
+    fn test_else_if_expr() {
+        let input = "姐妹们觉得呢 (x) { 1 } 我接受不等于我同意 姐妹们觉得呢 (y) { 2 } else if (z) { 3 } else { 4 }";
+
+        let mut parser = Parser::new(Lexer::new(input));
+        let program = parser.parse();
+
+        check_parse_errors(&mut parser);
+        let ident = |name: &str| Box::new(Expr::Ident(Ident(String::from(name))));
+        let int = |n| vec![Stmt::Expr(Expr::Literal(Literal::Int(n)))];
+        assert_eq!(
+            vec![Stmt::Expr(Expr::If {
+                cond: ident("x"),
+                consequence: int(1),
+                alternative: Some(vec![Stmt::Expr(Expr::If {
+                    cond: ident("y"),
+                    consequence: int(2),
+                    alternative: Some(vec![Stmt::Expr(Expr::If {
+                        cond: ident("z"),
+                        consequence: int(3),
+                        alternative: Some(int(4)),
+                    })]),
+                })]),
+            })],
+            program,
+        );
+
+        for input in ["if (x) { 1 } else if { 2 }", "if (x) { 1 } else if (y) 2"] {
+            let mut parser = Parser::new(Lexer::new(input));
+            parser.parse();
+            assert!(!parser.get_errors().is_empty(), "{input}");
+        }
+    }
+
+    #[test]
+    fn test_do_while_expr() {
+        let input = "先干再说 { x } 你再说一遍 (x < y)";
+
+        let mut parser = Parser::new(Lexer::new(input));
+        let program = parser.parse();
+
+        check_parse_errors(&mut parser);
+        assert_eq!(
+            vec![Stmt::Expr(Expr::DoWhile {
+                consequence: vec![Stmt::Expr(Expr::Ident(Ident(String::from("x"))))],
+                cond: Box::new(Expr::Infix(
+                    Infix::LessThan,
+                    Box::new(Expr::Ident(Ident(String::from("x")))),
+                    Box::new(Expr::Ident(Ident(String::from("y")))),
+                )),
+            })],
+            program,
+        );
+
+        for input in ["do { x }", "do { x } while x", "do x while (x)"] {
+            let mut parser = Parser::new(Lexer::new(input));
+            parser.parse();
+            assert!(!parser.get_errors().is_empty(), "{input}");
+        }
+    }
+
+    #[test]
+    fn test_match_expr() {
+        let input = r#"分情况讨论 x { 1 => "one", -2.5 => { y; z }, "a" => 那咋了, _ => x, }"#;
+
+        let mut parser = Parser::new(Lexer::new(input));
+        let program = parser.parse();
+
+        check_parse_errors(&mut parser);
+        let ident = |name: &str| Expr::Ident(Ident(String::from(name)));
+        assert_eq!(
+            vec![Stmt::Expr(Expr::Match {
+                subject: Box::new(ident("x")),
+                arms: vec![
+                    MatchArm {
+                        pattern: Pattern::Literal(Literal::Int(1)),
+                        body: vec![Stmt::Expr(Expr::Literal(Literal::String(String::from(
+                            "one"
+                        ))))],
+                    },
+                    MatchArm {
+                        pattern: Pattern::Literal(Literal::Float(-2.5)),
+                        body: vec![Stmt::Expr(ident("y")), Stmt::Expr(ident("z"))],
+                    },
+                    MatchArm {
+                        pattern: Pattern::Literal(Literal::String(String::from("a"))),
+                        body: vec![Stmt::Expr(Expr::Literal(Literal::Bool(false)))],
+                    },
+                    MatchArm {
+                        pattern: Pattern::Wildcard,
+                        body: vec![Stmt::Expr(ident("x"))],
+                    },
+                ],
+            })],
+            program,
+        );
+
+        for input in [
+            "match x { 1 2 }",
+            "match x { 1 => 2 _ => 3 }",
+            "match x { [...] => 2 }",
+            "match x { [...a, b] => 2 }",
+            "match x { {1: a} => 2 }",
+            "match x { {a: +} => 2 }",
+            "match x",
+        ] {
+            let mut parser = Parser::new(Lexer::new(input));
+            parser.parse();
+            assert!(!parser.get_errors().is_empty(), "{input}");
+        }
+    }
+
+    #[test]
+    fn test_match_structural_patterns() {
+        let input =
+            r#"match x { [老大, 0, ...其余] => 1, {名字, 年龄: 18, 家: [城]} => 2, [] => 3 }"#;
+
+        let mut parser = Parser::new(Lexer::new(input));
+        let program = parser.parse();
+
+        check_parse_errors(&mut parser);
+        let binding = |name: &str| Pattern::Binding(Ident(String::from(name)));
+        let patterns: Vec<_> = match &program[..] {
+            [Stmt::Expr(Expr::Match { arms, .. })] => {
+                arms.iter().map(|arm| arm.pattern.clone()).collect()
+            }
+            _ => panic!("not a match: {program:?}"),
+        };
+        assert_eq!(
+            vec![
+                Pattern::Array(
+                    vec![binding("老大"), Pattern::Literal(Literal::Int(0))],
+                    Some(Ident(String::from("其余"))),
+                ),
+                Pattern::Hash(vec![
+                    (String::from("名字"), binding("名字")),
+                    (String::from("年龄"), Pattern::Literal(Literal::Int(18))),
+                    (
+                        String::from("家"),
+                        Pattern::Array(vec![binding("城")], None)
+                    ),
+                ]),
+                Pattern::Array(vec![], None),
+            ],
+            patterns,
+        );
+    }
+
+    #[test]
+    fn test_loop_expr() {
+        let input = "一直摆烂 { 下头 }";
+
+        let mut parser = Parser::new(Lexer::new(input));
+        let program = parser.parse();
+
+        check_parse_errors(&mut parser);
+        assert_eq!(
+            vec![Stmt::Expr(Expr::While {
+                cond: Box::new(Expr::Literal(Literal::Bool(true))),
+                consequence: vec![Stmt::Break],
+            })],
+            program,
+        );
+
+        for input in ["loop", "loop x", "loop (true) { x }"] {
+            let mut parser = Parser::new(Lexer::new(input));
+            parser.parse();
+            assert!(!parser.get_errors().is_empty(), "{input}");
+        }
+    }
+
+    #[test]
+    fn test_for_expr() {
+        let input = "for (i, x in xs) { x }";
+
+        let mut parser = Parser::new(Lexer::new(input));
+        let program = parser.parse();
+
+        check_parse_errors(&mut parser);
+        assert_eq!(
+            vec![Stmt::Expr(Expr::For {
+                vars: vec![Ident(String::from("i")), Ident(String::from("x"))],
+                iterable: Box::new(Expr::Ident(Ident(String::from("xs")))),
+                body: vec![Stmt::Expr(Expr::Ident(Ident(String::from("x"))))],
+            })],
+            program,
+        );
+
+        for input in [
+            "for (x) { x }",
+            "for (1 in xs) { 1 }",
+            "for (a, b, c in xs) { a }",
+        ] {
+            let mut parser = Parser::new(Lexer::new(input));
+            parser.parse();
+            assert!(!parser.get_errors().is_empty(), "{input}");
+        }
+    }
+
+    #[test]
+    fn test_c_for_expr() {
+        let tests = vec![
+            (
+                "for (let i = 0; i < 3; i++) { if (x) { continue; } puts(i) }",
+                "if (true) { let i = 0; while (i < 3) { if (x) { i++; continue; } puts(i); i++; } }",
             ),
             (
-                "5 >= 5;",
-                Stmt::Expr(Expr::Infix(
-                    Infix::GreaterThanEqual,
-                    Box::new(Expr::Literal(Literal::Int(5))),
-                    Box::new(Expr::Literal(Literal::Int(5))),
-                )),
+                "for (; ; ) { while (x) { continue; } }",
+                "while (true) { while (x) { continue; } }",
             ),
             (
-                "5 <= 5;",
-                Stmt::Expr(Expr::Infix(
-                    Infix::LessThanEqual,
-                    Box::new(Expr::Literal(Literal::Int(5))),
-                    Box::new(Expr::Literal(Literal::Int(5))),
-                )),
+                "for (puts(1); x; ) { 1 }",
+                "if (true) { puts(1); while (x) { 1 } }",
             ),
         ];
 
         for (input, expect) in tests {
-            let mut parser = Parser::new(Lexer::new(input));
+            let mut parser = Parser::new(Lexer::new(input)).without_macro_expansion();
             let program = parser.parse();
 
             check_parse_errors(&mut parser);
-            assert_eq!(vec![expect], program);
+            let [
+                Stmt::Expr(Expr::CFor {
+                    init,
+                    cond,
+                    step,
+                    body,
+                }),
+            ] = &program[..]
+            else {
+                panic!("{program:?}");
+            };
+            let desugared = desugar_c_for(
+                init.as_deref(),
+                cond.as_deref(),
+                step.as_deref(),
+                body,
+                None,
+            );
+            assert_eq!(
+                Parser::new(Lexer::new(expect)).parse(),
+                vec![Stmt::Expr(desugared)],
+                "{input}"
+            );
+        }
+
+        let program = Parser::new(Lexer::new("for (let i = 0; i < 3; i++) { i }")).parse();
+        let Stmt::Expr(Expr::If { consequence, .. }) = &program[0] else {
+            panic!("{program:?}");
+        };
+        assert!(matches!(&consequence[0], Stmt::Let(Ident(name), ..) if name == "i#1"));
+
+        for input in [
+            "for (let i = 0 i < 3; i++) { i }",
+            "for (let i = 0; i < 3; i++ { i }",
+        ] {
+            let mut parser = Parser::new(Lexer::new(input));
+            parser.parse();
+            assert!(!parser.get_errors().is_empty(), "{input}");
         }
     }
 
     #[test]
-    fn test_if_expr() {
-        let input = "if (x < y) { x }";
+    fn test_test_stmt() {
+        let input = "考一考 \"加法\" { assert(x) }";
 
         let mut parser = Parser::new(Lexer::new(input));
         let program = parser.parse();
 
         check_parse_errors(&mut parser);
         assert_eq!(
-            vec![Stmt::Expr(Expr::If {
-                cond: Box::new(Expr::Infix(
-                    Infix::LessThan,
-                    Box::new(Expr::Ident(Ident(String::from("x")))),
-                    Box::new(Expr::Ident(Ident(String::from("y")))),
-                )),
-                consequence: vec![Stmt::Expr(Expr::Ident(Ident(String::from("x"))))],
-                alternative: None,
-            })],
+            vec![Stmt::Test {
+                name: String::from("加法"),
+                body: vec![Stmt::Expr(Expr::Call {
+                    func: Box::new(Expr::Ident(Ident(String::from("assert")))),
+                    args: vec![Expr::Ident(Ident(String::from("x")))],
+                })],
+            }],
             program,
         );
+
+        for input in ["考一考 加法 { 1 }", "考一考 \"加法\" 1"] {
+            let mut parser = Parser::new(Lexer::new(input));
+            parser.parse();
+            assert!(!parser.get_errors().is_empty(), "{input}");
+        }
     }
 
     #[test]
-    fn test_if_else_expr() {
-        let input = "if (x < y) { x } else { y }";
+    fn test_func_expr() {
+        let input = "fn(x, y) { x + y; }";
 
         let mut parser = Parser::new(Lexer::new(input));
         let program = parser.parse();
 
         check_parse_errors(&mut parser);
         assert_eq!(
-            vec![Stmt::Expr(Expr::If {
-                cond: Box::new(Expr::Infix(
-                    Infix::LessThan,
+            vec![Stmt::Expr(Expr::Func {
+                params: vec![
+                    Param::from(Ident(String::from("x"))),
+                    Param::from(Ident(String::from("y"))),
+                ],
+                return_ty: None,
+                body: vec![Stmt::Expr(Expr::Infix(
+                    Infix::Plus,
                     Box::new(Expr::Ident(Ident(String::from("x")))),
                     Box::new(Expr::Ident(Ident(String::from("y")))),
-                )),
-                consequence: vec![Stmt::Expr(Expr::Ident(Ident(String::from("x"))))],
-                alternative: Some(vec![Stmt::Expr(Expr::Ident(Ident(String::from("y"))))]),
+                ))],
+                is_async: false,
             })],
             program,
         );
-    }
-
-    #[test]
-    fn test_func_expr() {
-        let input = "fn(x, y) { x + y; }";
 
-        let mut parser = Parser::new(Lexer::new(input));
+        let mut parser = Parser::new(Lexer::new("慢慢来 fn() { 等它一下 f(); }"));
         let program = parser.parse();
 
         check_parse_errors(&mut parser);
         assert_eq!(
             vec![Stmt::Expr(Expr::Func {
-                params: vec![Ident(String::from("x")), Ident(String::from("y"))],
-                body: vec![Stmt::Expr(Expr::Infix(
-                    Infix::Plus,
-                    Box::new(Expr::Ident(Ident(String::from("x")))),
-                    Box::new(Expr::Ident(Ident(String::from("y")))),
+                params: vec![],
+                return_ty: None,
+                body: vec![Stmt::Expr(Expr::Prefix(
+                    Prefix::Await,
+                    Box::new(Expr::Call {
+                        func: Box::new(Expr::Ident(Ident(String::from("f")))),
+                        args: vec![],
+                    }),
                 ))],
+                is_async: true,
             })],
             program,
         );
@@ -1081,13 +2459,36 @@ return 993322;
     fn test_func_params() {
         let tests = vec![
             ("fn() {};", vec![]),
-            ("fn(x) {};", vec![Ident(String::from("x"))]),
+            ("fn(x) {};", vec![Param::from(Ident(String::from("x")))]),
             (
                 "fn(x, y, z) {};",
                 vec![
-                    Ident(String::from("x")),
-                    Ident(String::from("y")),
-                    Ident(String::from("z")),
+                    Param::from(Ident(String::from("x"))),
+                    Param::from(Ident(String::from("y"))),
+                    Param::from(Ident(String::from("z"))),
+                ],
+            ),
+            (
+                "fn(x, y: 整数 = x + 1, z = [1, 2]) {};",
+                vec![
+                    Param::from(Ident(String::from("x"))),
+                    Param {
+                        ident: Ident(String::from("y")),
+                        ty: Some(TypeAnnotation(String::from("整数"))),
+                        default: Some(Expr::Infix(
+                            Infix::Plus,
+                            Box::new(Expr::Ident(Ident(String::from("x")))),
+                            Box::new(Expr::Literal(Literal::Int(1))),
+                        )),
+                    },
+                    Param {
+                        ident: Ident(String::from("z")),
+                        ty: None,
+                        default: Some(Expr::Literal(Literal::Array(vec![
+                            Expr::Literal(Literal::Int(1)),
+                            Expr::Literal(Literal::Int(2)),
+                        ]))),
+                    },
                 ],
             ),
         ];
@@ -1100,11 +2501,305 @@ return 993322;
             assert_eq!(
                 vec![Stmt::Expr(Expr::Func {
                     params: expect,
+                    return_ty: None,
                     body: vec![],
+                    is_async: false,
                 })],
                 program,
             );
         }
+
+        for input in ["fn(x = 1, y) {};", "fn(x = ) {};"] {
+            let mut parser = Parser::new(Lexer::new(input));
+            parser.parse();
+            assert!(!parser.get_errors().is_empty(), "{input}");
+        }
+    }
+
+    #[test]
+    fn test_her_key_words() {
+        let tests = vec![
+            ("let 女性 = 1;", (1, 5)),
+            ("fn(x, her) { x };", (1, 7)),
+            ("for (her in xs) { 1 }", (1, 6)),
+            ("let f = 想要你一个态度(\n  lady: 整数\n) { 1 };", (2, 3)),
+        ];
+
+        for (input, (line, column)) in tests {
+            let mut parser = Parser::new(Lexer::new(input));
+            parser.parse();
+            let errors = parser.get_errors();
+
+            assert_eq!(1, errors.len(), "{input}");
+            assert_eq!(
+                (line, column),
+                (errors[0].span().start.line, errors[0].span().start.column),
+                "{input}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_macro() {
+        let parse = |input: &str| {
+            let mut parser = Parser::new(Lexer::new(input));
+            let program = parser.parse();
+            check_parse_errors(&mut parser);
+            program
+        };
+        let define = "造个句式 sum(a, b) => { let t = a; t + b }\n造个句式 twice(x) => { x * x }\n";
+
+        let tests = vec![
+            ("twice(1 + 2)", "(1 + 2) * (1 + 2)"),
+            ("twice(twice(y))", "(y * y) * (y * y)"),
+            ("sum(t, 1)", "fn() { let t#1 = t; t#1 + 1 }()"),
+            (
+                "[sum(1, 2), sum(3, 4)]",
+                "[fn() { let t#1 = 1; t#1 + 2 }(), fn() { let t#2 = 3; t#2 + 4 }()]",
+            ),
+            ("x.twice(1)", "x.twice(1)"),
+        ];
+        for (input, expect) in tests {
+            let program = parse(&format!("{define}{input}"));
+            // `#` can't be written, so the expected tree is renamed afterwards.
+            let mut expected = parse(&expect.replace('#', "__"));
+            crate::ast::visit::VisitorMut::visit_block_mut(&mut Unmangle, &mut expected);
+            assert_eq!(expected, program[2..], "{input}");
+        }
+
+        let mut parser = Parser::new(Lexer::new("造个句式 twice(x) => { x * x }\ntwice(1, 2)"));
+        parser.parse();
+        assert_eq!(
+            "啊啊啊啊啊啊啊啊啊啊啊啊 MacroError: twice takes 1 arguments, got 2",
+            parser.get_errors()[0].to_string()
+        );
+
+        let mut parser = Parser::new(Lexer::new("造个句式 twice(x) => { x * x }\ntwice(1)"))
+            .without_macro_expansion();
+        let program = parser.parse();
+        assert_eq!(
+            Stmt::Expr(Expr::Call {
+                func: Box::new(Expr::Ident(Ident(String::from("twice")))),
+                args: vec![Expr::Literal(Literal::Int(1))],
+            }),
+            program[1]
+        );
+    }
+
+    #[test]
+    fn test_block_recovery() {
+        let mut parser = Parser::new(Lexer::new("let f = fn(x) { x + };\nlet y = 1;"));
+        let program = parser.parse();
+
+        assert_eq!(1, parser.get_errors().len());
+        assert_eq!(
+            Some(&Stmt::Let(
+                Ident(String::from("y")),
+                None,
+                Expr::Literal(Literal::Int(1))
+            )),
+            program.last()
+        );
+    }
+
+    #[test]
+    fn test_step_stmt() {
+        let parse = |input: &str| {
+            let mut parser = Parser::new(Lexer::new(input));
+            let program = parser.parse();
+            check_parse_errors(&mut parser);
+            program
+        };
+
+        let step = |name: &str, infix| {
+            let ident = Ident(String::from(name));
+            Stmt::Assign(
+                ident.clone(),
+                Expr::Infix(
+                    infix,
+                    Box::new(Expr::Ident(ident)),
+                    Box::new(Expr::Literal(Literal::Int(1))),
+                ),
+            )
+        };
+        assert_eq!(
+            vec![
+                step("i", Infix::Plus),
+                step("j", Infix::Minus),
+                step("k", Infix::Plus),
+                step("l", Infix::Minus),
+            ],
+            parse("i++; j--\n++k --l;")
+        );
+
+        let mut parser = Parser::new(Lexer::new("a[0]++;"));
+        parser.parse();
+        assert!(!parser.get_errors().is_empty());
+    }
+
+    #[test]
+    fn test_parse_on_threads() {
+        // Parsers share no state, so files can be checked side by side.
+        let inputs = (0..8)
+            .map(|i| format!("造个句式 twice(x) => {{ x + x }}\nlet a{i} = twice({i}); a{i} + 1"))
+            .collect::<Vec<_>>();
+        let parse = |input: &str| {
+            let mut parser = Parser::new(Lexer::new(input));
+            let program = parser.parse();
+            check_parse_errors(&mut parser);
+            program
+        };
+
+        let expected = inputs.iter().map(|input| parse(input)).collect::<Vec<_>>();
+        let parsed = std::thread::scope(|scope| {
+            let handles = inputs
+                .iter()
+                .map(|input| scope.spawn(|| parse(input)))
+                .collect::<Vec<_>>();
+            handles
+                .into_iter()
+                .map(|handle| handle.join().unwrap())
+                .collect::<Vec<_>>()
+        });
+        assert_eq!(expected, parsed);
+    }
+
+    struct Unmangle;
+
+    impl crate::ast::visit::VisitorMut for Unmangle {
+        fn visit_ident_mut(&mut self, ident: &mut Ident) {
+            ident.0 = ident.0.replace("__", "#");
+        }
+    }
+
+    #[test]
+    fn test_her_key_word_policy() {
+        let input = "let 女性 = 1;";
+
+        let mut parser =
+            Parser::new(Lexer::new(input)).with_her_key_word_policy(HerKeyWordPolicy::Warning);
+        let program = parser.parse();
+        assert!(parser.get_errors().is_empty());
+        assert_eq!(1, parser.get_warnings().len());
+        assert_eq!(1, program.len());
+
+        let mut parser =
+            Parser::new(Lexer::new(input)).with_her_key_word_policy(HerKeyWordPolicy::Off);
+        parser.parse();
+        assert!(parser.get_errors().is_empty());
+        assert!(parser.get_warnings().is_empty());
+    }
+
+    #[test]
+    fn test_syntax() {
+        let errors = |input: &str, syntax: SyntaxConfig| {
+            let mut parser = Parser::new(Lexer::new(input)).with_syntax(syntax);
+            parser.parse();
+            parser
+                .get_errors()
+                .iter()
+                .map(|err| (err.span().start.column, err.to_string()))
+                .collect::<Vec<_>>()
+        };
+
+        let formulas = [
+            "1 + 2 * 3",
+            "// 打折\nif (price > 100) { price * 0.8 } else { price };",
+            "match level { 1 => \"a\", _ => max([1, 2]) }",
+            "{\"a\": [1, 2]}[\"a\"][0]",
+        ];
+        for input in formulas {
+            assert_eq!(
+                Vec::<(usize, String)>::new(),
+                errors(input, SyntaxConfig::expression()),
+                "{input}"
+            );
+        }
+
+        let not_allowed = [
+            ("let x = 1;", 1),
+            ("1; 2", 4),
+            ("if (x) { 1; 2 }", 13),
+            ("x++", 1),
+            ("1 + fn(x) { x }(2)", 5),
+            ("[慢慢来 fn() { 1 }]", 2),
+            ("while (x) { y }", 1),
+            ("1 + 挨个盘点 (x 在 xs) { x }", 5),
+            ("引入 \"a.her\"", 1),
+            ("造个句式 m(x) => { x }", 1),
+            ("考一考 \"a\" { 1 }", 1),
+            ("反手举报 1", 1),
+        ];
+        for (input, column) in not_allowed {
+            assert_eq!(
+                vec![(
+                    column,
+                    String::from("啊啊啊啊啊啊啊啊啊啊啊啊 SyntaxError: 这里只许写算式")
+                )],
+                errors(input, SyntaxConfig::expression()),
+                "{input}"
+            );
+            assert!(errors(input, SyntaxConfig::default()).is_empty(), "{input}");
+        }
+
+        let syntax = SyntaxConfig {
+            bindings: false,
+            ..SyntaxConfig::default()
+        };
+        assert_eq!(1, errors("let x = 1; x", syntax).len());
+        assert!(errors("fn(x) { x }(1); 2", syntax).is_empty());
+    }
+
+    #[test]
+    fn test_max_steps() {
+        let input = "let x = [1, 2, 3]; ".repeat(100) + "{";
+
+        let mut parser = Parser::new(Lexer::new(&input)).with_max_steps(50);
+        parser.parse();
+        let errors = parser.get_errors();
+        assert_eq!(1, errors.len());
+        assert_eq!("代码太长，姐妹歇歇", errors[0].to_string());
+
+        let mut parser = Parser::new(Lexer::new("let x = 1;")).with_max_steps(50);
+        assert_eq!(1, parser.parse().len());
+        assert!(parser.get_errors().is_empty());
+    }
+
+    #[test]
+    fn test_type_annotations() {
+        let input = "宝宝你是一个 年龄: 整数 = 18;\n想要你一个态度(x: 整数, y) -> 整数 { x };";
+
+        let mut parser = Parser::new(Lexer::new(input));
+        let program = parser.parse();
+
+        check_parse_errors(&mut parser);
+        assert_eq!(
+            vec![
+                Stmt::Let(
+                    Ident(String::from("年龄")),
+                    Some(TypeAnnotation(String::from("整数"))),
+                    Expr::Literal(Literal::Int(18)),
+                ),
+                Stmt::Expr(Expr::Func {
+                    params: vec![
+                        Param {
+                            ident: Ident(String::from("x")),
+                            ty: Some(TypeAnnotation(String::from("整数"))),
+                            default: None,
+                        },
+                        Param::from(Ident(String::from("y"))),
+                    ],
+                    return_ty: Some(TypeAnnotation(String::from("整数"))),
+                    body: vec![Stmt::Expr(Expr::Ident(Ident(String::from("x"))))],
+                    is_async: false,
+                }),
+            ],
+            program,
+        );
+
+        let mut parser = Parser::new(Lexer::new("let x: = 1;"));
+        parser.parse();
+        assert_eq!(8, parser.get_errors()[0].span().start.column);
     }
 
     #[test]
@@ -1160,6 +2855,22 @@ return 993322;
                     )),
                 )),
             ),
+            (
+                "a 或者 b && c == d",
+                Stmt::Expr(Expr::Infix(
+                    Infix::Or,
+                    Box::new(Expr::Ident(Ident(String::from("a")))),
+                    Box::new(Expr::Infix(
+                        Infix::And,
+                        Box::new(Expr::Ident(Ident(String::from("b")))),
+                        Box::new(Expr::Infix(
+                            Infix::Equal,
+                            Box::new(Expr::Ident(Ident(String::from("c")))),
+                            Box::new(Expr::Ident(Ident(String::from("d")))),
+                        )),
+                    )),
+                )),
+            ),
             (
                 "a + b + c",
                 Stmt::Expr(Expr::Infix(
@@ -0,0 +1,51 @@
+/// What a program that uses syntax it isn't allowed gets back.
+pub const ONLY_FORMULAS: &str = "这里只许写算式";
+
+/// Which syntax a program may use. Everything is allowed by default;
+/// `expression` allows a single expression, for hosts that take formulas
+/// from their users, like a config value computed from other values.
+///
+/// Operators, literals, `if`, `match`, and calls are always allowed; what a
+/// call may do is up to `SandboxConfig`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SyntaxConfig {
+    /// `let`, `i++` and `i--`.
+    pub bindings: bool,
+    /// `while`, `do`, `loop` and `for`.
+    pub loops: bool,
+    /// Function literals, `async fn` and `造个句式`.
+    pub functions: bool,
+    /// `引入`.
+    pub imports: bool,
+    /// `考一考` blocks.
+    pub tests: bool,
+    /// More than one statement in the program or a block, and `return`,
+    /// `break` and `continue`.
+    pub statements: bool,
+}
+
+impl Default for SyntaxConfig {
+    fn default() -> Self {
+        SyntaxConfig {
+            bindings: true,
+            loops: true,
+            functions: true,
+            imports: true,
+            tests: true,
+            statements: true,
+        }
+    }
+}
+
+impl SyntaxConfig {
+    pub fn expression() -> Self {
+        SyntaxConfig {
+            bindings: false,
+            loops: false,
+            functions: false,
+            imports: false,
+            tests: false,
+            statements: false,
+        }
+    }
+}
@@ -0,0 +1,365 @@
+//! A small playground server for classrooms and self-hosting: `POST /eval`
+//! with code as the body runs it in a fresh evaluator and answers with JSON.
+//! Plain `std::net` with a fixed pool of `WORKERS` threads, so the crate
+//! needs no async runtime for it.
+//!
+//! Each run happens in a child process, the running binary started again
+//! with `serve --worker` (which has to call `run_worker`), so a run that
+//! aborts, say by overflowing its stack, takes down only that child. Runs
+//! are interrupted after `TIMEOUT`, and the child is killed if it doesn't
+//! stop soon after. They use `SandboxConfig::locked` and the default
+//! `MAX_DEPTH`, so a program can't read the server's files. Connections
+//! past what the pool and its queue hold get `503`.
+
+use crate::diagnostic::{self, Diagnostic, Lang};
+use crate::evaluator::builtins::{capture_output, new_builtins};
+use crate::evaluator::env::Env;
+use crate::evaluator::object::Object;
+use crate::evaluator::sandbox::SandboxConfig;
+use crate::evaluator::shared::Shared;
+use crate::evaluator::{Evaluator, MAX_DEPTH};
+use crate::json;
+use crate::lexer::Lexer;
+use crate::parser::Parser;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How long one run may take before it is interrupted.
+pub const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How much longer than `TIMEOUT` a worker process gets to stop on its own
+/// before it is killed.
+const KILL_AFTER: Duration = Duration::from_secs(1);
+
+/// The `error` of a run whose worker process died or had to be killed.
+pub const CRASHED: &str = "跑崩了，姐妹换个写法试试";
+
+/// Requests handled at once.
+const WORKERS: usize = 4;
+
+/// Connections waiting for a worker before new ones are turned away.
+const QUEUE: usize = 32;
+
+/// Stack of the thread a worker process runs in, enough even in a debug
+/// build for `MAX_DEPTH` calls or for parsing `MAX_BODY` bytes of `(`.
+/// Pages are only committed as the stack grows.
+const STACK_SIZE: usize = 128 * 1024 * 1024;
+
+/// Largest request body accepted, in bytes.
+const MAX_BODY: usize = 64 * 1024;
+
+const INDEX: &str = r#"<!doctype html>
+<meta charset="utf-8">
+<title>herlang playground</title>
+<textarea id="code" rows="16" cols="80">小作文("姐妹们好");</textarea>
+<p><button onclick="run()">运行</button></p>
+<pre id="out"></pre>
+<script>
+async function run() {
+  const res = await fetch("/eval", { method: "POST", body: code.value });
+  const { output, result, error, diagnostics } = await res.json();
+  out.textContent = output + (error ?? result ?? "") +
+    diagnostics.map((d) => "\n" + d.range.start.line + ":" + d.range.start.column + ": " + d.message).join("");
+}
+</script>
+"#;
+
+/// Runs `src` and describes the run as JSON: what it printed, the value it
+/// ended with, the error it stopped with, and diagnostics for code that
+/// doesn't parse. Errors and diagnostics are worded in `lang`; the run stops
+/// when `interrupt` is set.
+pub fn eval_json(src: &str, lang: Lang, interrupt: Arc<AtomicBool>) -> String {
+    let mut parser = Parser::new(Lexer::new(src));
+    let program = parser.parse();
+    let errors = parser.get_errors();
+    if !errors.is_empty() {
+        let diagnostics = errors.iter().map(Diagnostic::from).collect::<Vec<_>>();
+        return to_json("", None, None, &diagnostics, lang);
+    }
+
+    let env = Env::from(new_builtins());
+    let mut evaluator = Evaluator::new(Shared::new(env))
+        .with_interrupt(interrupt)
+        .with_sandbox(SandboxConfig::locked())
+        .with_lang(lang)
+        .with_max_depth(MAX_DEPTH);
+    let (result, output) = capture_output(|| evaluator.eval(&program));
+
+    match result {
+        Some(Object::Error(msg)) => to_json(&output, None, Some(msg), &[], lang),
+        result => to_json(&output, result.map(|o| o.to_string()), None, &[], lang),
+    }
+}
+
+fn to_json(
+    output: &str,
+    result: Option<String>,
+    error: Option<String>,
+    diagnostics: &[Diagnostic],
+    lang: Lang,
+) -> String {
+    let or_null = |s: Option<String>| s.map_or(String::from("null"), |s| json::string(&s));
+
+    format!(
+        "{{\"output\":{},\"result\":{},\"error\":{},\"diagnostics\":{}}}",
+        json::string(output),
+        or_null(result),
+        or_null(error),
+        diagnostic::to_json(diagnostics, lang)
+    )
+}
+
+/// The worker process's side of a run: reads code from stdin and writes
+/// `eval_json` of it to stdout, interrupting the run after `TIMEOUT`.
+pub fn run_worker(lang: Lang) -> io::Result<()> {
+    let mut src = String::new();
+    io::stdin().read_to_string(&mut src)?;
+
+    let interrupt = Arc::new(AtomicBool::new(false));
+    let timer = Arc::clone(&interrupt);
+    thread::spawn(move || {
+        thread::sleep(TIMEOUT);
+        timer.store(true, Ordering::Relaxed);
+    });
+
+    let json = thread::Builder::new()
+        .name(String::from("herlang-serve-run"))
+        .stack_size(STACK_SIZE)
+        .spawn(move || eval_json(&src, lang, interrupt))?
+        .join()
+        .map_err(|_| io::Error::other("the run panicked"))?;
+    io::stdout().write_all(json.as_bytes())
+}
+
+/// `eval_json` of `src` in a worker process, or a `CRASHED` error when the
+/// process dies or outlives `TIMEOUT` and `KILL_AFTER`.
+fn eval_in_worker(src: &str, lang: Lang) -> String {
+    let crashed = || to_json("", None, Some(String::from(CRASHED)), &[], lang);
+    let lang_arg = match lang {
+        Lang::En => "--lang=en",
+        Lang::Zh => "--lang=zh",
+    };
+    let child = std::env::current_exe().and_then(|exe| {
+        Command::new(exe)
+            .args(["serve", "--worker", lang_arg])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+    });
+    let Ok(mut child) = child else {
+        return crashed();
+    };
+
+    // Read on another thread, so a run that prints a lot can't block on a
+    // full pipe while this one waits for it to exit.
+    let mut stdout = child.stdout.take().unwrap();
+    let reader = thread::spawn(move || {
+        let mut json = String::new();
+        stdout.read_to_string(&mut json).map(|_| json)
+    });
+    if let Some(mut stdin) = child.stdin.take() {
+        let _ = stdin.write_all(src.as_bytes());
+    }
+
+    let deadline = Instant::now() + TIMEOUT + KILL_AFTER;
+    let status = loop {
+        match child.try_wait() {
+            Ok(Some(status)) => break Some(status),
+            Ok(None) if Instant::now() < deadline => thread::sleep(Duration::from_millis(10)),
+            _ => {
+                let _ = child.kill();
+                let _ = child.wait();
+                break None;
+            }
+        }
+    };
+
+    match (status, reader.join()) {
+        (Some(status), Ok(Ok(json))) if status.success() => json,
+        _ => crashed(),
+    }
+}
+
+struct Request {
+    method: String,
+    path: String,
+    body: String,
+}
+
+fn read_request(reader: &mut impl BufRead) -> io::Result<Request> {
+    let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, msg.to_string());
+
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    let mut parts = line.split_whitespace();
+    let (Some(method), Some(path)) = (parts.next(), parts.next()) else {
+        return Err(invalid("malformed request line"));
+    };
+    let (method, path) = (method.to_string(), path.to_string());
+
+    let mut length = 0;
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 || line.trim_end().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':')
+            && name.eq_ignore_ascii_case("content-length")
+        {
+            length = value
+                .trim()
+                .parse()
+                .map_err(|_| invalid("bad content-length"))?;
+        }
+    }
+
+    if length > MAX_BODY {
+        return Err(invalid("request body too large"));
+    }
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body)?;
+    let body = String::from_utf8(body).map_err(|_| invalid("body is not utf-8"))?;
+
+    Ok(Request { method, path, body })
+}
+
+/// Status, content type and body of the response to `request`, running
+/// code with `eval`.
+fn route(request: &Request, eval: impl Fn(&str) -> String) -> (&'static str, &'static str, String) {
+    match (request.method.as_str(), request.path.as_str()) {
+        ("POST", "/eval") => ("200 OK", "application/json", eval(&request.body)),
+        ("GET", "/") => ("200 OK", "text/html; charset=utf-8", String::from(INDEX)),
+        ("OPTIONS", _) => ("204 No Content", "text/plain", String::new()),
+        _ => ("404 Not Found", "text/plain", String::from("not found")),
+    }
+}
+
+fn handle(stream: TcpStream, lang: Lang) -> io::Result<()> {
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    stream.set_write_timeout(Some(TIMEOUT))?;
+    let mut reader = BufReader::new(&stream);
+    let (status, content_type, body) = match read_request(&mut reader) {
+        Ok(request) => route(&request, |src| eval_in_worker(src, lang)),
+        Err(err) => ("400 Bad Request", "text/plain", err.to_string()),
+    };
+
+    respond(&stream, status, content_type, &body)
+}
+
+fn respond(stream: &TcpStream, status: &str, content_type: &str, body: &str) -> io::Result<()> {
+    write!(
+        &*stream,
+        "HTTP/1.1 {status}\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\n\
+         Access-Control-Allow-Origin: *\r\nAccess-Control-Allow-Methods: GET, POST, OPTIONS\r\n\
+         Access-Control-Allow-Headers: Content-Type\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    )
+}
+
+fn work(connections: &Mutex<Receiver<TcpStream>>, lang: Lang) {
+    loop {
+        let Ok(stream) = connections.lock().unwrap().recv() else {
+            return;
+        };
+        let _ = handle(stream, lang);
+    }
+}
+
+/// Serves the playground on `addr` until the process is stopped, with
+/// errors in `lang`.
+pub fn serve(addr: impl ToSocketAddrs, lang: Lang) -> io::Result<()> {
+    let listener = TcpListener::bind(addr)?;
+    let (queue, connections) = mpsc::sync_channel(QUEUE);
+    let connections = Arc::new(Mutex::new(connections));
+
+    for i in 0..WORKERS {
+        let connections = Arc::clone(&connections);
+        thread::Builder::new()
+            .name(format!("herlang-serve-{i}"))
+            .spawn(move || work(&connections, lang))?;
+    }
+
+    for stream in listener.incoming() {
+        if let Err(TrySendError::Full(stream)) = queue.try_send(stream?) {
+            let _ = respond(
+                &stream,
+                "503 Service Unavailable",
+                "text/plain",
+                "busy, try again later",
+            );
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::evaluator::{INTERRUPTED, TOO_DEEP};
+    use crate::serve::*;
+
+    fn eval(src: &str) -> String {
+        eval_json(src, Lang::En, Arc::default())
+    }
+
+    #[test]
+    fn test_eval_json() {
+        assert_eq!(
+            r#"{"output":"1\n","result":"3","error":null,"diagnostics":[]}"#,
+            eval("puts(1); 1 + 2")
+        );
+        assert_eq!(
+            r#"{"output":"","result":null,"error":"没权限，想peach","diagnostics":[]}"#,
+            eval("quit(1);")
+        );
+        assert!(eval("引入 \"a.her\";").contains("没权限，想peach"));
+        assert!(eval("let = 1;").contains(r#""severity":"error""#));
+        // The page reads diagnostics the way `Diagnostic::to_json` writes them.
+        assert!(eval("let = 1;").contains(r#""range":{"start":"#));
+        assert!(INDEX.contains("d.range.start.line"));
+        assert!(eval_json("1 / 0", Lang::Zh, Arc::default()).contains(r#""error":"除数为零""#));
+
+        let interrupt = Arc::new(AtomicBool::new(true));
+        assert!(
+            eval_json("for (i in range(1000000000)) { i }", Lang::En, interrupt)
+                .contains(INTERRUPTED)
+        );
+    }
+
+    #[test]
+    fn test_eval_json_deep() {
+        let result = thread::Builder::new()
+            .stack_size(STACK_SIZE)
+            .spawn(|| eval("let f = fn() { f() }; f();"))
+            .unwrap()
+            .join()
+            .unwrap();
+
+        assert!(result.contains(TOO_DEEP), "{result}");
+    }
+
+    #[test]
+    fn test_route() {
+        let request = read_request(
+            &mut "POST /eval HTTP/1.1\r\nHost: x\r\ncontent-length: 8\r\n\r\nputs(1);".as_bytes(),
+        )
+        .unwrap();
+        assert_eq!(
+            ("POST", "/eval"),
+            (request.method.as_str(), request.path.as_str())
+        );
+        let (status, content_type, body) = route(&request, eval);
+        assert_eq!(("200 OK", "application/json"), (status, content_type));
+        assert!(body.contains(r#""output":"1\n""#));
+
+        let request = read_request(&mut "GET /nope HTTP/1.1\r\n\r\n".as_bytes()).unwrap();
+        assert_eq!("404 Not Found", route(&request, eval).0);
+    }
+}
@@ -0,0 +1,286 @@
+//! The AST as indented S-expressions, for reading what the parser made of a
+//! program. A node stays on one line when it fits, otherwise each child gets
+//! a line of its own.
+
+use crate::ast::*;
+use crate::lexer::unescape::escape_str;
+
+const WIDTH: usize = 80;
+
+enum Node {
+    Atom(String),
+    List(Vec<Node>),
+}
+
+fn atom(s: impl Into<String>) -> Node {
+    Node::Atom(s.into())
+}
+
+fn list(head: &str, rest: impl IntoIterator<Item = Node>) -> Node {
+    Node::List(std::iter::once(atom(head)).chain(rest).collect())
+}
+
+impl Node {
+    fn flat(&self) -> String {
+        match self {
+            Node::Atom(s) => s.clone(),
+            Node::List(items) => {
+                let items = items.iter().map(Node::flat).collect::<Vec<_>>();
+                format!("({})", items.join(" "))
+            }
+        }
+    }
+
+    fn render(&self, indent: usize, out: &mut String) {
+        let flat = self.flat();
+        let Node::List(items) = self else {
+            out.push_str(&flat);
+            return;
+        };
+        if indent + flat.chars().count() <= WIDTH {
+            out.push_str(&flat);
+            return;
+        }
+
+        // Leading atoms, like `let x`, stay next to the opening paren.
+        let head = items
+            .iter()
+            .take_while(|item| matches!(item, Node::Atom(_)))
+            .count()
+            .max(1);
+
+        out.push('(');
+        let head_str = items[..head].iter().map(Node::flat).collect::<Vec<_>>();
+        out.push_str(&head_str.join(" "));
+        for item in &items[head..] {
+            out.push('\n');
+            out.push_str(&" ".repeat(indent + 2));
+            item.render(indent + 2, out);
+        }
+        out.push(')');
+    }
+}
+
+fn block(head: &str, stmts: &BlockStmt) -> Node {
+    list(head, stmts.iter().filter_map(stmt))
+}
+
+fn stmt(stmt: &Stmt) -> Option<Node> {
+    let node = match stmt {
+        Stmt::Blank | Stmt::Comment(_) => return None,
+        Stmt::Break => list("break", []),
+        Stmt::Continue => list("continue", []),
+        Stmt::Let(Ident(name), ty, value) => {
+            let ty = ty.iter().map(|ty| atom(format!(": {}", ty.0)));
+            list(
+                "let",
+                [atom(name)].into_iter().chain(ty).chain([expr(value)]),
+            )
+        }
+        Stmt::Assign(Ident(name), value) => list("assign", [atom(name), expr(value)]),
+        Stmt::Return(value) => list("return", [expr(value)]),
+        Stmt::Import(path) => list("import", [atom(escape_str(path))]),
+        Stmt::Macro {
+            name: Ident(name),
+            params,
+            body,
+        } => list(
+            "macro",
+            [
+                atom(name),
+                list("params", params.iter().map(param)),
+                block("body", body),
+            ],
+        ),
+        Stmt::Test { name, body } => list("test", [atom(escape_str(name)), block("body", body)]),
+        Stmt::Expr(value) => expr(value),
+        Stmt::Mark(span) => list("mark", [atom(span.to_string())]),
+    };
+
+    Some(node)
+}
+
+fn expr(expr: &Expr) -> Node {
+    match expr {
+        Expr::Ident(Ident(name)) => atom(name),
+        Expr::Literal(literal) => self::literal(literal),
+        Expr::Prefix(prefix, right) => list(&prefix.to_string(), [self::expr(right)]),
+        Expr::Infix(infix, left, right) => {
+            list(&infix.to_string(), [self::expr(left), self::expr(right)])
+        }
+        Expr::Index(left, index) => list("index", [self::expr(left), self::expr(index)]),
+        Expr::If {
+            cond,
+            consequence,
+            alternative,
+        } => list(
+            "if",
+            [self::expr(cond), block("then", consequence)]
+                .into_iter()
+                .chain(alternative.iter().map(|stmts| block("else", stmts))),
+        ),
+        Expr::While { cond, consequence } => {
+            list("while", [self::expr(cond), block("do", consequence)])
+        }
+        Expr::DoWhile { consequence, cond } => {
+            list("do-while", [block("do", consequence), self::expr(cond)])
+        }
+        Expr::Match { subject, arms } => list(
+            "match",
+            std::iter::once(self::expr(subject)).chain(
+                arms.iter()
+                    .map(|arm| list("arm", [pattern(&arm.pattern), block("do", &arm.body)])),
+            ),
+        ),
+        Expr::For {
+            vars,
+            iterable,
+            body,
+        } => list(
+            "for",
+            [
+                list("vars", vars.iter().map(|var| atom(&var.0))),
+                self::expr(iterable),
+                block("do", body),
+            ],
+        ),
+        Expr::CFor {
+            init,
+            cond,
+            step,
+            body,
+        } => list(
+            "c-for",
+            [
+                init.as_deref().and_then(stmt).unwrap_or_else(|| atom("_")),
+                cond.as_deref().map_or_else(|| atom("_"), self::expr),
+                step.as_deref().and_then(stmt).unwrap_or_else(|| atom("_")),
+                block("do", body),
+            ],
+        ),
+        Expr::Func {
+            params,
+            return_ty,
+            body,
+            is_async,
+        } => {
+            let params = params.iter().map(param);
+            let return_ty = return_ty.iter().map(|ty| atom(format!("-> {}", ty.0)));
+
+            list(
+                if *is_async { "async fn" } else { "fn" },
+                [list("params", params)]
+                    .into_iter()
+                    .chain(return_ty)
+                    .chain([block("body", body)]),
+            )
+        }
+        Expr::Call { func, args } => list(
+            "call",
+            [self::expr(func)]
+                .into_iter()
+                .chain(args.iter().map(self::expr)),
+        ),
+    }
+}
+
+/// `x`, `x: 类型`, or `(= x default)` when it has a default.
+fn param(param: &Param) -> Node {
+    let name = match &param.ty {
+        Some(ty) => atom(format!("{}: {}", param.ident.0, ty.0)),
+        None => atom(&param.ident.0),
+    };
+    match &param.default {
+        Some(default) => list("=", [name, expr(default)]),
+        None => name,
+    }
+}
+
+fn pattern(pattern: &Pattern) -> Node {
+    match pattern {
+        Pattern::Wildcard => atom("_"),
+        Pattern::Literal(literal) => self::literal(literal),
+        Pattern::Binding(Ident(name)) => atom(name),
+        Pattern::Array(items, rest) => list(
+            "array",
+            items
+                .iter()
+                .map(self::pattern)
+                .chain(rest.iter().map(|Ident(name)| list("rest", [atom(name)]))),
+        ),
+        Pattern::Hash(entries) => list(
+            "hash",
+            entries
+                .iter()
+                .map(|(key, value)| Node::List(vec![atom(escape_str(key)), self::pattern(value)])),
+        ),
+    }
+}
+
+fn literal(literal: &Literal) -> Node {
+    match literal {
+        Literal::Int(value) => atom(value.to_string()),
+        Literal::Float(value) => atom(format_float(*value)),
+        Literal::Quantity(value, unit) => atom(format!("{value}{unit}")),
+        Literal::String(value) => atom(escape_str(value)),
+        Literal::Bool(value) => atom(value.to_string()),
+        Literal::Array(items) => list("array", items.iter().map(expr)),
+        Literal::Hash(pairs) => list(
+            "hash",
+            pairs
+                .iter()
+                .map(|(key, value)| Node::List(vec![expr(key), expr(value)])),
+        ),
+    }
+}
+
+/// `program` as a tree of S-expressions, one top-level statement per line.
+pub fn to_sexpr(program: &Program) -> String {
+    let mut result = String::new();
+
+    for node in program.iter().filter_map(stmt) {
+        node.render(0, &mut result);
+        result.push('\n');
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+    use crate::sexpr::*;
+
+    fn sexpr(input: &str) -> String {
+        to_sexpr(&Parser::new(Lexer::new(input)).parse())
+    }
+
+    #[test]
+    fn test_to_sexpr() {
+        let tests = vec![
+            ("1 + 2 * 3;", "(+ 1 (* 2 3))\n"),
+            ("let x: 整数 = -a[0];", "(let x : 整数 (- (index a 0)))\n"),
+            (
+                "if (x) { 1 } else { \"a\" }",
+                "(if x (then 1) (else \"a\"))\n",
+            ),
+            (
+                "{\"a\": [1, 5元]}; f(x, true);",
+                "(hash (\"a\" (array 1 5元)))\n(call f x true)\n",
+            ),
+            (
+                "let add_all = fn(a: 整数, b) -> 整数 { return a + b + 1000000000000; }; add_all(1, 2);",
+                "(let add_all\n  (fn (params a: 整数 b) -> 整数 (body (return (+ (+ a b) 1000000000000)))))\n(call add_all 1 2)\n",
+            ),
+            (
+                "while (i < 10) { let i = i + 1; if (i == 5) { break; } puts(i, \"some long text\"); }",
+                "(while\n  (< i 10)\n  (do\n    (let i (+ i 1))\n    (if (== i 5) (then (break)))\n    (call puts i \"some long text\")))\n",
+            ),
+        ];
+
+        for (input, expect) in tests {
+            assert_eq!(expect, sexpr(input), "{input}");
+        }
+    }
+}
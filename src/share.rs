@@ -0,0 +1,121 @@
+//! Share links: source is deflated and written as unpadded base64url, so the
+//! playground and `herlang share` produce the same strings.
+
+use miniz_oxide::deflate::compress_to_vec;
+use miniz_oxide::inflate::decompress_to_vec_with_limit;
+use std::fmt;
+
+const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+
+/// Largest source a share string may expand to.
+const MAX_SOURCE_LEN: usize = 1 << 20;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ShareError {
+    InvalidBase64,
+    InvalidDeflate,
+    InvalidUtf8,
+}
+
+impl fmt::Display for ShareError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ShareError::InvalidBase64 => write!(f, "share string is not base64url"),
+            ShareError::InvalidDeflate => write!(f, "share string is not deflate data"),
+            ShareError::InvalidUtf8 => write!(f, "shared source is not UTF-8"),
+        }
+    }
+}
+
+pub fn encode_share(src: &str) -> String {
+    base64url_encode(&compress_to_vec(src.as_bytes(), 9))
+}
+
+pub fn decode_share(s: &str) -> Result<String, ShareError> {
+    let data = base64url_decode(s.trim()).ok_or(ShareError::InvalidBase64)?;
+    let bytes = decompress_to_vec_with_limit(&data, MAX_SOURCE_LEN)
+        .map_err(|_| ShareError::InvalidDeflate)?;
+
+    String::from_utf8(bytes).map_err(|_| ShareError::InvalidUtf8)
+}
+
+fn base64url_encode(data: &[u8]) -> String {
+    let mut result = String::with_capacity(data.len().div_ceil(3) * 4);
+
+    for chunk in data.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, b)| n | (*b as u32) << (16 - 8 * i));
+
+        for i in 0..=chunk.len() {
+            result.push(ALPHABET[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+        }
+    }
+
+    result
+}
+
+fn base64url_decode(s: &str) -> Option<Vec<u8>> {
+    let s = s.trim_end_matches('=').as_bytes();
+    if s.len() % 4 == 1 {
+        return None;
+    }
+
+    let mut result = Vec::with_capacity(s.len() / 4 * 3 + 2);
+
+    for chunk in s.chunks(4) {
+        let mut n = 0u32;
+        for (i, c) in chunk.iter().enumerate() {
+            let value = ALPHABET.iter().position(|a| a == c)? as u32;
+            n |= value << (18 - 6 * i);
+        }
+
+        for i in 0..chunk.len() - 1 {
+            result.push((n >> (16 - 8 * i)) as u8);
+        }
+    }
+
+    Some(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::share::*;
+
+    #[test]
+    fn test_base64url() {
+        let tests = vec![
+            ("", ""),
+            ("f", "Zg"),
+            ("fo", "Zm8"),
+            ("foo", "Zm9v"),
+            ("foob", "Zm9vYg"),
+            ("\u{fb}\u{ff}", "w7vDvw"),
+        ];
+
+        for (input, expect) in tests {
+            assert_eq!(expect, base64url_encode(input.as_bytes()));
+            assert_eq!(Some(input.as_bytes().to_vec()), base64url_decode(expect));
+        }
+
+        assert_eq!(Some(vec![0xfb, 0xff]), base64url_decode("-_8="));
+        assert_eq!(None, base64url_decode("Z"));
+        assert_eq!(None, base64url_decode("Zm+v"));
+    }
+
+    #[test]
+    fn test_share() {
+        let src = "宝宝你是一个 姐妹 = 1;\n小作文(姐妹);";
+        let encoded = encode_share(src);
+
+        assert!(
+            encoded
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        );
+        assert_eq!(Ok(src.to_string()), decode_share(&encoded));
+        assert_eq!(Err(ShareError::InvalidBase64), decode_share("姐妹"));
+        assert_eq!(Err(ShareError::InvalidDeflate), decode_share("Zm9v"));
+    }
+}
@@ -0,0 +1,179 @@
+use std::fmt;
+
+/// A location in the source. `offset` counts chars, `line` and `column` start at 1.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Position {
+    pub offset: usize,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Default for Position {
+    fn default() -> Self {
+        Position {
+            offset: 0,
+            line: 1,
+            column: 1,
+        }
+    }
+}
+
+impl fmt::Display for Position {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
+
+/// Half-open range `[start, end)` of source.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Hash)]
+pub struct Span {
+    pub start: Position,
+    pub end: Position,
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.start)
+    }
+}
+
+/// Converts char offsets, which spans count in, to and from UTF-16 code
+/// units, which JS strings and so the playground's editor count in. The two
+/// differ after every char outside the Basic Multilingual Plane, like emoji.
+#[derive(Debug, Clone)]
+pub struct Utf16Index {
+    /// UTF-16 offset of every char, and of the end.
+    offsets: Vec<usize>,
+}
+
+impl Utf16Index {
+    pub fn new(input: &str) -> Self {
+        let mut offsets = vec![0];
+        for c in input.chars() {
+            offsets.push(offsets[offsets.len() - 1] + c.len_utf16());
+        }
+
+        Utf16Index { offsets }
+    }
+
+    /// UTF-16 offset of char `offset`; past the end is the end.
+    pub fn to_utf16(&self, offset: usize) -> usize {
+        self.offsets[offset.min(self.offsets.len() - 1)]
+    }
+
+    /// Char offset of UTF-16 `offset`. The middle of a surrogate pair is
+    /// the char it belongs to.
+    pub fn to_char(&self, offset: usize) -> usize {
+        match self.offsets.binary_search(&offset) {
+            Ok(i) => i,
+            Err(i) => i - 1,
+        }
+    }
+
+    /// `pos` with its offset and column in UTF-16 code units.
+    pub fn position(&self, pos: Position) -> Position {
+        let line_start = pos.offset.saturating_sub(pos.column - 1);
+        let offset = self.to_utf16(pos.offset);
+
+        Position {
+            offset,
+            line: pos.line,
+            column: offset - self.to_utf16(line_start) + 1,
+        }
+    }
+
+    pub fn span(&self, span: Span) -> Span {
+        Span {
+            start: self.position(span.start),
+            end: self.position(span.end),
+        }
+    }
+}
+
+/// Maps char offsets back to line/column pairs.
+#[derive(Debug, Clone)]
+pub struct LineIndex {
+    line_starts: Vec<usize>,
+}
+
+impl LineIndex {
+    pub fn new(input: &[char]) -> Self {
+        let mut line_starts = vec![0];
+
+        for (i, c) in input.iter().enumerate() {
+            if *c == '\n' {
+                line_starts.push(i + 1);
+            }
+        }
+
+        LineIndex { line_starts }
+    }
+
+    pub fn position(&self, offset: usize) -> Position {
+        let line = match self.line_starts.binary_search(&offset) {
+            Ok(line) => line,
+            Err(line) => line - 1,
+        };
+
+        Position {
+            offset,
+            line: line + 1,
+            column: offset - self.line_starts[line] + 1,
+        }
+    }
+
+    pub fn span(&self, start: usize, end: usize) -> Span {
+        Span {
+            start: self.position(start),
+            end: self.position(end),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lexer::Lexer;
+    use crate::span::*;
+    use crate::token::Token;
+
+    #[test]
+    fn test_utf16_index() {
+        let input = "let 😀 = 1;\n😀 + 𝒳";
+        let index = Utf16Index::new(input);
+        let chars = input.chars().collect::<Vec<_>>();
+        let utf16 = input.encode_utf16().collect::<Vec<_>>();
+
+        let mut lexer = Lexer::new(input);
+        let mut spans = vec![];
+        loop {
+            let (tok, span) = lexer.next_token_with_span();
+            if tok == Token::Eof {
+                break;
+            }
+            spans.push(span);
+        }
+
+        // Every token is the same text whichever way it is indexed.
+        for span in &spans {
+            let converted = index.span(*span);
+            let text = chars[span.start.offset..span.end.offset]
+                .iter()
+                .collect::<String>();
+            assert_eq!(
+                text,
+                String::from_utf16(&utf16[converted.start.offset..converted.end.offset]).unwrap()
+            );
+        }
+
+        let last = index.span(spans[spans.len() - 1]);
+        assert_eq!(
+            (2, 6, 8),
+            (last.start.line, last.start.column, last.end.column)
+        );
+
+        assert_eq!(4, index.to_char(5));
+        assert_eq!(4, index.to_char(4));
+        assert_eq!(chars.len(), index.to_char(utf16.len()));
+        assert_eq!(utf16.len(), index.to_utf16(chars.len() + 1));
+    }
+}
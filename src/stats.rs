@@ -0,0 +1,201 @@
+//! Numbers about scripts for `herlang stats`: how long they are, how many
+//! functions they define, how deep their blocks nest and which keyword
+//! spellings they reach for most.
+
+use crate::ast::visit::{Visitor, walk_block, walk_expr};
+use crate::ast::*;
+use crate::json;
+use crate::lexer::Lexer;
+use crate::token::{Token, TokenCategory};
+use std::collections::{HashMap, HashSet};
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Stats {
+    pub files: usize,
+    pub lines: usize,
+    /// Lines with anything but comments and whitespace on them.
+    pub code_lines: usize,
+    /// Lines with nothing but comments on them.
+    pub comment_lines: usize,
+    pub blank_lines: usize,
+    /// Function literals, named or not.
+    pub functions: usize,
+    /// Deepest nesting of blocks; top-level code is at 0.
+    pub max_depth: usize,
+    /// Keywords as they are spelled in the source, most used first.
+    pub keywords: Vec<(String, usize)>,
+}
+
+#[derive(Default)]
+struct Counter {
+    functions: usize,
+    depth: usize,
+    max_depth: usize,
+}
+
+impl Visitor for Counter {
+    fn visit_block(&mut self, block: &BlockStmt) {
+        self.depth += 1;
+        self.max_depth = self.max_depth.max(self.depth);
+        walk_block(self, block);
+        self.depth -= 1;
+    }
+
+    fn visit_expr(&mut self, expr: &Expr) {
+        if let Expr::Func { .. } = expr {
+            self.functions += 1;
+        }
+        walk_expr(self, expr);
+    }
+}
+
+impl Stats {
+    /// Stats for one file: `src` and the `program` parsed from it.
+    pub fn collect(src: &str, program: &Program) -> Self {
+        let chars = src.chars().collect::<Vec<_>>();
+        let mut lexer = Lexer::new(src);
+        let mut code = HashSet::new();
+        let mut comments = HashSet::new();
+        let mut keywords = HashMap::new();
+
+        loop {
+            let (tok, span) = lexer.next_token_with_span();
+            if tok == Token::Eof {
+                break;
+            }
+            let Some(category) = tok.category() else {
+                continue;
+            };
+
+            let lines = span.start.line..=span.end.line;
+            match category {
+                TokenCategory::Comment => comments.extend(lines),
+                TokenCategory::Keyword | TokenCategory::Boolean => {
+                    let spelling = chars[span.start.offset..span.end.offset]
+                        .iter()
+                        .collect::<String>();
+                    *keywords.entry(spelling).or_insert(0) += 1;
+                    code.extend(lines);
+                }
+                _ => code.extend(lines),
+            }
+        }
+
+        let mut counter = Counter::default();
+        walk_block(&mut counter, program);
+
+        Stats {
+            files: 1,
+            lines: src.lines().count(),
+            code_lines: code.len(),
+            comment_lines: comments.difference(&code).count(),
+            blank_lines: src.lines().filter(|line| line.trim().is_empty()).count(),
+            functions: counter.functions,
+            max_depth: counter.max_depth,
+            keywords: ranked(keywords),
+        }
+    }
+
+    /// Adds up the stats of another file.
+    pub fn add(&mut self, other: Stats) {
+        self.files += other.files;
+        self.lines += other.lines;
+        self.code_lines += other.code_lines;
+        self.comment_lines += other.comment_lines;
+        self.blank_lines += other.blank_lines;
+        self.functions += other.functions;
+        self.max_depth = self.max_depth.max(other.max_depth);
+
+        let mut keywords = self.keywords.drain(..).collect::<HashMap<_, _>>();
+        for (spelling, count) in other.keywords {
+            *keywords.entry(spelling).or_insert(0) += count;
+        }
+        self.keywords = ranked(keywords);
+    }
+
+    pub fn to_json(&self) -> String {
+        format!(
+            "{{\"files\":{},\"lines\":{},\"code_lines\":{},\"comment_lines\":{},\"blank_lines\":{},\"functions\":{},\"max_depth\":{},\"keywords\":{}}}",
+            self.files,
+            self.lines,
+            self.code_lines,
+            self.comment_lines,
+            self.blank_lines,
+            self.functions,
+            self.max_depth,
+            json::array(self.keywords.iter().map(|(spelling, count)| {
+                format!(
+                    "{{\"keyword\":{},\"count\":{}}}",
+                    json::string(spelling),
+                    count
+                )
+            }))
+        )
+    }
+}
+
+/// Most used first, ties in spelling order.
+fn ranked(counts: HashMap<String, usize>) -> Vec<(String, usize)> {
+    let mut ranked = counts.into_iter().collect::<Vec<_>>();
+    ranked.sort_by(|(a, m), (b, n)| n.cmp(m).then_with(|| a.cmp(b)));
+    ranked
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+    use crate::stats::*;
+
+    fn collect(input: &str) -> Stats {
+        Stats::collect(input, &Parser::new(Lexer::new(input)).parse())
+    }
+
+    #[test]
+    fn test_collect() {
+        let stats = collect(
+            r#"// 算一算
+let 加 = 想要你一个态度(a, b) { a + b };
+
+let f = fn(n) {
+    if (n > 0) { 姐妹们觉得呢 (true) { 小作文(n) } } // 吐槽
+};
+let g = 想要你一个态度() { fn() { 1 } };
+"#,
+        );
+
+        assert_eq!(1, stats.files);
+        assert_eq!(7, stats.lines);
+        assert_eq!(5, stats.code_lines);
+        assert_eq!(1, stats.comment_lines);
+        assert_eq!(1, stats.blank_lines);
+        assert_eq!(4, stats.functions);
+        assert_eq!(3, stats.max_depth);
+        assert_eq!(
+            vec![
+                ("let", 3),
+                ("fn", 2),
+                ("想要你一个态度", 2),
+                ("if", 1),
+                ("true", 1),
+                ("姐妹们觉得呢", 1),
+            ],
+            stats
+                .keywords
+                .iter()
+                .map(|(spelling, count)| (spelling.as_str(), *count))
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_add() {
+        let mut stats = collect("let a = 1;\n");
+        stats.add(collect("let f = fn() { if (true) { 1 } };\n"));
+
+        assert_eq!(
+            r#"{"files":2,"lines":2,"code_lines":2,"comment_lines":0,"blank_lines":0,"functions":1,"max_depth":2,"keywords":[{"keyword":"let","count":2},{"keyword":"fn","count":1},{"keyword":"if","count":1},{"keyword":"true","count":1}]}"#,
+            stats.to_json()
+        );
+    }
+}
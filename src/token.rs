@@ -1,20 +1,42 @@
+use crate::ast::format_float;
+use crate::lexer::KEYWORDS;
+use crate::lexer::unescape::escape_str;
+use std::fmt;
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum Token {
     Illegal,
     Blank,
     Eof,
+    /// `// text` or `吐槽 text`, holding what follows the marker.
+    Comment(String),
+    /// `/* text */`, holding what is between the markers.
+    BlockComment(String),
+    /// The `#她版本 n` header.
+    Version(u32),
 
     // Identifiers + literals
     Ident(String),
     Int(i64),
+    /// `3.14`: digits on both sides of the point.
+    Float(f64),
     String(String),
     Bool(bool),
+    /// `第N个`, counting from 1.
+    Ordinal(i64),
+    /// A number with one of `UNITS` right after it, like `5元`.
+    Quantity(i64, String),
 
     // Statements
     Assign,
     If,
     Else,
     While,
+    Do,
+    Loop,
+    Match,
+    For,
+    In,
     Break,
     Continue,
 
@@ -24,6 +46,10 @@ pub enum Token {
     Bang,
     Asterisk,
     Slash,
+    /// `++`, only as a statement: `i++` or `++i`.
+    Increment,
+    /// `--`, only as a statement: `i--` or `--i`.
+    Decrement,
 
     Equal,
     NotEqual,
@@ -31,10 +57,16 @@ pub enum Token {
     LessThanEqual,
     GreaterThan,
     GreaterThanEqual,
+    And,
+    Or,
+    Coalesce,
 
     // Delimiters
     Comma,
     Colon,
+    Arrow,
+    /// `=>`, between a macro's parameters and its template.
+    FatArrow,
     Semicolon,
     Lparen,
     Rparen,
@@ -43,9 +75,194 @@ pub enum Token {
     Lbracket,
     Rbracket,
     Dot,
+    /// `的`, as in `清单 的 第1个`.
+    Of,
 
     // Reseved keywords
     Func,
     Let,
     Return,
+    Import,
+    Async,
+    Await,
+    Macro,
+    Test,
+}
+
+/// Coarse classes of tokens, used for syntax highlighting.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenCategory {
+    Keyword,
+    Identifier,
+    Number,
+    String,
+    Boolean,
+    Operator,
+    Punctuation,
+    Comment,
+    Illegal,
+}
+
+impl TokenCategory {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TokenCategory::Keyword => "keyword",
+            TokenCategory::Identifier => "identifier",
+            TokenCategory::Number => "number",
+            TokenCategory::String => "string",
+            TokenCategory::Boolean => "boolean",
+            TokenCategory::Operator => "operator",
+            TokenCategory::Punctuation => "punctuation",
+            TokenCategory::Comment => "comment",
+            TokenCategory::Illegal => "illegal",
+        }
+    }
+}
+
+impl Token {
+    /// `None` for tokens that take up no visible source (blank lines, EOF).
+    pub fn category(&self) -> Option<TokenCategory> {
+        let category = match self {
+            Token::Blank | Token::Eof => return None,
+            Token::Illegal => TokenCategory::Illegal,
+            Token::Comment(_) | Token::BlockComment(_) | Token::Version(_) => {
+                TokenCategory::Comment
+            }
+            Token::Ident(_) => TokenCategory::Identifier,
+            Token::Int(_) | Token::Float(_) | Token::Ordinal(_) | Token::Quantity(..) => {
+                TokenCategory::Number
+            }
+            Token::String(_) => TokenCategory::String,
+            Token::Bool(_) => TokenCategory::Boolean,
+            Token::If
+            | Token::Else
+            | Token::While
+            | Token::Do
+            | Token::Loop
+            | Token::Match
+            | Token::For
+            | Token::In
+            | Token::Break
+            | Token::Continue
+            | Token::Func
+            | Token::Let
+            | Token::Return
+            | Token::Import
+            | Token::Async
+            | Token::Await
+            | Token::Macro
+            | Token::Test
+            | Token::Of => TokenCategory::Keyword,
+            Token::Assign
+            | Token::Plus
+            | Token::Minus
+            | Token::Bang
+            | Token::Asterisk
+            | Token::Slash
+            | Token::Increment
+            | Token::Decrement
+            | Token::Equal
+            | Token::NotEqual
+            | Token::LessThan
+            | Token::LessThanEqual
+            | Token::GreaterThan
+            | Token::GreaterThanEqual
+            | Token::And
+            | Token::Or
+            | Token::Coalesce => TokenCategory::Operator,
+            Token::Comma
+            | Token::Colon
+            | Token::Arrow
+            | Token::FatArrow
+            | Token::Semicolon
+            | Token::Lparen
+            | Token::Rparen
+            | Token::Lbrace
+            | Token::Rbrace
+            | Token::Lbracket
+            | Token::Rbracket
+            | Token::Dot => TokenCategory::Punctuation,
+        };
+
+        Some(category)
+    }
+}
+
+/// The token as it is written in source, for messages. Keywords are shown
+/// in their aba-aba spelling.
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let text = match self {
+            Token::Illegal => "illegal character",
+            Token::Blank => "blank line",
+            Token::Eof => "end of input",
+            Token::Comment(text) => return write!(f, "//{text}"),
+            Token::BlockComment(text) => return write!(f, "/*{text}*/"),
+            Token::Version(version) => return write!(f, "#她版本 {version}"),
+            Token::Ident(name) => name,
+            Token::Int(n) => return write!(f, "{n}"),
+            Token::Float(n) => return write!(f, "{}", format_float(*n)),
+            Token::String(s) => return write!(f, "{}", escape_str(s)),
+            Token::Ordinal(n) => return write!(f, "第{n}个"),
+            Token::Quantity(n, unit) => return write!(f, "{n}{unit}"),
+            Token::Assign => "=",
+            Token::Plus => "+",
+            Token::Minus => "-",
+            Token::Bang => "!",
+            Token::Asterisk => "*",
+            Token::Slash => "/",
+            Token::Increment => "++",
+            Token::Decrement => "--",
+            Token::Equal => "==",
+            Token::NotEqual => "!=",
+            Token::LessThan => "<",
+            Token::LessThanEqual => "<=",
+            Token::GreaterThan => ">",
+            Token::GreaterThanEqual => ">=",
+            Token::And => "&&",
+            Token::Or => "||",
+            Token::Coalesce => "??",
+            Token::Comma => ",",
+            Token::Colon => ":",
+            Token::Arrow => "->",
+            Token::FatArrow => "=>",
+            Token::Semicolon => ";",
+            Token::Lparen => "(",
+            Token::Rparen => ")",
+            Token::Lbrace => "{",
+            Token::Rbrace => "}",
+            Token::Lbracket => "[",
+            Token::Rbracket => "]",
+            Token::Dot => ".",
+            Token::Bool(_)
+            | Token::If
+            | Token::Else
+            | Token::While
+            | Token::Do
+            | Token::Loop
+            | Token::Match
+            | Token::For
+            | Token::In
+            | Token::Break
+            | Token::Continue
+            | Token::Of
+            | Token::Func
+            | Token::Let
+            | Token::Return
+            | Token::Import
+            | Token::Async
+            | Token::Await
+            | Token::Macro
+            | Token::Test => {
+                let mut spellings = KEYWORDS.iter().filter(|(_, tok)| tok == self);
+                spellings
+                    .clone()
+                    .find(|(spelling, _)| !spelling.is_ascii())
+                    .or_else(|| spellings.next())
+                    .map_or("", |(spelling, _)| spelling)
+            }
+        };
+
+        write!(f, "{text}")
+    }
 }
@@ -0,0 +1,643 @@
+//! A conservative type pass run before evaluation. It only reports operations
+//! that are certain to fail at runtime once reached; anything it cannot be
+//! sure about is `Unknown` and left alone.
+//!
+//! Diagnostics point at the statement they come from, using the marks of a
+//! program parsed `with_positions`.
+
+use crate::ast::*;
+use crate::diagnostic::{Diagnostic, DiagnosticCode};
+use crate::evaluator::builtins::{method, new_builtins, new_easter_eggs};
+use crate::evaluator::object::Object;
+use crate::lexer::Lexer;
+use crate::parser::{Parser, desugar_c_for};
+use crate::span::Span;
+use std::collections::HashMap;
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Type {
+    Int,
+    Float,
+    String,
+    Bool,
+    Array,
+    Hash,
+    Null,
+    /// A function taking from the first to the second number of arguments,
+    /// `None` for variadic builtins.
+    Func(Option<(usize, usize)>),
+    Unknown,
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Type::Int => write!(f, "int"),
+            Type::Float => write!(f, "float"),
+            Type::String => write!(f, "string"),
+            Type::Bool => write!(f, "bool"),
+            Type::Array => write!(f, "array"),
+            Type::Hash => write!(f, "hash"),
+            Type::Null => write!(f, "null"),
+            Type::Func(_) => write!(f, "function"),
+            Type::Unknown => write!(f, "unknown"),
+        }
+    }
+}
+
+impl Type {
+    fn of(obj: &Object) -> Self {
+        match obj {
+            Object::Int(_) => Type::Int,
+            Object::Float(_) => Type::Float,
+            Object::String(_) => Type::String,
+            Object::Bool(_) => Type::Bool,
+            Object::Array(_) => Type::Array,
+            Object::Hash(_) => Type::Hash,
+            Object::Null => Type::Null,
+            Object::Func(params, ..) => Type::Func(Some((required_params(params), params.len()))),
+            Object::Builtin(n, _) => Type::Func(usize::try_from(*n).ok().map(|n| (n, n))),
+            Object::EnvBuiltin(builtin) => Type::Func(Some((builtin.arity(), builtin.arity()))),
+            Object::Async(func) => Type::of(func),
+            _ => Type::Unknown,
+        }
+    }
+
+    fn join(self, other: Type) -> Type {
+        if self == other { self } else { Type::Unknown }
+    }
+}
+
+type Scope = HashMap<String, Type>;
+
+struct Checker {
+    /// One scope per function being checked. Names from enclosing functions
+    /// may be rebound before the call happens, so only the innermost is used.
+    scopes: Vec<Scope>,
+    span: Span,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Checker {
+    fn scope(&mut self) -> &mut Scope {
+        self.scopes.last_mut().expect("scope stack is never empty")
+    }
+
+    fn error(&mut self, code: DiagnosticCode) {
+        self.diagnostics.push(Diagnostic::error(code, self.span));
+    }
+
+    fn warning(&mut self, code: DiagnosticCode) {
+        self.diagnostics.push(Diagnostic::warning(code, self.span));
+    }
+
+    fn check_block(&mut self, stmts: &BlockStmt) -> Type {
+        let mut result = Type::Null;
+
+        for stmt in stmts {
+            match stmt {
+                Stmt::Mark(span) => self.span = *span,
+                Stmt::Let(Ident(name), _, expr) => {
+                    let value = self.check_expr(expr);
+                    self.scope().insert(name.clone(), value);
+                    result = Type::Null;
+                }
+                Stmt::Assign(Ident(name), expr) => {
+                    let value = self.check_expr(expr);
+                    // A name from an enclosing function is out of sight.
+                    if let Some(slot) = self.scope().get_mut(name) {
+                        *slot = value;
+                    }
+                    result = Type::Null;
+                }
+                Stmt::Return(expr) => {
+                    self.check_expr(expr);
+                    result = Type::Unknown;
+                }
+                Stmt::Expr(expr) => result = self.check_expr(expr),
+                Stmt::Import(_) => {
+                    // The module may bind anything.
+                    for value in self.scope().values_mut() {
+                        *value = Type::Unknown;
+                    }
+                    result = Type::Null;
+                }
+                Stmt::Test { body, .. } => {
+                    let span = self.span;
+                    self.scopes.push(HashMap::new());
+                    self.check_block(body);
+                    self.scopes.pop();
+                    self.span = span;
+                }
+                Stmt::Blank
+                | Stmt::Comment(_)
+                | Stmt::Macro { .. }
+                | Stmt::Break
+                | Stmt::Continue => {}
+            }
+        }
+
+        result
+    }
+
+    fn check_expr(&mut self, expr: &Expr) -> Type {
+        match expr {
+            Expr::Ident(Ident(name)) => self.scope().get(name).copied().unwrap_or(Type::Unknown),
+            Expr::Literal(literal) => self.check_literal(literal),
+            Expr::Prefix(prefix, right) => {
+                let right = self.check_expr(right);
+                self.check_prefix(prefix, right)
+            }
+            Expr::Infix(infix, left, right) => {
+                let left = self.check_expr(left);
+                let right = self.check_expr(right);
+                self.check_infix(infix, left, right)
+            }
+            Expr::Index(left, index) => {
+                let left = self.check_expr(left);
+                let index = self.check_expr(index);
+                self.check_index(left, index)
+            }
+            Expr::If {
+                cond,
+                consequence,
+                alternative,
+            } => {
+                self.check_expr(cond);
+                self.check_if(consequence, alternative.as_ref());
+                Type::Unknown
+            }
+            Expr::Match { subject, arms } => {
+                let subject = self.check_expr(subject);
+                self.check_exhaustive(subject, arms);
+                for arm in arms {
+                    let names = arm.pattern.bindings();
+                    let names: Vec<_> = names.into_iter().map(|Ident(name)| name.clone()).collect();
+                    self.forget(&names);
+                    self.check_if(&arm.body, None);
+                }
+                Type::Unknown
+            }
+            Expr::While { cond, consequence } | Expr::DoWhile { consequence, cond } => {
+                let mut names = vec![];
+                bound_names(consequence, &mut names);
+                self.check_loop(&names, Some(cond), consequence);
+                Type::Unknown
+            }
+            Expr::For {
+                vars,
+                iterable,
+                body,
+            } => {
+                self.check_expr(iterable);
+
+                let mut names = vars.iter().map(|Ident(name)| name.clone()).collect();
+                bound_names(body, &mut names);
+                self.check_loop(&names, None, body);
+                Type::Unknown
+            }
+            Expr::CFor {
+                init,
+                cond,
+                step,
+                body,
+            } => self.check_expr(&desugar_c_for(
+                init.as_deref(),
+                cond.as_deref(),
+                step.as_deref(),
+                body,
+                None,
+            )),
+            Expr::Func { params, body, .. } => {
+                let span = self.span;
+
+                // Defaults see the parameters before them.
+                self.scopes.push(HashMap::new());
+                for param in params {
+                    if let Some(default) = &param.default {
+                        self.check_expr(default);
+                    }
+                    self.scope().insert(param.ident.0.clone(), Type::Unknown);
+                }
+                self.check_block(body);
+                self.scopes.pop();
+                self.span = span;
+
+                Type::Func(Some((required_params(params), params.len())))
+            }
+            Expr::Call { func, args } => {
+                let func = match func.as_dot_access() {
+                    Some((receiver, name)) => self.check_method(receiver, name),
+                    None => self.check_expr(func),
+                };
+                for arg in args {
+                    self.check_expr(arg);
+                }
+                self.check_call(func, args.len())
+            }
+        }
+    }
+
+    fn check_literal(&mut self, literal: &Literal) -> Type {
+        match literal {
+            Literal::Int(_) => Type::Int,
+            Literal::Float(_) => Type::Float,
+            // Mixing units is only known to fail once both are in hand.
+            Literal::Quantity(..) => Type::Unknown,
+            Literal::String(_) => Type::String,
+            Literal::Bool(_) => Type::Bool,
+            Literal::Array(items) => {
+                for item in items {
+                    self.check_expr(item);
+                }
+                Type::Array
+            }
+            Literal::Hash(pairs) => {
+                for (key, value) in pairs {
+                    let key = self.check_expr(key);
+                    self.check_hash_key(key);
+                    self.check_expr(value);
+                }
+                Type::Hash
+            }
+        }
+    }
+
+    /// Warns about a `match` on a bool that leaves out `true` or `false`.
+    /// Other types have too many values to list, so they need a `_` or a
+    /// name anyway.
+    fn check_exhaustive(&mut self, subject: Type, arms: &[MatchArm]) {
+        if subject != Type::Bool
+            || arms
+                .iter()
+                .any(|arm| matches!(arm.pattern, Pattern::Wildcard | Pattern::Binding(_)))
+        {
+            return;
+        }
+
+        for value in [true, false] {
+            let covered = arms
+                .iter()
+                .any(|arm| arm.pattern == Pattern::Literal(Literal::Bool(value)));
+            if !covered {
+                self.warning(DiagnosticCode::NonExhaustiveMatch {
+                    missing: format!("`{value}`"),
+                });
+            }
+        }
+    }
+
+    /// Checks a loop whose body binds `names`. Those may differ from one
+    /// iteration to the next, and the body may not run in full, or at all,
+    /// so they are unknown inside the loop and after it.
+    fn check_loop(&mut self, names: &[String], cond: Option<&Expr>, body: &BlockStmt) {
+        self.forget(names);
+        if let Some(cond) = cond {
+            self.check_expr(cond);
+        }
+        self.check_block(body);
+        self.forget(names);
+    }
+
+    fn forget(&mut self, names: &[String]) {
+        for name in names {
+            self.scope().insert(name.clone(), Type::Unknown);
+        }
+    }
+
+    fn check_if(&mut self, consequence: &BlockStmt, alternative: Option<&BlockStmt>) {
+        // Blocks share the enclosing scope, so a `let` in one branch leaks out.
+        let before = self.scope().clone();
+        self.check_block(consequence);
+        let after_consequence = std::mem::replace(self.scope(), before);
+        if let Some(alternative) = alternative {
+            self.check_block(alternative);
+        }
+
+        let after_alternative = self.scope();
+        for (name, value) in after_alternative.iter_mut() {
+            *value = match after_consequence.get(name) {
+                Some(other) => value.join(*other),
+                None => Type::Unknown,
+            };
+        }
+        for name in after_consequence.into_keys() {
+            after_alternative.entry(name).or_insert(Type::Unknown);
+        }
+    }
+
+    fn check_prefix(&mut self, prefix: &Prefix, right: Type) -> Type {
+        match (prefix, right) {
+            (Prefix::Not, _) => Type::Bool,
+            (Prefix::Await, right) => right,
+            (_, Type::Int) => Type::Int,
+            (_, Type::Float) => Type::Float,
+            (_, Type::Unknown) => Type::Unknown,
+            (_, right) => {
+                self.error(DiagnosticCode::UnknownPrefixOperator {
+                    op: prefix.to_string(),
+                    right: right.to_string(),
+                });
+                Type::Unknown
+            }
+        }
+    }
+
+    fn check_infix(&mut self, infix: &Infix, left: Type, right: Type) -> Type {
+        if let Infix::And | Infix::Or | Infix::Equal | Infix::NotEqual = infix {
+            return Type::Bool;
+        }
+        if let Infix::Coalesce = infix {
+            return match left {
+                Type::Null => right,
+                left => left,
+            };
+        }
+
+        match (left, right) {
+            (Type::Int, Type::Int) => match infix {
+                Infix::Plus | Infix::Minus | Infix::Multiply | Infix::Divide => Type::Int,
+                _ => Type::Bool,
+            },
+            (Type::Int | Type::Float, Type::Int | Type::Float) => match infix {
+                Infix::Plus | Infix::Minus | Infix::Multiply | Infix::Divide => Type::Float,
+                _ => Type::Bool,
+            },
+            (Type::String, Type::String)
+            | (Type::Bool, Type::Bool)
+            | (Type::Array, Type::Array)
+                if infix.is_comparison() =>
+            {
+                Type::Bool
+            }
+            (Type::Unknown, _) | (_, Type::Unknown) if infix.is_comparison() => Type::Unknown,
+            _ if infix.is_comparison() => {
+                self.error(DiagnosticCode::TypeMismatch {
+                    left: left.to_string(),
+                    op: infix.to_string(),
+                    right: right.to_string(),
+                });
+                Type::Unknown
+            }
+            (Type::String, Type::String) if *infix == Infix::Plus => Type::String,
+            (Type::Unknown, _) | (Type::Int | Type::Float | Type::String, Type::Unknown) => {
+                Type::Unknown
+            }
+            (Type::Int | Type::Float, _)
+            | (Type::String, Type::Int | Type::Float | Type::Bool | Type::Array | Type::Hash) => {
+                self.error(DiagnosticCode::TypeMismatch {
+                    left: left.to_string(),
+                    op: infix.to_string(),
+                    right: right.to_string(),
+                });
+                Type::Unknown
+            }
+            (Type::String, Type::Null | Type::Func(_)) => {
+                self.error(DiagnosticCode::TypeMismatch {
+                    left: left.to_string(),
+                    op: infix.to_string(),
+                    right: right.to_string(),
+                });
+                Type::Unknown
+            }
+            _ => {
+                self.error(DiagnosticCode::UnknownInfixOperator {
+                    left: left.to_string(),
+                    op: infix.to_string(),
+                    right: right.to_string(),
+                });
+                Type::Unknown
+            }
+        }
+    }
+
+    fn check_index(&mut self, left: Type, index: Type) -> Type {
+        match left {
+            Type::Array => match index {
+                Type::Int | Type::Unknown => {}
+                _ => self.error(DiagnosticCode::IndexNotSupported {
+                    left: left.to_string(),
+                    index: Some(index.to_string()),
+                }),
+            },
+            Type::Hash => self.check_hash_key(index),
+            Type::Unknown => {}
+            _ => self.error(DiagnosticCode::IndexNotSupported {
+                left: left.to_string(),
+                index: None,
+            }),
+        }
+
+        Type::Unknown
+    }
+
+    fn check_hash_key(&mut self, key: Type) {
+        match key {
+            Type::Int | Type::String | Type::Bool | Type::Unknown => {}
+            _ => self.error(DiagnosticCode::UnusableHashKey {
+                key: key.to_string(),
+            }),
+        }
+    }
+
+    /// Hash entries can shadow methods, and an unknown receiver may be a hash,
+    /// so only other receivers are matched against the builtin methods.
+    fn check_method(&mut self, receiver: &Expr, name: &str) -> Type {
+        let receiver = self.check_expr(receiver);
+
+        match method(&receiver.to_string(), name) {
+            Some(Object::Builtin(n, _)) if !matches!(receiver, Type::Hash | Type::Unknown) => {
+                Type::Func(usize::try_from(n - 1).ok().map(|n| (n, n)))
+            }
+            _ => self.check_index(receiver, Type::String),
+        }
+    }
+
+    fn check_call(&mut self, func: Type, got: usize) -> Type {
+        match func {
+            Type::Func(Some((min, max))) if !(min..=max).contains(&got) => {
+                let want = if got < min { min } else { max };
+                self.error(DiagnosticCode::WrongArgumentCount {
+                    want: want.to_string(),
+                    got,
+                })
+            }
+            Type::Func(_) | Type::Unknown => {}
+            _ => self.error(DiagnosticCode::NotAFunction {
+                func: func.to_string(),
+            }),
+        }
+
+        Type::Unknown
+    }
+}
+
+/// Names bound by `let` or assigned to in `stmts`, including inside nested `if` and `while`
+/// blocks, which share the enclosing scope.
+fn bound_names(stmts: &BlockStmt, names: &mut Vec<String>) {
+    for stmt in stmts {
+        match stmt {
+            Stmt::Let(Ident(name), ..) | Stmt::Assign(Ident(name), _) => names.push(name.clone()),
+            Stmt::Expr(Expr::If {
+                consequence,
+                alternative,
+                ..
+            }) => {
+                bound_names(consequence, names);
+                if let Some(alternative) = alternative {
+                    bound_names(alternative, names);
+                }
+            }
+            Stmt::Expr(Expr::While { consequence, .. } | Expr::DoWhile { consequence, .. }) => {
+                bound_names(consequence, names)
+            }
+            Stmt::Expr(Expr::Match { arms, .. }) => {
+                for arm in arms {
+                    let bindings = arm.pattern.bindings().into_iter();
+                    names.extend(bindings.map(|Ident(name)| name.clone()));
+                    bound_names(&arm.body, names);
+                }
+            }
+            Stmt::Expr(Expr::For { vars, body, .. }) => {
+                names.extend(vars.iter().map(|Ident(name)| name.clone()));
+                bound_names(body, names);
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Type checks a program. Without `Stmt::Mark`s every diagnostic is at 1:1.
+pub fn check_program(program: &Program) -> Vec<Diagnostic> {
+    let globals = new_easter_eggs()
+        .into_iter()
+        .chain(new_builtins())
+        .map(|(name, obj)| (name, Type::of(&obj)))
+        .collect();
+
+    let mut checker = Checker {
+        scopes: vec![globals],
+        span: Span::default(),
+        diagnostics: vec![],
+    };
+    checker.check_block(program);
+
+    checker.diagnostics
+}
+
+/// Parses and type checks `input`. Parse errors are left to `diagnostic::check`.
+pub fn check(input: &str) -> Vec<Diagnostic> {
+    let mut parser = Parser::new(Lexer::new(input)).with_positions();
+    let program = parser.parse();
+
+    check_program(&program)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::diagnostic::Lang;
+    use crate::typecheck::*;
+
+    fn messages(input: &str) -> Vec<String> {
+        check(input)
+            .into_iter()
+            .map(|d| d.message(Lang::En))
+            .collect()
+    }
+
+    #[test]
+    fn test_check() {
+        let tests = vec![
+            (
+                "1 + 2 * 3; \"a\" + \"b\"; [1][0]; {\"a\": 1}[\"a\"];",
+                vec![],
+            ),
+            ("\"a\" * {};", vec!["type mismatch: string * hash"]),
+            ("1 + \"a\";", vec!["type mismatch: int + string"]),
+            ("1 + 0.5 < 2; -1.5;", vec![]),
+            ("1.5 + \"a\";", vec!["type mismatch: float + string"]),
+            ("\"a\" - \"b\";", vec!["unknown operator: string - string"]),
+            ("-true;", vec!["unknown operator: -bool"]),
+            ("\"a\" < \"b\"; [1] == {}; 1 != \"1\";", vec![]),
+            ("{} < {};", vec!["type mismatch: hash < hash"]),
+            ("let x = 1; x();", vec!["int is not a function"]),
+            (
+                "let f = fn(a, b) { a }; f(1);",
+                vec!["wrong number of arguments: 2 expected but 1 given"],
+            ),
+            (
+                "len(1, 2);",
+                vec!["wrong number of arguments: 1 expected but 2 given"],
+            ),
+            ("{[1]: 2};", vec!["unusable as hash key: array"]),
+            ("1[0];", vec!["index operator not supported: int"]),
+            ("let f = fn(x) { x * {} }; f(1);", vec![]),
+            ("let x = 1; if (true) { let x = \"a\"; }; x + 1;", vec![]),
+            ("if (true) { 1 } else { let y = 1; }; y();", vec![]),
+            ("let x = 1; while (x) { x + 1; let x = \"a\"; };", vec![]),
+            ("let i = 0; while (false) { let i = \"s\"; } i + 1;", vec![]),
+            (
+                "let i = 0; do { let i = \"s\"; } while (false); i + 1;",
+                vec![],
+            ),
+            (
+                "let x = 0; for (x in [\"a\"]) { let y = \"s\"; } x + 1; y + 1;",
+                vec![],
+            ),
+            ("let x = 1; let f = fn() { x(); };", vec![]),
+            ("\"abc\".len(); [1].push(2); {}.keys(); 1.str();", vec![]),
+            (
+                "\"abc\".len(1);",
+                vec!["wrong number of arguments: 0 expected but 1 given"],
+            ),
+            ("1.len();", vec!["index operator not supported: int"]),
+            ("let h = {\"len\": fn(a, b) { a }}; h.len(1, 2);", vec![]),
+            (
+                "let f = fn(a, b = a, c = 1) { a }; f(1); f(1, 2, 3);",
+                vec![],
+            ),
+            (
+                "let f = fn(a, b = 1) { a }; f();",
+                vec!["wrong number of arguments: 1 expected but 0 given"],
+            ),
+            (
+                "let f = fn(a, b = {} * a) { a };",
+                vec!["unknown operator: hash * unknown"],
+            ),
+            (
+                "match 1 > 2 { true => 1 }; match 1 { 1 => 2 }; match true { false => 1, _ => 2 };",
+                vec!["match has no arm for `false`, so it gives null then"],
+            ),
+            (
+                "let x = true; 下头菜单 x { true => 1, false => 0 }; 下头菜单 !x { 1 => 1 };",
+                vec![
+                    "match has no arm for `true`, so it gives null then",
+                    "match has no arm for `false`, so it gives null then",
+                ],
+            ),
+            (
+                "let x = 1; match true { false => 0, b => b }; match [x] { [x] => x + \"a\" };",
+                vec![],
+            ),
+        ];
+
+        for (input, expect) in tests {
+            assert_eq!(expect, messages(input), "{input}");
+        }
+    }
+
+    #[test]
+    fn test_check_span() {
+        let diagnostics = check("宝宝你是一个 x = 1;\n\n  x(1);");
+
+        assert_eq!(1, diagnostics.len());
+        assert_eq!(
+            (3, 3),
+            (
+                diagnostics[0].span.start.line,
+                diagnostics[0].span.start.column
+            )
+        );
+    }
+}
@@ -1,48 +1,156 @@
+// The exports read strings through pointers from the host, which keeps to
+// the memory contract below `parse`. Marking them `unsafe` changes nothing
+// for the JS calling them, so the lint is off here.
+#![allow(clippy::not_unsafe_ptr_arg_deref)]
+
 extern crate herlang;
 
 use herlang::ast::Program;
+#[cfg(feature = "diagnostics")]
+use herlang::completion;
+#[cfg(feature = "diagnostics")]
+use herlang::diagnostic;
+use herlang::diagnostic::Lang;
 use herlang::evaluator::Evaluator;
-use herlang::evaluator::builtins::new_builtins;
+use herlang::evaluator::builtins::{new_builtins, set_output_sink, sleep_millis};
+use herlang::evaluator::debug::{DebugSession, Step};
 use herlang::evaluator::env::Env;
 use herlang::evaluator::object::Object;
-use herlang::formatter::Formatter;
+use herlang::evaluator::shared::Shared;
+#[cfg(feature = "formatter")]
+use herlang::formatter;
+#[cfg(feature = "diagnostics")]
+use herlang::highlight;
+use herlang::json;
 use herlang::lexer::Lexer;
-use herlang::parser::Parser;
-use std::cell::RefCell;
+use herlang::parser::{ParseError, Parser};
+#[cfg(feature = "share")]
+use herlang::share;
+use herlang::span::{Span, Utf16Index};
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
 use std::ffi::{CStr, CString};
 use std::mem;
 use std::os::raw::{c_char, c_void};
-use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, LazyLock};
 
 fn main() {}
 
+/// Cancellation flag shared with the host. A worker running `eval` can be
+/// stopped by writing a non-zero byte at `interrupt_flag()` in a shared memory.
+/// Without shared memory the host's `should_stop` import is asked instead.
+static INTERRUPT: LazyLock<Arc<AtomicBool>> = LazyLock::new(|| Arc::new(AtomicBool::new(false)));
+
+/// Longest input in bytes any export looks at, see `set_limits`.
+static MAX_INPUT: AtomicUsize = AtomicUsize::new(256 * 1024);
+
+/// Tokens the parser may consume before giving up, see `set_limits`.
+static MAX_PARSE_STEPS: AtomicUsize = AtomicUsize::new(100_000);
+
+thread_local! {
+    /// Language of runtime errors and diagnostics, see `set_lang`.
+    static LANG: Cell<Lang> = const { Cell::new(Lang::En) };
+}
+
+/// The last session id handed out, and the open sessions by id with the
+/// index their spans are converted with.
+type Sessions = (u32, HashMap<u32, (DebugSession, Utf16Index)>);
+
+thread_local! {
+    /// Open stepping sessions by id, see `debug_start`.
+    static SESSIONS: RefCell<Sessions> = RefCell::new((0, HashMap::new()));
+}
+
+#[cfg(not(test))]
 unsafe extern "C" {
     unsafe fn print(input_ptr: *mut c_char);
+    /// Waits `ms` milliseconds, or returns right away where the host can't
+    /// block, like the page's main thread.
+    unsafe fn sleep(ms: f64);
+    /// Non-zero to stop the running program, asked before every loop
+    /// iteration and function call. Works without shared memory, e.g. to
+    /// end runs that go past a time limit.
+    unsafe fn should_stop() -> i32;
 }
 
+fn host_should_stop() -> bool {
+    unsafe { should_stop() != 0 }
+}
+
+/// The host copies the string out during the call; we keep ownership.
 fn internal_print(msg: &str) {
+    let msg = CString::new(msg).unwrap_or_default();
     unsafe {
-        print(string_to_ptr(msg.to_string()));
+        print(msg.as_ptr() as *mut c_char);
     }
 }
 
-fn wasm_output(args: Vec<Object>) -> Object {
-    for arg in args {
-        internal_print(&format!("{}", arg));
+thread_local! {
+    /// Output after the last line break, see `with_host_output`.
+    static PENDING: RefCell<String> = const { RefCell::new(String::new()) };
+}
+
+/// Runs `f` with the output builtins going to the host, a line per `print`
+/// call since the host ends each with a line break.
+fn with_host_output<T>(f: impl FnOnce() -> T) -> T {
+    let outer = set_output_sink(Box::new(|text: &str| {
+        PENDING.with_borrow_mut(|pending| {
+            pending.push_str(text);
+            while let Some(end) = pending.find('\n') {
+                internal_print(&pending[..end]);
+                pending.drain(..=end);
+            }
+        })
+    }));
+    let result = f();
+    set_output_sink(outer);
+
+    let rest = PENDING.take();
+    if !rest.is_empty() {
+        internal_print(&rest);
+    }
+    result
+}
+
+/// `std::thread::sleep` isn't there in the browser, so the host waits.
+fn wasm_sleep(args: Vec<Object>) -> Object {
+    match sleep_millis(&args[0]) {
+        Ok(ms) => {
+            unsafe { sleep(ms as f64) };
+            Object::Null
+        }
+        Err(err) => err,
     }
-    Object::Null
 }
 
+/// Hands a result string to the host, which must release it with `free_result`.
 fn string_to_ptr(s: String) -> *mut c_char {
-    CString::new(s).unwrap().into_raw()
+    CString::new(s).unwrap_or_default().into_raw()
+}
+
+fn is_too_long(input: &str) -> bool {
+    input.len() > MAX_INPUT.load(Ordering::Relaxed)
+}
+
+/// A parser within the limits, or `ParseError::TooLong` for an input over
+/// `MAX_INPUT`.
+fn parser(input: &str) -> Result<Parser, Box<ParseError>> {
+    if is_too_long(input) {
+        return Err(Box::new(ParseError::TooLong {
+            span: Span::default(),
+        }));
+    }
+
+    Ok(Parser::new(Lexer::new(input)).with_max_steps(MAX_PARSE_STEPS.load(Ordering::Relaxed)))
 }
 
 fn parse(input: &str) -> Result<Program, String> {
-    let mut parser = Parser::new(Lexer::new(input));
+    let mut parser = parser(input).map_err(|err| format!("{err}\n"))?;
     let program = parser.parse();
     let errors = parser.get_errors();
 
-    if errors.len() > 0 {
+    if !errors.is_empty() {
         let msg = errors
             .into_iter()
             .map(|e| format!("{}\n", e))
@@ -54,9 +162,16 @@ fn parse(input: &str) -> Result<Program, String> {
     Ok(program)
 }
 
+// Memory contract with the host:
+//
+// * Input strings go into a buffer from `alloc(size)` and must be given back
+//   with `dealloc(ptr, size)` using the same size once the call returns.
+// * Every exported function returning `*mut c_char` hands over a NUL
+//   terminated string the host must release with `free_result(ptr)`.
+
 #[unsafe(no_mangle)]
 pub fn alloc(size: usize) -> *mut c_void {
-    let mut buf = Vec::with_capacity(size);
+    let mut buf = Vec::<u8>::with_capacity(size);
     let ptr = buf.as_mut_ptr();
     mem::forget(buf);
     ptr as *mut c_void
@@ -64,13 +179,22 @@ pub fn alloc(size: usize) -> *mut c_void {
 
 #[unsafe(no_mangle)]
 pub fn dealloc(ptr: *mut c_void, size: usize) {
-    // Clear memory to zero for security purposes (optional).
-    if !ptr.is_null() && size > 0 {
-        unsafe {
-            std::ptr::write_bytes(ptr, 0, size);
-        }
+    if ptr.is_null() {
+        return;
+    }
+    unsafe {
+        drop(Vec::from_raw_parts(ptr as *mut u8, 0, size));
+    }
+}
+
+#[unsafe(no_mangle)]
+pub fn free_result(ptr: *mut c_char) {
+    if ptr.is_null() {
+        return;
+    }
+    unsafe {
+        drop(CString::from_raw(ptr));
     }
-    // The memory deallocation is deferred to the caller (e.g., via `free`).
 }
 
 #[unsafe(no_mangle)]
@@ -83,29 +207,360 @@ pub fn eval(input_ptr: *mut c_char) -> *mut c_char {
 
     let mut env = Env::from(new_builtins());
 
-    env.set(String::from("小作文"), &Object::Builtin(-1, wasm_output));
-    env.set(String::from("家人们"), &Object::Builtin(-1, wasm_output));
+    env.define(String::from("sleep"), &Object::Builtin(1, wasm_sleep));
+    env.define(String::from("躺平"), &Object::Builtin(1, wasm_sleep));
+
+    INTERRUPT.store(false, Ordering::Relaxed);
 
-    let mut evaluator = Evaluator::new(Rc::new(RefCell::new(env)));
-    let evaluated = evaluator.eval(&program).unwrap_or(Object::Null);
+    let mut evaluator = Evaluator::new(Shared::new(env))
+        .with_interrupt(Arc::clone(&INTERRUPT))
+        .with_stop_check(host_should_stop)
+        .with_lang(LANG.get());
+    let evaluated = with_host_output(|| evaluator.eval(&program)).unwrap_or(Object::Null);
     let output = format!("{}", evaluated);
 
     string_to_ptr(output)
 }
 
+/// Sets the longest input in bytes and the most tokens a parse may take.
+/// Longer code gets `代码太长，姐妹歇歇` instead of freezing the page.
+#[unsafe(no_mangle)]
+pub fn set_limits(max_input: usize, max_parse_steps: usize) {
+    MAX_INPUT.store(max_input, Ordering::Relaxed);
+    MAX_PARSE_STEPS.store(max_parse_steps, Ordering::Relaxed);
+}
+
+/// Words runtime errors and diagnostics in `en` or `zh`; anything else
+/// is ignored.
+#[unsafe(no_mangle)]
+pub fn set_lang(lang_ptr: *mut c_char) {
+    let lang = unsafe { CStr::from_ptr(lang_ptr).to_string_lossy().into_owned() };
+    if let Ok(lang) = lang.parse() {
+        LANG.set(lang);
+    }
+}
+
+/// Address of the cancellation flag, for `Atomics.store(heap, ptr, 1)`.
+#[unsafe(no_mangle)]
+pub fn interrupt_flag() -> *const AtomicBool {
+    Arc::as_ptr(&INTERRUPT)
+}
+
+/// Formats as much as parses; the lines with errors are kept as they are
+/// and the errors printed.
+#[cfg(feature = "formatter")]
 #[unsafe(no_mangle)]
 pub fn format(input_ptr: *mut c_char) -> *mut c_char {
     let input = unsafe { CStr::from_ptr(input_ptr).to_string_lossy().into_owned() };
-    let program = match parse(&input) {
-        Ok(program) => program,
-        Err(msg) => {
-            internal_print(&msg);
-            return string_to_ptr(String::new());
+    if let Err(err) = parser(&input) {
+        internal_print(&format!("{err}\n"));
+        return string_to_ptr(input);
+    }
+
+    let (output, errors) = formatter::format_lenient(&input);
+    if !errors.is_empty() {
+        internal_print(&errors.iter().map(|e| format!("{e}\n")).collect::<String>());
+    }
+
+    string_to_ptr(output)
+}
+
+/// Lexes and parses only, returning a JSON array of diagnostics with ranges.
+/// Cheap enough to call on every keystroke.
+///
+/// Offsets and columns in and out of the exports count UTF-16 code units,
+/// like the JS strings they index into.
+#[cfg(feature = "diagnostics")]
+#[unsafe(no_mangle)]
+pub fn diagnostics(input_ptr: *mut c_char) -> *mut c_char {
+    let input = unsafe { CStr::from_ptr(input_ptr).to_string_lossy().into_owned() };
+    let diagnostics = match parser(&input) {
+        Ok(parser) => diagnostic::check_with(parser),
+        Err(err) => vec![diagnostic::Diagnostic::from(&*err)],
+    };
+    let index = Utf16Index::new(&input);
+    let diagnostics = diagnostics
+        .into_iter()
+        .map(|d| diagnostic::Diagnostic {
+            span: index.span(d.span),
+            ..d
+        })
+        .collect::<Vec<_>>();
+
+    string_to_ptr(diagnostic::to_json(&diagnostics, LANG.get()))
+}
+
+/// Returns the lexer's own token classification as JSON, for the editor's tokenizer.
+#[cfg(feature = "diagnostics")]
+#[unsafe(no_mangle)]
+pub fn tokens(input_ptr: *mut c_char) -> *mut c_char {
+    let input = unsafe { CStr::from_ptr(input_ptr).to_string_lossy().into_owned() };
+    if is_too_long(&input) {
+        return string_to_ptr(String::from("[]"));
+    }
+
+    let index = Utf16Index::new(&input);
+    let tokens = highlight::tokens(&input)
+        .into_iter()
+        .map(|t| highlight::HighlightToken {
+            span: index.span(t.span),
+            ..t
+        })
+        .collect::<Vec<_>>();
+
+    string_to_ptr(highlight::to_json(&tokens))
+}
+
+/// Completion candidates for the word ending at `offset`, as JSON.
+#[cfg(feature = "diagnostics")]
+#[unsafe(no_mangle)]
+pub fn complete(input_ptr: *mut c_char, offset: usize) -> *mut c_char {
+    let input = unsafe { CStr::from_ptr(input_ptr).to_string_lossy().into_owned() };
+    if is_too_long(&input) {
+        return string_to_ptr(format!("{{\"from\":{offset},\"items\":[]}}"));
+    }
+
+    let index = Utf16Index::new(&input);
+    let mut completions = completion::complete(&input, index.to_char(offset));
+    completions.from = index.to_utf16(completions.from);
+
+    string_to_ptr(completions.to_json())
+}
+
+/// Formats and reports where the cursor moved to, as
+/// `{"code": ..., "cursor": ...}`. On parse errors only the statements that
+/// parse are formatted, see `formatter::format_lenient`.
+#[cfg(feature = "formatter")]
+#[unsafe(no_mangle)]
+pub fn format_with_cursor(input_ptr: *mut c_char, cursor: usize) -> *mut c_char {
+    let input = unsafe { CStr::from_ptr(input_ptr).to_string_lossy().into_owned() };
+    let (code, cursor) = match is_too_long(&input) {
+        true => (input, cursor),
+        false => {
+            let cursor = Utf16Index::new(&input).to_char(cursor);
+            let (code, cursor) = match formatter::format_with_cursor(&input, cursor) {
+                Ok(formatted) => (formatted.code, formatted.cursor),
+                Err(_) => {
+                    let (code, _) = formatter::format_lenient(&input);
+                    let cursor = formatter::map_cursor(&input, &code, cursor);
+                    (code, cursor)
+                }
+            };
+            let cursor = Utf16Index::new(&code).to_utf16(cursor);
+            (code, cursor)
+        }
+    };
+
+    string_to_ptr(format!(
+        "{{\"code\":{},\"cursor\":{}}}",
+        json::string(&code),
+        cursor
+    ))
+}
+
+/// Encodes source as a playground share string, same as `herlang share`.
+#[cfg(feature = "share")]
+#[unsafe(no_mangle)]
+pub fn encode_share(input_ptr: *mut c_char) -> *mut c_char {
+    let input = unsafe { CStr::from_ptr(input_ptr).to_string_lossy().into_owned() };
+
+    string_to_ptr(share::encode_share(&input))
+}
+
+/// Decodes a share string back to source, or an empty string if it is malformed.
+#[cfg(feature = "share")]
+#[unsafe(no_mangle)]
+pub fn decode_share(input_ptr: *mut c_char) -> *mut c_char {
+    let input = unsafe { CStr::from_ptr(input_ptr).to_string_lossy().into_owned() };
+
+    string_to_ptr(share::decode_share(&input).unwrap_or_default())
+}
+
+/// Runs the program in step mode and returns a session id for `debug_step`,
+/// or 0 after printing the parse errors. It can be stopped like `eval`.
+#[unsafe(no_mangle)]
+pub fn debug_start(input_ptr: *mut c_char) -> u32 {
+    let input = unsafe { CStr::from_ptr(input_ptr).to_string_lossy().into_owned() };
+    let parser = match parser(&input) {
+        Ok(parser) => parser,
+        Err(err) => {
+            internal_print(&err.to_string());
+            return 0;
         }
     };
 
-    let mut formatter = Formatter::new();
-    let output = formatter.format(program);
+    INTERRUPT.store(false, Ordering::Relaxed);
+
+    let evaluator = Evaluator::new(Shared::new(Env::from(new_builtins())))
+        .with_interrupt(Arc::clone(&INTERRUPT))
+        .with_stop_check(host_should_stop)
+        .with_lang(LANG.get());
+    let index = Utf16Index::new(&input);
+    // The whole program runs here; `debug_step` only replays the trace and
+    // prints each statement's output as it's stepped past.
+    let session = match DebugSession::with(parser, evaluator) {
+        Ok(session) => session,
+        Err(errors) => {
+            for err in errors {
+                internal_print(&err.to_string());
+            }
+            return 0;
+        }
+    };
+
+    SESSIONS.with_borrow_mut(|(last_id, sessions)| {
+        *last_id += 1;
+        sessions.insert(*last_id, (session, index));
+        *last_id
+    })
+}
+
+/// Advances a session by one statement, as `{"done": false, "step": ...}`, or
+/// `{"done": true, "result": ...}` once the program has finished. What the
+/// statement stepped past printed goes to the host first.
+#[unsafe(no_mangle)]
+pub fn debug_step(session: u32) -> *mut c_char {
+    let output = SESSIONS.with_borrow_mut(|(_, sessions)| match sessions.get_mut(&session) {
+        Some((session, index)) => {
+            if let Some(step) = session.current() {
+                for line in step.output.lines() {
+                    internal_print(line);
+                }
+            }
+            match session.step() {
+                Some(step) => {
+                    let step = Step {
+                        span: index.span(step.span),
+                        ..step.clone()
+                    };
+                    format!("{{\"done\":false,\"step\":{}}}", step.to_json())
+                }
+                None => format!(
+                    "{{\"done\":true,\"result\":{}}}",
+                    json::string(&session.result().unwrap_or(&Object::Null).to_string())
+                ),
+            }
+        }
+        None => String::from("{\"done\":true,\"result\":null}"),
+    });
 
     string_to_ptr(output)
 }
+
+#[unsafe(no_mangle)]
+pub fn debug_end(session: u32) {
+    SESSIONS.with_borrow_mut(|(_, sessions)| sessions.remove(&session));
+}
+
+/// Stands in for the page: keeps every `print` line for the tests to read.
+#[cfg(test)]
+mod host {
+    use std::cell::RefCell;
+    use std::ffi::CStr;
+    use std::os::raw::c_char;
+
+    thread_local! {
+        pub static PRINTED: RefCell<Vec<String>> = const { RefCell::new(Vec::new()) };
+    }
+
+    pub unsafe fn print(input_ptr: *mut c_char) {
+        let line = unsafe { CStr::from_ptr(input_ptr).to_string_lossy().into_owned() };
+        PRINTED.with_borrow_mut(|printed| printed.push(line));
+    }
+
+    pub unsafe fn sleep(_ms: f64) {}
+
+    thread_local! {
+        pub static STOP: std::cell::Cell<bool> = const { std::cell::Cell::new(false) };
+    }
+
+    pub unsafe fn should_stop() -> i32 {
+        STOP.get() as i32
+    }
+}
+
+#[cfg(test)]
+use host::{print, should_stop, sleep};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn take_printed() -> Vec<String> {
+        host::PRINTED.take()
+    }
+
+    fn step(session: u32) -> String {
+        let ptr = debug_step(session);
+        let output = unsafe { CStr::from_ptr(ptr).to_string_lossy().into_owned() };
+        free_result(ptr);
+        output
+    }
+
+    fn eval_str(input: &str) -> String {
+        let input = CString::new(input).unwrap();
+        let ptr = eval(input.as_ptr() as *mut c_char);
+        let output = unsafe { CStr::from_ptr(ptr).to_string_lossy().into_owned() };
+        free_result(ptr);
+        output
+    }
+
+    #[test]
+    fn test_should_stop() {
+        host::STOP.set(true);
+        assert_eq!(
+            Object::Error(String::from(herlang::evaluator::INTERRUPTED)).to_string(),
+            eval_str("while (true) { 1 }")
+        );
+
+        host::STOP.set(false);
+        assert_eq!("3", eval_str("1 + 2"));
+    }
+
+    #[test]
+    fn test_debug_session_output() {
+        let input = CString::new("小作文(1); print(\"二\"); 小作文(3);").unwrap();
+        take_printed();
+
+        let session = debug_start(input.as_ptr() as *mut c_char);
+        assert_ne!(session, 0);
+        assert!(take_printed().is_empty());
+
+        for expect in [vec![], vec!["1"], vec!["二"]] {
+            assert!(step(session).starts_with("{\"done\":false"));
+            assert_eq!(take_printed(), expect);
+        }
+        assert!(step(session).starts_with("{\"done\":true"));
+        assert_eq!(take_printed(), vec!["3"]);
+        debug_end(session);
+    }
+
+    #[test]
+    fn test_debug_session_limits() {
+        let input = CString::new("1;".repeat(200_000)).unwrap();
+        take_printed();
+        assert_eq!(debug_start(input.as_ptr() as *mut c_char), 0);
+        assert_eq!(
+            take_printed(),
+            vec![
+                ParseError::TooLong {
+                    span: Span::default()
+                }
+                .to_string()
+            ]
+        );
+
+        host::STOP.set(true);
+        let input = CString::new("while (true) { 1 }").unwrap();
+        let session = debug_start(input.as_ptr() as *mut c_char);
+        host::STOP.set(false);
+        assert_ne!(session, 0);
+
+        let mut output = step(session);
+        while !output.starts_with("{\"done\":true") {
+            output = step(session);
+        }
+        assert!(output.contains(herlang::evaluator::INTERRUPTED), "{output}");
+        debug_end(session);
+    }
+}
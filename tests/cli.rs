@@ -0,0 +1,131 @@
+//! Runs the `herlang` binary the way a user would.
+
+use herlang::herc;
+use herlang::lexer::{LANGUAGE_VERSION, Lexer};
+use herlang::parser::Parser;
+use std::fs;
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::process::{Child, Command, Output, Stdio};
+use std::thread;
+use std::time::Duration;
+
+fn herlang(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_herlang"))
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("herlang-cli-{name}-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn test_test_file() {
+    let dir = temp_dir("test-file");
+    let file = dir.join("add.her");
+    fs::write(&file, "考一考 \"加法\" { assert(1 + 1 == 2) }").unwrap();
+    fs::write(dir.join("other.her"), "考一考 \"不跑\" { assert(false) }").unwrap();
+
+    let output = herlang(&["test", file.to_str().unwrap()]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "{stdout}");
+    assert!(stdout.contains("ok      "), "{stdout}");
+    assert!(stdout.ends_with("1 passed, 0 failed\n"), "{stdout}");
+
+    let output = herlang(&["test", dir.join("missing.her").to_str().unwrap()]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!output.status.success());
+    assert!(stderr.contains("missing.her: "), "{stderr}");
+    assert!(!stderr.contains("Os {"), "{stderr}");
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_build_herc() {
+    let dir = temp_dir("build-herc");
+    let file = dir.join("utils.her");
+    let source = "宝宝你是一个 double = fn(x) { x * 2 };";
+    fs::write(&file, source).unwrap();
+
+    let output = herlang(&["build", "--target=herc", file.to_str().unwrap()]);
+    assert!(output.status.success());
+
+    let program = Parser::new(Lexer::new(source)).parse();
+    let bytes = fs::read(dir.join("utils.herc")).unwrap();
+    assert_eq!(Ok(program), herc::decode(&bytes, LANGUAGE_VERSION));
+
+    fs::write(
+        dir.join("main.her"),
+        "引入 \"utils.her\"; print(double(21));",
+    )
+    .unwrap();
+    let output = herlang(&["run", dir.join("main.her").to_str().unwrap()]);
+    assert_eq!("42\n", String::from_utf8_lossy(&output.stdout));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+/// The body of the response to `POST /eval` with `code`.
+fn post_eval(port: u16, code: &str) -> String {
+    let mut stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+    write!(
+        stream,
+        "POST /eval HTTP/1.1\r\nContent-Length: {}\r\n\r\n{code}",
+        code.len()
+    )
+    .unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    response.split_once("\r\n\r\n").unwrap().1.to_string()
+}
+
+/// A server process that is killed when dropped, also when a test fails.
+struct Server(Child);
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = self.0.kill();
+        let _ = self.0.wait();
+    }
+}
+
+#[test]
+fn test_serve_survives_crashing_runs() {
+    let port = TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let _server = Server(
+        Command::new(env!("CARGO_BIN_EXE_herlang"))
+            .args(["serve", &format!("--port={port}")])
+            .stdout(Stdio::null())
+            .spawn()
+            .unwrap(),
+    );
+    while TcpStream::connect(("127.0.0.1", port)).is_err() {
+        thread::sleep(Duration::from_millis(20));
+    }
+
+    let nested_json = r#"let s = "["; for (_ in range(22)) { let s = s + s }; json_parse(s)"#;
+    let body = post_eval(port, nested_json);
+    assert!(body.contains("nested too deep"), "{body}");
+
+    // Printing a value this deep overflows the stack of the worker process.
+    let nested_value = "let a = []; for (_ in range(300000)) { let a = [a] }; a";
+    let body = post_eval(port, nested_value);
+    // With `invariants` every value is checked after each expression, which
+    // is too slow to build this one before the run times out.
+    let stopped = cfg!(feature = "invariants") && body.contains("被姐妹叫停");
+    assert!(body.contains(r#""error":"跑崩了"#) || stopped, "{body}");
+
+    let body = post_eval(port, "1 + 2");
+    assert!(body.contains(r#""result":"3""#), "{body}");
+}
@@ -71,14 +71,23 @@ export const Command = {
       Command.setValue(result);
     }
   },
+
+  // Share links need the wasm module, so they are restored once it has loaded.
+  // Links made before the Rust encoder fall back to lz-string.
+  loadShared: () => {
+    if (!query.has(SHARE_QUERY_KEY)) return;
+
+    const value = query.get(SHARE_QUERY_KEY);
+    const source = Module.decodeShare(value) ||
+      LZString.decompressFromEncodedURIComponent(value);
+
+    Command.setValue(source ?? "");
+  },
 };
 
 const query = new window.URLSearchParams(window.location.search);
 
 if (query.has(SHARE_QUERY_KEY)) {
-  Command.setValue(
-    LZString.decompressFromEncodedURIComponent(query.get(SHARE_QUERY_KEY)),
-  );
   snippet.selectedIndex = 0;
 } else {
   Command.setValue(SNIPPETS[0].value);
@@ -1,5 +1,5 @@
-import LZString from "lz-string";
 import { SHARE_QUERY_KEY } from "../constants";
+import { Module } from "../module";
 import { Command } from "./";
 
 const share = document.getElementById("share");
@@ -11,8 +11,10 @@ share.addEventListener(
   (e) => {
     e.preventDefault();
 
+    if (!Module.isReady()) return;
+
     const { protocol, host, pathname } = window.location;
-    const value = LZString.compressToEncodedURIComponent(Command.getValue());
+    const value = Module.encodeShare(Command.getValue());
 
     window.history.pushState(
       {},
@@ -11,4 +11,16 @@ Module.load("./herlang.wasm", {
     console.log(str);
     Command.print(str);
   },
-}).catch((e) => console.error(e));
+  // `Atomics.wait` only blocks in workers; on the main thread `sleep` doesn't wait.
+  sleep: (ms) => {
+    try {
+      Atomics.wait(new Int32Array(new SharedArrayBuffer(4)), 0, 0, ms);
+    } catch {}
+  },
+})
+  .then(() => {
+    // Keeps an endless loop from freezing the page for good.
+    Module.setTimeLimit(10000);
+    Command.loadShared();
+  })
+  .catch((e) => console.error(e));
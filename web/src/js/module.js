@@ -2,17 +2,37 @@ export const Module = {
   _memory: null,
   _alloc: null,
   _dealloc: null,
+  _freeResult: null,
   _eval: null,
   _format: null,
+  _diagnostics: null,
+  _tokens: null,
+  _complete: null,
+  _formatWithCursor: null,
+  _interruptFlag: null,
+  _setLimits: null,
+  _setLang: null,
+  _encodeShare: null,
+  _decodeShare: null,
+  _debugStart: null,
+  _debugStep: null,
+  _debugEnd: null,
+  _timeLimit: Infinity,
+  _deadline: Infinity,
   _textEncoder: new TextEncoder("UTF-8"),
   _textDecoder: new TextDecoder("UTF-8"),
 
   isReady: () => Module._memory != null,
 
+  // `env` can bring its own `should_stop`, e.g. one reading a flag the page
+  // sets through a SharedArrayBuffer while a worker runs eval.
   load: async (path, env = {}) => {
     try {
       const imports = {
-        env,
+        env: {
+          should_stop: () => (performance.now() > Module._deadline ? 1 : 0),
+          ...env,
+        },
       };
 
       const {
@@ -22,8 +42,21 @@ export const Module = {
       Module._memory = exports.memory;
       Module._alloc = exports.alloc;
       Module._dealloc = exports.dealloc;
+      Module._freeResult = exports.free_result;
       Module._eval = exports.eval;
       Module._format = exports.format;
+      Module._diagnostics = exports.diagnostics;
+      Module._tokens = exports.tokens;
+      Module._complete = exports.complete;
+      Module._formatWithCursor = exports.format_with_cursor;
+      Module._interruptFlag = exports.interrupt_flag;
+      Module._setLimits = exports.set_limits;
+      Module._setLang = exports.set_lang;
+      Module._encodeShare = exports.encode_share;
+      Module._decodeShare = exports.decode_share;
+      Module._debugStart = exports.debug_start;
+      Module._debugStep = exports.debug_step;
+      Module._debugEnd = exports.debug_end;
     } catch (e) {
       console.error(e);
     }
@@ -43,8 +76,19 @@ export const Module = {
     return { buf, ptr };
   },
 
-  dealloc: (ptr, len) => {
-    Module._dealloc(ptr);
+  dealloc: (ptr, size) => {
+    Module._dealloc(ptr, size);
+  },
+
+  // Passes `str` to `fn` and returns the string result. The input buffer
+  // goes back through `dealloc` and the result through `free_result`.
+  callWithStr: (fn, str, ...args) => {
+    const { buf, ptr } = Module.allocStr(str);
+    const resultPtr = fn(ptr, ...args);
+    Module.dealloc(ptr, buf.length + 1);
+    const result = Module.copyCStr(resultPtr);
+    Module._freeResult(resultPtr);
+    return result;
   },
 
   copyCStr: (ptr) => {
@@ -63,17 +107,98 @@ export const Module = {
 
   eval: (str) => {
     if (!Module.isReady()) return;
-    const { buf, ptr } = Module.allocStr(str);
-    const resultPtr = Module._eval(ptr);
-    Module.dealloc(resultPtr, buf.length);
-    return Module.copyCStr(resultPtr);
+    Module._deadline = performance.now() + Module._timeLimit;
+    try {
+      return Module.callWithStr(Module._eval, str);
+    } finally {
+      Module._deadline = Infinity;
+    }
   },
 
   format: (str) => {
     if (!Module.isReady()) return;
+    return Module.callWithStr(Module._format, str);
+  },
+
+  diagnostics: (str) => {
+    if (!Module.isReady()) return [];
+    return JSON.parse(Module.callWithStr(Module._diagnostics, str));
+  },
+
+  tokens: (str) => {
+    if (!Module.isReady()) return [];
+    return JSON.parse(Module.callWithStr(Module._tokens, str));
+  },
+
+  complete: (str, offset) => {
+    if (!Module.isReady()) return { from: offset, items: [] };
+    return JSON.parse(Module.callWithStr(Module._complete, str, offset));
+  },
+
+  formatWithCursor: (str, cursor) => {
+    if (!Module.isReady()) return { code: str, cursor };
+    return JSON.parse(Module.callWithStr(Module._formatWithCursor, str, cursor));
+  },
+
+  encodeShare: (str) => {
+    if (!Module.isReady()) return;
+    return Module.callWithStr(Module._encodeShare, str);
+  },
+
+  decodeShare: (str) => {
+    if (!Module.isReady()) return "";
+    return Module.callWithStr(Module._decodeShare, str);
+  },
+
+  // Returns a session id, or 0 if the code does not parse.
+  debugStart: (str) => {
+    if (!Module.isReady()) return 0;
     const { buf, ptr } = Module.allocStr(str);
-    const resultPtr = Module._format(ptr);
-    Module.dealloc(resultPtr, buf.length);
-    return Module.copyCStr(resultPtr);
+    const session = Module._debugStart(ptr);
+    Module.dealloc(ptr, buf.length + 1);
+    return session;
+  },
+
+  debugStep: (session) => {
+    if (!Module.isReady()) return { done: true, result: null };
+    const resultPtr = Module._debugStep(session);
+    const result = Module.copyCStr(resultPtr);
+    Module._freeResult(resultPtr);
+    return JSON.parse(result);
+  },
+
+  debugEnd: (session) => {
+    if (!Module.isReady()) return;
+    Module._debugEnd(session);
+  },
+
+  // Longer input, in bytes, or more tokens than `maxParseSteps` gets
+  // "代码太长，姐妹歇歇" instead of being parsed.
+  setLimits: (maxInput, maxParseSteps) => {
+    if (!Module.isReady()) return;
+    Module._setLimits(maxInput, maxParseSteps);
+  },
+
+  // "en" or "zh", for runtime errors and diagnostics.
+  setLang: (lang) => {
+    if (!Module.isReady()) return;
+    const { buf, ptr } = Module.allocStr(lang);
+    Module._setLang(ptr);
+    Module.dealloc(ptr, buf.length + 1);
+  },
+
+  // Stops eval runs that take longer than `ms` milliseconds with
+  // "被姐妹叫停", through the default `should_stop` import.
+  setTimeLimit: (ms) => {
+    Module._timeLimit = ms;
+  },
+
+  // Only takes effect mid-run when the module's memory is a SharedArrayBuffer
+  // and eval runs in a worker; otherwise the flag is cleared by the next eval.
+  // Hosts without shared memory use `setTimeLimit` or their own `should_stop`.
+  interrupt: () => {
+    if (!Module.isReady()) return;
+    const heap = new Int8Array(Module._memory.buffer);
+    Atomics.store(heap, Module._interruptFlag(), 1);
   },
 };